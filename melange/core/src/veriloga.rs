@@ -9,7 +9,8 @@ use libc::c_void;
 use libloading::Library;
 use log::{debug, error, info, warn};
 use openvaf::{
-    AbsPathBuf, CompilationDestination, CompilationTermination, LintLevel, OptLevel, Target,
+    AbsPathBuf, AutoDiffOptions, CompilationDestination, CompilationTermination, FloatPolicy,
+    LinkerKind, LintLevel, OptLevel, Target,
 };
 
 use crate::devices::DeviceImpl;
@@ -67,19 +68,33 @@ pub fn compile_va(path: &Utf8Path, opts: &Opts) -> Result<Vec<Box<dyn DeviceImpl
         codegen_opts: opts.codegen_opts.clone(),
         lints: opts.lints.clone(),
         input: path.to_owned(),
-        output: CompilationDestination::Cache { cache_dir },
+        output: CompilationDestination::Cache { cache_dir, max_size: openvaf::DEFAULT_CACHE_SIZE },
         include: opts.include.clone(),
         opt_lvl: opts.opt_lvl.unwrap_or(OptLevel::Aggressive),
         target: Target::host_target()
             .context("openvaf does currently not support this hardware/os")?,
         target_cpu: "native".to_owned(),
         dry_run: false,
+        sensitivities: Vec::new(),
+        hessian: Vec::new(),
+        dump_json: false,
+        emit: Vec::new(),
+        modules: Vec::new(),
+        split_modules: false,
+        linker: LinkerKind::External,
+        reproducible: false,
+        autodiff: AutoDiffOptions::default(),
+        fp_policy: FloatPolicy::default(),
+        check_fp: false,
+        debug_info: false,
+        symbolic_delays: false,
     };
 
     let res = openvaf::compile(&openvaf_opts);
     let res = res.with_context(|| format!("openvaf: compilation of {path} failed"))?;
     let lib_file = match res {
         CompilationTermination::Compiled { lib_file } => lib_file,
+        CompilationTermination::CompiledModules { .. } => unreachable!(),
         CompilationTermination::FatalDiagnostic => {
             bail!("openvaf: compilation of {path} failed");
         }
@@ -36,6 +36,9 @@ pub const ANALYSIS_TRAN: u32 = 8192;
 pub const ANALYSIS_IC: u32 = 16384;
 pub const ANALYSIS_STATIC: u32 = 32768;
 pub const ANALYSIS_NODESET: u32 = 65536;
+pub const ANALYSIS_HB: u32 = 131072;
+pub const CALC_SENSITIVITY: u32 = 262144;
+pub const CALC_HESSIAN: u32 = 524288;
 pub const EVAL_RET_FLAG_LIM: u32 = 1;
 pub const EVAL_RET_FLAG_FATAL: u32 = 2;
 pub const EVAL_RET_FLAG_FINISH: u32 = 4;
@@ -124,6 +127,16 @@ pub struct OsdiNoiseSource {
     pub nodes: OsdiNodePair,
 }
 #[repr(C)]
+pub struct OsdiDelay {
+    pub node: u32,
+    pub delay_offset: u32,
+}
+#[repr(C)]
+pub struct OsdiHessianEntry {
+    pub node: u32,
+    pub nodes: OsdiNodePair,
+}
+#[repr(C)]
 #[non_exhaustive]
 pub struct OsdiDescriptor {
     pub name: *mut c_char,
@@ -163,6 +176,15 @@ pub struct OsdiDescriptor {
     pub load_jacobian_resist: fn(*mut c_void, *mut c_void),
     pub load_jacobian_react: fn(*mut c_void, *mut c_void, f64),
     pub load_jacobian_tran: fn(*mut c_void, *mut c_void, f64),
+    pub discontinuity_offset: u32,
+    pub num_delays: u32,
+    pub delays: *mut OsdiDelay,
+    pub num_sensitivity_params: u32,
+    pub sensitivity_params: *mut u32,
+    pub load_sensitivity: fn(*mut c_void, *mut c_void, *mut *mut f64, *mut *mut f64),
+    pub num_hessian_entries: u32,
+    pub hessian_entries: *mut OsdiHessianEntry,
+    pub load_hessian: fn(*mut c_void, *mut c_void, *mut f64, *mut f64),
 }
 impl OsdiDescriptor {
     pub fn access(
@@ -204,6 +226,24 @@ impl OsdiDescriptor {
     ) -> u32 {
         (self.eval)(handle, inst, model, info)
     }
+    pub fn load_sensitivity(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        dst_resist: *mut *mut f64,
+        dst_react: *mut *mut f64,
+    ) {
+        (self.load_sensitivity)(inst, model, dst_resist, dst_react)
+    }
+    pub fn load_hessian(
+        &self,
+        inst: *mut c_void,
+        model: *mut c_void,
+        dst_resist: *mut f64,
+        dst_react: *mut f64,
+    ) {
+        (self.load_hessian)(inst, model, dst_resist, dst_react)
+    }
     pub fn load_noise(
         &self,
         inst: *mut c_void,
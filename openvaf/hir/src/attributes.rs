@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use basedb::{AstIdMap, BaseDB, ErasedAstId, FileId};
+use basedb::{AstId, AstIdMap, BaseDB, ErasedAstId, FileId};
 use syntax::ast::{self};
 use syntax::AstNode;
 
@@ -15,6 +15,10 @@ impl AstCache {
     pub(crate) fn new(db: &CompilationDB, root_file: FileId) -> AstCache {
         AstCache { ast: db.parse(root_file).tree(), id_map: db.ast_id_map(root_file) }
     }
+    pub(crate) fn resolve_node<N: AstNode>(&self, id: AstId<N>) -> N {
+        self.id_map.get(id).to_node(self.ast.syntax())
+    }
+
     /// Tries to resolve an attr as a string if it exists.  Emits an error to `sink`
     ///if the attribute exists but is not a string literal.
     ///
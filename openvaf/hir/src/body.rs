@@ -1,5 +1,7 @@
+use std::fmt;
 use std::sync::Arc;
 
+use basedb::{BaseDB, FileId};
 use hir_def::db::HirDefDB;
 use hir_def::DefWithBodyId;
 use hir_ty::db::HirTyDB;
@@ -9,36 +11,74 @@ use hir_ty::types::{Signature, Ty};
 pub use hir_def::expr::Event;
 pub use hir_def::{expr::CaseCond, BuiltIn, Case, ExprId, Literal, ParamSysFun, StmtId, Type};
 pub use syntax::ast::{BinaryOp, UnaryOp};
+use syntax::TextRange;
 
 use crate::{Branch, CompilationDB, Node};
 use crate::{BranchWrite, Function, FunctionArg, NatureAttribute, Parameter, Variable};
 
 #[derive(Debug, Clone)]
 pub struct Body {
+    id: DefWithBodyId,
     body: Arc<hir_def::body::Body>,
     infere: Arc<inference::InferenceResult>,
 }
 impl Body {
     pub(crate) fn new(id: DefWithBodyId, db: &CompilationDB) -> Body {
-        Body { body: db.body(id), infere: db.inference_result(id) }
+        Body { id, body: db.body(id), infere: db.inference_result(id) }
     }
 
     pub fn borrow(&self) -> BodyRef<'_> {
-        BodyRef { body: &self.body, infere: &self.infere }
+        BodyRef { id: self.id, body: &self.body, infere: &self.infere }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct BodyRef<'a> {
+    id: DefWithBodyId,
     body: &'a hir_def::body::Body,
     infere: &'a inference::InferenceResult,
 }
 
+/// The position in the Verilog-A source code where an expression starts.
+/// Lines and columns start at one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl SourceLocation {
+    /// The location where `range` of the preprocessed `root_file` starts.
+    pub(crate) fn new(db: &CompilationDB, root_file: FileId, range: TextRange) -> SourceLocation {
+        let span = db.parse(root_file).to_file_span(range, &db.sourcemap(root_file));
+        let pos = db.line_index(span.file).line_col(span.range.start());
+        SourceLocation {
+            file: db.file_path(span.file).to_string(),
+            line: pos.line + 1,
+            col: pos.col + 1,
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
 impl<'a> BodyRef<'a> {
     pub fn entry(&self) -> &'a [StmtId] {
         &self.body.entry_stmts
     }
 
+    /// Returns where `expr` is located in the source code. Expressions that were not
+    /// written by the user (and therefore have no source) return `None`.
+    pub fn expr_location(&self, db: &CompilationDB, expr: ExprId) -> Option<SourceLocation> {
+        let ptr = db.body_source_map(self.id).expr_map_back.get(expr)?.clone()?;
+        Some(SourceLocation::new(db, self.id.file(db), ptr.range()))
+    }
+
     /// Returns the type that was inferred for this expression
     pub fn expr_type(&self, expr: ExprId) -> Type {
         self.infere.expr_types[expr].to_value().unwrap()
@@ -145,6 +185,14 @@ impl<'a> BodyRef<'a> {
         self.get_stmt(self.entry()[i]).unwrap().unwrap_expr()
     }
 
+    /// All variables that are assigned by a statement within the body
+    pub fn assigned_variables(&self) -> impl Iterator<Item = Variable> + 'a {
+        self.infere.assignment_destination.values().filter_map(|dst| match *dst {
+            inference::AssignDst::Var(id) => Some(Variable { id }),
+            _ => None,
+        })
+    }
+
     pub fn get_stmt(&self, stmnt: StmtId) -> Option<Stmt<'a>> {
         match self.body.stmts[stmnt] {
             hir_def::Stmt::Empty | hir_def::Stmt::Missing => None,
@@ -69,6 +69,36 @@ impl CompilationDB {
         CompilationUnit { root_file: self.root_file }
     }
 
+    pub fn root_file(&self) -> FileId {
+        self.root_file
+    }
+
+    /// Adds another root file that is compiled with the same include directories, macro
+    /// flags and lints as the root file of this database. The compilation units share the
+    /// database so files used by multiple units (like headers) are only read once.
+    /// Use [`CompilationDB::with_root_file`] to compile the new root file.
+    pub fn add_root_file(&mut self, root_file: AbsPathBuf) -> FileId {
+        let contents = fs::read(&root_file);
+        let file = {
+            let mut vfs = self.vfs.write();
+            let file = vfs.ensure_file_id(root_file.into());
+            vfs.set_file_contents(file, contents.into());
+            file
+        };
+        self.set_include_dirs(file, self.include_dirs(self.root_file));
+        self.set_macro_flags(file, self.macro_flags(self.root_file));
+        self.set_global_lint_overwrites(file, self.global_lint_overwrites(self.root_file));
+        file
+    }
+
+    /// Returns a snapshot of this database that compiles `root_file`
+    /// (see [`CompilationDB::add_root_file`]).
+    pub fn with_root_file(&self, root_file: FileId) -> salsa::Snapshot<CompilationDB> {
+        let db =
+            CompilationDB { storage: self.storage.snapshot(), vfs: self.vfs.clone(), root_file };
+        salsa::Snapshot::new(db)
+    }
+
     pub fn new<'a>(
         root_file: VfsPath,
         contents: Result<Vec<u8>, io::Error>,
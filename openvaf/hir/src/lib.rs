@@ -43,7 +43,8 @@ pub use syntax::name::Name;
 
 pub use crate::attributes::AstCache;
 pub use crate::body::{
-    AssignmentLhs, Body, BodyRef, ContributeKind, Expr, ExprId, Ref, ResolvedFun, Stmt, StmtId,
+    AssignmentLhs, Body, BodyRef, ContributeKind, Expr, ExprId, Ref, ResolvedFun, SourceLocation,
+    Stmt, StmtId,
 };
 pub use crate::db::CompilationDB;
 
@@ -77,6 +78,11 @@ impl CompilationUnit {
         db.file_path(self.root_file).name().unwrap_or_else(|| String::from("~.va"))
     }
 
+    /// The path of the root file, formatted like [`SourceLocation::file`]
+    pub fn path(self, db: &CompilationDB) -> String {
+        db.file_path(self.root_file).to_string()
+    }
+
     pub fn diagnostics(self, db: &CompilationDB, sink: &mut impl DiagnosticSink) {
         diagnostics::collect(db, self.root_file, sink)
     }
@@ -238,6 +244,16 @@ impl Function {
     pub fn body(&self, db: &CompilationDB) -> Body {
         Body::new(self.id.into(), db)
     }
+
+    pub fn get_attr(&self, db: &CompilationDB, ast: &AstCache, name: &str) -> Option<ast::Attr> {
+        ast.resolve_attribute(name, self.id.lookup(db).ast_id(db).erased())
+    }
+
+    /// Where this function is declared in the source code
+    pub fn location(self, db: &CompilationDB) -> SourceLocation {
+        let loc = self.id.lookup(db);
+        SourceLocation::new(db, loc.scope.root_file, loc.ast_ptr(db).range())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -445,6 +461,12 @@ impl Variable {
     pub fn get_attr(&self, db: &CompilationDB, ast: &AstCache, name: &str) -> Option<ast::Attr> {
         ast.resolve_attribute(name, self.id.lookup(db).ast_id(db).erased())
     }
+
+    /// Where this variable is declared in the source code
+    pub fn location(self, db: &CompilationDB) -> SourceLocation {
+        let loc = self.id.lookup(db);
+        SourceLocation::new(db, loc.scope.root_file, loc.ast_ptr(db).range())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -476,6 +498,11 @@ impl Parameter {
     pub fn get_attr(&self, db: &CompilationDB, ast: &AstCache, name: &str) -> Option<ast::Attr> {
         ast.resolve_attribute(name, self.id.lookup(db).ast_id(db).erased())
     }
+
+    /// The declaration of this parameter in the (preprocessed) source code.
+    pub fn ast(&self, db: &CompilationDB, ast: &AstCache) -> ast::Param {
+        ast.resolve_node(self.id.lookup(db).ast_id(db))
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -1,7 +1,7 @@
 use hir::Node;
 use hir::{BodyRef, ExprId};
 use mir::builder::InstBuilder;
-use mir::{Block, Value};
+use mir::{Block, SourceLoc, Value};
 use stdx::iter::zip;
 
 use crate::ctx::LoweringCtx;
@@ -20,6 +20,24 @@ impl<'c1, 'c2> BodyLoweringCtx<'_, 'c1, 'c2> {
         }
     }
 
+    /// The source location attached to the instructions created for `expr`.
+    ///
+    /// By default this is just the index of `expr` (offset by one). These indices are only
+    /// unique within a single body. When the source locations are tracked an index into
+    /// [`HirInterner::source_locations`](crate::HirInterner::source_locations) is used instead.
+    pub fn srcloc(&mut self, expr: ExprId) -> SourceLoc {
+        if !self.ctx.source_locations {
+            return SourceLoc::new(u32::from(expr) as i32 + 1);
+        }
+        match self.body.expr_location(self.ctx.db, expr) {
+            Some(loc) => {
+                let (idx, _) = self.ctx.intern.source_locations.insert_full(loc);
+                SourceLoc::new(idx as i32 + 1)
+            }
+            None => SourceLoc::default(),
+        }
+    }
+
     pub fn nodes_from_args(
         &mut self,
         args: &[ExprId],
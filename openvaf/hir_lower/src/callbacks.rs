@@ -1,4 +1,4 @@
-use hir::{Node, Parameter};
+use hir::{Function, Node, Parameter};
 use lasso::Spur;
 use mir::{FunctionSignature, Param};
 use stdx::Ieee64;
@@ -33,6 +33,12 @@ pub enum CallBackKind {
     WhiteNoise { name: Spur, idx: u32 },
     FlickerNoise { name: Spur, idx: u32 },
     NoiseTable(Box<NoiseTable>),
+    // a call to an analog function that was lowered to a dedicated function
+    // (see `AnalogFunction`)
+    AnalogFunction { fun: Function, params: u16, returns: u16 },
+    // computes the partial derivatives of all values returned by an analog function
+    // with respect to each of its parameters
+    AnalogFunctionDerivative { fun: Function, params: u16, returns: u16 },
 }
 
 impl CallBackKind {
@@ -139,6 +145,18 @@ impl CallBackKind {
                 returns: 1,
                 has_sideeffects: false,
             },
+            CallBackKind::AnalogFunction { fun, params, returns } => FunctionSignature {
+                name: format!("{fun:?}"),
+                params: *params,
+                returns: *returns,
+                has_sideeffects: false,
+            },
+            CallBackKind::AnalogFunctionDerivative { fun, params, returns } => FunctionSignature {
+                name: format!("ddx_{fun:?}"),
+                params: *params,
+                returns: params * returns,
+                has_sideeffects: false,
+            },
         }
     }
     pub fn is_noise(&self) -> bool {
@@ -168,7 +186,12 @@ impl CallBackKind {
     }
 
     pub fn tracked(&self) -> bool {
-        !matches!(self, CallBackKind::Print { .. })
+        !matches!(
+            self,
+            CallBackKind::Print { .. }
+                | CallBackKind::AnalogFunction { .. }
+                | CallBackKind::AnalogFunctionDerivative { .. }
+        )
     }
 }
 
@@ -2,14 +2,15 @@ use ahash::AHashSet;
 use hir::{CompilationDB, Node, Type, Variable};
 use mir::builder::{InsertBuilder, InstBuilder};
 use mir::{
-    Block, DataFlowGraph, FuncRef, Inst, Opcode, SourceLoc, Value, FALSE, F_ZERO, INFINITY, TRUE,
+    Block, DataFlowGraph, FuncRef, Inst, Opcode, SourceLoc, Value, FALSE, F_ZERO, INFINITY, N_ONE,
+    TRUE,
 };
 use mir_build::{FuncInstBuilder, FunctionBuilder, Place};
 use typed_indexmap::TiSet;
 
 use crate::{
-    CallBackKind, HirInterner, ImplicitEquation, ImplicitEquationKind, LimitState, ParamKind,
-    PlaceKind,
+    AnalogFunction, CallBackKind, HirInterner, ImplicitEquation, ImplicitEquationKind, LimitState,
+    ParamKind, PlaceKind,
 };
 
 pub struct LoweringCtx<'a, 'c> {
@@ -25,6 +26,14 @@ pub struct LoweringCtx<'a, 'c> {
     /// but necessary to avoid accidental correlation/opimization.
     /// For example white_noise(x) - white_noise(x) is not zero.
     pub num_noise_sources: u32,
+    /// Whether analog functions are called instead of inlined (see [`AnalogFunction`])
+    pub analog_function_calls: bool,
+    /// Analog functions that can not be called and are therefore always inlined
+    inlined_functions: AHashSet<hir::Function>,
+    /// Whether [`HirInterner::source_locations`] is populated
+    pub source_locations: bool,
+    /// Whether `absdelay` is lowered to an implicit equation (see [`PlaceKind::Delay`])
+    pub symbolic_delays: bool,
 }
 
 impl<'a, 'c> LoweringCtx<'a, 'c> {
@@ -43,6 +52,10 @@ impl<'a, 'c> LoweringCtx<'a, 'c> {
             inside_lim: false,
             intern,
             num_noise_sources: 0,
+            analog_function_calls: false,
+            inlined_functions: AHashSet::default(),
+            source_locations: false,
+            symbolic_delays: false,
         }
     }
 
@@ -80,10 +93,13 @@ impl<'a, 'c> LoweringCtx<'a, 'c> {
                 | PlaceKind::ParamMax(_) => return place,
 
                 PlaceKind::Var(var) => self.use_param(ParamKind::HiddenState(var)),
-                PlaceKind::ImplicitResidual { .. } | PlaceKind::Contribute { .. } => F_ZERO,
+                PlaceKind::ImplicitResidual { .. }
+                | PlaceKind::Contribute { .. }
+                | PlaceKind::Delay(_) => F_ZERO,
                 PlaceKind::CollapseImplicitEquation(_) => TRUE,
                 PlaceKind::IsVoltageSrc(_) => FALSE,
                 PlaceKind::BoundStep => INFINITY,
+                PlaceKind::Discontinuity => N_ONE,
             };
             let entry = self.func.func.layout.entry_block().unwrap();
             self.func.def_var_at(place, init, entry);
@@ -158,6 +174,34 @@ impl<'a, 'c> LoweringCtx<'a, 'c> {
         func_ref
     }
 
+    /// Returns the callback that calls the analog function `fun`. The body of `fun` is lowered
+    /// on first use. Returns `None` if `fun` must be inlined instead.
+    pub fn analog_function(&mut self, fun: hir::Function) -> Option<CallBackKind> {
+        if !self.analog_function_calls || self.inlined_functions.contains(&fun) {
+            return None;
+        }
+        if let Some(lowered) = self.intern.analog_functions.get(&fun) {
+            return Some(lowered.callback(fun));
+        }
+        match AnalogFunction::lower(
+            self.db,
+            fun,
+            self.func.interner,
+            self.no_equations,
+            self.source_locations,
+        ) {
+            Some(lowered) => {
+                let callback = lowered.callback(fun);
+                self.intern.analog_functions.insert(fun, lowered);
+                Some(callback)
+            }
+            None => {
+                self.inlined_functions.insert(fun);
+                None
+            }
+        }
+    }
+
     pub fn node(&self, node: Node) -> Option<Node> {
         if node.is_gnd(self.db) {
             None
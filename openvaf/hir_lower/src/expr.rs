@@ -2,12 +2,12 @@ use hir::builtin::{
     FLICKER_NOISE_NAME, NOISE_TABLE_FILE_NAME, NOISE_TABLE_INLINE_NAME, WHITE_NOISE_NAME,
 };
 use hir::signatures::{
-    ABS_INT, ABS_REAL, BOOL_EQ, DDX_POT, IDTMOD_IC, IDTMOD_IC_MODULUS, IDTMOD_IC_MODULUS_OFFSET,
-    IDTMOD_IC_MODULUS_OFFSET_NATURE, IDTMOD_IC_MODULUS_OFFSET_TOL, IDTMOD_NO_IC, IDT_IC,
-    IDT_IC_ASSERT, IDT_IC_ASSERT_NATURE, IDT_IC_ASSERT_TOL, IDT_NO_IC, INT_EQ, INT_OP,
-    LIMIT_BUILTIN_FUNCTION, MAX_INT, MAX_REAL, NATURE_ACCESS_BRANCH, NATURE_ACCESS_NODES,
-    NATURE_ACCESS_NODE_GND, NATURE_ACCESS_PORT_FLOW, REAL_EQ, REAL_OP, SIMPARAM_DEFAULT,
-    SIMPARAM_NO_DEFAULT, STR_EQ,
+    ABSDELAY_MAX, ABS_INT, ABS_REAL, BOOL_EQ, DDX_POT, IDTMOD_IC, IDTMOD_IC_MODULUS,
+    IDTMOD_IC_MODULUS_OFFSET, IDTMOD_IC_MODULUS_OFFSET_NATURE, IDTMOD_IC_MODULUS_OFFSET_TOL,
+    IDTMOD_NO_IC, IDT_IC, IDT_IC_ASSERT, IDT_IC_ASSERT_NATURE, IDT_IC_ASSERT_TOL, IDT_NO_IC,
+    INT_EQ, INT_OP, LIMIT_BUILTIN_FUNCTION, MAX_INT, MAX_REAL, NATURE_ACCESS_BRANCH,
+    NATURE_ACCESS_NODES, NATURE_ACCESS_NODE_GND, NATURE_ACCESS_PORT_FLOW, REAL_EQ, REAL_OP,
+    SIMPARAM_DEFAULT, SIMPARAM_NO_DEFAULT, STR_EQ,
 };
 use hir::{Body, BuiltIn, Expr, ExprId, Literal, ParamSysFun, Ref, ResolvedFun, Type};
 use mir::builder::InstBuilder;
@@ -25,7 +25,8 @@ use crate::{
 impl BodyLoweringCtx<'_, '_, '_> {
     pub fn lower_expr(&mut self, expr: ExprId) -> Value {
         let old_loc = self.ctx.get_srcloc();
-        self.ctx.set_srcloc(mir::SourceLoc::new(u32::from(expr) as i32 + 1));
+        let loc = self.srcloc(expr);
+        self.ctx.set_srcloc(loc);
 
         let mut res = match self.body.get_expr(expr) {
             Expr::Read(Ref::Variable(var)) => self.ctx.read_variable(var),
@@ -210,6 +211,12 @@ impl BodyLoweringCtx<'_, '_, '_> {
         args: &[ExprId],
         inside_lim: bool,
     ) -> Value {
+        if !inside_lim {
+            if let Some(callback) = self.ctx.analog_function(fun) {
+                return self.lower_analog_function_call(fun, callback, args);
+            }
+        }
+
         // FIXME proper path for functions
         let mut path = self.path.to_owned();
         path.push_str(&fun.name(self.ctx.db));
@@ -260,6 +267,35 @@ impl BodyLoweringCtx<'_, '_, '_> {
         self.ctx.use_place(PlaceKind::FunctionReturn(fun))
     }
 
+    fn lower_analog_function_call(
+        &mut self,
+        fun: hir::Function,
+        callback: CallBackKind,
+        args: &[ExprId],
+    ) -> Value {
+        let db = self.ctx.db;
+        let call_args: Vec<_> = zip(fun.args(db), args)
+            .filter(|(arg, _)| arg.is_input(db))
+            .map(|(_, &expr)| self.lower_expr(expr))
+            .collect();
+        let inst = self.ctx.call(callback, &call_args);
+        let results = self.ctx.dfg().inst_results(inst).to_vec();
+
+        // write outputs back to original (including possibly required cast)
+        let outputs = zip(fun.args(db), args).filter(|(arg, _)| arg.is_output(db));
+        for ((_, &expr), &res) in zip(outputs, &results[1..]) {
+            let mut val = res;
+            // casting in reverse here since we write back
+            if let Some((dst, src)) = self.body.needs_cast(expr) {
+                val = self.ctx.insert_cast(val, src, &dst)
+            }
+            let dst = self.body.get_expr(expr).as_assignment_lhs();
+            self.ctx.def_place(dst.into(), val);
+        }
+
+        results[0]
+    }
+
     fn lower_builtin(&mut self, expr: ExprId, builtin: BuiltIn, args: &[ExprId]) -> Value {
         let signature = self.body.get_call_signature(expr);
         match builtin {
@@ -625,7 +661,11 @@ impl BodyLoweringCtx<'_, '_, '_> {
                 self.ctx.use_param(ParamKind::PortConnected { port: self.body.into_node(args[0]) })
             }
             BuiltIn::bound_step => {
+                // the simulator must respect the smallest bound requested during an evaluation
                 let step_size = self.lower_expr(args[0]);
+                let prev_step_size = self.ctx.use_place(PlaceKind::BoundStep);
+                let cond = self.ctx.ins().flt(step_size, prev_step_size);
+                let step_size = self.lower_select_with(cond, |_| step_size, |_| prev_step_size);
                 self.ctx.def_place(PlaceKind::BoundStep, step_size);
                 GRAVESTONE
             }
@@ -654,42 +694,42 @@ impl BodyLoweringCtx<'_, '_, '_> {
                 self.ctx.finish_limit(state, res)
             }
             BuiltIn::discontinuity => {
-                if self.ctx.inside_lim && Some(&Literal::Int(-1)) == self.body.as_literal(args[0]) {
-                    self.ctx.call(CallBackKind::LimDiscontinuity, &[]);
-                } else {
-                    // TODO implement support for discontinuity?
+                let degree = args.first().map(|&arg| (arg, self.body.as_literal(arg)));
+                match degree {
+                    Some((_, Some(&Literal::Int(-1)))) if self.ctx.inside_lim => {
+                        self.ctx.call(CallBackKind::LimDiscontinuity, &[]);
+                    }
+                    // a negative degree has no meaning outside of $limit
+                    Some((_, Some(&Literal::Int(degree)))) if degree < 0 => (),
+                    _ => {
+                        // $discontinuity without arguments announces a discontinuity of order 0
+                        let degree = degree.map_or(ZERO, |(arg, _)| self.lower_expr(arg));
+                        self.lower_discontinuity(degree);
+                    }
                 }
                 GRAVESTONE
             }
             BuiltIn::finish | BuiltIn::stop => GRAVESTONE,
 
-            /* TODO: absdealy
-            BuiltIn::absdelay => {
+            BuiltIn::absdelay if self.ctx.symbolic_delays && !self.ctx.no_equations => {
                 let arg = self.lower_expr(args[0]);
                 let mut delay = self.lower_expr(args[1]);
-                let (eq1, res) = self.ctx.implicit_eqation(ImplicitEquationKind::Absdelay);
-                let (eq2, intermediate) = self.ctx.implicit_eqation(ImplicitEquationKind::Absdelay);
                 if signature == ABSDELAY_MAX {
                     let max_delay = self.lower_expr(args[2]);
                     let use_delay = self.ctx.ins().fle(delay, max_delay);
                     delay = self.lower_select_with(use_delay, |_| delay, |_| max_delay);
-                } else {
-                    delay = self.ctx.call1(CallBackKind::StoreDelayTime(eq1), &[delay]);
                 }
 
-                let mut resist_val = self.ctx.ins().fsub(res, arg);
-                resist_val = self.ctx.ins().fdiv(resist_val, delay);
-                self.ctx.def_resist_residual(resist_val, eq1);
-                self.ctx.def_react_residual(intermediate, eq1);
-
-                let mut resist_val = self.ctx.ins().fsub(res, intermediate);
-                resist_val = self.ctx.ins().fdiv(resist_val, delay);
-                self.ctx.def_resist_residual(resist_val, eq2);
-                let react_val = self.ctx.ins().fdiv(res, F_THREE);
-                self.ctx.def_react_residual(react_val, eq2);
-
+                // The delay is not resolved here. Instead it is represented as an implicit
+                // equation `res - arg = 0` (a delay of zero) and the delay time is exported
+                // so that simulators can apply the delay themselves (for example as a phase
+                // shift during harmonic balance analysis).
+                let (equation, res) = self.ctx.implicit_eqation(ImplicitEquationKind::Absdelay);
+                let residual = self.ctx.ins().fsub(res, arg);
+                self.ctx.def_resist_residual(residual, equation);
+                self.ctx.def_place(PlaceKind::Delay(equation), delay);
                 res
-            }*/
+            }
             BuiltIn::slew | BuiltIn::transition | BuiltIn::limit | BuiltIn::absdelay => {
                 self.lower_expr(args[0])
             }
@@ -760,6 +800,22 @@ impl BodyLoweringCtx<'_, '_, '_> {
         val
     }
 
+    /// Records a discontinuity of order `degree`. If multiple discontinuities are announced
+    /// during an evaluation only the lowest (most severe) order is kept.
+    fn lower_discontinuity(&mut self, degree: Value) {
+        let prev_degree = self.ctx.use_place(PlaceKind::Discontinuity);
+        let unset = self.ctx.ins().ilt(prev_degree, ZERO);
+        let degree = self.lower_select_with(
+            unset,
+            |_| degree,
+            |mut ctx| {
+                let more_severe = ctx.ctx.ins().ilt(degree, prev_degree);
+                ctx.lower_select_with(more_severe, |_| degree, |_| prev_degree)
+            },
+        );
+        self.ctx.def_place(PlaceKind::Discontinuity, degree);
+    }
+
     pub fn resolved_ty(&self, expr: ExprId) -> Type {
         self.body
             .needs_cast(expr)
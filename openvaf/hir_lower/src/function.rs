@@ -0,0 +1,177 @@
+use hir::{CompilationDB, SourceLocation, Type};
+use indexmap::IndexSet;
+use lasso::Rodeo;
+use mir::builder::InstBuilder;
+use mir::{Callee, FloatPolicy, Function, InlineHint, Value, F_ZERO, ZERO};
+use mir_build::{FunctionBuilder, FunctionBuilderContext};
+
+use crate::body::BodyLoweringCtx;
+use crate::ctx::LoweringCtx;
+use crate::{CallBackKind, HirInterner, ParamKind, PlaceKind};
+
+/// A Verilog-A analog function that was lowered to a dedicated MIR function.
+/// Calls to this function are represented by [`CallBackKind::AnalogFunction`].
+///
+/// The parameters of the function are the input arguments of the analog function (in the order
+/// of their declaration). The values returned by the function are the return value followed by
+/// the output arguments.
+#[derive(Debug, Clone)]
+pub struct AnalogFunction {
+    pub func: Function,
+    pub num_params: u16,
+    pub returns: Vec<Value>,
+    pub param_tys: Vec<Type>,
+    pub return_tys: Vec<Type>,
+    /// Set with the `(* inline *)` and `(* noinline *)` attributes
+    pub inline: InlineHint,
+    /// Set with the `(* fp_policy="strict" *)` attribute, overwrites the floating point
+    /// policy of the module for this function
+    pub fp_policy: Option<FloatPolicy>,
+    /// A function that computes the partial derivatives of all `returns` with respect to
+    /// each parameter and the values it returns. Only created when the function is called
+    /// with [`CallBackKind::AnalogFunctionDerivative`].
+    pub derivative: Option<(Function, Vec<Value>)>,
+    /// The source code location of each `SourceLoc` in `func`, see
+    /// [`HirInterner::source_locations`]
+    pub source_locations: IndexSet<SourceLocation, ahash::RandomState>,
+}
+
+// `mir::Function` has no structural equality, two bodies are equal if they print the same
+impl PartialEq for AnalogFunction {
+    fn eq(&self, other: &Self) -> bool {
+        let same_body =
+            |func1: &Function, func2: &Function| format!("{func1:?}") == format!("{func2:?}");
+        let same_derivative = match (&self.derivative, &other.derivative) {
+            (Some((func1, returns1)), Some((func2, returns2))) => {
+                returns1 == returns2 && same_body(func1, func2)
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        self.num_params == other.num_params
+            && self.returns == other.returns
+            && self.param_tys == other.param_tys
+            && self.return_tys == other.return_tys
+            && self.inline == other.inline
+            && self.fp_policy == other.fp_policy
+            && self.source_locations == other.source_locations
+            && same_derivative
+            && same_body(&self.func, &other.func)
+    }
+}
+
+impl AnalogFunction {
+    /// Lowers the body of `fun` to a dedicated function. Analog functions that interact
+    /// with the simulator (read `$temperature`, call `ddx` or `$strobe`, ...) can not be lowered
+    /// this way and must always be inlined. For these `None` is returned.
+    pub(crate) fn lower(
+        db: &CompilationDB,
+        fun: hir::Function,
+        literals: &mut Rodeo,
+        no_equations: bool,
+        source_locations: bool,
+    ) -> Option<AnalogFunction> {
+        let mut func = Function::with_name(fun.name(db));
+        let mut intern = HirInterner::default();
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let builder = FunctionBuilder::new(&mut func, literals, &mut builder_ctx, false);
+        let mut ctx = LoweringCtx::new(db, builder, no_equations, &mut intern);
+        ctx.source_locations = source_locations;
+
+        // input arguments must be the first parameters
+        let mut param_tys = Vec::new();
+        for arg in fun.args(db) {
+            let init = if arg.is_input(db) {
+                param_tys.push(arg.ty(db));
+                ctx.use_param(ParamKind::FunctionArg(arg))
+            } else {
+                zero(&arg.ty(db))
+            };
+            ctx.def_place(PlaceKind::FunctionArg(arg), init);
+        }
+        ctx.def_place(PlaceKind::FunctionReturn(fun), zero(&fun.return_ty(db)));
+
+        let body = fun.body(db);
+        let path = fun.name(db);
+        BodyLoweringCtx { ctx: &mut ctx, body: body.borrow(), path: &path }.lower_entry_stmts();
+
+        let mut returns = vec![ctx.use_place(PlaceKind::FunctionReturn(fun))];
+        let mut return_tys = vec![fun.return_ty(db)];
+        for arg in fun.args(db) {
+            if arg.is_output(db) {
+                returns.push(ctx.use_place(PlaceKind::FunctionArg(arg)));
+                return_tys.push(arg.ty(db));
+            }
+        }
+        for ret in &mut returns {
+            *ret = ctx.func.ins().ensure_optbarrier(*ret);
+        }
+        ctx.func.ins().ret();
+        ctx.func.finalize();
+        drop(ctx);
+        intern.insert_var_init(db, &mut func, literals, source_locations);
+
+        let is_pure = intern.callbacks.is_empty()
+            && intern.params.iter().all(|(kind, &val)| {
+                matches!(kind, ParamKind::FunctionArg(_)) || func.dfg.value_dead(val)
+            });
+        if !is_pure {
+            return None;
+        }
+
+        let ast = db.compilation_unit().ast(db);
+        let fp_policy = fun
+            .get_attr(db, &ast, "fp_policy")
+            .and_then(|attr| FloatPolicy::from_name(&attr.val()?.as_str_literal()?));
+        // the policy only applies to the function itself, so it is never inlined
+        let inline = if fp_policy.is_some() {
+            InlineHint::Never
+        } else if fun.get_attr(db, &ast, "inline").is_some() {
+            InlineHint::Always
+        } else if fun.get_attr(db, &ast, "noinline").is_some() {
+            InlineHint::Never
+        } else {
+            InlineHint::Auto
+        };
+
+        Some(AnalogFunction {
+            func,
+            num_params: param_tys.len() as u16,
+            returns,
+            param_tys,
+            return_tys,
+            inline,
+            fp_policy,
+            derivative: None,
+            source_locations: intern.source_locations,
+        })
+    }
+
+    pub fn callback(&self, fun: hir::Function) -> CallBackKind {
+        CallBackKind::AnalogFunction {
+            fun,
+            params: self.num_params,
+            returns: self.returns.len() as u16,
+        }
+    }
+
+    pub fn derivative_callback(&self, fun: hir::Function) -> CallBackKind {
+        CallBackKind::AnalogFunctionDerivative {
+            fun,
+            params: self.num_params,
+            returns: self.returns.len() as u16,
+        }
+    }
+
+    pub fn callee(&self) -> Callee<'_> {
+        Callee { func: &self.func, returns: &self.returns, hint: self.inline }
+    }
+}
+
+fn zero(ty: &Type) -> Value {
+    match ty {
+        Type::Real => F_ZERO,
+        Type::Integer => ZERO,
+        ty => unreachable!("invalid function arg type {:?}", ty),
+    }
+}
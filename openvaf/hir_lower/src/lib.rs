@@ -3,9 +3,10 @@ use std::iter::FilterMap;
 use ahash::{AHashMap, AHashSet};
 use bitset::HybridBitSet;
 use hir::{
-    Branch, BranchWrite, CompilationDB, Module, Node, ParamSysFun, Parameter, Type, Variable,
+    Branch, BranchWrite, CompilationDB, Module, Node, ParamSysFun, Parameter, SourceLocation, Type,
+    Variable,
 };
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use lasso::Rodeo;
 use mir::builder::InstBuilder;
 use mir::{DataFlowGraph, FuncRef, Function, Inst, KnownDerivatives, Param, Unknown, Value};
@@ -16,6 +17,7 @@ use typed_index_collections::TiVec;
 use typed_indexmap::{map, TiMap, TiSet};
 
 pub use callbacks::{CallBackKind, NoiseTable, ParamInfoKind};
+pub use function::AnalogFunction;
 
 use crate::body::BodyLoweringCtx;
 use crate::ctx::LoweringCtx;
@@ -35,6 +37,7 @@ mod callbacks;
 mod ctx;
 mod expr;
 pub mod fmt;
+mod function;
 mod parameters;
 mod state;
 mod stmt;
@@ -44,6 +47,7 @@ pub enum ImplicitEquationKind {
     Ddt,
     NoiseSrc,
     Idt(IdtKind),
+    Absdelay,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -89,6 +93,8 @@ pub enum ParamKind {
     ParamSysFun(ParamSysFun),
     HiddenState(Variable),
     ImplicitUnknown(ImplicitEquation),
+    // an input argument of an analog function that was lowered to a dedicated function
+    FunctionArg(hir::FunctionArg),
 }
 
 impl ParamKind {
@@ -170,7 +176,13 @@ pub enum PlaceKind {
     Param(Parameter),
     ParamMin(Parameter),
     ParamMax(Parameter),
+    /// The smallest step size requested with `$bound_step` during an evaluation
     BoundStep,
+    /// The lowest (most severe) order announced with `$discontinuity` during an evaluation.
+    /// Negative values indicate that no discontinuity was announced.
+    Discontinuity,
+    /// The delay time of an `absdelay` operator represented by an implicit equation
+    Delay(ImplicitEquation),
 }
 
 impl PlaceKind {
@@ -182,11 +194,13 @@ impl PlaceKind {
 
             PlaceKind::ImplicitResidual { .. }
             | PlaceKind::Contribute { .. }
-            | PlaceKind::BoundStep => Type::Real,
+            | PlaceKind::BoundStep
+            | PlaceKind::Delay(_) => Type::Real,
             PlaceKind::ParamMin(param) | PlaceKind::ParamMax(param) | PlaceKind::Param(param) => {
                 param.ty(db)
             }
             PlaceKind::IsVoltageSrc(_) | PlaceKind::CollapseImplicitEquation(_) => Type::Bool,
+            PlaceKind::Discontinuity => Type::Integer,
         }
     }
 
@@ -230,6 +244,12 @@ pub struct HirInterner {
     pub tagged_reads: IndexMap<Value, Variable, ahash::RandomState>,
     pub implicit_equations: TiVec<ImplicitEquation, ImplicitEquationKind>,
     pub lim_state: TiMap<LimitState, Value, Vec<(Value, bool)>>,
+    /// The bodies of all analog functions called with [`CallBackKind::AnalogFunction`]
+    pub analog_functions: IndexMap<hir::Function, AnalogFunction, ahash::RandomState>,
+    /// The source code location of each `SourceLoc` (a `SourceLoc` of `n` refers to the
+    /// location at index `n - 1`). Only populated when the function was lowered
+    /// with [`MirBuilder::with_source_locations`].
+    pub source_locations: IndexSet<SourceLocation, ahash::RandomState>,
 }
 
 pub type LiveParams<'a> = FilterMap<
@@ -342,7 +362,17 @@ impl HirInterner {
             }
         }
 
-        KnownDerivatives { unknowns, ddx_calls }
+        let call_derivatives = self
+            .analog_functions
+            .iter()
+            .filter_map(|(&fun, lowered)| {
+                let call = self.callbacks.index(&lowered.callback(fun))?;
+                let derivative = self.callbacks.index(&lowered.derivative_callback(fun))?;
+                Some((call, derivative))
+            })
+            .collect();
+
+        KnownDerivatives { unknowns, ddx_calls, call_derivatives }
     }
 
     pub fn is_param_live(&self, func: impl AsRef<Function>, kind: &ParamKind) -> bool {
@@ -406,6 +436,9 @@ pub struct MirBuilder<'a> {
     tag_writes: bool,
     ctx: Option<&'a mut FunctionBuilderContext>,
     lower_equations: bool,
+    analog_function_calls: bool,
+    source_locations: bool,
+    symbolic_delays: bool,
 }
 
 impl<'a> MirBuilder<'a> {
@@ -424,6 +457,9 @@ impl<'a> MirBuilder<'a> {
             ctx: None,
             lower_equations: false,
             tag_writes: false,
+            analog_function_calls: false,
+            source_locations: false,
+            symbolic_delays: false,
         }
     }
 
@@ -454,6 +490,27 @@ impl<'a> MirBuilder<'a> {
         self
     }
 
+    /// Lower analog functions to dedicated functions (see [`AnalogFunction`]) that are
+    /// called with [`CallBackKind::AnalogFunction`] instead of inlining them.
+    pub fn with_analog_function_calls(mut self) -> Self {
+        self.analog_function_calls = true;
+        self
+    }
+
+    /// Attach source locations that are unique across all lowered bodies to the instructions
+    /// and record them in [`HirInterner::source_locations`].
+    pub fn with_source_locations(mut self) -> Self {
+        self.source_locations = true;
+        self
+    }
+
+    /// Represent `absdelay` with an implicit equation and record its delay time in a
+    /// [`PlaceKind::Delay`] instead of ignoring the delay.
+    pub fn with_symbolic_delays(mut self) -> Self {
+        self.symbolic_delays = true;
+        self
+    }
+
     pub fn with_ctx(mut self, ctx: &'a mut FunctionBuilderContext) -> Self {
         self.ctx = Some(ctx);
         self
@@ -484,6 +541,9 @@ impl<'a> MirBuilder<'a> {
 
         let mut ctx = LoweringCtx::new(self.db, builder, !self.lower_equations, &mut interner)
             .with_tagged_vars(self.tagged_reads);
+        ctx.analog_function_calls = self.analog_function_calls;
+        ctx.source_locations = self.source_locations;
+        ctx.symbolic_delays = self.symbolic_delays;
         let mut body_ctx =
             BodyLoweringCtx { ctx: &mut ctx, body: analog_initial_body.borrow(), path: &path };
 
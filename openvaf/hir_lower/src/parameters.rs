@@ -52,6 +52,7 @@ impl HirInterner {
         literals: &mut Rodeo,
         build_min_max: bool,
         build_stores: bool,
+        source_locations: bool,
         params: &[Parameter],
     ) {
         let mut default_vals = if build_stores { vec![GRAVESTONE; params.len()] } else { vec![] };
@@ -64,6 +65,7 @@ impl HirInterner {
         let mut ctx = FunctionBuilderContext::default();
         let (builder, term) = FunctionBuilder::edit(func, literals, &mut ctx, false);
         let mut ctx = LoweringCtx::new(db, builder, true, self);
+        ctx.source_locations = source_locations;
 
         for (i, param) in params.iter().copied().enumerate() {
             let mut param_val = ctx.use_param(ParamKind::Param(param));
@@ -12,10 +12,12 @@ impl HirInterner {
         db: &CompilationDB,
         func: &mut Function,
         literals: &mut Rodeo,
+        source_locations: bool,
     ) {
         let mut ctx = FunctionBuilderContext::default();
         let (builder, term) = FunctionBuilder::edit(func, literals, &mut ctx, false);
         let mut ctx = LoweringCtx::new(db, builder, true, self);
+        ctx.source_locations = source_locations;
         for (kind, param) in ctx.intern.params.clone().iter() {
             if let ParamKind::HiddenState(var) = *kind {
                 if ctx.dfg().value_dead(*param) {
@@ -85,7 +85,8 @@ impl BodyLoweringCtx<'_, '_, '_> {
                 let val_ = self.lower_expr(*val);
 
                 let old_loc = self.ctx.get_srcloc();
-                self.ctx.set_srcloc(mir::SourceLoc::new(u32::from(*val) as i32 + 1));
+                let loc = self.srcloc(*val);
+                self.ctx.set_srcloc(loc);
                 let cond = self.ctx.ins().binary1(discr_op, val_, discr);
                 self.ctx.set_srcloc(old_loc);
 
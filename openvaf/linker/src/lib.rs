@@ -1,4 +1,4 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use cc::windows_registry;
 
@@ -11,13 +11,53 @@ use std::process::{Output, Stdio};
 use std::{ascii, env, io};
 use target::spec::{LinkerFlavor, Target};
 
+/// Which linker is used to create the shared library
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LinkerKind {
+    /// The platform linker (`ld`/`link.exe`) installed on the system
+    External,
+    /// LLD linked into openvaf, does not require a system toolchain.
+    /// If openvaf was built without LLD or LLD reports an error a warning is printed
+    /// and the library is linked with the external linker instead.
+    Internal,
+}
+
+impl LinkerKind {
+    pub const ALL: [LinkerKind; 2] = [LinkerKind::External, LinkerKind::Internal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkerKind::External => "external",
+            LinkerKind::Internal => "internal",
+        }
+    }
+
+    /// Whether this binary was built with support for [`LinkerKind::Internal`]
+    pub const fn internal_available() -> bool {
+        cfg!(feature = "lld")
+    }
+}
+
+/// Links the objects added by `add_objects` into the shared library `out_filename`.
+/// If `reproducible` is set the linker is instructed to not embed anything that depends on
+/// the host or time of linking (timestamps, random build ids). If `debug_info` is set
+/// the debug information of the objects is preserved.
 pub fn link(
+    kind: LinkerKind,
     path: Option<Utf8PathBuf>,
     target: &Target,
     out_filename: &Utf8Path,
+    reproducible: bool,
+    debug_info: bool,
     add_objects: impl FnOnce(&mut dyn Linker),
 ) -> Result<()> {
     let mut linker = linker_with_args(path, target, out_filename, add_objects);
+    if reproducible {
+        linker.reproducible();
+    }
+    if debug_info {
+        linker.debug_info();
+    }
 
     let import_lib_path = out_filename.with_file_name("__openvaf__import.lib");
     if !target.options.import_lib.is_empty() {
@@ -25,11 +65,38 @@ pub fn link(
         file.write_all(target.options.import_lib).context("failed to write importlib")?;
         linker.add_object(&import_lib_path);
     }
-    let res = exec_linker(linker.take_cmd(), out_filename);
+    let res = if kind == LinkerKind::Internal {
+        link_internal_with_fallback(target, &mut *linker, out_filename)
+    } else {
+        link_external(&mut *linker, out_filename)
+    };
     if !target.options.import_lib.is_empty() {
         remove_file(import_lib_path).context("failed to delete importlib")?;
     }
-    match res {
+    res
+}
+
+/// Links with LLD and falls back to the external linker (with a warning) if openvaf
+/// was built without LLD or LLD fails.
+fn link_internal_with_fallback(
+    target: &Target,
+    linker: &mut dyn Linker,
+    out_filename: &Utf8Path,
+) -> Result<()> {
+    let err = if LinkerKind::internal_available() {
+        match link_internal(target, linker.cmd()) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        }
+    } else {
+        anyhow!("openvaf was built without the internal linker")
+    };
+    eprintln!("warning: {err:#}\nfalling back to the external linker");
+    link_external(linker, out_filename)
+}
+
+fn link_external(linker: &mut dyn Linker, out_filename: &Utf8Path) -> Result<()> {
+    match exec_linker(linker.take_cmd(), out_filename) {
         Ok(prog) if !prog.status.success() => {
             let mut output = prog.stderr.clone();
             output.extend_from_slice(&prog.stdout);
@@ -42,6 +109,62 @@ pub fn link(
     }
 }
 
+#[cfg(feature = "lld")]
+fn link_internal(target: &Target, cmd: &Command) -> Result<()> {
+    use std::ffi::CString;
+
+    use llvm::lld::{self, LldFlavor};
+
+    let flavor = target.options.linker_flavor;
+    let lld_flavor = match flavor {
+        LinkerFlavor::Ld => LldFlavor::Elf,
+        LinkerFlavor::Ld64 => LldFlavor::MachO,
+        LinkerFlavor::Msvc => LldFlavor::Coff,
+        LinkerFlavor::WasmLd => LldFlavor::Wasm,
+    };
+
+    let mut args = Vec::with_capacity(cmd.args.len());
+    for arg in &cmd.args {
+        let arg = arg.to_str().context("linker arguments must be valid UTF-8")?;
+        // -m64 is only accepted by the system ld64 (for compatibility with the compiler driver)
+        if flavor == LinkerFlavor::Ld64 && arg == "-m64" {
+            continue;
+        }
+        args.push(CString::new(arg).context("linker arguments must not contain NUL bytes")?);
+    }
+    // ld64.lld does not infer the platform, use the deployment target from the triple
+    if flavor == LinkerFlavor::Ld64 {
+        let version = target.llvm_target.split("macosx").nth(1).unwrap_or("10.15.0");
+        for arg in ["-platform_version", "macos", version, version] {
+            args.push(CString::new(arg).unwrap());
+        }
+    }
+    // lld-link can not see the environment that was prepared for link.exe
+    // so the library search path is passed explicitly instead
+    if flavor == LinkerFlavor::Msvc {
+        for (key, val) in &cmd.env {
+            if key == "LIB" {
+                for dir in env::split_paths(val) {
+                    let arg = format!("/LIBPATH:{}", dir.display());
+                    args.push(CString::new(arg).context("invalid library path")?);
+                }
+            }
+        }
+    }
+
+    match lld::link(lld_flavor, &args) {
+        Ok(_) => Ok(()),
+        Err(output) => {
+            bail!("linking failed:\n{}", escape_stdout_stderr_string(output.to_bytes()))
+        }
+    }
+}
+
+#[cfg(not(feature = "lld"))]
+fn link_internal(_target: &Target, _cmd: &Command) -> Result<()> {
+    unreachable!("openvaf was built without the internal linker")
+}
+
 fn escape_stdout_stderr_string(s: &[u8]) -> String {
     std::str::from_utf8(s).map(|s| s.to_owned()).unwrap_or_else(|_| {
         let mut x = "Non-UTF-8 output: ".to_string();
@@ -149,6 +272,10 @@ fn get_linker<'a>(
             Box::new(LdLinker { cmd: Command::new(path.unwrap_or_else(|| "ld".into())), target })
                 as Box<dyn Linker>
         }
+        LinkerFlavor::WasmLd => {
+            Box::new(WasmLinker { cmd: Command::new(path.unwrap_or_else(|| "wasm-ld".into())) })
+                as Box<dyn Linker>
+        }
     }
 }
 
@@ -189,6 +316,10 @@ pub trait Linker {
     fn output_filename(&mut self, path: &Utf8Path);
     fn add_object(&mut self, path: &Utf8Path);
     fn set_output_kind(&mut self);
+    /// Produce a bit-for-bit identical output on every invocation
+    fn reproducible(&mut self);
+    /// Keep the debug information of the objects in the output
+    fn debug_info(&mut self);
 }
 
 impl dyn Linker + '_ {
@@ -268,6 +399,18 @@ impl<'a> Linker for LdLinker<'a> {
     fn set_output_kind(&mut self) {
         self.build_dylib();
     }
+
+    fn reproducible(&mut self) {
+        // ld64 derives the UUID from the contents of the output so only ELF needs to be
+        // prevented from using a random build id (some distributions enable it by default)
+        if !self.target.options.is_like_osx {
+            self.linker_arg("--build-id=none");
+        }
+    }
+
+    fn debug_info(&mut self) {
+        // DWARF sections are only removed when explicitly requested (-S, --strip-debug)
+    }
 }
 
 pub struct MsvcLinker {
@@ -294,6 +437,48 @@ impl Linker for MsvcLinker {
     fn set_output_kind(&mut self) {
         self.cmd.arg("/DLL");
     }
+
+    fn reproducible(&mut self) {
+        // replaces the timestamp in the PE header with a hash of the output
+        self.cmd.arg("/Brepro");
+    }
+
+    fn debug_info(&mut self) {
+        // link.exe discards the CodeView information unless a PDB is requested
+        self.cmd.arg("/DEBUG");
+    }
+}
+
+pub struct WasmLinker {
+    cmd: Command,
+}
+
+impl Linker for WasmLinker {
+    fn cmd(&mut self) -> &mut Command {
+        &mut self.cmd
+    }
+
+    fn output_filename(&mut self, path: &Utf8Path) {
+        self.cmd.arg("-o").arg(path.as_str());
+    }
+
+    fn add_object(&mut self, path: &Utf8Path) {
+        self.cmd.arg(path.as_str());
+    }
+
+    fn set_output_kind(&mut self) {
+        // a wasm module is used like a shared library: there is no entry point and all
+        // (non-hidden) symbols such as the OSDI descriptors are exported
+        self.cmd.arg("--no-entry").arg("--export-dynamic");
+    }
+
+    fn reproducible(&mut self) {
+        // wasm-ld does not embed timestamps or build ids
+    }
+
+    fn debug_info(&mut self) {
+        // the DWARF custom sections are only removed with --strip-debug
+    }
 }
 
 pub struct Command {
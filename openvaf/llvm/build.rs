@@ -129,6 +129,9 @@ fn main() {
     let is_crossed = target != host;
     let is_wine = is_crossed && target.contains("windows-msvc") && !host.contains("windows");
 
+    // CARGO_FEATURE_* is set by cargo and therefore not tracked
+    let use_lld = env::var_os("CARGO_FEATURE_LLD").is_some();
+
     let optional_components = &[
         "x86", "arm",
         "aarch64",
@@ -136,15 +139,15 @@ fn main() {
         // "avr",
         // "m68k",
         // "mips",
-        // "powerpc",
+        "powerpc",
         // "systemz",
         // "jsbackend",
-        // "webassembly",
+        "webassembly",
         // "msp430",
         // "sparc",
         // "nvptx",
         // "hexagon",
-        // "riscv",
+        "riscv",
         // "bpf",
     ];
 
@@ -162,11 +165,18 @@ fn main() {
                      // "instrumentation",
     ];
 
+    // additional components required by the LLD drivers
+    let lld_components: &[&str] = if use_lld { &["objcarcopts", "passes", "textapi"] } else { &[] };
+
     let components = output(Command::new(&llvm_config).arg("--components"));
     let mut components = components.split_whitespace().collect::<Vec<_>>();
-    components.retain(|c| optional_components.contains(c) || required_components.contains(c));
+    components.retain(|c| {
+        optional_components.contains(c)
+            || required_components.contains(c)
+            || lld_components.contains(c)
+    });
 
-    for component in required_components {
+    for component in required_components.iter().chain(lld_components) {
         if !components.contains(component) {
             panic!("require llvm component {} but wasn't found", component);
         }
@@ -246,6 +256,9 @@ fn main() {
     // }
 
     rerun_if_changed_anything_in_dir(Path::new("wrapper"));
+    if use_lld {
+        cfg.file("wrapper/LldWrapper.cpp");
+    }
     cfg.cpp(true)
         .warnings(true)
         .file("wrapper/OpenVafWrapper.cpp")
@@ -254,6 +267,16 @@ fn main() {
 
     let (llvm_kind, llvm_link_arg) = detect_llvm_link();
 
+    if use_lld {
+        // The LLD libraries must be linked before the LLVM libraries they depend on.
+        // LLD is usually only distributed as static libraries (also with a shared LLVM).
+        // The Mach-O port was called lldMachO2 while the old port was still around
+        let macho = if version.starts_with("13.") { "lldMachO2" } else { "lldMachO" };
+        for lib in ["lldELF", "lldCOFF", macho, "lldWasm", "lldCommon"] {
+            println!("cargo:rustc-link-lib=static={lib}");
+        }
+    }
+
     // Link in all LLVM libraries, if we're using the "wrong" llvm-config then
     // we don't pick up system libs because unfortunately they're for the host
     // of llvm-config, not the target that we're attempting to link.
@@ -0,0 +1,173 @@
+use libc::{c_char, c_uint, size_t};
+
+use crate::{BasicBlock, Bool, Builder, Context, DIBuilder, Metadata, Module, Value};
+
+/// Source languages known to DWARF, Verilog-A is not one of them.
+/// Only the variants used by OpenVAF are listed here.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DWARFSourceLanguage {
+    C99 = 11,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DWARFEmissionKind {
+    None = 0,
+    Full = 1,
+    LineTablesOnly = 2,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleFlagBehavior {
+    Error = 0,
+    Warning = 1,
+    Require = 2,
+    Override = 3,
+    Append = 4,
+    AppendUnique = 5,
+}
+
+pub type DIFlags = c_uint;
+pub const DIFlagZero: DIFlags = 0;
+
+pub type DWARFTypeEncoding = c_uint;
+pub const DW_ATE_float: DWARFTypeEncoding = 0x04;
+pub const DW_ATE_signed: DWARFTypeEncoding = 0x05;
+
+// DebugInfo
+extern "C" {
+    /// The current debug metadata version number.
+    pub fn LLVMDebugMetadataVersion() -> c_uint;
+
+    /// Add a module-level flag to the module-level flags metadata if it doesn't already exist.
+    pub fn LLVMAddModuleFlag(
+        module: &Module,
+        behavior: ModuleFlagBehavior,
+        key: *const c_char,
+        key_len: size_t,
+        val: &Metadata,
+    );
+    pub fn LLVMValueAsMetadata(val: &Value) -> &Metadata;
+
+    pub fn LLVMCreateDIBuilder(module: &Module) -> &mut DIBuilder<'_>;
+    pub fn LLVMDisposeDIBuilder<'a>(builder: &'a mut DIBuilder<'a>);
+    /// Construct any deferred debug info descriptors.
+    pub fn LLVMDIBuilderFinalize(builder: &DIBuilder<'_>);
+
+    pub fn LLVMDIBuilderCreateCompileUnit<'a>(
+        builder: &DIBuilder<'a>,
+        lang: DWARFSourceLanguage,
+        file: &'a Metadata,
+        producer: *const c_char,
+        producer_len: size_t,
+        is_optimized: Bool,
+        flags: *const c_char,
+        flags_len: size_t,
+        runtime_ver: c_uint,
+        split_name: *const c_char,
+        split_name_len: size_t,
+        kind: DWARFEmissionKind,
+        dwo_id: c_uint,
+        split_debug_inlining: Bool,
+        debug_info_for_profiling: Bool,
+        sys_root: *const c_char,
+        sys_root_len: size_t,
+        sdk: *const c_char,
+        sdk_len: size_t,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateFile<'a>(
+        builder: &DIBuilder<'a>,
+        filename: *const c_char,
+        filename_len: size_t,
+        directory: *const c_char,
+        directory_len: size_t,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateFunction<'a>(
+        builder: &DIBuilder<'a>,
+        scope: &'a Metadata,
+        name: *const c_char,
+        name_len: size_t,
+        linkage_name: *const c_char,
+        linkage_name_len: size_t,
+        file: &'a Metadata,
+        line: c_uint,
+        ty: &'a Metadata,
+        is_local_to_unit: Bool,
+        is_definition: Bool,
+        scope_line: c_uint,
+        flags: DIFlags,
+        is_optimized: Bool,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateLexicalBlockFile<'a>(
+        builder: &DIBuilder<'a>,
+        scope: &'a Metadata,
+        file: &'a Metadata,
+        discriminator: c_uint,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateSubroutineType<'a>(
+        builder: &DIBuilder<'a>,
+        file: &'a Metadata,
+        parameter_types: *const &'a Metadata,
+        num_parameter_types: c_uint,
+        flags: DIFlags,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateBasicType<'a>(
+        builder: &DIBuilder<'a>,
+        name: *const c_char,
+        name_len: size_t,
+        size_in_bits: u64,
+        encoding: DWARFTypeEncoding,
+        flags: DIFlags,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateAutoVariable<'a>(
+        builder: &DIBuilder<'a>,
+        scope: &'a Metadata,
+        name: *const c_char,
+        name_len: size_t,
+        file: &'a Metadata,
+        line: c_uint,
+        ty: &'a Metadata,
+        always_preserve: Bool,
+        flags: DIFlags,
+        align_in_bits: u32,
+    ) -> &'a Metadata;
+
+    pub fn LLVMDIBuilderCreateExpression<'a>(
+        builder: &DIBuilder<'a>,
+        addr: *const u64,
+        len: size_t,
+    ) -> &'a Metadata;
+
+    /// Insert a new `llvm.dbg.value` intrinsic call at the end of `block`.
+    pub fn LLVMDIBuilderInsertDbgValueAtEnd<'a>(
+        builder: &DIBuilder<'a>,
+        val: &'a Value,
+        var_info: &'a Metadata,
+        expr: &'a Metadata,
+        debug_loc: &'a Metadata,
+        block: &'a BasicBlock,
+    ) -> &'a Value;
+
+    pub fn LLVMDIBuilderCreateDebugLocation<'a>(
+        ctx: &'a Context,
+        line: c_uint,
+        column: c_uint,
+        scope: &'a Metadata,
+        inlined_at: Option<&'a Metadata>,
+    ) -> &'a Metadata;
+
+    /// Set the subprogram attached to a function.
+    pub fn LLVMSetSubprogram<'a>(fun: &'a Value, subprogram: &'a Metadata);
+
+    /// Set the location that is attached to all instructions created with `builder`
+    /// (`None` removes the location).
+    pub fn LLVMSetCurrentDebugLocation2<'a>(builder: &Builder<'a>, loc: Option<&'a Metadata>);
+}
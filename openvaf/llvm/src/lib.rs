@@ -29,8 +29,10 @@ pub mod basic_block;
 pub mod bitcode;
 pub mod builder;
 pub mod context;
+pub mod debug_info;
 pub mod initialization;
-// pub mod lld;
+#[cfg(feature = "lld")]
+pub mod lld;
 pub mod module;
 pub mod pass_manager;
 pub mod support;
@@ -43,6 +45,7 @@ pub use basic_block::*;
 pub use bitcode::*;
 pub use builder::*;
 pub use context::*;
+pub use debug_info::*;
 pub use initialization::*;
 pub use module::*;
 pub use pass_manager::*;
@@ -80,6 +83,9 @@ pub struct Builder<'a>(InvariantOpaque<'a>);
 #[repr(C)]
 pub struct PassManager<'a>(InvariantOpaque<'a>);
 
+#[repr(C)]
+pub struct DIBuilder<'a>(InvariantOpaque<'a>);
+
 pub enum Type {}
 
 impl fmt::Debug for Type {
@@ -96,6 +102,14 @@ impl fmt::Debug for Value {
     }
 }
 
+pub enum Metadata {}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
 pub enum Attribute {}
 
 impl fmt::Debug for Attribute {
@@ -174,7 +188,7 @@ pub enum OptLevel {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RelocMode {
     Default = 0,
-    // Static = 1,
+    Static = 1,
     PIC = 2,
     // DynamicNoPic = 3,
     // ROPI = 4,
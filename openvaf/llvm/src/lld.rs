@@ -0,0 +1,61 @@
+//! Bindings to LLD (LLVM's linker) which allows linking without a system toolchain.
+//! Only available if the `lld` feature is enabled (requires the LLD libraries of the
+//! LLVM installation).
+
+use std::ffi::CString;
+
+use libc::{c_char, size_t};
+
+use crate::support::LLVMString;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LldFlavor {
+    /// `ld.lld` (ELF, Linux and BSDs)
+    Elf = 0,
+    /// `lld-link` (COFF, Windows)
+    Coff = 1,
+    /// `ld64.lld` (Mach-O, macOS)
+    MachO = 2,
+    /// `wasm-ld` (WebAssembly)
+    Wasm = 3,
+}
+
+impl LldFlavor {
+    /// Name of the program that usually provides this flavor (used as `argv[0]`)
+    pub fn program_name(self) -> &'static str {
+        match self {
+            LldFlavor::Elf => "ld.lld",
+            LldFlavor::Coff => "lld-link",
+            LldFlavor::MachO => "ld64.lld",
+            LldFlavor::Wasm => "wasm-ld",
+        }
+    }
+}
+
+extern "C" {
+    fn LLVMLinkLld(
+        flavor: LldFlavor,
+        args: *const *const c_char,
+        num_args: size_t,
+        output: *mut *const c_char,
+    ) -> bool;
+}
+
+/// Links with LLD in process. `args` are the arguments that would be passed to the
+/// corresponding executable (without the program name). The output of the linker is
+/// returned both on success and failure.
+pub fn link(flavor: LldFlavor, args: &[CString]) -> Result<LLVMString, LLVMString> {
+    let program = CString::new(flavor.program_name()).unwrap();
+    let argv: Vec<_> = [&program].into_iter().chain(args).map(|arg| arg.as_ptr()).collect();
+    let mut output = std::ptr::null();
+    unsafe {
+        let res = LLVMLinkLld(flavor, argv.as_ptr(), argv.len(), &mut output);
+        let output = LLVMString::new(output);
+        if res {
+            Ok(output)
+        } else {
+            Err(output)
+        }
+    }
+}
@@ -54,6 +54,11 @@ extern "C" {
 
     // pub fn LLVMDumpModule(module: &Module);
     pub fn LLVMPrintModuleToString(module: &Module) -> *mut c_char;
+    pub fn LLVMPrintModuleToFile(
+        module: &Module,
+        filename: *const c_char,
+        error_message: *mut *mut c_char,
+    ) -> Bool;
 
     // pub fn LLVMGetModuleInlineAsm(module: &Module, Len: *mut size_t) -> *const c_char;
     // pub fn LLVMSetModuleInlineAsm2(module: &Module, Asm: *const c_char, Len: size_t);
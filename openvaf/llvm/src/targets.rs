@@ -26,6 +26,7 @@ extern "C" {
         CodeModel: CodeModel,
     ) -> Option<&'static mut TargetMachine>;
     pub fn LLVMDisposeTargetMachine(target_machine: &'static mut TargetMachine);
+    fn LLVMTargetMachineSetABIName(target_machine: &TargetMachine, abi_name: *const c_char);
     /// Create a DataLayout based on the target machine.
     pub fn LLVMTargetMachineEmitToFile(
         target: &TargetMachine,
@@ -65,6 +66,7 @@ pub unsafe fn create_target(
     triple: &str,
     cpu: &str,
     features: &str,
+    abi_name: &str,
     level: OptLevel,
     reloc_mode: RelocMode,
     code_model: CodeModel,
@@ -94,6 +96,13 @@ pub unsafe fn create_target(
         code_model,
     );
 
+    if let Some(target_machine) = &target_machine {
+        if !abi_name.is_empty() {
+            let abi_name = CString::new(abi_name).unwrap();
+            LLVMTargetMachineSetABIName(target_machine, abi_name.as_ptr());
+        }
+    }
+
     target_machine.ok_or_else(|| {
         LLVMString::create_from_c_str(
             CStr::from_bytes_with_nul(
@@ -0,0 +1,75 @@
+#include "lld/Common/Driver.h"
+#include "llvm/Config/llvm-config.h"
+#include "llvm/Support/raw_ostream.h"
+#include <llvm-c/Core.h>
+
+#include <mutex>
+#include <string>
+
+using namespace llvm;
+
+// LLD uses global state and is therefore not reentrant. All invocations are
+// serialized with this lock (batch compilation may link from multiple threads)
+static std::mutex LldLock;
+
+extern "C" {
+
+// Must match `LldFlavor` in lld.rs
+enum LLVMLldFlavor {
+  LLVMLldElf = 0,
+  LLVMLldCoff = 1,
+  LLVMLldMachO = 2,
+  LLVMLldWasm = 3,
+};
+
+// Runs LLD in process with the command line `Args` (Args[0] is the program
+// name). The output of the linker (stdout followed by stderr) is stored in
+// `Output` and must be freed with LLVMDisposeMessage.
+bool LLVMLinkLld(LLVMLldFlavor Flavor, const char **Args, size_t NumArgs,
+                 char **Output) {
+  std::lock_guard<std::mutex> Guard(LldLock);
+
+  std::string StdoutStr, StderrStr;
+  raw_string_ostream StdoutOS(StdoutStr), StderrOS(StderrStr);
+  ArrayRef<const char *> ArgsRef(Args, NumArgs);
+
+  bool Res;
+  switch (Flavor) {
+#if LLVM_VERSION_MAJOR < 14
+  case LLVMLldElf:
+    Res = lld::elf::link(ArgsRef, false, StdoutOS, StderrOS);
+    break;
+  case LLVMLldCoff:
+    Res = lld::coff::link(ArgsRef, false, StdoutOS, StderrOS);
+    break;
+  case LLVMLldMachO:
+    Res = lld::macho::link(ArgsRef, false, StdoutOS, StderrOS);
+    break;
+  case LLVMLldWasm:
+    Res = lld::wasm::link(ArgsRef, false, StdoutOS, StderrOS);
+    break;
+#else
+  case LLVMLldElf:
+    Res = lld::elf::link(ArgsRef, StdoutOS, StderrOS, false, false);
+    break;
+  case LLVMLldCoff:
+    Res = lld::coff::link(ArgsRef, StdoutOS, StderrOS, false, false);
+    break;
+  case LLVMLldMachO:
+    Res = lld::macho::link(ArgsRef, StdoutOS, StderrOS, false, false);
+    break;
+  case LLVMLldWasm:
+    Res = lld::wasm::link(ArgsRef, StdoutOS, StderrOS, false, false);
+    break;
+#endif
+  default:
+    Res = false;
+    StderrOS << "unknown lld flavor";
+  }
+
+  StdoutOS.flush();
+  StderrOS.flush();
+  *Output = LLVMCreateMessage((StdoutStr + StderrStr).c_str());
+  return Res;
+}
+}
@@ -2,6 +2,8 @@
 #include "llvm/Support/CrashRecoveryContext.h"
 #include <llvm/IR/Attributes.h>
 #include <llvm/IR/Function.h>
+#include <llvm-c/TargetMachine.h>
+#include <llvm/Target/TargetMachine.h>
 #include <llvm/Transforms/IPO/PassManagerBuilder.h>
 
 #include <iostream>
@@ -40,6 +42,13 @@ void LLVMPurgeAttrs(LLVMValueRef V) {
   }
 }
 
+// The C API has no way to select the ABI (e.g. lp64d on riscv64).
+// The ABI is only read when subtargets and the MC layer are created (during
+// codegen) so it can be set after the target machine was created.
+void LLVMTargetMachineSetABIName(LLVMTargetMachineRef TM, const char *ABIName) {
+  reinterpret_cast<TargetMachine *>(TM)->Options.MCOptions.ABIName = ABIName;
+}
+
 void LLVMPassManagerBuilderSLPVectorize(LLVMPassManagerBuilderRef PMB) {
   PassManagerBuilder *Builder = unwrap(PMB);
   Builder->SLPVectorize = true;
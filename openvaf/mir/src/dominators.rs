@@ -113,6 +113,10 @@ impl DominatorTree {
         block == dominator
     }
 
+    pub fn idom(&self, block: Block) -> Option<Block> {
+        self.nodes[block].idom.expand()
+    }
+
     pub fn ipdom(&self, block: Block) -> Option<Block> {
         self.reverse_nodes[block].idom.expand()
     }
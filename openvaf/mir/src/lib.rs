@@ -48,12 +48,13 @@ pub use crate::dfg::{
     ValueDef,
 };
 pub use crate::dominators::DominatorTree;
-pub use crate::entities::{AnyEntity, Block, FuncRef, Inst, Param, Use, Value};
+pub use crate::entities::{AnyEntity, Block, FuncRef, Inst, Param, Tag, Use, Value};
 pub use crate::flowgraph::ControlFlowGraph;
 pub use crate::instructions::{
     InstructionData, InstructionFormat, Opcode, PhiMap, PhiNode, ValueList, ValueListPool,
 };
 pub use crate::layout::{InstCursor, InstIter, Layout};
+pub use crate::serialize::JsonEscaped;
 use crate::write::DummyResolver;
 pub use stdx::Ieee64;
 
@@ -247,6 +248,59 @@ impl fmt::Display for SourceLoc {
     }
 }
 
+/// How strictly the IEEE 754 semantics of floating point instructions are preserved
+/// during code generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FloatPolicy {
+    /// No fast-math optimizations: every instruction is evaluated exactly as written.
+    Strict,
+    /// Instructions generated by the compiler (derivatives, loading the residual and matrix)
+    /// may be reassociated, contracted and use reciprocals. Expressions written in
+    /// Verilog-A are evaluated exactly. `NaN` and `Inf` are always preserved.
+    #[default]
+    Partial,
+    /// All fast-math optimizations for all instructions. Assumes that `NaN` and `Inf` never occur.
+    Fast,
+}
+
+impl FloatPolicy {
+    pub const ALL: [FloatPolicy; 3] =
+        [FloatPolicy::Strict, FloatPolicy::Partial, FloatPolicy::Fast];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FloatPolicy::Strict => "strict",
+            FloatPolicy::Partial => "partial",
+            FloatPolicy::Fast => "fast",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FloatPolicy> {
+        FloatPolicy::ALL.into_iter().find(|policy| policy.name() == name)
+    }
+}
+
+/// Whether calls to a function are inlined by `mir_opt::inline_calls`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InlineHint {
+    /// Calls are always inlined (`(* inline *)`).
+    Always,
+    /// Calls are never inlined (`(* noinline *)`).
+    Never,
+    /// The inliner decides based on the size of the function and the number of call sites.
+    #[default]
+    Auto,
+}
+
+/// A function that can be inlined by `mir_opt::inline_calls`.
+#[derive(Debug, Clone, Copy)]
+pub struct Callee<'a> {
+    pub func: &'a Function,
+    /// The values returned by the function (in the same order as the results of the call).
+    pub returns: &'a [Value],
+    pub hint: InlineHint,
+}
+
 impl Function {
     pub fn remove_opt_barriers(&mut self) {
         for inst in self.dfg.insts.iter() {
@@ -266,6 +320,9 @@ impl Function {
 pub struct KnownDerivatives {
     pub unknowns: TiSet<Unknown, Value>,
     pub ddx_calls: AHashMap<FuncRef, (HybridBitSet<Unknown>, HybridBitSet<Unknown>)>,
+    /// Calls to functions whose partial derivatives (with respect to each argument) are
+    /// computed by calling another function. See `mir_autodiff::partial_derivatives`.
+    pub call_derivatives: AHashMap<FuncRef, FuncRef>,
     // pub standin_calls: AHashMap<FuncRef, u32>,
 }
 
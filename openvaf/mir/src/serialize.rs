@@ -5,7 +5,8 @@ use indexmap::{IndexMap, IndexSet};
 use lasso::Rodeo;
 
 use crate::{
-    Block, Const, ControlFlowGraph, Function, Inst, InstructionData, Param, Value, ValueDef,
+    Block, Const, ControlFlowGraph, FuncRef, Function, Inst, InstructionData, Param, Value,
+    ValueDef,
 };
 
 macro_rules! wln {
@@ -27,11 +28,39 @@ macro_rules! w {
 }
 
 impl Function {
+    /// Serializes the function as json. Blocks, instructions and values are numbered by their
+    /// position in the respective list (blocks in reverse postorder):
+    ///
+    /// ```text
+    /// {
+    ///     "cfg": [{ "predecessors": [0], "successors": [2], "instructions": [3, 4] }],
+    ///     "instructions": [
+    ///         { "opcode": "fadd", "arguments": [0, 1], "results": [2] },
+    ///         { "opcode": "call", "callback": 0, "arguments": [2], "results": [3] },
+    ///         { "opcode": "phi", "arguments": { "1": 3, "2": 4 }, "results": [5] }
+    ///     ],
+    ///     "vals": [
+    ///         { "instruction": 0, "idx": 0, "uses": [1] },
+    ///         { "voltages": "(a, c)", "uses": [0] },
+    ///         { "fconst": 1.5, "uses": [0] }
+    ///     ],
+    ///     "callbacks": [{ "kind": "ddt", "name": "", "params": 1, "returns": 1,
+    ///                     "has_sideeffects": false }],
+    ///     "inputs": { "voltages": { "(a, c)": 1 } },
+    ///     "outputs": { "id": 5 }
+    /// }
+    /// ```
+    ///
+    /// Constants are tagged with `fconst`, `iconst`, `sconst` or `bconst`. Parameters (inputs)
+    /// are tagged with the category returned by `param_name`, calls reference an entry of
+    /// `callbacks` described by `callback_name` and the arguments of phi nodes are keyed by the
+    /// predecessor block.
     pub fn to_json(
         &self,
         cfg: &ControlFlowGraph,
         intern: &Rodeo,
         mut param_name: impl FnMut(Param) -> (&'static str, String),
+        mut callback_name: impl FnMut(FuncRef) -> (&'static str, String),
         outputs: impl Iterator<Item = (String, Value)>,
     ) -> String {
         let mut inst_map = IndexSet::default();
@@ -43,7 +72,11 @@ impl Function {
             })
             .collect();
         let mut val_map: IndexSet<Value, RandomState> = IndexSet::default();
+        let mut callback_map: IndexSet<FuncRef, RandomState> = IndexSet::default();
         for &inst in inst_map.iter() {
+            if let Some(func_ref) = self.dfg.func_ref(inst) {
+                callback_map.insert(func_ref);
+            }
             if let InstructionData::PhiNode(phi) = &self.dfg.insts[inst] {
                 val_map.extend(self.dfg.phi_edges(phi).map(|(_, val)| val))
             } else {
@@ -66,6 +99,7 @@ impl Function {
             inst_map: &inst_map,
             bb_map: &bb_map,
             val_map: &val_map,
+            callback_map: &callback_map,
             inputs: &inputs,
             intern,
             buf: String::new(),
@@ -86,6 +120,11 @@ impl Function {
                 sel.serialize_val(*val, &mut param_name)
             });
             wln!(sel, ",");
+            sel.serialize_key("callbacks");
+            sel.serialize_list_entries_with(sel.callback_map.iter(), |sel, func_ref| {
+                sel.serialize_callback(*func_ref, &mut callback_name)
+            });
+            wln!(sel, ",");
             sel.serialize_key("inputs");
             sel.serialize_inputs();
             wln!(sel, ",");
@@ -102,6 +141,7 @@ struct Serializer<'a> {
     inst_map: &'a IndexSet<Inst, RandomState>,
     bb_map: &'a IndexSet<Block, RandomState>,
     val_map: &'a IndexSet<Value, RandomState>,
+    callback_map: &'a IndexSet<FuncRef, RandomState>,
     inputs: &'a IndexMap<&'static str, Vec<(String, usize)>>,
     intern: &'a Rodeo,
     buf: String,
@@ -142,7 +182,23 @@ impl Serializer<'_> {
 
     fn serialize_inputs(&mut self) {
         self.serialize_dict_entries_with(self.inputs.keys(), |sel, input| {
-            sel.serialize_dict_entries(sel.inputs[input].iter().map(|(k, v)| (k, *v)));
+            sel.serialize_dict_entries(sel.inputs[input].iter().map(|(k, v)| (JsonEscaped(k), *v)));
+        })
+    }
+
+    fn serialize_callback(
+        &mut self,
+        func_ref: FuncRef,
+        mut callback_name: impl FnMut(FuncRef) -> (&'static str, String),
+    ) {
+        let (kind, name) = callback_name(func_ref);
+        let signature = &self.func.dfg.signatures[func_ref];
+        self.serialize_dict(|sel| {
+            wln!(sel, "\"kind\": \"{kind}\",");
+            wln!(sel, "\"name\": \"{}\",", JsonEscaped(&name));
+            wln!(sel, "\"params\": {},", signature.params);
+            wln!(sel, "\"returns\": {},", signature.returns);
+            w!(sel, "\"has_sideeffects\": {}", signature.has_sideeffects);
         })
     }
 
@@ -159,14 +215,14 @@ impl Serializer<'_> {
                 }
                 ValueDef::Param(param) => {
                     let (kind, name) = param_name(param);
-                    wln!(sel, "\"{kind}\": \"{name}\",")
+                    wln!(sel, "\"{kind}\": \"{}\",", JsonEscaped(&name))
                 }
                 ValueDef::Const(Const::Float(val)) => {
                     wln!(sel, "\"fconst\": {},", f64::from(val))
                 }
                 ValueDef::Const(Const::Int(val)) => wln!(sel, "\"iconst\": {val},"),
                 ValueDef::Const(Const::Str(val)) => {
-                    wln!(sel, "\"sconst\": \"{}\",", &sel.intern[val])
+                    wln!(sel, "\"sconst\": \"{}\",", JsonEscaped(&sel.intern[val]))
                 }
                 ValueDef::Const(Const::Bool(val)) => wln!(sel, "\"bconst\": {val},"),
                 ValueDef::Invalid => unreachable!(),
@@ -182,14 +238,15 @@ impl Serializer<'_> {
         self.serialize_dict(|sel| {
             sel.serialize_key("opcode");
             wln!(sel, "\"{}\",", sel.func.dfg.insts[inst].opcode());
+            if let Some(func_ref) = sel.func.dfg.func_ref(inst) {
+                wln!(sel, "\"callback\": {},", sel.callback_map.get_index_of(&func_ref).unwrap());
+            }
             if let InstructionData::PhiNode(phi) = &sel.func.dfg.insts[inst] {
                 sel.serialize_key("arguments");
-                sel.serialize_dict_entries(
-                    sel.func
-                        .dfg
-                        .phi_edges(phi)
-                        .map(|(bb, val)| (bb, sel.val_map.get_index_of(&val).unwrap())),
-                )
+                sel.serialize_dict_entries(sel.func.dfg.phi_edges(phi).filter_map(|(bb, val)| {
+                    let bb = sel.bb_map.get_index_of(&bb)?;
+                    Some((bb, sel.val_map.get_index_of(&val).unwrap()))
+                }))
             } else {
                 sel.serialize_key("arguments");
                 sel.serialize_list_entries(
@@ -298,6 +355,25 @@ impl Serializer<'_> {
     }
 }
 
+/// Escapes a string for use inside a json string literal.
+pub struct JsonEscaped<'a>(pub &'a str);
+
+impl Display for JsonEscaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<'a> Write for Serializer<'a> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for line in s.split_inclusive('\n') {
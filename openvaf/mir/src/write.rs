@@ -2,6 +2,7 @@
 //!
 //! The `write` module provides the `write_function` function which converts an IR `Function` to an
 //! equivalent textual form. This textual form can be read back by the `mir-reader` crate.
+//! The [`ExprWriter`] instead prints selected values as nested expressions (for humans).
 
 use core::fmt::{self, Write};
 
@@ -9,6 +10,9 @@ use crate::entities::AnyEntity;
 use crate::instructions::PhiNode;
 use crate::{Block, Const, DataFlowGraph, Function, Inst, InstructionData, Value, ValueDef};
 
+pub use expr::{ExprWriter, Syntax};
+
+mod expr;
 #[cfg(test)]
 mod tests;
 
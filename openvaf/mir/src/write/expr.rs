@@ -0,0 +1,947 @@
+//! Converting MIR back to (nested) expressions.
+//!
+//! The [`ExprWriter`] prints selected values of a function as expressions in the syntax of
+//! Verilog-A, C, Python/NumPy or LaTeX. The SSA form is collapsed back into nested
+//! expressions: values that are used more than once are assigned to temporaries and
+//! everything else is inlined. Phi nodes are turned back into conditional expressions by
+//! finding the branch that separates their predecessors. Phi nodes of loops can not be
+//! represented as an expression and are printed as opaque `phi_<value>` symbols instead.
+
+use core::fmt::{self, Write};
+
+use ahash::{AHashMap, AHashSet};
+use lasso::Resolver;
+
+use crate::{
+    strip_optbarrier, Block, Const, ControlFlowGraph, DominatorTree, FuncRef, Function, Inst,
+    InstructionData, Opcode, Param, Value, ValueDef,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The language that [`ExprWriter`] prints expressions in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Syntax {
+    /// Verilog-A expressions, integer to real conversions are implicit. Temporaries are
+    /// declared as variables and conversions from real to integer are assigned to an integer
+    /// temporary (which rounds implicitly).
+    VerilogA,
+    /// C99 expressions that only require `math.h` (and `string.h` for string comparisons).
+    /// Temporaries are declared as local variables.
+    C,
+    /// Python expressions that use NumPy (imported as `np`) and therefore also work
+    /// element-wise on arrays
+    NumPy,
+    /// The body of a LaTeX `align` environment
+    Latex,
+}
+
+impl Syntax {
+    pub const ALL: [Syntax; 4] = [Syntax::VerilogA, Syntax::C, Syntax::NumPy, Syntax::Latex];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Syntax::VerilogA => "verilog-a",
+            Syntax::C => "c",
+            Syntax::NumPy => "numpy",
+            Syntax::Latex => "latex",
+        }
+    }
+
+    fn comment(self) -> &'static str {
+        match self {
+            Syntax::VerilogA | Syntax::C => "//",
+            Syntax::NumPy => "#",
+            Syntax::Latex => "%",
+        }
+    }
+}
+
+// precedence of the outermost operator of an expression (higher binds stronger)
+const COND: u8 = 1;
+const BIT: u8 = 2;
+const EQ: u8 = 3;
+const CMP: u8 = 4;
+const ADD: u8 = 5;
+const MUL: u8 = 6;
+const UNARY: u8 = 7;
+const POW: u8 = 8;
+const ATOM: u8 = 9;
+
+const C_KEYWORDS: [&str; 34] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while",
+];
+
+const VERILOG_A_KEYWORDS: [&str; 43] = [
+    "aliasparam",
+    "analog",
+    "begin",
+    "branch",
+    "case",
+    "default",
+    "disable",
+    "discipline",
+    "else",
+    "end",
+    "endcase",
+    "enddiscipline",
+    "endfunction",
+    "endmodule",
+    "endnature",
+    "exclude",
+    "final_step",
+    "for",
+    "from",
+    "function",
+    "ground",
+    "if",
+    "inf",
+    "initial",
+    "initial_step",
+    "inout",
+    "input",
+    "integer",
+    "localparam",
+    "module",
+    "nature",
+    "output",
+    "parameter",
+    "real",
+    "reg",
+    "root",
+    "string",
+    "uwire",
+    "wand",
+    "while",
+    "wire",
+    "wor",
+    "wreal",
+];
+
+const PYTHON_KEYWORDS: [&str; 36] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield", "np",
+];
+
+/// Writes the values of functions as expressions in a given [`Syntax`].
+pub struct ExprWriter<'a> {
+    syntax: Syntax,
+    literals: &'a dyn Resolver,
+    num_temps: usize,
+}
+
+impl<'a> ExprWriter<'a> {
+    pub fn new(syntax: Syntax, literals: &'a dyn Resolver) -> ExprWriter<'a> {
+        ExprWriter { syntax, literals, num_temps: 0 }
+    }
+
+    /// Writes `text` as a single line comment.
+    pub fn write_comment(&self, w: &mut dyn Write, text: &str) -> fmt::Result {
+        writeln!(w, "{} {}", self.syntax.comment(), text)
+    }
+
+    /// Writes the assignment `name = <expr>` for each of the `outputs`. Temporaries for
+    /// values that are used multiple times are defined right before the first output that
+    /// requires them. Temporaries are numbered consecutively across calls so the expressions
+    /// of multiple functions can be written into the same scope.
+    ///
+    /// `param_name` and `callback_name` name the parameters and callbacks of `func`. These
+    /// names (and the names of the outputs) are converted to identifiers of the syntax.
+    pub fn write_expressions(
+        &mut self,
+        w: &mut dyn Write,
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        mut param_name: impl FnMut(Param) -> String,
+        mut callback_name: impl FnMut(FuncRef) -> String,
+        outputs: impl IntoIterator<Item = (String, Value)>,
+    ) -> fmt::Result {
+        let outputs: Vec<_> =
+            outputs.into_iter().map(|(name, val)| (name, strip_optbarrier(func, val))).collect();
+        let mut dom_tree = DominatorTree::default();
+        dom_tree.compute(func, cfg, true, false, false);
+
+        let mut exprs = Exprs {
+            func,
+            dom_tree: &dom_tree,
+            syntax: self.syntax,
+            literals: self.literals,
+            uses: AHashMap::default(),
+            selects: AHashMap::default(),
+            params: AHashMap::default(),
+            callbacks: AHashMap::default(),
+            temps: AHashMap::default(),
+            visited: AHashSet::default(),
+        };
+        exprs.count_uses(outputs.iter().map(|(_, val)| *val));
+
+        let mut vals: Vec<_> = exprs.uses.keys().copied().collect();
+        vals.sort_unstable();
+        for val in vals {
+            match func.dfg.value_def(val) {
+                ValueDef::Param(param) => {
+                    let name = exprs.ident(&param_name(param));
+                    exprs.params.insert(param, name);
+                }
+                ValueDef::Result(inst, _) => {
+                    if let Some(func_ref) = func.dfg.func_ref(inst) {
+                        if !exprs.callbacks.contains_key(&func_ref) {
+                            let name = exprs.ident(&callback_name(func_ref));
+                            exprs.callbacks.insert(func_ref, name);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for (name, val) in outputs {
+            exprs.define_temps(w, val, &mut self.num_temps)?;
+            let expr = exprs.expr(val);
+            exprs.write_assignment(w, &exprs.ident(&name), &expr.text, None)?;
+        }
+        Ok(())
+    }
+}
+
+/// A conditional expression reconstructed from a phi node
+enum Select {
+    Value(Value),
+    Cond { cond: Value, then_: Box<Select>, else_: Box<Select> },
+}
+
+impl Select {
+    fn values(&self, dst: &mut Vec<Value>) {
+        match self {
+            Select::Value(val) => dst.push(*val),
+            Select::Cond { cond, then_, else_ } => {
+                dst.push(*cond);
+                then_.values(dst);
+                else_.values(dst);
+            }
+        }
+    }
+}
+
+/// An expression and the precedence of its outermost operator
+struct Expr {
+    text: String,
+    prec: u8,
+}
+
+impl Expr {
+    fn new(prec: u8, text: String) -> Expr {
+        Expr { text, prec }
+    }
+
+    fn atom(text: String) -> Expr {
+        Expr { text, prec: ATOM }
+    }
+
+    /// Returns the text of the expression, parenthesized if it binds weaker than `prec`
+    fn wrap(self, prec: u8, syntax: Syntax) -> String {
+        if self.prec >= prec {
+            self.text
+        } else if syntax == Syntax::Latex {
+            format!("\\left({}\\right)", self.text)
+        } else {
+            format!("({})", self.text)
+        }
+    }
+}
+
+struct Exprs<'a> {
+    func: &'a Function,
+    dom_tree: &'a DominatorTree,
+    syntax: Syntax,
+    literals: &'a dyn Resolver,
+    /// How often each value is used by the expressions of the outputs
+    uses: AHashMap<Value, u32>,
+    /// The conditional expressions of phi nodes (`None` if no expression could be found)
+    selects: AHashMap<Inst, Option<Select>>,
+    params: AHashMap<Param, String>,
+    callbacks: AHashMap<FuncRef, String>,
+    temps: AHashMap<Value, String>,
+    visited: AHashSet<Value>,
+}
+
+impl Exprs<'_> {
+    fn count_uses(&mut self, outputs: impl Iterator<Item = Value>) {
+        let mut stack: Vec<Value> = outputs.collect();
+        let mut operands = Vec::new();
+        while let Some(val) = stack.pop() {
+            let uses = self.uses.entry(val).or_insert(0);
+            *uses += 1;
+            if *uses != 1 {
+                continue;
+            }
+            if let ValueDef::Result(inst, _) = self.func.dfg.value_def(val) {
+                if self.func.dfg.insts[inst].is_phi() {
+                    let select = self.reconstruct_phi(inst);
+                    self.selects.insert(inst, select);
+                }
+            }
+            self.operands(val, &mut operands);
+            stack.append(&mut operands);
+        }
+    }
+
+    /// The values that are part of the expression of `val`
+    fn operands(&self, val: Value, dst: &mut Vec<Value>) {
+        if let ValueDef::Result(inst, _) = self.func.dfg.value_def(val) {
+            if self.func.dfg.insts[inst].is_phi() {
+                if let Some(Some(select)) = self.selects.get(&inst) {
+                    select.values(dst)
+                }
+            } else {
+                let args = self.func.dfg.instr_args(inst);
+                dst.extend(args.iter().map(|&arg| strip_optbarrier(self.func, arg)))
+            }
+        }
+    }
+
+    fn reconstruct_phi(&self, inst: Inst) -> Option<Select> {
+        let block = self.func.layout.inst_block(inst)?;
+        let phi = self.func.dfg.insts[inst].unwrap_phi();
+        let edges: Vec<_> = self
+            .func
+            .dfg
+            .phi_edges(phi)
+            .map(|(pred, val)| (pred, strip_optbarrier(self.func, val)))
+            .collect();
+        self.select_edges(block, &edges)
+    }
+
+    /// Finds the conditional expression that selects between the values of a phi node in
+    /// `block` for the incoming `edges`. The predecessors must be separated by the branch
+    /// at the end of their nearest common dominator (recursively). This is not the case for
+    /// loops.
+    fn select_edges(&self, block: Block, edges: &[(Block, Value)]) -> Option<Select> {
+        let (first_pred, first_val) = *edges.first()?;
+        if edges.iter().all(|&(_, val)| val == first_val) {
+            return Some(Select::Value(first_val));
+        }
+
+        let mut dom = first_pred;
+        for &(pred, _) in &edges[1..] {
+            while !self.dom_tree.dominates(pred, dom) {
+                dom = self.dom_tree.idom(dom)?;
+            }
+        }
+        let term = self.func.layout.block_terminator(dom)?;
+        let (cond, then_dst, else_dst) = self.func.dfg.as_branch(term)?;
+        let reaches = |dst: Block, pred: Block| {
+            if dst == block {
+                pred == dom
+            } else {
+                self.dom_tree.dominates(pred, dst)
+            }
+        };
+        let (then_edges, else_edges): (Vec<_>, Vec<_>) =
+            edges.iter().copied().partition(|&(pred, _)| reaches(then_dst, pred));
+        if then_edges.is_empty()
+            || else_edges.is_empty()
+            || else_edges.iter().any(|&(pred, _)| !reaches(else_dst, pred))
+        {
+            return None;
+        }
+
+        Some(Select::Cond {
+            cond: strip_optbarrier(self.func, cond),
+            then_: Box::new(self.select_edges(block, &then_edges)?),
+            else_: Box::new(self.select_edges(block, &else_edges)?),
+        })
+    }
+
+    fn is_temp(&self, val: Value) -> bool {
+        let inst = match self.func.dfg.value_def(val) {
+            ValueDef::Result(inst, _) => inst,
+            _ => return false,
+        };
+        // Verilog-A has no rounding function, the conversion happens when the value is
+        // assigned to an integer temporary
+        if self.syntax == Syntax::VerilogA
+            && matches!(
+                self.func.dfg.insts[inst],
+                InstructionData::Unary { opcode: Opcode::FIcast, .. }
+            )
+        {
+            return true;
+        }
+        if self.uses.get(&val).map_or(true, |&uses| uses < 2) {
+            return false;
+        }
+        !matches!(self.selects.get(&inst), Some(None))
+    }
+
+    /// Writes the definitions of all temporaries that the expression of `val` requires
+    /// (and `val` itself if it is a temporary) that were not written yet.
+    fn define_temps(
+        &mut self,
+        w: &mut dyn Write,
+        val: Value,
+        num_temps: &mut usize,
+    ) -> fmt::Result {
+        if !self.visited.insert(val) {
+            return Ok(());
+        }
+        let mut operands = Vec::new();
+        self.operands(val, &mut operands);
+        for operand in operands {
+            self.define_temps(w, operand, num_temps)?;
+        }
+        if self.is_temp(val) {
+            let name = match self.syntax {
+                Syntax::Latex => format!("\\tau_{{{num_temps}}}"),
+                _ => format!("_t{num_temps}"),
+            };
+            *num_temps += 1;
+            let expr = self.def(val);
+            self.write_assignment(w, &name, &expr.text, Some(self.c_type(val)))?;
+            self.temps.insert(val, name);
+        }
+        Ok(())
+    }
+
+    fn write_assignment(
+        &self,
+        w: &mut dyn Write,
+        name: &str,
+        expr: &str,
+        ty: Option<&str>,
+    ) -> fmt::Result {
+        // escaped Verilog-A identifiers end with a space that is not required here
+        let name = name.trim_end();
+        match (self.syntax, ty) {
+            (Syntax::VerilogA | Syntax::C, None) => writeln!(w, "{name} = {expr};"),
+            (Syntax::VerilogA, Some(ty)) => {
+                let ty = match ty {
+                    "int" => "integer",
+                    "double" => "real",
+                    _ => "string",
+                };
+                writeln!(w, "{ty} {name} = {expr};")
+            }
+            (Syntax::C, Some(ty)) => writeln!(w, "{ty} {name} = {expr};"),
+            (Syntax::NumPy, _) => writeln!(w, "{name} = {expr}"),
+            (Syntax::Latex, _) => writeln!(w, "{name} &= {expr} \\\\"),
+        }
+    }
+
+    /// The type of a temporary in C (also used to derive the Verilog-A type)
+    fn c_type(&self, mut val: Value) -> &'static str {
+        loop {
+            let inst = match self.func.dfg.value_def(val) {
+                ValueDef::Const(Const::Int(_) | Const::Bool(_)) => return "int",
+                ValueDef::Const(Const::Str(_)) => return "const char *",
+                ValueDef::Result(inst, _) => inst,
+                _ => return "double",
+            };
+            val = match self.func.dfg.insts[inst] {
+                InstructionData::PhiNode(ref phi) => self.func.dfg.phi_edges(phi).next().unwrap().1,
+                InstructionData::Unary { opcode: Opcode::OptBarrier, arg } => arg,
+                InstructionData::Unary { opcode, .. } | InstructionData::Binary { opcode, .. } => {
+                    return match opcode {
+                        Opcode::Inot
+                        | Opcode::Bnot
+                        | Opcode::Ineg
+                        | Opcode::FIcast
+                        | Opcode::BIcast
+                        | Opcode::IBcast
+                        | Opcode::FBcast
+                        | Opcode::Clog2
+                        | Opcode::Iadd
+                        | Opcode::Isub
+                        | Opcode::Imul
+                        | Opcode::Idiv
+                        | Opcode::Irem
+                        | Opcode::Ishl
+                        | Opcode::Ishr
+                        | Opcode::Ixor
+                        | Opcode::Iand
+                        | Opcode::Ior
+                        | Opcode::Ilt
+                        | Opcode::Igt
+                        | Opcode::Ige
+                        | Opcode::Ile
+                        | Opcode::Flt
+                        | Opcode::Fgt
+                        | Opcode::Fge
+                        | Opcode::Fle
+                        | Opcode::Ieq
+                        | Opcode::Feq
+                        | Opcode::Seq
+                        | Opcode::Beq
+                        | Opcode::Ine
+                        | Opcode::Fne
+                        | Opcode::Sne
+                        | Opcode::Bne => "int",
+                        _ => "double",
+                    };
+                }
+                _ => return "double",
+            };
+        }
+    }
+
+    fn ident(&self, name: &str) -> String {
+        match self.syntax {
+            Syntax::VerilogA => {
+                let mut chars = name.chars();
+                let simple = chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+                if simple && !VERILOG_A_KEYWORDS.contains(&name) {
+                    name.to_owned()
+                } else {
+                    // escaped identifiers can contain any character except whitespace
+                    // and are terminated by a space
+                    let mut ident = "\\".to_owned();
+                    ident.extend(name.chars().filter(|c| !c.is_whitespace()));
+                    ident.push(' ');
+                    ident
+                }
+            }
+            Syntax::C | Syntax::NumPy => {
+                let mut ident = String::with_capacity(name.len());
+                for c in name.chars() {
+                    if c.is_ascii_alphanumeric() {
+                        ident.push(c)
+                    } else if !ident.is_empty() && !ident.ends_with('_') {
+                        ident.push('_')
+                    }
+                }
+                while ident.ends_with('_') {
+                    ident.pop();
+                }
+                if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+                    ident.insert(0, '_');
+                }
+                let keywords: &[&str] =
+                    if self.syntax == Syntax::C { &C_KEYWORDS } else { &PYTHON_KEYWORDS };
+                if keywords.contains(&&*ident) {
+                    ident.push('_');
+                }
+                ident
+            }
+            Syntax::Latex => {
+                let escaped = latex_escape(name);
+                if name.chars().count() == 1 {
+                    escaped
+                } else {
+                    format!("\\mathrm{{{escaped}}}")
+                }
+            }
+        }
+    }
+
+    fn expr(&self, val: Value) -> Expr {
+        match self.temps.get(&val) {
+            Some(name) => Expr::atom(name.clone()),
+            None => self.def(val),
+        }
+    }
+
+    fn operand(&self, val: Value, prec: u8) -> String {
+        self.expr(val).wrap(prec, self.syntax)
+    }
+
+    /// The expression that defines `val` (even if `val` is a temporary)
+    fn def(&self, val: Value) -> Expr {
+        match self.func.dfg.value_def(val) {
+            ValueDef::Const(val) => self.constant(val),
+            ValueDef::Param(param) => Expr::atom(self.params[&param].clone()),
+            ValueDef::Result(inst, i) => match self.func.dfg.insts[inst] {
+                InstructionData::Unary { opcode, arg } => {
+                    self.unary(opcode, strip_optbarrier(self.func, arg))
+                }
+                InstructionData::Binary { opcode, args } => self.binary(
+                    opcode,
+                    strip_optbarrier(self.func, args[0]),
+                    strip_optbarrier(self.func, args[1]),
+                ),
+                InstructionData::Call { func_ref, .. } => {
+                    let args: Vec<_> = self
+                        .func
+                        .dfg
+                        .instr_args(inst)
+                        .iter()
+                        .map(|&arg| strip_optbarrier(self.func, arg))
+                        .collect();
+                    let call = self.call(&self.callbacks[&func_ref], &args);
+                    if self.func.dfg.signatures[func_ref].returns > 1 {
+                        match self.syntax {
+                            Syntax::Latex => Expr::atom(format!("{}_{{{i}}}", call.text)),
+                            _ => Expr::atom(format!("{}[{i}]", call.text)),
+                        }
+                    } else {
+                        call
+                    }
+                }
+                InstructionData::PhiNode(_) => match &self.selects[&inst] {
+                    Some(select) => self.select(select),
+                    None => Expr::atom(self.ident(&format!("phi_{val}"))),
+                },
+                InstructionData::Branch { .. } | InstructionData::Jump { .. } => {
+                    unreachable!("terminators have no results")
+                }
+            },
+            ValueDef::Invalid => unreachable!("invalid value {val}"),
+        }
+    }
+
+    fn constant(&self, val: Const) -> Expr {
+        let text = match val {
+            Const::Float(val) => return self.float(val.into()),
+            Const::Int(val) if val < 0 => return Expr::new(UNARY, val.to_string()),
+            Const::Int(val) => val.to_string(),
+            Const::Bool(val) => match self.syntax {
+                Syntax::VerilogA | Syntax::C => (val as i32).to_string(),
+                Syntax::NumPy if val => "True".to_owned(),
+                Syntax::NumPy => "False".to_owned(),
+                Syntax::Latex => format!("\\mathrm{{{val}}}"),
+            },
+            Const::Str(val) => {
+                let val = self.literals.resolve(&val);
+                match self.syntax {
+                    Syntax::Latex => format!("\\text{{``{}''}}", latex_escape(val)),
+                    _ => format!("{val:?}"),
+                }
+            }
+        };
+        Expr::atom(text)
+    }
+
+    fn float(&self, val: f64) -> Expr {
+        // Verilog-A has no literals for infinity and NaN, a literal that is too large for
+        // a double is rounded to infinity
+        let text = if val.is_nan() {
+            match self.syntax {
+                Syntax::VerilogA => "(1.0e999 - 1.0e999)".to_owned(),
+                Syntax::C => "NAN".to_owned(),
+                Syntax::NumPy => "np.nan".to_owned(),
+                Syntax::Latex => "\\mathrm{NaN}".to_owned(),
+            }
+        } else if val.is_infinite() {
+            let inf = match self.syntax {
+                Syntax::VerilogA => "1.0e999",
+                Syntax::C => "INFINITY",
+                Syntax::NumPy => "np.inf",
+                Syntax::Latex => "\\infty",
+            };
+            if val < 0.0 {
+                format!("-{inf}")
+            } else {
+                inf.to_owned()
+            }
+        } else if self.syntax == Syntax::Latex && val != 0.0 && !(1e-3..1e4).contains(&val.abs()) {
+            let scientific = format!("{val:e}");
+            let (mantissa, exp) = scientific.split_once('e').unwrap();
+            match mantissa {
+                "1" => format!("10^{{{exp}}}"),
+                "-1" => format!("-10^{{{exp}}}"),
+                _ => format!("{mantissa} \\cdot 10^{{{exp}}}"),
+            }
+        } else if self.syntax == Syntax::Latex {
+            val.to_string()
+        } else {
+            format!("{val:?}")
+        };
+
+        let prec = if text.starts_with('-') {
+            UNARY
+        } else if text.contains("\\cdot") {
+            MUL
+        } else if text.contains('^') {
+            POW
+        } else {
+            ATOM
+        };
+        Expr::new(prec, text)
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Expr {
+        let args: Vec<_> = args.iter().map(|&arg| self.expr(arg).text).collect();
+        let args = args.join(", ");
+        match self.syntax {
+            Syntax::Latex => Expr::atom(format!("{name}\\left({args}\\right)")),
+            _ => Expr::atom(format!("{name}({args})")),
+        }
+    }
+
+    fn prefix(&self, op: &str, arg: Value) -> Expr {
+        Expr::new(UNARY, format!("{op}{}", self.operand(arg, UNARY + 1)))
+    }
+
+    fn infix(&self, lhs: Value, op: &str, rhs: Value, prec: u8) -> Expr {
+        // all operators are left associative
+        self.infix_with(lhs, prec, op, rhs, prec + 1, prec)
+    }
+
+    fn infix_with(
+        &self,
+        lhs: Value,
+        lhs_prec: u8,
+        op: &str,
+        rhs: Value,
+        rhs_prec: u8,
+        prec: u8,
+    ) -> Expr {
+        let lhs = self.operand(lhs, lhs_prec);
+        let rhs = self.operand(rhs, rhs_prec);
+        Expr::new(prec, format!("{} {op} {rhs}", lhs.trim_end()))
+    }
+
+    /// Comparisons never chain (parenthesized operands)
+    fn comparison(&self, lhs: Value, op: &str, latex_op: &str, rhs: Value, prec: u8) -> Expr {
+        let op = if self.syntax == Syntax::Latex { latex_op } else { op };
+        self.infix_with(lhs, prec + 1, op, rhs, prec + 1, prec)
+    }
+
+    /// Bitwise operators bind differently in Python and C, so the operands and the
+    /// operation itself are always parenthesized.
+    fn bitwise(&self, lhs: Value, op: &str, latex_op: &str, rhs: Value) -> Expr {
+        let op = if self.syntax == Syntax::Latex { latex_op } else { op };
+        self.infix_with(lhs, UNARY, op, rhs, UNARY, BIT)
+    }
+
+    fn math(&self, names: [&str; 4], args: &[Value]) -> Expr {
+        self.call(names[self.syntax as usize], args)
+    }
+
+    fn unary(&self, opcode: Opcode, arg: Value) -> Expr {
+        let latex = self.syntax == Syntax::Latex;
+        match opcode {
+            Opcode::OptBarrier => self.expr(arg),
+            Opcode::Fneg | Opcode::Ineg => self.prefix("-", arg),
+            Opcode::Inot if latex => self.prefix("\\sim ", arg),
+            Opcode::Inot => self.prefix("~", arg),
+            Opcode::Bnot => match self.syntax {
+                Syntax::VerilogA | Syntax::C => self.prefix("!", arg),
+                Syntax::NumPy => self.call("np.logical_not", &[arg]),
+                Syntax::Latex => self.prefix("\\neg ", arg),
+            },
+            Opcode::IFcast | Opcode::BFcast if self.syntax == Syntax::C => {
+                self.prefix("(double)", arg)
+            }
+            Opcode::IFcast | Opcode::BFcast | Opcode::BIcast => self.expr(arg),
+            // rounds half away from zero (like lround)
+            Opcode::FIcast => match self.syntax {
+                // always assigned to an integer temporary
+                Syntax::VerilogA => self.expr(arg),
+                Syntax::C => Expr::new(UNARY, format!("(int){}", self.call("lround", &[arg]).text)),
+                // the fractional part is exact, np.round rounds half to even
+                Syntax::NumPy => {
+                    let arg = self.expr(arg).text;
+                    Expr::atom(format!(
+                        "np.int32(np.trunc({arg}) + np.trunc(2.0 * np.fmod({arg}, 1.0)))"
+                    ))
+                }
+                Syntax::Latex => {
+                    Expr::atom(format!("\\left\\lfloor {} \\right\\rceil", self.expr(arg).text))
+                }
+            },
+            Opcode::IBcast | Opcode::FBcast => {
+                let op = if latex { "\\neq" } else { "!=" };
+                let arg = self.operand(arg, EQ + 1);
+                Expr::new(EQ, format!("{arg} {op} 0"))
+            }
+            Opcode::Sqrt if latex => Expr::atom(format!("\\sqrt{{{}}}", self.expr(arg).text)),
+            Opcode::Sqrt => self.math(["sqrt", "sqrt", "np.sqrt", ""], &[arg]),
+            Opcode::Exp if latex => Expr::new(POW, format!("e^{{{}}}", self.expr(arg).text)),
+            Opcode::Exp => self.math(["exp", "exp", "np.exp", ""], &[arg]),
+            Opcode::Ln => self.math(["ln", "log", "np.log", "\\ln"], &[arg]),
+            Opcode::Log => self.math(["log", "log10", "np.log10", "\\log_{10}"], &[arg]),
+            // the number of bits required to represent the (unsigned) argument
+            Opcode::Clog2 => match self.syntax {
+                Syntax::VerilogA => self.call("$clog2", &[arg]),
+                Syntax::C => {
+                    let cond = self.operand(arg, EQ + 1);
+                    let arg = self.operand(arg, UNARY);
+                    Expr::new(COND, format!("{cond} != 0 ? ilogb((unsigned){arg}) + 1 : 0"))
+                }
+                Syntax::NumPy => Expr::atom(format!(
+                    "np.frexp(np.int64({}) & 0xFFFFFFFF)[1]",
+                    self.expr(arg).text
+                )),
+                Syntax::Latex => Expr::new(
+                    ADD,
+                    format!(
+                        "\\left\\lfloor {} \\right\\rfloor + 1",
+                        self.call("\\log_{2}", &[arg]).text
+                    ),
+                ),
+            },
+            Opcode::Floor if latex => {
+                Expr::atom(format!("\\left\\lfloor {} \\right\\rfloor", self.expr(arg).text))
+            }
+            Opcode::Floor => self.math(["floor", "floor", "np.floor", ""], &[arg]),
+            Opcode::Ceil if latex => {
+                Expr::atom(format!("\\left\\lceil {} \\right\\rceil", self.expr(arg).text))
+            }
+            Opcode::Ceil => self.math(["ceil", "ceil", "np.ceil", ""], &[arg]),
+            Opcode::Sin => self.math(["sin", "sin", "np.sin", "\\sin"], &[arg]),
+            Opcode::Cos => self.math(["cos", "cos", "np.cos", "\\cos"], &[arg]),
+            Opcode::Tan => self.math(["tan", "tan", "np.tan", "\\tan"], &[arg]),
+            Opcode::Asin => self.math(["asin", "asin", "np.arcsin", "\\arcsin"], &[arg]),
+            Opcode::Acos => self.math(["acos", "acos", "np.arccos", "\\arccos"], &[arg]),
+            Opcode::Atan => self.math(["atan", "atan", "np.arctan", "\\arctan"], &[arg]),
+            Opcode::Sinh => self.math(["sinh", "sinh", "np.sinh", "\\sinh"], &[arg]),
+            Opcode::Cosh => self.math(["cosh", "cosh", "np.cosh", "\\cosh"], &[arg]),
+            Opcode::Tanh => self.math(["tanh", "tanh", "np.tanh", "\\tanh"], &[arg]),
+            Opcode::Asinh => {
+                self.math(["asinh", "asinh", "np.arcsinh", "\\operatorname{arsinh}"], &[arg])
+            }
+            Opcode::Acosh => {
+                self.math(["acosh", "acosh", "np.arccosh", "\\operatorname{arcosh}"], &[arg])
+            }
+            Opcode::Atanh => {
+                self.math(["atanh", "atanh", "np.arctanh", "\\operatorname{artanh}"], &[arg])
+            }
+            _ => unreachable!("{opcode} is not an unary instruction"),
+        }
+    }
+
+    fn binary(&self, opcode: Opcode, lhs: Value, rhs: Value) -> Expr {
+        let latex = self.syntax == Syntax::Latex;
+        match opcode {
+            Opcode::Iadd | Opcode::Fadd => self.infix(lhs, "+", rhs, ADD),
+            Opcode::Isub | Opcode::Fsub => self.infix(lhs, "-", rhs, ADD),
+            Opcode::Imul | Opcode::Fmul if latex => self.infix(lhs, "\\cdot", rhs, MUL),
+            Opcode::Imul | Opcode::Fmul => self.infix(lhs, "*", rhs, MUL),
+            Opcode::Fdiv if latex => {
+                Expr::atom(format!("\\frac{{{}}}{{{}}}", self.expr(lhs).text, self.expr(rhs).text))
+            }
+            Opcode::Fdiv => self.infix(lhs, "/", rhs, MUL),
+            // integer division truncates towards zero (python's // rounds down)
+            Opcode::Idiv => {
+                match self.syntax {
+                    Syntax::VerilogA | Syntax::C => self.infix(lhs, "/", rhs, MUL),
+                    Syntax::NumPy => {
+                        let (lhs, rhs) = (self.expr(lhs).text, self.expr(rhs).text);
+                        Expr::new(
+                        MUL,
+                        format!("np.sign({lhs}) * np.sign({rhs}) * (np.abs({lhs}) // np.abs({rhs}))"),
+                    )
+                    }
+                    Syntax::Latex => self.infix(lhs, "\\operatorname{div}", rhs, MUL),
+                }
+            }
+            Opcode::Irem | Opcode::Frem => match self.syntax {
+                Syntax::VerilogA => self.infix(lhs, "%", rhs, MUL),
+                Syntax::C if opcode == Opcode::Irem => self.infix(lhs, "%", rhs, MUL),
+                Syntax::C => self.call("fmod", &[lhs, rhs]),
+                Syntax::NumPy => self.call("np.fmod", &[lhs, rhs]),
+                Syntax::Latex => self.infix(lhs, "\\bmod", rhs, MUL),
+            },
+            Opcode::Ishl => self.bitwise(lhs, "<<", "\\ll", rhs),
+            // logical shift (like `>>` in Verilog-A)
+            Opcode::Ishr => match self.syntax {
+                Syntax::VerilogA | Syntax::Latex => self.bitwise(lhs, ">>", "\\gg", rhs),
+                Syntax::C => Expr::new(
+                    UNARY,
+                    format!(
+                        "(int)((unsigned){} >> {})",
+                        self.operand(lhs, UNARY),
+                        self.operand(rhs, UNARY)
+                    ),
+                ),
+                Syntax::NumPy => Expr::atom(format!(
+                    "np.int32((np.int64({}) & 0xFFFFFFFF) >> {})",
+                    self.expr(lhs).text,
+                    self.operand(rhs, UNARY)
+                )),
+            },
+            Opcode::Ixor => self.bitwise(lhs, "^", "\\oplus", rhs),
+            Opcode::Iand => self.bitwise(lhs, "&", "\\mathbin{\\&}", rhs),
+            Opcode::Ior => self.bitwise(lhs, "|", "\\mathbin{|}", rhs),
+            Opcode::Ilt | Opcode::Flt => self.comparison(lhs, "<", "<", rhs, CMP),
+            Opcode::Igt | Opcode::Fgt => self.comparison(lhs, ">", ">", rhs, CMP),
+            Opcode::Ige | Opcode::Fge => self.comparison(lhs, ">=", "\\geq", rhs, CMP),
+            Opcode::Ile | Opcode::Fle => self.comparison(lhs, "<=", "\\leq", rhs, CMP),
+            Opcode::Seq | Opcode::Sne if self.syntax == Syntax::C => {
+                let op = if opcode == Opcode::Seq { "==" } else { "!=" };
+                Expr::new(EQ, format!("{} {op} 0", self.call("strcmp", &[lhs, rhs]).text))
+            }
+            Opcode::Ieq | Opcode::Feq | Opcode::Beq | Opcode::Seq => {
+                self.comparison(lhs, "==", "=", rhs, EQ)
+            }
+            Opcode::Ine | Opcode::Fne | Opcode::Bne | Opcode::Sne => {
+                self.comparison(lhs, "!=", "\\neq", rhs, EQ)
+            }
+            Opcode::Hypot => {
+                self.math(["hypot", "hypot", "np.hypot", "\\operatorname{hypot}"], &[lhs, rhs])
+            }
+            Opcode::Atan2 => {
+                self.math(["atan2", "atan2", "np.arctan2", "\\operatorname{atan2}"], &[lhs, rhs])
+            }
+            Opcode::Pow => match self.syntax {
+                Syntax::VerilogA | Syntax::C => self.call("pow", &[lhs, rhs]),
+                Syntax::NumPy => self.infix_with(lhs, ATOM, "**", rhs, UNARY, POW),
+                Syntax::Latex => Expr::new(
+                    POW,
+                    format!("{}^{{{}}}", self.operand(lhs, ATOM), self.expr(rhs).text),
+                ),
+            },
+            _ => unreachable!("{opcode} is not a binary instruction"),
+        }
+    }
+
+    fn select(&self, select: &Select) -> Expr {
+        let (cond, then_, else_) = match select {
+            Select::Value(val) => return self.expr(*val),
+            Select::Cond { cond, then_, else_ } => (*cond, then_, else_),
+        };
+        match self.syntax {
+            Syntax::VerilogA | Syntax::C => {
+                let cond = self.operand(cond, BIT);
+                let then_ = self.select(then_).wrap(COND, self.syntax);
+                let else_ = self.select(else_).wrap(COND, self.syntax);
+                Expr::new(COND, format!("{cond} ? {then_} : {else_}"))
+            }
+            Syntax::NumPy => Expr::atom(format!(
+                "np.where({}, {}, {})",
+                self.expr(cond).text,
+                self.select(then_).text,
+                self.select(else_).text
+            )),
+            Syntax::Latex => {
+                // else if chains become additional cases
+                let mut text = "\\begin{cases}".to_owned();
+                let mut case = select;
+                while let Select::Cond { cond, then_, else_ } = case {
+                    let cond = self.expr(*cond).text;
+                    let _ =
+                        write!(text, " {} & \\text{{if }} {cond} \\\\", self.select(then_).text);
+                    case = else_;
+                }
+                let _ = write!(
+                    text,
+                    " {} & \\text{{otherwise}} \\end{{cases}}",
+                    self.select(case).text
+                );
+                Expr::atom(text)
+            }
+        }
+    }
+}
+
+fn latex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\backslash "),
+            '_' | '$' | '&' | '%' | '#' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,283 @@
+use std::process::{Command, Output};
+
+use expect_test::{expect, Expect};
+
+use crate::builder::InstBuilder;
+use crate::cursor::{Cursor, FuncCursor};
+use crate::write::{DummyResolver, ExprWriter, Syntax};
+use crate::{ControlFlowGraph, Function, Value};
+
+/// `I(a)` of a diode that is linearized for negative voltages and its conductance
+fn diode() -> (Function, [(String, Value); 2]) {
+    let mut func = Function::with_name("diode".to_owned());
+    let block0 = func.layout.append_new_block();
+    let block1 = func.layout.append_new_block();
+    let block2 = func.layout.append_new_block();
+    let block3 = func.layout.append_new_block();
+    let is = func.dfg.make_param(0u32.into());
+    let vt = func.dfg.make_param(1u32.into());
+    let vd = func.dfg.make_param(2u32.into());
+    let zero = func.dfg.f64const(0.0);
+    let one = func.dfg.f64const(1.0);
+
+    let mut pos = FuncCursor::new(&mut func).at_bottom(block0);
+    let x = pos.ins().fdiv(vd, vt);
+    let e = pos.ins().exp(x);
+    let lt = pos.ins().flt(vd, zero);
+    pos.ins().br(lt, block1, block2);
+
+    pos.goto_bottom(block1);
+    let lin = pos.ins().fmul(is, x);
+    pos.ins().jump(block3);
+
+    pos.goto_bottom(block2);
+    let em1 = pos.ins().fsub(e, one);
+    let ex = pos.ins().fmul(is, em1);
+    pos.ins().jump(block3);
+
+    pos.goto_bottom(block3);
+    let id = pos.ins().phi(&[(block1, lin), (block2, ex)]);
+    let ie = pos.ins().fmul(is, e);
+    let gd = pos.ins().fdiv(ie, vt);
+
+    (func, [("I(a)".to_owned(), id), ("gd".to_owned(), gd)])
+}
+
+fn check(syntax: Syntax, expect: Expect) {
+    let (func, outputs) = diode();
+    let cfg = ControlFlowGraph::with_function(&func);
+    let names = ["is", "vt", "V(a, c)"];
+    let mut res = String::new();
+    let mut writer = ExprWriter::new(syntax, &DummyResolver);
+    writer.write_comment(&mut res, "diode").unwrap();
+    writer
+        .write_expressions(
+            &mut res,
+            &func,
+            &cfg,
+            |param| names[usize::from(param)].to_owned(),
+            |_| unreachable!("no calls"),
+            outputs,
+        )
+        .unwrap();
+    expect.assert_eq(&res);
+}
+
+#[test]
+fn verilog_a() {
+    check(
+        Syntax::VerilogA,
+        expect![[r#"
+            // diode
+            real _t0 = \V(a,c) / vt;
+            real _t1 = exp(_t0);
+            \I(a) = \V(a,c) < 0.0 ? is * _t0 : is * (_t1 - 1.0);
+            gd = is * _t1 / vt;
+        "#]],
+    );
+}
+
+#[test]
+fn c() {
+    check(
+        Syntax::C,
+        expect![[r#"
+            // diode
+            double _t0 = V_a_c / vt;
+            double _t1 = exp(_t0);
+            I_a = V_a_c < 0.0 ? is * _t0 : is * (_t1 - 1.0);
+            gd = is * _t1 / vt;
+        "#]],
+    );
+}
+
+#[test]
+fn numpy() {
+    check(
+        Syntax::NumPy,
+        expect![[r#"
+            # diode
+            _t0 = V_a_c / vt
+            _t1 = np.exp(_t0)
+            I_a = np.where(V_a_c < 0.0, is_ * _t0, is_ * (_t1 - 1.0))
+            gd = is_ * _t1 / vt
+        "#]],
+    );
+}
+
+#[test]
+fn latex() {
+    check(
+        Syntax::Latex,
+        expect![[r#"
+            % diode
+            \tau_{0} &= \frac{\mathrm{V(a, c)}}{\mathrm{vt}} \\
+            \tau_{1} &= e^{\tau_{0}} \\
+            \mathrm{I(a)} &= \begin{cases} \mathrm{is} \cdot \tau_{0} & \text{if } \mathrm{V(a, c)} < 0 \\ \mathrm{is} \cdot \left(\tau_{1} - 1\right) & \text{otherwise} \end{cases} \\
+            \mathrm{gd} &= \frac{\mathrm{is} \cdot \tau_{1}}{\mathrm{vt}} \\
+        "#]],
+    );
+}
+
+/// Conversions and integer operations whose semantics differ between the languages
+fn int_ops() -> (Function, Vec<(String, Value)>) {
+    let mut func = Function::with_name("int_ops".to_owned());
+    let block0 = func.layout.append_new_block();
+    let a = func.dfg.make_param(0u32.into());
+    let i = func.dfg.make_param(1u32.into());
+    let j = func.dfg.make_param(2u32.into());
+    let two = func.dfg.iconst(2);
+    let inf = func.dfg.f64const(f64::INFINITY);
+    let nan = func.dfg.f64const(f64::NAN);
+
+    let mut pos = FuncCursor::new(&mut func).at_bottom(block0);
+    let rounded = pos.ins().ficast(a);
+    let quot = pos.ins().idiv(i, j);
+    let sum = pos.ins().iadd(rounded, quot);
+    let shifted = pos.ins().ishr(i, two);
+    let bits = pos.ins().clog2(j);
+    let infinity = pos.ins().fadd(a, inf);
+    let not_a_number = pos.ins().fadd(a, nan);
+
+    let outputs = [
+        ("rounded", rounded),
+        ("quot", quot),
+        ("sum", sum),
+        ("shifted", shifted),
+        ("bits", bits),
+        ("infinity", infinity),
+        ("not_a_number", not_a_number),
+    ];
+    (func, outputs.into_iter().map(|(name, val)| (name.to_owned(), val)).collect())
+}
+
+fn write_int_ops(syntax: Syntax) -> (String, Vec<String>) {
+    let (func, outputs) = int_ops();
+    let cfg = ControlFlowGraph::with_function(&func);
+    let names: Vec<_> = outputs.iter().map(|(name, _)| name.clone()).collect();
+    let mut res = String::new();
+    ExprWriter::new(syntax, &DummyResolver)
+        .write_expressions(
+            &mut res,
+            &func,
+            &cfg,
+            |param| ["a", "i", "j"][usize::from(param)].to_owned(),
+            |_| unreachable!("no calls"),
+            outputs,
+        )
+        .unwrap();
+    (res, names)
+}
+
+#[test]
+fn int_ops_verilog_a() {
+    let (res, _) = write_int_ops(Syntax::VerilogA);
+    expect![[r#"
+        integer _t0 = a;
+        integer _t1 = i / j;
+        rounded = _t0;
+        quot = _t1;
+        sum = _t0 + _t1;
+        shifted = i >> 2;
+        bits = $clog2(j);
+        infinity = a + 1.0e999;
+        not_a_number = a + (1.0e999 - 1.0e999);
+    "#]]
+    .assert_eq(&res);
+}
+
+/// Runs the expressions written in C and NumPy (if a C compiler and NumPy are available)
+/// and compares the results with the semantics of the MIR (as implemented by LLVM).
+#[test]
+fn int_ops_evaluate() {
+    let cases = [
+        (2.5, -7, 2),
+        (-2.5, 7, -3),
+        (0.49999999999999994, -8, 5),
+        (-1.4, 9, 4),
+        (1e6 + 0.5, 0, 1),
+    ];
+    let expected: Vec<Vec<f64>> = cases
+        .iter()
+        .map(|&(a, i, j): &(f64, i32, i32)| {
+            let rounded = a.round() as i32;
+            vec![
+                rounded as f64,
+                (i / j) as f64,
+                (rounded + i / j) as f64,
+                ((i as u32) >> 2) as f64,
+                (32 - (j as u32).leading_zeros()) as f64,
+                f64::INFINITY,
+                f64::NAN,
+            ]
+        })
+        .collect();
+
+    let dir = std::env::temp_dir().join("openvaf_expr_eval");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let check_output = |lang: &str, output: Output| {
+        assert!(output.status.success(), "{lang}: {}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let results: Vec<Vec<f64>> = stdout
+            .lines()
+            .map(|line| line.split_whitespace().map(|val| val.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(results.len(), expected.len(), "{lang}: {stdout}");
+        for ((res, expected), case) in results.iter().zip(&expected).zip(&cases) {
+            for (res, expected) in res.iter().zip(expected) {
+                assert!(
+                    res == expected || res.is_nan() && expected.is_nan(),
+                    "{lang} {case:?}: {res:?} != {expected:?}\n{stdout}"
+                );
+            }
+        }
+    };
+
+    let (body, names) = write_int_ops(Syntax::C);
+    let mut src = "#include <math.h>\n#include <stdio.h>\n\n".to_owned();
+    src.push_str("static void eval(double a, int i, int j) {\n");
+    src.push_str(&format!("    double {};\n", names.join(", ")));
+    for line in body.lines() {
+        src.push_str(&format!("    {line}\n"));
+    }
+    let format = vec!["%.17g"; names.len()].join(" ");
+    src.push_str(&format!("    printf(\"{format}\\n\", {});\n}}\n\n", names.join(", ")));
+    src.push_str("int main(void) {\n");
+    for (a, i, j) in cases {
+        src.push_str(&format!("    eval({a:?}, {i}, {j});\n"));
+    }
+    src.push_str("    return 0;\n}\n");
+    let c_file = dir.join("int_ops.c");
+    let exe = dir.join("int_ops");
+    std::fs::write(&c_file, src).unwrap();
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    match Command::new(cc).arg("-std=c99").arg("-o").arg(&exe).arg(&c_file).arg("-lm").status() {
+        Ok(status) => {
+            assert!(status.success(), "failed to compile {}", c_file.display());
+            check_output("C", Command::new(&exe).output().unwrap());
+        }
+        Err(err) => eprintln!("skipping C: no C compiler ({err})"),
+    }
+
+    let numpy_available = Command::new("python3")
+        .args(["-c", "import numpy"])
+        .output()
+        .map_or(false, |output| output.status.success());
+    if !numpy_available {
+        eprintln!("skipping NumPy: python3 or numpy not found");
+        return;
+    }
+    let (body, names) = write_int_ops(Syntax::NumPy);
+    let mut src = "import numpy as np\n\n\ndef eval(a, i, j):\n".to_owned();
+    for line in body.lines() {
+        src.push_str(&format!("    {line}\n"));
+    }
+    src.push_str(&format!("    print({})\n\n\n", names.join(", ")));
+    for (a, i, j) in cases {
+        src.push_str(&format!("eval({a:?}, {i}, {j})\n"));
+    }
+    let py_file = dir.join("int_ops.py");
+    std::fs::write(&py_file, src).unwrap();
+    check_output("NumPy", Command::new("python3").arg(&py_file).output().unwrap());
+}
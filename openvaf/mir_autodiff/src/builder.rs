@@ -5,8 +5,8 @@ use arena::{Arena, IdxRange};
 use bitset::{BitSet, HybridBitSet};
 use mir::builder::{InsertBuilder, InstBuilder, InstInserterBase};
 use mir::{
-    Block, Function, Inst, InstructionData, Opcode, SourceLoc, Unknown, Value, F_LOG10_E, F_ONE,
-    F_TWO, F_ZERO,
+    Block, FuncRef, Function, Inst, InstructionData, Opcode, SourceLoc, Unknown, Value, F_LOG10_E,
+    F_ONE, F_TWO, F_ZERO,
 };
 use stdx::iter::zip;
 use stdx::packed_option::{PackedOption, ReservedValue};
@@ -14,14 +14,18 @@ use stdx::packed_option::{PackedOption, ReservedValue};
 use crate::intern::{Derivative, DerivativeIntern};
 use crate::live_derivatives::LiveDerivatives;
 
+mod reverse;
 #[cfg(test)]
 mod tests;
 
+pub(crate) use reverse::{partition, ReverseSweep};
+
 pub fn build_derivatives(
     func: &mut Function,
     intern: &mut DerivativeIntern,
     live_derivatives: &LiveDerivatives,
     post_order: &[Block],
+    reverse_sweeps: &[ReverseSweep],
 ) -> AHashMap<(Value, Unknown), Value> {
     let derivative_values: AHashMap<(Value, Unknown), Value> =
         intern.unknowns.iter_enumerated().map(|(unknown, &val)| ((val, unknown), F_ONE)).collect();
@@ -45,6 +49,9 @@ pub fn build_derivatives(
     };
 
     builder.run(post_order);
+    for sweep in reverse_sweeps {
+        builder.build_reverse_sweep(sweep);
+    }
     builder.derivative_values
 }
 
@@ -208,10 +215,20 @@ impl<'a, 'u> DerivativeBuilder<'a, 'u> {
                             None => res, // 0th order is the original result
                         };
                         let unknown = self.intern.get_unknown(derivative);
-                        self.insert_derivative(prev_order, unknown, ddx_val);
+                        // the result of a ddx call is not always a new value (ddx(x*y, x) = y)
+                        // so its derivatives may already be known, these are the same
+                        // derivatives that would be generated here
+                        if !self.derivative_values.contains_key(&(prev_order, unknown)) {
+                            self.insert_derivative(prev_order, unknown, ddx_val);
+                        }
                     }
 
                     debug_assert!(self.live_derivatives.conversions.get(&inst).is_none());
+                } else if let Some(&partials) = self.intern.call_derivatives.get(&func_ref) {
+                    if let Some(derivatives) = derivatives {
+                        let args = args.as_slice(&self.func.dfg.insts.value_lists).to_vec();
+                        self.build_call_derivatives(partials, &args, derivatives);
+                    }
                 }
             }
 
@@ -333,6 +350,45 @@ impl<'a, 'u> DerivativeBuilder<'a, 'u> {
         }
     }
 
+    /// Applies the chain rule to a call of a function whose partial derivatives are computed by
+    /// `partials`: `d res_j/dx = sum_i d res_j/d arg_i * d arg_i/dx`
+    fn build_call_derivatives(
+        &mut self,
+        partials: FuncRef,
+        args: &[Value],
+        derivatives: &HybridBitSet<Derivative>,
+    ) {
+        let results = self.func.dfg.inst_results(self.dst.0).to_vec();
+        let partials = self.ins().call(partials, args);
+        let partials = self.func.dfg.inst_results(partials).to_vec();
+
+        for derivative in derivatives.iter() {
+            // higher order derivatives are not propagated trough calls, calls that require
+            // them must be inlined before (see `sim_back`)
+            if self.intern.previous_order(derivative).is_some() {
+                continue;
+            }
+            let unknown = self.intern.get_unknown(derivative);
+            for (i, &res) in results.iter().enumerate() {
+                let mut val = F_ZERO;
+                for (j, &arg) in args.iter().enumerate() {
+                    let arg_derivative = self.derivative_of_1(arg, unknown);
+                    if arg_derivative == F_ZERO {
+                        continue;
+                    }
+                    let partial = partials[i * args.len() + j];
+                    let summand = if arg_derivative == F_ONE {
+                        partial
+                    } else {
+                        self.ins().fmul(partial, arg_derivative)
+                    };
+                    val = if val == F_ZERO { summand } else { self.ins().fadd(val, summand) };
+                }
+                self.insert_derivative(res, unknown, val);
+            }
+        }
+    }
+
     fn build_normal_inst_derivatives(
         &mut self,
         bcache: &mut BuilderCache,
@@ -0,0 +1,211 @@
+use ahash::AHashMap;
+use bitset::BitSet;
+use indexmap::IndexMap;
+use mir::{Function, Inst, Opcode, Unknown, Value, ValueDef, F_N_ONE, F_ONE, F_TWO, F_ZERO};
+
+use crate::builder::DerivativeBuilder;
+use crate::intern::DerivativeIntern;
+
+/// Reverse mode is only used for values that are differentiated by at least this many unknowns.
+/// For fewer unknowns the forward sweep produces (nearly) the same code.
+pub const MIN_REVERSE_MODE_UNKNOWNS: usize = 3;
+
+/// A value whose derivatives by `unknowns` are computed in a single reverse sweep
+/// over the instructions it depends on.
+#[derive(Debug, Clone)]
+pub struct ReverseSweep {
+    pub val: Value,
+    pub unknowns: Vec<Unknown>,
+    /// All instructions (that depend on an unknown) `val` is computed from.
+    /// Arguments are always placed before the instructions they are used in.
+    cone: Vec<Inst>,
+    /// The values within `cone` that depend on an unknown
+    dependent: BitSet<Value>,
+}
+
+/// Splits the requested derivatives into derivatives that are computed with a reverse sweep
+/// and derivatives that are left to the (forward) derivative builder.
+///
+/// Reverse mode is limited to values that are computed by straight-line code: Phis, calls and
+/// `pow` (which requires a branch to be differentiated) are not supported. Since all
+/// instructions of such a value dominate it, the reverse sweep can be placed directly after it.
+pub fn partition(
+    func: &Function,
+    intern: &DerivativeIntern,
+    extra_derivatives: &[(Value, Unknown)],
+) -> (Vec<(Value, Unknown)>, Vec<ReverseSweep>) {
+    let mut grouped: IndexMap<Value, Vec<Unknown>, ahash::RandomState> = IndexMap::default();
+    for &(val, unknown) in extra_derivatives {
+        grouped.entry(val).or_default().push(unknown);
+    }
+
+    let mut forward = Vec::new();
+    let mut reverse = Vec::new();
+    for (val, unknowns) in grouped {
+        let sweep = if unknowns.len() >= MIN_REVERSE_MODE_UNKNOWNS {
+            ReverseSweep::new(func, intern, val, unknowns.clone())
+        } else {
+            None
+        };
+        match sweep {
+            Some(sweep) => reverse.push(sweep),
+            None => forward.extend(unknowns.into_iter().map(|unknown| (val, unknown))),
+        }
+    }
+    (forward, reverse)
+}
+
+impl ReverseSweep {
+    fn new(
+        func: &Function,
+        intern: &DerivativeIntern,
+        val: Value,
+        unknowns: Vec<Unknown>,
+    ) -> Option<ReverseSweep> {
+        if !matches!(func.dfg.value_def(val), ValueDef::Result(..)) {
+            return None;
+        }
+
+        let mut visited = BitSet::new_empty(func.dfg.num_values());
+        let mut dependent = BitSet::new_empty(func.dfg.num_values());
+        let mut cone = Vec::new();
+        // iterative postorder DFS, without phis the dataflow graph is acyclic
+        let mut stack = vec![(val, false)];
+        while let Some((val, finished)) = stack.pop() {
+            if finished {
+                let inst = func.dfg.value_def(val).unwrap_inst();
+                if func.dfg.instr_args(inst).iter().any(|&arg| dependent.contains(arg)) {
+                    dependent.insert(val);
+                    cone.push(inst);
+                }
+                continue;
+            }
+
+            if !visited.insert(val) {
+                continue;
+            }
+
+            let inst = match func.dfg.value_def(val) {
+                ValueDef::Result(inst, _) => inst,
+                ValueDef::Param(_) => {
+                    if intern.unknowns.contains(&val) {
+                        dependent.insert(val);
+                    }
+                    continue;
+                }
+                ValueDef::Const(_) | ValueDef::Invalid => continue,
+            };
+
+            // unknowns that are computed by an instruction may themselves depend on other
+            // unknowns which a reverse sweep can not account for
+            if intern.unknowns.contains(&val) {
+                return None;
+            }
+
+            if matches!(func.dfg.insts[inst].opcode(), Opcode::Phi | Opcode::Call | Opcode::Pow) {
+                return None;
+            }
+
+            stack.push((val, true));
+            for &arg in func.dfg.instr_args(inst) {
+                if !visited.contains(arg) {
+                    stack.push((arg, false));
+                }
+            }
+        }
+
+        Some(ReverseSweep { val, unknowns, cone, dependent })
+    }
+}
+
+impl DerivativeBuilder<'_, '_> {
+    /// Computes the derivatives of `sweep.val` by propagating adjoints backwards trough
+    /// all instructions it depends upon. Each local partial derivative is computed
+    /// exactly once, regardless of the number of unknowns.
+    pub(super) fn build_reverse_sweep(&mut self, sweep: &ReverseSweep) {
+        let root = self.func.dfg.value_def(sweep.val).unwrap_inst();
+        let mut srcloc = self.func.srclocs.get(root).copied().unwrap_or_default();
+        srcloc.0 *= -1;
+        self.dst = (root, srcloc);
+
+        let mut adjoints: AHashMap<Value, Value> = AHashMap::new();
+        adjoints.insert(sweep.val, F_ONE);
+
+        for &inst in sweep.cone.iter().rev() {
+            let res = self.func.dfg.first_result(inst);
+            let adjoint = match adjoints.get(&res) {
+                Some(&adjoint) if adjoint != F_ZERO => adjoint,
+                _ => continue,
+            };
+
+            let mut args = self.func.dfg.instr_args(inst).to_vec();
+            args.retain(|&arg| sweep.dependent.contains(arg));
+            args.dedup();
+            if args.is_empty() {
+                continue;
+            }
+
+            let partials = self.local_partials(inst, &args);
+            for (arg, partial) in args.into_iter().zip(partials) {
+                let contribution = self.mul(adjoint, partial);
+                if contribution == F_ZERO {
+                    continue;
+                }
+                let adjoint = match adjoints.get(&arg) {
+                    Some(&prev) if prev != F_ZERO => self.ins().fadd(prev, contribution),
+                    _ => contribution,
+                };
+                adjoints.insert(arg, adjoint);
+            }
+        }
+
+        for &unknown in &sweep.unknowns {
+            if self.derivative_values.contains_key(&(sweep.val, unknown)) {
+                continue;
+            }
+            let unknown_val = self.intern.unknowns[unknown];
+            let val = adjoints.get(&unknown_val).copied().unwrap_or(F_ZERO);
+            self.insert_derivative(sweep.val, unknown, val);
+        }
+    }
+
+    /// Returns the partial derivatives of the result of `inst` with respect to each of `args`.
+    fn local_partials(&mut self, inst: Inst, args: &[Value]) -> Vec<Value> {
+        let inst_args = self.func.dfg.instr_args(inst);
+        match self.func.dfg.insts[inst].opcode() {
+            Opcode::OptBarrier => return vec![F_ONE],
+            Opcode::Fadd if inst_args[0] == inst_args[1] => return vec![F_TWO],
+            Opcode::Fadd => return vec![F_ONE; args.len()],
+            Opcode::Fsub if inst_args[0] == inst_args[1] => return vec![F_ZERO],
+            Opcode::Fsub => {
+                let rhs = inst_args[1];
+                return args.iter().map(|&arg| if arg == rhs { F_N_ONE } else { F_ONE }).collect();
+            }
+            Opcode::Fneg => return vec![F_N_ONE],
+            _ => (),
+        }
+
+        // the forward derivative of an instruction for an unknown that only the argument
+        // depends upon (with a derivative of one) is the local partial derivative
+        let cache = self.inst_cache(inst);
+        let res = self.func.dfg.first_result(inst);
+        let seed = Unknown::from(self.intern.unknowns.len());
+        args.iter()
+            .map(|&arg| {
+                self.derivative_values.insert((arg, seed), F_ONE);
+                self.inst_derivative(inst, seed, cache);
+                self.derivative_values.remove(&(arg, seed));
+                self.derivative_values.remove(&(res, seed)).unwrap_or(F_ZERO)
+            })
+            .collect()
+    }
+
+    fn mul(&mut self, lhs: Value, rhs: Value) -> Value {
+        match (lhs, rhs) {
+            (F_ZERO, _) | (_, F_ZERO) => F_ZERO,
+            (F_ONE, val) | (val, F_ONE) => val,
+            (F_N_ONE, val) | (val, F_N_ONE) => self.ins().fneg(val),
+            _ => self.ins().fmul(lhs, rhs),
+        }
+    }
+}
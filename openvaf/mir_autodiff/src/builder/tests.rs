@@ -6,7 +6,7 @@ use mir_interpret::{Data, Interpreter};
 use mir_reader::parse_function;
 use typed_index_collections::TiSlice;
 
-use crate::auto_diff;
+use crate::{auto_diff, auto_diff_with_options, AutoDiffMode, AutoDiffOptions};
 
 fn check_simple(src: &str, data_flow_result: Expect) {
     let (mut func, _) = parse_function(src).unwrap();
@@ -31,7 +31,7 @@ fn check_simple(src: &str, data_flow_result: Expect) {
     .into_iter()
     .collect();
 
-    let unknowns = KnownDerivatives { unknowns, ddx_calls };
+    let unknowns = KnownDerivatives { unknowns, ddx_calls, ..Default::default() };
 
     auto_diff(&mut func, &dom_tree, &unknowns, &[]);
     data_flow_result.assert_eq(&func.to_debug_string());
@@ -55,7 +55,7 @@ fn check_num(src: &str, data_flow_result: Expect, args: &[f64], num: f64) {
     .into_iter()
     .collect();
 
-    let unknowns = KnownDerivatives { unknowns, ddx_calls };
+    let unknowns = KnownDerivatives { unknowns, ddx_calls, ..Default::default() };
 
     let mut dom_tree = DominatorTree::default();
     dom_tree.compute(&func, &cfg, true, false, true);
@@ -928,3 +928,140 @@ fn subgraph() {
 
     check_simple(src, expect);
 }
+
+#[test]
+fn partial_derivatives() {
+    let src = r##"
+        function %f(v10, v11) {
+        block0:
+            v12 = fmul v10, v11
+            v13 = exp v10
+            v14 = fadd v12, v13
+            v15 = optbarrier v14
+            v16 = optbarrier v12
+        }
+    "##;
+    let (func, _) = parse_function(src).unwrap();
+    let (func, partials) = crate::partial_derivatives(&func, &[15u32.into(), 16u32.into()], 2);
+
+    let (x, y) = (0.5f64, 3.0f64);
+    let mut interpret = Interpreter::new(
+        &func,
+        TiSlice::from_ref(&[]),
+        TiSlice::from_ref(Data::from_f64_slice(&[x, y])),
+    );
+    interpret.run();
+    let margin = F64Margin::default().epsilon(10f64 * f64::EPSILON);
+    for (&partial, expected) in partials.iter().zip([y + x.exp(), x, y, x]) {
+        let val: f64 = interpret.state.read(partial);
+        assert!(val.approx_eq(expected, margin), "{val} != {expected}");
+    }
+}
+
+fn check_modes(src: &str, args: &[f64], expected: &[f64]) {
+    let modes = [AutoDiffMode::Forward, AutoDiffMode::Reverse];
+    for (mode, cse) in modes.into_iter().flat_map(|mode| [(mode, false), (mode, true)]) {
+        let (mut func, _) = parse_function(src).unwrap();
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(&func);
+        let mut dom_tree = DominatorTree::default();
+        dom_tree.compute(&func, &cfg, true, false, true);
+
+        let unknowns = [10u32.into(), 11u32.into(), 12u32.into()].into_iter().collect();
+        let unknowns = KnownDerivatives { unknowns, ..Default::default() };
+        let extra_derivatives: Vec<_> =
+            (0..3u32).map(|unknown| (100u32.into(), unknown.into())).collect();
+        let options = AutoDiffOptions { mode, cse };
+        let derivatives =
+            auto_diff_with_options(&mut func, &dom_tree, &unknowns, &extra_derivatives, options);
+        assert!(func.validate());
+
+        let mut interpret = Interpreter::new(
+            &func,
+            TiSlice::from_ref(&[]),
+            TiSlice::from_ref(Data::from_f64_slice(args)),
+        );
+        interpret.run();
+        let margin = F64Margin::default().epsilon(10f64 * f64::EPSILON);
+        for (derivative, &expected) in extra_derivatives.iter().zip(expected) {
+            let val: f64 = match derivatives.get(derivative) {
+                Some(&val) => interpret.state.read(val),
+                None => 0.0,
+            };
+            assert!(
+                val.approx_eq(expected, margin),
+                "{mode:?} (cse: {cse}): {val} != {expected}\n{}",
+                func.to_debug_string()
+            );
+        }
+    }
+}
+
+#[test]
+fn reverse_mode() {
+    // f = x*y*exp(z) + sin(x) - y/z
+    let src = r##"
+        function %f(v10, v11, v12) {
+        block0:
+            v13 = fmul v10, v11
+            v14 = exp v12
+            v15 = fmul v13, v14
+            v16 = sin v10
+            v17 = fadd v15, v16
+            v18 = fdiv v11, v12
+            v19 = fsub v17, v18
+            v100 = optbarrier v19
+        }
+    "##;
+    let (x, y, z) = (0.5f64, 3.0f64, 1.5f64);
+    let expected = [y * z.exp() + x.cos(), x * z.exp() - 1.0 / z, x * y * z.exp() + y / (z * z)];
+    check_modes(src, &[x, y, z], &expected);
+}
+
+#[test]
+fn reverse_mode_shared_args() {
+    // f = (x + x) * x - x*y + z*z
+    let src = r##"
+        function %f(v10, v11, v12) {
+        block0:
+            v13 = fadd v10, v10
+            v14 = fmul v13, v10
+            v15 = fmul v10, v11
+            v16 = fsub v14, v15
+            v17 = fmul v12, v12
+            v18 = fadd v16, v17
+            v100 = optbarrier v18
+        }
+    "##;
+    let (x, y, z) = (0.5f64, 3.0f64, 1.5f64);
+    let expected = [4.0 * x - y, -x, 2.0 * z];
+    check_modes(src, &[x, y, z], &expected);
+}
+
+#[test]
+fn nested_ddx_of_param() {
+    // ddx(x*y, x) = y is not a new value, its derivatives are already known
+    let src = r##"
+        function %bar(v10, v11) {
+            fn0 = const fn %ddx_v10(1) -> 1
+            fn1 = const fn %ddx_v11(1) -> 1
+
+        block0:
+            v13 = fmul v10, v11
+            v14 = call fn0 (v13)
+            v15 = call fn1 (v14)
+            v100 = optbarrier v15
+        }"##;
+    let expect = expect![[r#"
+        function %bar(v10, v11) {
+            inst0 = const fn %ddx_v10(1) -> 1
+            inst1 = const fn %ddx_v11(1) -> 1
+            v6 = fconst 0x1.0000000000000p0
+
+        block0:
+            v13 = fmul v10, v11
+            v100 = optbarrier v6
+        }
+    "#]];
+    check_num(src, expect, &[0.5, 3.0], 1.0);
+}
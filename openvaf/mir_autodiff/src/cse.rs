@@ -0,0 +1,74 @@
+use ahash::AHashMap;
+use mir::{ControlFlowGraph, DominatorTree, Function, Inst, Unknown, Value};
+use mir_opt::{ClassId, GVN};
+
+/// Removes redundant instructions that were created while building derivatives, like
+/// identical chain rule products (for example the derivative of `exp(x)` is required for every
+/// unknown `x` depends on).
+///
+/// Equivalent instructions are found with global value numbering. Only instructions created
+/// after `first_derivative_inst` are removed, so that values of the original function that
+/// are referenced by the caller remain valid. Values in `derivatives` that are removed are
+/// replaced by the equivalent value that remains.
+pub(crate) fn eliminate_common_subexpressions(
+    func: &mut Function,
+    first_derivative_inst: Inst,
+    derivatives: &mut AHashMap<(Value, Unknown), Value>,
+) {
+    // building derivatives may add blocks, so the cfg must be recomputed
+    let mut cfg = ControlFlowGraph::new();
+    cfg.compute(func);
+    let mut dom_tree = DominatorTree::default();
+    dom_tree.compute(func, &cfg, true, false, true);
+
+    let num_params = func
+        .dfg
+        .values()
+        .filter_map(|val| Some(u32::from(func.dfg.value_def(val).as_param()?) + 1))
+        .max()
+        .unwrap_or(0);
+
+    let mut gvn = GVN::default();
+    gvn.init(func, &dom_tree, num_params);
+    gvn.solve(func);
+
+    // a reverse postorder visits dominators first
+    let mut leaders: AHashMap<ClassId, Vec<Inst>> = AHashMap::new();
+    let mut redundant = Vec::new();
+    for &bb in dom_tree.cfg_postorder().iter().rev() {
+        for inst in func.layout.block_insts(bb) {
+            let class = match gvn.inst_class(inst).expand() {
+                Some(class) if func.dfg.inst_results(inst).len() == 1 => class,
+                _ => continue,
+            };
+            let leaders = leaders.entry(class).or_default();
+            if inst >= first_derivative_inst {
+                let leader = leaders.iter().copied().find(|&leader| {
+                    dom_tree.dominates(bb, func.layout.inst_block(leader).unwrap())
+                });
+                if let Some(leader) = leader {
+                    redundant.push((inst, leader));
+                    continue;
+                }
+            }
+            leaders.push(inst);
+        }
+    }
+    gvn.clear(func);
+
+    let mut replaced = AHashMap::new();
+    for (inst, leader) in redundant {
+        let old = func.dfg.first_result(inst);
+        let new = func.dfg.first_result(leader);
+        func.dfg.replace_uses(old, new);
+        func.dfg.zap_inst(inst);
+        func.layout.remove_inst(inst);
+        replaced.insert(old, new);
+    }
+
+    for val in derivatives.values_mut() {
+        if let Some(&new) = replaced.get(val) {
+            *val = new;
+        }
+    }
+}
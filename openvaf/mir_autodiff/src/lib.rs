@@ -1,4 +1,5 @@
 mod builder;
+mod cse;
 mod intern;
 mod live_derivatives;
 mod postorder;
@@ -7,27 +8,146 @@ mod subgraph;
 use ahash::AHashMap;
 pub use builder::build_derivatives;
 pub use live_derivatives::LiveDerivatives;
+use mir::builder::InstBuilder;
+use mir::cursor::{Cursor, FuncCursor};
 use mir::{
-    DataFlowGraph, DominatorTree, Function, Inst, InstructionData, KnownDerivatives, Opcode, Value,
+    ControlFlowGraph, DataFlowGraph, DominatorTree, Function, Inst, InstructionData,
+    KnownDerivatives, Opcode, Value, ValueDef, F_ZERO,
 };
 
+use crate::builder::partition;
+use crate::cse::eliminate_common_subexpressions;
 use crate::intern::{Derivative, DerivativeIntern};
 
+/// How the derivatives requested with `extra_derivatives` are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AutoDiffMode {
+    /// Derivatives are propagated forward trough the function, one unknown at a time.
+    #[default]
+    Forward,
+    /// Values that are differentiated by many unknowns are differentiated with a single
+    /// reverse sweep (where possible) instead, so every local partial derivative
+    /// is only computed once.
+    Reverse,
+}
+
+impl AutoDiffMode {
+    pub const ALL: [AutoDiffMode; 2] = [AutoDiffMode::Forward, AutoDiffMode::Reverse];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AutoDiffMode::Forward => "forward",
+            AutoDiffMode::Reverse => "reverse",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AutoDiffOptions {
+    pub mode: AutoDiffMode,
+    /// Remove redundant instructions (mostly repeated chain rule products)
+    /// after the derivatives were built.
+    pub cse: bool,
+}
+
 pub fn auto_diff(
+    func: impl AsMut<Function>,
+    dom_tree: &DominatorTree,
+    derivatives: &KnownDerivatives,
+    extra_derivatives: &[(Value, mir::Unknown)],
+) -> AHashMap<(Value, mir::Unknown), Value> {
+    auto_diff_with_options(
+        func,
+        dom_tree,
+        derivatives,
+        extra_derivatives,
+        AutoDiffOptions::default(),
+    )
+}
+
+pub fn auto_diff_with_options(
     mut func: impl AsMut<Function>,
     dom_tree: &DominatorTree,
     derivatives: &KnownDerivatives,
     extra_derivatives: &[(Value, mir::Unknown)],
+    options: AutoDiffOptions,
 ) -> AHashMap<(Value, mir::Unknown), Value> {
     let func = func.as_mut();
+    let first_derivative_inst = func.dfg.num_insts().into();
     let mut intern = DerivativeIntern::new(derivatives);
-    let live_derivative = LiveDerivatives::build(func, &mut intern, extra_derivatives, dom_tree);
-    build_derivatives(func, &mut intern, &live_derivative, dom_tree.cfg_postorder())
+    let (forward_derivatives, reverse_sweeps) = match options.mode {
+        AutoDiffMode::Forward => (extra_derivatives.to_vec(), Vec::new()),
+        AutoDiffMode::Reverse => partition(func, &intern, extra_derivatives),
+    };
+    let live_derivative = LiveDerivatives::build(func, &mut intern, &forward_derivatives, dom_tree);
+    let mut res = build_derivatives(
+        func,
+        &mut intern,
+        &live_derivative,
+        dom_tree.cfg_postorder(),
+        &reverse_sweeps,
+    );
+    if options.cse {
+        eliminate_common_subexpressions(func, first_derivative_inst, &mut res);
+    }
+    res
+}
+
+/// Creates a function that computes the partial derivatives of the values `returns` of `func`
+/// with respect to each of the `num_params` parameters of `func`. The returned values of the
+/// new function are ordered by return value first: the derivative of `returns[i]` with respect
+/// to parameter `j` is found at index `i * num_params + j`.
+///
+/// The result is intended as the target of [`KnownDerivatives::call_derivatives`] so that calls to
+/// `func` can be differentiated without inlining `func`.
+pub fn partial_derivatives(
+    func: &Function,
+    returns: &[Value],
+    num_params: usize,
+) -> (Function, Vec<Value>) {
+    let mut func = func.clone();
+    let mut params = vec![None; num_params];
+    for val in func.dfg.values() {
+        if let ValueDef::Param(param) = func.dfg.value_def(val) {
+            if let Some(dst) = params.get_mut(usize::from(param)) {
+                *dst = Some(val);
+            }
+        }
+    }
+    let unknowns = params
+        .into_iter()
+        .enumerate()
+        .map(|(param, val)| val.unwrap_or_else(|| func.dfg.make_param(param.into())))
+        .collect();
+    let known = KnownDerivatives { unknowns, ..KnownDerivatives::default() };
+    let extra_derivatives: Vec<_> = returns
+        .iter()
+        .flat_map(|&val| (0..num_params).map(move |param| (val, param.into())))
+        .collect();
+
+    let mut cfg = ControlFlowGraph::new();
+    cfg.compute(&func);
+    let mut dom_tree = DominatorTree::default();
+    dom_tree.compute(&func, &cfg, true, false, true);
+    let derivatives = auto_diff(&mut func, &dom_tree, &known, &extra_derivatives);
+
+    // auto_diff may add blocks at the end so the exit block must be looked up afterwards
+    let exit = func.layout.last_block().unwrap();
+    let mut cursor = FuncCursor::new(&mut func).at_bottom(exit);
+    let partials = extra_derivatives
+        .iter()
+        .map(|derivative| {
+            let val = derivatives.get(derivative).copied().unwrap_or(F_ZERO);
+            cursor.ins().ensure_optbarrier(val)
+        })
+        .collect();
+    (func, partials)
 }
 
 fn is_zero_call(dfg: &DataFlowGraph, inst: Inst, intern: &DerivativeIntern) -> bool {
     if let InstructionData::Call { func_ref, .. } = dfg.insts[inst] {
         !intern.ddx_calls.contains_key(&func_ref)
+            && !intern.call_derivatives.contains_key(&func_ref)
     } else {
         false
     }
@@ -200,8 +200,17 @@ impl<'a, 'b> LiveDerivativeBuilder<'a, 'b> {
                             self.intern.num_derivatives(),
                         );
                     }
+                } else if self.intern.call_derivatives.contains_key(&func_ref) {
+                    // only first order derivatives are propagated trough function calls
+                    let mut first_order = HybridBitSet::new_empty();
+                    for derivative in dst.iter() {
+                        if self.intern.previous_order(derivative).is_none() {
+                            first_order.insert(derivative, self.intern.num_derivatives());
+                        }
+                    }
+                    dst = first_order;
                 } else {
-                    continue; // TODO call derivatives
+                    continue;
                 }
             }
 
@@ -65,7 +65,11 @@ impl<'a, 'b> SubGraphExplorer<'a, 'b> {
 
         let args = self.func.dfg.instr_args(inst);
         let results = self.func.dfg.inst_results(inst);
-        if derivatives.len() < 2 || args.len() < 2 || results.len() != 1 {
+        if derivatives.len() < 2
+            || args.len() < 2
+            || results.len() != 1
+            || self.func.dfg.insts[inst].opcode() == Opcode::Call
+        {
             return None;
         }
 
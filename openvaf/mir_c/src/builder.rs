@@ -0,0 +1,507 @@
+use std::fmt::Write;
+use std::slice;
+
+use lasso::Rodeo;
+use mir::{
+    Block, ControlFlowGraph, FuncRef, Function, Inst, InstructionData, Opcode, Param, Value,
+    ValueDef, ValueList,
+};
+use typed_index_collections::TiVec;
+
+use crate::{CType, CValue, CallbackFun, FpCheck};
+
+const INDENT: &str = "    ";
+
+/// Lowers a MIR function to the body of a C function.
+///
+/// Every instruction result is stored in a local variable that is named after the value
+/// (for example `v12`). Blocks are emitted in reverse postorder and are connected by `goto`
+/// statements. The code for the function is only assembled once [`Builder::finish`] is called
+/// so that additional statements (like stores of outputs) can be placed at the end of any
+/// block with [`Builder::append`].
+pub struct Builder<'a> {
+    pub func: &'a Function,
+    pub literals: &'a Rodeo,
+    pub values: TiVec<Value, Option<CValue>>,
+    pub params: TiVec<Param, Option<CValue>>,
+    pub callbacks: TiVec<FuncRef, Option<CallbackFun>>,
+    /// Checks the results of all floating point instructions for `NaN`/`Inf` (disabled by default)
+    pub fp_check: Option<FpCheck>,
+    /// Values that are stored in a local variable
+    locals: Vec<Value>,
+    /// Phis without any defined incoming value at the time they were built
+    unresolved_phis: Vec<Inst>,
+    stmts: TiVec<Block, Vec<String>>,
+    order: Vec<Block>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(func: &'a Function, literals: &'a Rodeo) -> Self {
+        Builder {
+            func,
+            literals,
+            values: vec![None; func.dfg.num_values()].into(),
+            params: Default::default(),
+            callbacks: Default::default(),
+            fp_check: None,
+            locals: Vec::new(),
+            unresolved_phis: Vec::new(),
+            stmts: vec![Vec::new(); func.layout.num_blocks()].into(),
+            order: Vec::new(),
+        }
+    }
+
+    pub fn build_consts(&mut self) {
+        for val in self.func.dfg.values() {
+            match self.func.dfg.value_def(val) {
+                ValueDef::Result(_, _) | ValueDef::Invalid => (),
+                ValueDef::Param(param) => {
+                    self.values[val] = self.params.get(param).cloned().flatten()
+                }
+                ValueDef::Const(const_val) => {
+                    self.values[val] = Some(CValue::from_const(&const_val, self.literals));
+                }
+            }
+        }
+    }
+
+    /// Lowers all instructions of the blocks that are reachable from the entry block.
+    /// Must only be called once.
+    pub fn build_func(&mut self) {
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(self.func);
+        let mut order: Vec<_> = cfg.postorder(self.func).collect();
+        order.reverse();
+        for &bb in &order {
+            for inst in self.func.layout.block_insts(bb) {
+                self.build_inst(bb, inst);
+            }
+        }
+        self.order = order;
+
+        // phis in loop headers may only receive defined values trough back edges
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..self.unresolved_phis.len()).rev() {
+                let inst = self.unresolved_phis[i];
+                if let Some(ty) = self.phi_ty(inst) {
+                    let res = self.func.dfg.first_result(inst);
+                    self.values[res].as_mut().unwrap().ty = ty;
+                    self.unresolved_phis.swap_remove(i);
+                    changed = true;
+                }
+            }
+        }
+        // the remaining phis only receive undefined values so their type does not matter
+    }
+
+    /// The type of the first incoming value of the phi `inst` with a known type
+    fn phi_ty(&self, inst: Inst) -> Option<CType> {
+        let phi = self.func.dfg.insts[inst].unwrap_phi();
+        self.func.dfg.phi_edges(phi).find_map(|(_, val)| {
+            if let ValueDef::Result(def, _) = self.func.dfg.value_def(val) {
+                if self.unresolved_phis.contains(&def) {
+                    return None;
+                }
+            }
+            self.values[val].as_ref().map(|val| val.ty)
+        })
+    }
+
+    /// The C expression of `val`
+    pub fn value(&self, val: Value) -> &CValue {
+        self.values[val]
+            .as_ref()
+            .unwrap_or_else(|| unreachable!("attempted to read undefined value {val}"))
+    }
+
+    /// Adds `stmt` to the end of `bb` (but before its terminator)
+    pub fn append(&mut self, bb: Block, stmt: impl Into<String>) {
+        self.stmts[bb].push(stmt.into())
+    }
+
+    /// Assembles the body of the C function (without the surrounding braces).
+    /// The function must return at the end of the exit block (see [`Builder::append`]).
+    pub fn finish(&self) -> String {
+        let mut body = String::new();
+        for &val in &self.locals {
+            let ty = self.value(val).ty;
+            writeln!(body, "{INDENT}{};", ty.declare(&val.to_string())).unwrap();
+        }
+        if !self.locals.is_empty() {
+            body.push('\n');
+        }
+
+        let mut jump_targets: TiVec<Block, bool> = vec![false; self.stmts.len()].into();
+        let blocks: Vec<_> = self
+            .order
+            .iter()
+            .enumerate()
+            .map(|(i, &bb)| {
+                let next = self.order.get(i + 1).copied();
+                let mut lines: Vec<_> =
+                    self.stmts[bb].iter().map(|stmt| format!("{INDENT}{stmt}")).collect();
+                let term = self.func.layout.last_inst(bb).map(|inst| &self.func.dfg.insts[inst]);
+                match term {
+                    Some(&InstructionData::Jump { destination }) => {
+                        self.edge(bb, destination, next, 1, &mut lines, &mut jump_targets);
+                    }
+                    Some(&InstructionData::Branch { cond, then_dst, else_dst, .. }) => {
+                        let mut then_lines = Vec::new();
+                        self.edge(bb, then_dst, next, 2, &mut then_lines, &mut jump_targets);
+                        let mut else_lines = Vec::new();
+                        self.edge(bb, else_dst, next, 2, &mut else_lines, &mut jump_targets);
+                        let cond = self.value(cond);
+                        match (then_lines.is_empty(), else_lines.is_empty()) {
+                            (true, true) => (),
+                            (false, true) => {
+                                lines.push(format!("{INDENT}if ({cond}) {{"));
+                                lines.append(&mut then_lines);
+                                lines.push(format!("{INDENT}}}"));
+                            }
+                            (true, false) => {
+                                lines.push(format!("{INDENT}if (!{}) {{", cond.operand()));
+                                lines.append(&mut else_lines);
+                                lines.push(format!("{INDENT}}}"));
+                            }
+                            (false, false) => {
+                                lines.push(format!("{INDENT}if ({cond}) {{"));
+                                lines.append(&mut then_lines);
+                                lines.push(format!("{INDENT}}} else {{"));
+                                lines.append(&mut else_lines);
+                                lines.push(format!("{INDENT}}}"));
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+                (bb, lines)
+            })
+            .collect();
+
+        for (bb, lines) in blocks {
+            if jump_targets[bb] {
+                // a label must be followed by a statement
+                let empty = if lines.is_empty() { ";" } else { "" };
+                writeln!(body, "bb{}:{empty}", usize::from(bb)).unwrap();
+            }
+            for line in lines {
+                body.push_str(&line);
+                body.push('\n');
+            }
+        }
+
+        body
+    }
+
+    /// Emits the phi copies for the edge from `from` to `to` followed by a jump
+    /// (unless `to` is the block placed directly after `from`).
+    fn edge(
+        &self,
+        from: Block,
+        to: Block,
+        next: Option<Block>,
+        indent: usize,
+        lines: &mut Vec<String>,
+        jump_targets: &mut TiVec<Block, bool>,
+    ) {
+        let func = self.func;
+        let pad = INDENT.repeat(indent);
+        let copies: Vec<_> = func
+            .layout
+            .block_insts(to)
+            .map_while(|inst| match &func.dfg.insts[inst] {
+                InstructionData::PhiNode(phi) => Some((inst, phi)),
+                _ => None,
+            })
+            .filter_map(|(inst, phi)| {
+                let dst = func.dfg.first_result(inst);
+                let (_, src) = func.dfg.phi_edges(phi).find(|&(bb, _)| bb == from)?;
+                // undefined values do not need to be copied
+                self.values[src].as_ref()?;
+                (dst != src).then_some((dst, src))
+            })
+            .collect();
+
+        // the copies happen in parallel so phis that are read by other copies must be
+        // saved to temporaries first (sources may alias a phi trough an `OptBarrier`)
+        let needs_tmp = copies.iter().any(|&(_, src)| {
+            let src = &self.value(src).expr;
+            copies.iter().any(|&(dst, _)| *src == dst.to_string())
+        });
+        if needs_tmp {
+            lines.push(format!("{pad}{{"));
+            for (i, &(_, src)) in copies.iter().enumerate() {
+                let src = self.value(src);
+                lines.push(format!("{pad}{INDENT}{} = {src};", src.ty.declare(&format!("tmp{i}"))));
+            }
+            for (i, &(dst, _)) in copies.iter().enumerate() {
+                lines.push(format!("{pad}{INDENT}{dst} = tmp{i};"));
+            }
+            lines.push(format!("{pad}}}"));
+        } else {
+            for &(dst, src) in &copies {
+                lines.push(format!("{pad}{dst} = {};", self.value(src)));
+            }
+        }
+
+        if next != Some(to) {
+            lines.push(format!("{pad}goto bb{};", usize::from(to)));
+            jump_targets[to] = true;
+        }
+    }
+
+    /// Stores `res` in a new local variable of type `ty`
+    fn define(&mut self, res: Value, ty: CType) {
+        self.values[res] = Some(CValue::new(res.to_string(), ty));
+        self.locals.push(res);
+    }
+
+    fn build_inst(&mut self, bb: Block, inst: Inst) {
+        let func = self.func;
+        let (opcode, args) = match func.dfg.insts[inst] {
+            InstructionData::Unary { opcode, ref arg } => (opcode, slice::from_ref(arg)),
+            InstructionData::Binary { opcode, ref args } => (opcode, args.as_slice()),
+            // terminators are emitted by `finish` once all phis are known
+            InstructionData::Branch { .. } | InstructionData::Jump { .. } => return,
+            InstructionData::PhiNode(_) => {
+                let ty = self.phi_ty(inst);
+                if ty.is_none() {
+                    self.unresolved_phis.push(inst);
+                }
+                self.define(func.dfg.first_result(inst), ty.unwrap_or(CType::Real));
+                return;
+            }
+            InstructionData::Call { func_ref, ref args } => {
+                self.build_call(bb, inst, func_ref, args);
+                return;
+            }
+        };
+
+        let res = func.dfg.first_result(inst);
+        let (expr, ty) = match opcode {
+            Opcode::Inot => (self.unary("~", args), CType::Int),
+            Opcode::Bnot => (self.unary("!", args), CType::Bool),
+            Opcode::Ineg => {
+                (format!("(int32_t)(0u - (uint32_t){})", self.operand(args[0])), CType::Int)
+            }
+            Opcode::Fneg => (self.unary("-", args), CType::Real),
+            Opcode::FIcast => (format!("(int32_t)lround({})", self.value(args[0])), CType::Int),
+            Opcode::IFcast | Opcode::BFcast => (self.unary("(double)", args), CType::Real),
+            Opcode::BIcast => (self.unary("(int32_t)", args), CType::Int),
+            Opcode::IBcast => (format!("{} != 0", self.operand(args[0])), CType::Bool),
+            Opcode::FBcast => {
+                let arg = self.operand(args[0]);
+                (format!("({arg} < 0.0 || {arg} > 0.0)"), CType::Bool)
+            }
+            // signed overflow is undefined behaviour in C
+            Opcode::Iadd => (self.wrapping("+", args), CType::Int),
+            Opcode::Isub => (self.wrapping("-", args), CType::Int),
+            Opcode::Imul => (self.wrapping("*", args), CType::Int),
+            Opcode::Idiv => (self.binary("/", args), CType::Int),
+            Opcode::Irem => (self.binary("%", args), CType::Int),
+            Opcode::Ishl => (self.wrapping("<<", args), CType::Int),
+            // logical shift
+            Opcode::Ishr => (self.wrapping(">>", args), CType::Int),
+            Opcode::Ixor => (self.binary("^", args), CType::Int),
+            Opcode::Iand => (self.binary("&", args), CType::Int),
+            Opcode::Ior => (self.binary("|", args), CType::Int),
+            Opcode::Fadd => (self.binary("+", args), CType::Real),
+            Opcode::Fsub => (self.binary("-", args), CType::Real),
+            Opcode::Fmul => (self.binary("*", args), CType::Real),
+            Opcode::Fdiv => (self.binary("/", args), CType::Real),
+            Opcode::Frem => (self.call("fmod", args), CType::Real),
+            Opcode::Ilt | Opcode::Flt => (self.binary("<", args), CType::Bool),
+            Opcode::Igt | Opcode::Fgt => (self.binary(">", args), CType::Bool),
+            Opcode::Ile | Opcode::Fle => (self.binary("<=", args), CType::Bool),
+            Opcode::Ige | Opcode::Fge => (self.binary(">=", args), CType::Bool),
+            Opcode::Ieq | Opcode::Feq | Opcode::Beq => (self.binary("==", args), CType::Bool),
+            Opcode::Ine | Opcode::Bne => (self.binary("!=", args), CType::Bool),
+            // ordered comparison: false if either operand is NaN
+            Opcode::Fne => {
+                let lhs = self.operand(args[0]);
+                let rhs = self.operand(args[1]);
+                (format!("({lhs} < {rhs} || {lhs} > {rhs})"), CType::Bool)
+            }
+            Opcode::Seq => (format!("{} == 0", self.call("strcmp", args)), CType::Bool),
+            Opcode::Sne => (format!("{} != 0", self.call("strcmp", args)), CType::Bool),
+            Opcode::Sqrt => (self.call("sqrt", args), CType::Real),
+            Opcode::Exp => (self.call("exp", args), CType::Real),
+            Opcode::Ln => (self.call("log", args), CType::Real),
+            Opcode::Log => (self.call("log10", args), CType::Real),
+            Opcode::Clog2 => (self.call("mir_c_clog2", args), CType::Int),
+            Opcode::Floor => (self.call("floor", args), CType::Real),
+            Opcode::Ceil => (self.call("ceil", args), CType::Real),
+            Opcode::Sin => (self.call("sin", args), CType::Real),
+            Opcode::Cos => (self.call("cos", args), CType::Real),
+            Opcode::Tan => (self.call("tan", args), CType::Real),
+            Opcode::Hypot => (self.call("hypot", args), CType::Real),
+            Opcode::Asin => (self.call("asin", args), CType::Real),
+            Opcode::Acos => (self.call("acos", args), CType::Real),
+            Opcode::Atan => (self.call("atan", args), CType::Real),
+            Opcode::Atan2 => (self.call("atan2", args), CType::Real),
+            Opcode::Sinh => (self.call("sinh", args), CType::Real),
+            Opcode::Cosh => (self.call("cosh", args), CType::Real),
+            Opcode::Tanh => (self.call("tanh", args), CType::Real),
+            Opcode::Asinh => (self.call("asinh", args), CType::Real),
+            Opcode::Acosh => (self.call("acosh", args), CType::Real),
+            Opcode::Atanh => (self.call("atanh", args), CType::Real),
+            Opcode::Pow => (self.call("pow", args), CType::Real),
+            Opcode::OptBarrier => {
+                self.values[res] = self.values[args[0]].clone();
+                return;
+            }
+            Opcode::Br | Opcode::Jmp | Opcode::Call | Opcode::Phi => unreachable!(),
+        };
+
+        self.define(res, ty);
+        self.stmts[bb].push(format!("{res} = {expr};"));
+
+        if matches!(
+            opcode,
+            Opcode::Fneg
+                | Opcode::IFcast
+                | Opcode::BFcast
+                | Opcode::Fadd
+                | Opcode::Fsub
+                | Opcode::Fmul
+                | Opcode::Fdiv
+                | Opcode::Frem
+                | Opcode::Sqrt
+                | Opcode::Exp
+                | Opcode::Ln
+                | Opcode::Log
+                | Opcode::Floor
+                | Opcode::Ceil
+                | Opcode::Sin
+                | Opcode::Cos
+                | Opcode::Tan
+                | Opcode::Hypot
+                | Opcode::Asin
+                | Opcode::Acos
+                | Opcode::Atan
+                | Opcode::Atan2
+                | Opcode::Sinh
+                | Opcode::Cosh
+                | Opcode::Tanh
+                | Opcode::Asinh
+                | Opcode::Acosh
+                | Opcode::Atanh
+                | Opcode::Pow
+        ) {
+            self.check_fp(bb, inst, res);
+        }
+    }
+
+    fn build_call(&mut self, bb: Block, inst: Inst, func_ref: FuncRef, args: &ValueList) {
+        let func = self.func;
+        let callback = if let Some(Some(res)) = self.callbacks.get(func_ref) {
+            res.clone()
+        } else {
+            return; // assume nooop
+        };
+
+        let args: Vec<_> = args
+            .as_slice(&func.dfg.insts.value_lists)
+            .iter()
+            .map(|&arg| self.value(arg).expr.clone())
+            .collect();
+        let results = func.dfg.inst_results(inst);
+
+        let (fun, state, num_state, returns) = match callback {
+            CallbackFun::Fun { fun, state, num_state, returns } => (fun, state, num_state, returns),
+            CallbackFun::Const(val) => {
+                for &res in results {
+                    self.values[res] = Some(val.clone());
+                }
+                return;
+            }
+        };
+
+        if num_state != 0 {
+            debug_assert!(results.is_empty());
+            for state in state.chunks(num_state as usize) {
+                let operands: Vec<_> = state.iter().chain(&args).cloned().collect();
+                self.stmts[bb].push(format!("{fun}({});", operands.join(", ")));
+            }
+            return;
+        }
+
+        let mut operands: Vec<_> = state.iter().chain(&args).cloned().collect();
+        let stmt = match *results {
+            [] => format!("{fun}({});", operands.join(", ")),
+            [res] => {
+                self.define(res, returns[0]);
+                format!("{res} = {fun}({});", operands.join(", "))
+            }
+            _ => {
+                for (&res, &ty) in results.iter().zip(&returns) {
+                    self.define(res, ty);
+                    operands.push(format!("&{res}"));
+                }
+                format!("{fun}({});", operands.join(", "))
+            }
+        };
+        self.stmts[bb].push(stmt);
+
+        // the results of analog functions that were not inlined are checked at the call site
+        for (&res, &ty) in results.iter().zip(&returns) {
+            if ty == CType::Real {
+                self.check_fp(bb, inst, res);
+            }
+        }
+    }
+
+    /// Passes the result `val` of `inst` to [`Builder::fp_check`].
+    fn check_fp(&mut self, bb: Block, inst: Inst, val: Value) {
+        let fp_check = match &self.fp_check {
+            Some(fp_check) => fp_check,
+            None => return,
+        };
+        let srcloc = self.func.srclocs.get(inst).map_or(0, |loc| loc.0);
+        let location = (srcloc.unsigned_abs() as usize)
+            .checked_sub(1)
+            .and_then(|idx| fp_check.locations.get(idx));
+        let location = match location {
+            Some(location) => location,
+            None => return,
+        };
+        let generated = (srcloc < 0) as i32;
+        let operands: Vec<_> = fp_check
+            .state
+            .iter()
+            .cloned()
+            .chain([location.clone(), generated.to_string(), val.to_string()])
+            .collect();
+        let stmt = format!("{}({});", fp_check.fun, operands.join(", "));
+        self.stmts[bb].push(stmt);
+    }
+
+    fn operand(&self, val: Value) -> String {
+        self.value(val).operand()
+    }
+
+    fn unary(&self, op: &str, args: &[Value]) -> String {
+        format!("{op}{}", self.operand(args[0]))
+    }
+
+    fn binary(&self, op: &str, args: &[Value]) -> String {
+        format!("{} {op} {}", self.operand(args[0]), self.operand(args[1]))
+    }
+
+    /// Integer operations with the same two's complement semantics as LLVM
+    fn wrapping(&self, op: &str, args: &[Value]) -> String {
+        let lhs = self.operand(args[0]);
+        let rhs = self.operand(args[1]);
+        match op {
+            "<<" | ">>" => format!("(int32_t)((uint32_t){lhs} {op} {rhs})"),
+            _ => format!("(int32_t)((uint32_t){lhs} {op} (uint32_t){rhs})"),
+        }
+    }
+
+    fn call(&self, fun: &str, args: &[Value]) -> String {
+        let args: Vec<_> = args.iter().map(|&arg| self.value(arg).expr.as_str()).collect();
+        format!("{fun}({})", args.join(", "))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{CType, CValue};
+
+#[derive(Clone, Debug)]
+pub enum CallbackFun {
+    /// Calls the C function `fun`.
+    Fun {
+        fun: String,
+        /// Some Callbacks need to read/modify some state (typically passed as pointers)
+        /// outside of the arguments provided in Verilog-A.
+        /// These arguments are always passed before any arguments specified in the CFG
+        state: Box<[String]>,
+        /// If this is not zero `fun` is called once for every `num_state` elements of `state`
+        num_state: u32,
+        /// The types of the values returned by the callback. If the callback returns more than
+        /// one value, pointers to the results are passed as the last arguments instead.
+        returns: Vec<CType>,
+    },
+    /// A callback that ignores its arguments and always returns the same value.
+    Const(CValue),
+}
+
+impl CallbackFun {
+    pub fn new(fun: impl Into<String>, state: Box<[String]>, returns: Vec<CType>) -> CallbackFun {
+        CallbackFun::Fun { fun: fun.into(), state, num_state: 0, returns }
+    }
+
+    /// The types of the values the callback returns
+    pub fn returns(&self) -> &[CType] {
+        match self {
+            CallbackFun::Fun { returns, .. } => returns,
+            CallbackFun::Const(val) => std::slice::from_ref(&val.ty),
+        }
+    }
+}
+
+/// A callback that is called with the result of every floating point instruction to
+/// detect `NaN` and `Inf` (see [`Builder::fp_check`](crate::Builder::fp_check)).
+///
+/// The arguments of the callback are `state`, the entry of `locations` that belongs to the
+/// instruction, an `int` that is one if the instruction was generated by the compiler (for
+/// example to compute a derivative) and finally the result of the instruction.
+pub struct FpCheck {
+    pub fun: String,
+    pub state: Box<[String]>,
+    /// `locations[n - 1]` belongs to instructions with the source location `n` (or `-n` if
+    /// the instruction was generated by the compiler). Instructions without a source location
+    /// are not checked.
+    pub locations: Vec<String>,
+}
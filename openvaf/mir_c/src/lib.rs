@@ -0,0 +1,165 @@
+//! Generates portable C99 source code from MIR functions.
+//!
+//! This crate is an alternative to `mir_llvm` for users that require the generated model
+//! code to be available as (auditable) source code. Every instruction is lowered to an
+//! assignment to a local variable and the control flow graph is translated to labels and
+//! `goto` statements. Phi nodes are resolved by copies on the incoming edges.
+
+use std::fmt::{self, Display, Write};
+
+use lasso::Rodeo;
+use mir::Const;
+
+mod builder;
+mod callbacks;
+
+#[cfg(test)]
+mod tests;
+
+pub use builder::Builder;
+pub use callbacks::{CallbackFun, FpCheck};
+
+/// Declarations required by the code generated with [`Builder`]. Must be included once at the
+/// start of every translation unit.
+pub const PRELUDE: &str = r#"#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <string.h>
+
+/* 32 minus the number of leading zeros of x (matches the other backends) */
+static int32_t mir_c_clog2(int32_t x) {
+    uint32_t val = (uint32_t)x;
+    int32_t bits = 0;
+    while (val != 0) {
+        bits++;
+        val >>= 1;
+    }
+    return bits;
+}
+"#;
+
+/// The C types used to represent the values of the MIR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CType {
+    Bool,
+    Int,
+    Real,
+    Str,
+}
+
+impl CType {
+    pub fn name(self) -> &'static str {
+        match self {
+            CType::Bool => "bool",
+            CType::Int => "int32_t",
+            CType::Real => "double",
+            CType::Str => "char *",
+        }
+    }
+
+    /// Declaration of a variable called `name` with this type (without a trailing `;`)
+    pub fn declare(self, name: &str) -> String {
+        match self {
+            CType::Str => format!("char *{name}"),
+            _ => format!("{} {name}", self.name()),
+        }
+    }
+}
+
+impl Display for CType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A C expression and its type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CValue {
+    pub expr: String,
+    pub ty: CType,
+}
+
+impl CValue {
+    pub fn new(expr: impl Into<String>, ty: CType) -> CValue {
+        CValue { expr: expr.into(), ty }
+    }
+
+    pub fn real(val: f64) -> CValue {
+        CValue::new(const_real(val), CType::Real)
+    }
+
+    pub fn int(val: i32) -> CValue {
+        CValue::new(const_int(val), CType::Int)
+    }
+
+    pub fn bool(val: bool) -> CValue {
+        CValue::new(if val { "true" } else { "false" }, CType::Bool)
+    }
+
+    pub fn str(val: &str) -> CValue {
+        CValue::new(const_str(val), CType::Str)
+    }
+
+    pub fn from_const(val: &Const, literals: &Rodeo) -> CValue {
+        match *val {
+            Const::Float(val) => CValue::real(val.into()),
+            Const::Int(val) => CValue::int(val),
+            Const::Str(val) => CValue::str(literals.resolve(&val)),
+            Const::Bool(val) => CValue::bool(val),
+        }
+    }
+
+    /// The expression wrapped in parentheses if required to use it as an operand of an operator
+    pub fn operand(&self) -> String {
+        if self.expr.contains(char::is_whitespace) || self.expr.starts_with('-') {
+            format!("({})", self.expr)
+        } else {
+            self.expr.clone()
+        }
+    }
+}
+
+impl Display for CValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+/// A C literal that is exactly equal to `val`
+pub fn const_real(val: f64) -> String {
+    if val.is_nan() {
+        "NAN".to_owned()
+    } else if val.is_infinite() {
+        if val > 0.0 { "INFINITY" } else { "-INFINITY" }.to_owned()
+    } else {
+        // the debug representation round trips and always contains a `.` or an exponent
+        format!("{val:?}")
+    }
+}
+
+pub fn const_int(val: i32) -> String {
+    if val == i32::MIN {
+        // -2147483648 is the negation of a literal that does not fit into an int
+        "(-2147483647 - 1)".to_owned()
+    } else {
+        val.to_string()
+    }
+}
+
+/// A C string literal that represents `val`. Everything except printable ASCII is escaped.
+pub fn const_str(val: &str) -> String {
+    let mut res = String::with_capacity(val.len() + 2);
+    res.push('"');
+    for byte in val.bytes() {
+        match byte {
+            b'"' => res.push_str("\\\""),
+            b'\\' => res.push_str("\\\\"),
+            // avoid trigraphs
+            b'?' => res.push_str("\\?"),
+            b' '..=b'~' => res.push(byte as char),
+            _ => write!(res, "\\{byte:03o}").unwrap(),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -0,0 +1,89 @@
+use expect_test::{expect, Expect};
+use mir::{Block, Value};
+use mir_reader::parse_function;
+
+use crate::{const_real, const_str, Builder, CType, CValue, FpCheck};
+
+fn check_body(src: &str, ret: (Block, Value), expect: Expect) {
+    let (func, literals) = parse_function(src).unwrap();
+    let mut builder = Builder::new(&func, &literals);
+    builder.params =
+        vec![Some(CValue::new("a", CType::Int)), Some(CValue::new("b", CType::Int))].into();
+    builder.fp_check = Some(FpCheck {
+        fun: "check_fp".to_owned(),
+        state: Box::new(["&reported".to_owned()]),
+        locations: vec![const_str("a.va:3")],
+    });
+    builder.build_consts();
+    builder.build_func();
+    let (bb, val) = ret;
+    let stmt = format!("return {};", builder.value(val));
+    builder.append(bb, stmt);
+    expect.assert_eq(&builder.finish());
+}
+
+#[test]
+fn loop_with_swap() {
+    let src = r##"
+        function %bar(v10, v11) {
+            v1 = iconst 1
+            v2 = fconst 0x1.0000000000000p1
+        block0:
+            jmp block1
+        block1:
+            v12 = phi [v10, block0], [v13, block2]
+            v13 = phi [v11, block0], [v14, block2]
+            v16 = ilt v12, v13
+            br v16, block2, block3
+        block2:
+            v14 = iadd v12, v1
+            jmp block1
+        block3:
+            v17 = ifcast v12
+            @0001 v18 = fmul v17, v2
+        }
+    "##;
+
+    check_body(
+        src,
+        (Block::from(3u32), Value::from(18u32)),
+        expect![[r#"
+            int32_t v12;
+            int32_t v13;
+            bool v16;
+            double v17;
+            double v18;
+            int32_t v14;
+
+            v12 = a;
+            v13 = b;
+        bb1:
+            v16 = v12 < v13;
+            if (v16) {
+                goto bb2;
+            }
+            v17 = (double)v12;
+            v18 = v17 * 2.0;
+            check_fp(&reported, "a.va:3", 0, v18);
+            return v18;
+        bb2:
+            v14 = (int32_t)((uint32_t)v12 + (uint32_t)1);
+            {
+                int32_t tmp0 = v13;
+                int32_t tmp1 = v14;
+                v12 = tmp0;
+                v13 = tmp1;
+            }
+            goto bb1;
+        "#]],
+    );
+}
+
+#[test]
+fn literals() {
+    assert_eq!(const_real(1e-5), "1e-5");
+    assert_eq!(const_real(-3.0), "-3.0");
+    assert_eq!(const_real(f64::NEG_INFINITY), "-INFINITY");
+    assert_eq!(CValue::int(i32::MIN).expr, "(-2147483647 - 1)");
+    assert_eq!(const_str("a\"b\\c??=\n\u{b5}"), r#""a\"b\\c\?\?=\012\302\265""#);
+}
@@ -7,12 +7,13 @@ use llvm::{
     UNNAMED,
 };
 use mir::{
-    Block, ControlFlowGraph, FuncRef, Function, Inst, Opcode, Param, PhiNode, Value, ValueDef,
-    F_ZERO, ZERO,
+    Block, ControlFlowGraph, FloatPolicy, FuncRef, Function, Inst, Opcode, Param, PhiNode, Value,
+    ValueDef, F_ZERO, ZERO,
 };
 use typed_index_collections::TiVec;
 
-use crate::callbacks::CallbackFun;
+use crate::callbacks::{CallbackFun, FpCheck};
+use crate::debug_info::FunctionDebugInfo;
 use crate::CodegenCx;
 
 #[derive(Clone)]
@@ -146,6 +147,13 @@ pub struct Builder<'a, 'cx, 'll> {
     pub prepend_pos: &'ll llvm::BasicBlock,
     pub unfinished_phis: Vec<(PhiNode, &'ll llvm::Value)>,
     pub fun: &'ll llvm::Value,
+    /// Fast-math flags used for the instructions of `func` (defaults to the policy of `cx`)
+    pub fp_policy: FloatPolicy,
+    /// Checks the results of all floating point instructions for `NaN`/`Inf` (disabled by default)
+    pub fp_check: Option<FpCheck<'ll>>,
+    /// Emits source locations and local variables for the instructions of `func`
+    /// (disabled by default)
+    pub debug_info: Option<FunctionDebugInfo<'a, 'll>>,
 }
 
 impl Drop for Builder<'_, '_, '_> {
@@ -162,6 +170,19 @@ pub enum FastMathMode {
     Disabled,
 }
 
+impl FastMathMode {
+    /// The fast-math flags `policy` allows for an instruction. Instructions generated by the
+    /// compiler (like derivatives) are marked with a negative source location.
+    pub fn new(policy: FloatPolicy, generated: bool) -> FastMathMode {
+        match policy {
+            FloatPolicy::Strict => FastMathMode::Disabled,
+            FloatPolicy::Partial if generated => FastMathMode::Partial,
+            FloatPolicy::Partial => FastMathMode::Disabled,
+            FloatPolicy::Fast => FastMathMode::Full,
+        }
+    }
+}
+
 impl<'a, 'cx, 'll> Builder<'a, 'cx, 'll> {
     pub fn new(
         cx: &'a CodegenCx<'cx, 'll>,
@@ -188,6 +209,9 @@ impl<'a, 'cx, 'll> Builder<'a, 'cx, 'll> {
             fun: llfunc,
             prepend_pos: entry,
             unfinished_phis: Vec::new(),
+            fp_policy: cx.fp_policy,
+            fp_check: None,
+            debug_info: None,
         }
     }
 }
@@ -357,6 +381,11 @@ impl<'ll> Builder<'_, '_, 'll> {
             self.build_bb(bb)
         }
 
+        if self.debug_info.is_some() {
+            // code generated outside of the MIR has no location in the Verilog-A source
+            llvm::LLVMSetCurrentDebugLocation2(self.llbuilder, None);
+        }
+
         for (phi, llval) in self.unfinished_phis.iter() {
             let (blocks, vals): (Vec<_>, Vec<_>) = self
                 .func
@@ -395,12 +424,69 @@ impl<'ll> Builder<'_, '_, 'll> {
     pub unsafe fn build_bb(&mut self, bb: Block) {
         self.select_bb(bb);
 
+        // with fast-math LLVM may assume that NaN/Inf never occur and remove the checks
+        let fp_policy = if self.fp_check.is_some() && self.fp_policy == FloatPolicy::Fast {
+            FloatPolicy::Partial
+        } else {
+            self.fp_policy
+        };
+        // llvm.dbg.value must not be placed in between phis
+        let mut phis = Vec::new();
         for inst in self.func.layout.block_insts(bb) {
-            let fast_math = self.func.srclocs.get(inst).map_or(false, |loc| loc.0 < 0);
-            self.build_inst(
-                inst,
-                if fast_math { FastMathMode::Partial } else { FastMathMode::Disabled },
-            )
+            let is_phi = matches!(self.func.dfg.insts[inst], mir::InstructionData::PhiNode(_));
+            if !is_phi {
+                for phi in phis.drain(..) {
+                    self.declare_variables(phi);
+                }
+            }
+
+            let generated = self.func.srclocs.get(inst).map_or(false, |loc| loc.0 < 0);
+            if let Some(loc) = self.debug_loc(inst) {
+                llvm::LLVMSetCurrentDebugLocation2(self.llbuilder, Some(loc));
+            }
+            self.build_inst(inst, FastMathMode::new(fp_policy, generated));
+
+            if is_phi {
+                phis.push(inst)
+            } else {
+                self.declare_variables(inst)
+            }
+        }
+    }
+
+    /// The debug location of `inst` if [`Builder::debug_info`] is enabled. Instructions
+    /// without a location in the source code are placed at line zero.
+    fn debug_loc(&self, inst: Inst) -> Option<&'ll llvm::Metadata> {
+        let debug_info = self.debug_info.as_ref()?;
+        let srcloc = self.func.srclocs.get(inst).map_or(0, |loc| loc.0);
+        let loc = (srcloc.unsigned_abs() as usize)
+            .checked_sub(1)
+            .and_then(|idx| debug_info.locations.get(idx))
+            .copied()
+            .unwrap_or_else(|| debug_info.builder.location(debug_info.scope, 0, 0));
+        Some(loc)
+    }
+
+    /// Emits `llvm.dbg.value` for all results of `inst` that are assigned to a variable
+    /// in [`Builder::debug_info`].
+    ///
+    /// # Safety
+    /// Must not be called when a block that already contains a terminator is selected
+    unsafe fn declare_variables(&self, inst: Inst) {
+        let debug_info = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return,
+        };
+        for &val in self.func.dfg.inst_results(inst) {
+            let var = match self.func.dfg.tag(val).and_then(|tag| debug_info.variables.get(&tag)) {
+                Some(&var) => var,
+                None => continue,
+            };
+            if let BuilderVal::Eager(llval) = self.values[val] {
+                let loc = self.debug_loc(inst).unwrap();
+                let bb = llvm::LLVMGetInsertBlock(self.llbuilder);
+                debug_info.builder.declare_value(llval, var, loc, bb);
+            }
         }
     }
 
@@ -484,12 +570,16 @@ impl<'ll> Builder<'_, '_, 'll> {
 
                     match inst_res {
                         [] => (),
-                        [val] => self.values[*val] = res.into(),
+                        [val] => {
+                            self.values[*val] = res.into();
+                            self.check_fp_call_result(inst, res);
+                        }
                         vals => {
                             for (i, val) in vals.iter().enumerate() {
                                 let res =
                                     LLVMBuildExtractValue(self.llbuilder, res, i as u32, UNNAMED);
                                 self.values[*val] = res.into();
+                                self.check_fp_call_result(inst, res);
                             }
                         }
                     }
@@ -700,6 +790,78 @@ impl<'ll> Builder<'_, '_, 'll> {
                 FastMathMode::Disabled => (),
             }
         }
+
+        if self.fp_check.is_some()
+            && matches!(
+                opcode,
+                Opcode::Fneg
+                    | Opcode::IFcast
+                    | Opcode::BFcast
+                    | Opcode::Fadd
+                    | Opcode::Fsub
+                    | Opcode::Fmul
+                    | Opcode::Fdiv
+                    | Opcode::Frem
+                    | Opcode::Sqrt
+                    | Opcode::Exp
+                    | Opcode::Ln
+                    | Opcode::Log
+                    | Opcode::Floor
+                    | Opcode::Ceil
+                    | Opcode::Sin
+                    | Opcode::Cos
+                    | Opcode::Tan
+                    | Opcode::Hypot
+                    | Opcode::Asin
+                    | Opcode::Acos
+                    | Opcode::Atan
+                    | Opcode::Atan2
+                    | Opcode::Sinh
+                    | Opcode::Cosh
+                    | Opcode::Tanh
+                    | Opcode::Asinh
+                    | Opcode::Acosh
+                    | Opcode::Atanh
+                    | Opcode::Pow
+            )
+        {
+            self.check_fp(inst, val)
+        }
+    }
+
+    /// Passes the result `val` of `inst` to [`Builder::fp_check`].
+    ///
+    /// # Safety
+    /// Must not be called when a block that already contains a terminator is selected
+    unsafe fn check_fp(&self, inst: Inst, val: &'ll llvm::Value) {
+        let fp_check = match &self.fp_check {
+            Some(fp_check) => fp_check,
+            None => return,
+        };
+        let srcloc = self.func.srclocs.get(inst).map_or(0, |loc| loc.0);
+        let location = (srcloc.unsigned_abs() as usize)
+            .checked_sub(1)
+            .and_then(|idx| fp_check.locations.get(idx));
+        let location = match location {
+            Some(&location) => location,
+            None => return,
+        };
+        let generated = self.cx.const_int((srcloc < 0) as i32);
+        let callback = &fp_check.callback;
+        let operands: Vec<_> =
+            callback.state.iter().copied().chain([location, generated, val]).collect();
+        self.call(callback.fun_ty, callback.fun, &operands);
+    }
+
+    /// Checks the real results of calls (like analog functions that were not inlined) with
+    /// [`Builder::fp_check`]. The instructions within the called functions are not checked.
+    ///
+    /// # Safety
+    /// Must not be called when a block that already contains a terminator is selected
+    unsafe fn check_fp_call_result(&self, inst: Inst, val: &'ll llvm::Value) {
+        if self.fp_check.is_some() && std::ptr::eq(self.cx.val_ty(val), self.cx.ty_double()) {
+            self.check_fp(inst, val)
+        }
     }
 
     unsafe fn strcmp(&mut self, args: &[Value], invert: bool) -> &'ll llvm::Value {
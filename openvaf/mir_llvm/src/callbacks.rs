@@ -13,6 +13,20 @@ pub struct CallbackFun<'ll> {
     pub num_state: u32,
 }
 
+/// A callback that is called with the result of every floating point instruction to
+/// detect `NaN` and `Inf` (see [`Builder::fp_check`](crate::Builder::fp_check)).
+///
+/// The arguments of the callback are `state`, the entry of `locations` that belongs to the
+/// instruction, an `int` that is one if the instruction was generated by the compiler (for
+/// example to compute a derivative) and finally the result of the instruction.
+pub struct FpCheck<'ll> {
+    pub callback: CallbackFun<'ll>,
+    /// `locations[n - 1]` belongs to instructions with the source location `n` (or `-n` if
+    /// the instruction was generated by the compiler). Instructions without a source location
+    /// are not checked.
+    pub locations: Vec<&'ll llvm::Value>,
+}
+
 impl<'ll> CodegenCx<'_, 'll> {
     pub fn const_callback(
         &self,
@@ -9,6 +9,7 @@ use llvm::{
     LLVMCreateMemoryBufferWithMemoryRange, LLVMGetNamedFunction, LLVMLinkModules2,
     LLVMParseBitcodeInContext2, Type, Value,
 };
+use mir::FloatPolicy;
 use target::spec::Target;
 
 use crate::types::Types;
@@ -24,6 +25,9 @@ pub struct CodegenCx<'a, 'll> {
     pub(crate) intrinsics: RefCell<AHashMap<&'static str, (&'ll Type, &'ll Value)>>,
     pub(crate) local_gen_sym_counter: Cell<u32>,
     pub(crate) tys: Types<'ll>,
+    /// Fast-math flags used for all code generated with this context, can be overwritten
+    /// for individual functions with [`Builder::fp_policy`](crate::Builder::fp_policy)
+    pub fp_policy: FloatPolicy,
 }
 
 impl<'a, 'll> CodegenCx<'a, 'll> {
@@ -45,6 +49,19 @@ impl<'a, 'll> CodegenCx<'a, 'll> {
             // target_cpu,
             target,
             tys: Types::new(llvm_module.llcx, target.pointer_width),
+            fp_policy: FloatPolicy::default(),
+        }
+    }
+
+    /// Places the fast-math flags allowed by [`CodegenCx::fp_policy`] on a floating point
+    /// instruction that was generated by the compiler (and not written in Verilog-A).
+    pub fn set_fast_math(&self, val: &'ll Value) {
+        unsafe {
+            match self.fp_policy {
+                FloatPolicy::Strict => (),
+                FloatPolicy::Partial => llvm::LLVMSetPartialFastMath(val),
+                FloatPolicy::Fast => llvm::LLVMSetFastMath(val),
+            }
         }
     }
 
@@ -0,0 +1,245 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::path::Path;
+
+use ahash::AHashMap;
+use libc::c_char;
+use llvm::{Metadata, UNNAMED};
+use mir::Tag;
+
+use crate::CodegenCx;
+
+/// Creates the DWARF debug information of a single LLVM module. All debug information
+/// is only written to the module once [`DebugInfoBuilder::finalize`] is called.
+pub struct DebugInfoBuilder<'ll> {
+    raw: &'ll mut llvm::DIBuilder<'ll>,
+    llcx: &'ll llvm::Context,
+    compile_unit: &'ll Metadata,
+    files: RefCell<AHashMap<String, &'ll Metadata>>,
+    optimized: bool,
+}
+
+impl<'ll> DebugInfoBuilder<'ll> {
+    /// Creates a compile unit for the Verilog-A file `root_file`. `optimized` indicates
+    /// whether the code is optimized (which may cause variables to be unavailable).
+    pub fn new(cx: &CodegenCx<'_, 'll>, root_file: &str, optimized: bool) -> Self {
+        unsafe {
+            // windows debuggers only understand CodeView while everything else uses DWARF
+            if cx.target.options.is_like_windows {
+                add_module_flag(cx, "CodeView", 1);
+            } else {
+                add_module_flag(cx, "Dwarf Version", 4);
+            }
+            add_module_flag(cx, "Debug Info Version", llvm::LLVMDebugMetadataVersion());
+
+            let raw = llvm::LLVMCreateDIBuilder(cx.llmod);
+            let file = create_file(raw, root_file);
+            let producer = "OpenVAF";
+            let compile_unit = llvm::LLVMDIBuilderCreateCompileUnit(
+                raw,
+                llvm::DWARFSourceLanguage::C99,
+                file,
+                producer.as_ptr() as *const c_char,
+                producer.len(),
+                optimized as llvm::Bool,
+                UNNAMED,
+                0,
+                0,
+                UNNAMED,
+                0,
+                llvm::DWARFEmissionKind::Full,
+                0,
+                llvm::False,
+                llvm::False,
+                UNNAMED,
+                0,
+                UNNAMED,
+                0,
+            );
+            let files = RefCell::new(AHashMap::from_iter([(root_file.to_owned(), file)]));
+            DebugInfoBuilder { raw, llcx: cx.llcx, compile_unit, files, optimized }
+        }
+    }
+
+    /// Returns the debug information for the source file at `path`.
+    pub fn file(&self, path: &str) -> &'ll Metadata {
+        if let Some(file) = self.files.borrow().get(path) {
+            return file;
+        }
+
+        let file = unsafe { create_file(self.raw, path) };
+        self.files.borrow_mut().insert(path.to_owned(), file);
+        file
+    }
+
+    /// Attaches a subprogram called `name` that starts at `line` of `file` to `fun`.
+    /// The subprogram is returned so that it can be used as a scope.
+    pub fn function(
+        &self,
+        fun: &'ll llvm::Value,
+        name: &str,
+        file: &'ll Metadata,
+        line: u32,
+    ) -> &'ll Metadata {
+        unsafe {
+            let ty = llvm::LLVMDIBuilderCreateSubroutineType(
+                self.raw,
+                file,
+                [].as_ptr(),
+                0,
+                llvm::DIFlagZero,
+            );
+            let subprogram = llvm::LLVMDIBuilderCreateFunction(
+                self.raw,
+                self.compile_unit,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                name.as_ptr() as *const c_char,
+                name.len(),
+                file,
+                line,
+                ty,
+                llvm::False,
+                llvm::True,
+                line,
+                llvm::DIFlagZero,
+                self.optimized as llvm::Bool,
+            );
+            llvm::LLVMSetSubprogram(fun, subprogram);
+            subprogram
+        }
+    }
+
+    /// A scope within `scope` for code that is located in a different `file`
+    /// (for example code included with `` `include``).
+    pub fn file_scope(&self, scope: &'ll Metadata, file: &'ll Metadata) -> &'ll Metadata {
+        unsafe { llvm::LLVMDIBuilderCreateLexicalBlockFile(self.raw, scope, file, 0) }
+    }
+
+    /// A location in the source code. Lines and columns start at one,
+    /// a line of zero indicates code without a location.
+    pub fn location(&self, scope: &'ll Metadata, line: u32, col: u32) -> &'ll Metadata {
+        unsafe { llvm::LLVMDIBuilderCreateDebugLocation(self.llcx, line, col, scope, None) }
+    }
+
+    /// A local variable called `name` with type `ty` that is declared at `line` of `file`.
+    pub fn variable(
+        &self,
+        scope: &'ll Metadata,
+        name: &str,
+        file: &'ll Metadata,
+        line: u32,
+        ty: &'ll Metadata,
+    ) -> &'ll Metadata {
+        unsafe {
+            llvm::LLVMDIBuilderCreateAutoVariable(
+                self.raw,
+                scope,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                file,
+                line,
+                ty,
+                llvm::True,
+                llvm::DIFlagZero,
+                0,
+            )
+        }
+    }
+
+    /// The type of Verilog-A `real` variables.
+    pub fn ty_real(&self) -> &'ll Metadata {
+        self.basic_type("real", 64, llvm::DW_ATE_float)
+    }
+
+    /// The type of Verilog-A `integer` variables.
+    pub fn ty_integer(&self) -> &'ll Metadata {
+        self.basic_type("integer", 32, llvm::DW_ATE_signed)
+    }
+
+    fn basic_type(
+        &self,
+        name: &str,
+        bits: u64,
+        encoding: llvm::DWARFTypeEncoding,
+    ) -> &'ll Metadata {
+        unsafe {
+            llvm::LLVMDIBuilderCreateBasicType(
+                self.raw,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                bits,
+                encoding,
+                llvm::DIFlagZero,
+            )
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `var` must be a variable created with this builder, `loc` must be a location within
+    /// the same function and the type of `val` must match the type of the variable.
+    pub(crate) unsafe fn declare_value(
+        &self,
+        val: &'ll llvm::Value,
+        var: &'ll Metadata,
+        loc: &'ll Metadata,
+        bb: &'ll llvm::BasicBlock,
+    ) {
+        let expr = llvm::LLVMDIBuilderCreateExpression(self.raw, [].as_ptr(), 0);
+        llvm::LLVMDIBuilderInsertDbgValueAtEnd(self.raw, val, var, expr, loc, bb);
+    }
+
+    /// Writes all debug information to the module.
+    /// Must be called before the module is verified or emitted.
+    pub fn finalize(self) {
+        unsafe { llvm::LLVMDIBuilderFinalize(self.raw) }
+    }
+}
+
+impl Drop for DebugInfoBuilder<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposeDIBuilder(&mut *(self.raw as *mut _));
+        }
+    }
+}
+
+unsafe fn create_file<'ll>(builder: &llvm::DIBuilder<'ll>, path: &str) -> &'ll Metadata {
+    let path = Path::new(path);
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let dir = path.parent().and_then(|dir| dir.to_str()).unwrap_or_default();
+    llvm::LLVMDIBuilderCreateFile(
+        builder,
+        name.as_ptr() as *const c_char,
+        name.len(),
+        dir.as_ptr() as *const c_char,
+        dir.len(),
+    )
+}
+
+unsafe fn add_module_flag(cx: &CodegenCx<'_, '_>, key: &str, val: u32) {
+    let val = llvm::LLVMValueAsMetadata(cx.const_unsigned_int(val));
+    let key_ = CString::new(key).unwrap();
+    llvm::LLVMAddModuleFlag(
+        cx.llmod,
+        llvm::ModuleFlagBehavior::Warning,
+        key_.as_ptr(),
+        key.len(),
+        val,
+    );
+}
+
+/// The debug information of a function that is generated with a [`Builder`](crate::Builder)
+/// (see [`Builder::debug_info`](crate::Builder::debug_info)).
+pub struct FunctionDebugInfo<'a, 'll> {
+    pub builder: &'a DebugInfoBuilder<'ll>,
+    /// The subprogram created with [`DebugInfoBuilder::function`]
+    pub scope: &'ll Metadata,
+    /// `locations[n - 1]` belongs to instructions with the source location `n` (or `-n` if
+    /// the instruction was generated by the compiler). Instructions without a source location
+    /// are placed at line zero.
+    pub locations: Vec<&'ll Metadata>,
+    /// The local variable that is assigned by the values with a [`Tag`]
+    pub variables: AHashMap<Tag, &'ll Metadata>,
+}
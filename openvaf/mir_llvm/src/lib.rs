@@ -16,6 +16,7 @@ use target::spec::Target;
 
 mod builder;
 mod context;
+mod debug_info;
 mod declarations;
 mod intrinsics;
 mod types;
@@ -25,8 +26,10 @@ mod callbacks;
 mod tests;
 
 pub use builder::{Builder, BuilderVal, MemLoc};
-pub use callbacks::CallbackFun;
+pub use callbacks::{CallbackFun, FpCheck};
 pub use context::CodegenCx;
+pub use debug_info::{DebugInfoBuilder, FunctionDebugInfo};
+pub use mir::FloatPolicy;
 
 pub struct LLVMBackend<'t> {
     target: &'t Target,
@@ -124,6 +127,16 @@ impl<'t> LLVMBackend<'t> {
     pub fn target(&self) -> &'t Target {
         self.target
     }
+
+    /// The cpu code is generated for (`native` is resolved to the host cpu).
+    pub fn target_cpu(&self) -> &str {
+        &self.target_cpu
+    }
+
+    /// Comma separated list of the enabled target features.
+    pub fn target_features(&self) -> &str {
+        &self.features
+    }
 }
 
 impl Drop for LLVMBackend<'_> {
@@ -169,12 +182,19 @@ impl ModuleLlvm {
         llvm::LLVMSetDataLayout(llmod, data_layout.as_ptr());
         llvm::set_normalized_target(llmod, &target.llvm_target);
 
+        // wasm modules are not position independent, memory addresses are always absolute
+        let reloc_mode = if target.options.is_like_wasm {
+            llvm::RelocMode::Static
+        } else {
+            llvm::RelocMode::PIC
+        };
         let tm = llvm::create_target(
             &target.llvm_target,
             target_cpu,
             features,
+            &target.options.llvm_abiname,
             opt_lvl,
-            llvm::RelocMode::PIC,
+            reloc_mode,
             llvm::CodeModel::Default,
         )?;
         let llmod_raw = llmod as _;
@@ -244,6 +264,42 @@ impl ModuleLlvm {
     }
 
     pub fn emit_object(&self, dst: &Path) -> Result<(), LLVMString> {
+        self.emit_file(dst, llvm::CodeGenFileType::ObjectFile)
+    }
+
+    /// Writes the target specific assembly of this module to `dst`
+    pub fn emit_asm(&self, dst: &Path) -> Result<(), LLVMString> {
+        self.emit_file(dst, llvm::CodeGenFileType::AssemblyFile)
+    }
+
+    /// Writes the (textual) LLVM IR of this module to `dst`
+    pub fn emit_llvm_ir(&self, dst: &Path) -> Result<(), LLVMString> {
+        let path = CString::new(dst.to_str().unwrap()).unwrap();
+        let mut err_string = MaybeUninit::uninit();
+        let return_code = unsafe {
+            llvm::LLVMPrintModuleToFile(self.llmod(), path.as_ptr(), err_string.as_mut_ptr())
+        };
+
+        if return_code == llvm::True {
+            unsafe {
+                return Err(LLVMString::new(err_string.assume_init()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the LLVM bitcode of this module to `dst`
+    pub fn emit_llvm_bc(&self, dst: &Path) -> Result<(), String> {
+        let path = CString::new(dst.to_str().unwrap()).unwrap();
+        let return_code = unsafe { llvm::LLVMWriteBitcodeToFile(self.llmod(), path.as_ptr()) };
+        if return_code != 0 {
+            return Err(format!("failed to write bitcode to {}", dst.display()));
+        }
+        Ok(())
+    }
+
+    fn emit_file(&self, dst: &Path, file_type: llvm::CodeGenFileType) -> Result<(), LLVMString> {
         let path = CString::new(dst.to_str().unwrap()).unwrap();
 
         let mut err_string = MaybeUninit::uninit();
@@ -254,7 +310,7 @@ impl ModuleLlvm {
                 self.tm,
                 self.llmod(),
                 path.as_ptr(),
-                llvm::CodeGenFileType::ObjectFile,
+                file_type,
                 err_string.as_mut_ptr(),
             )
         };
@@ -0,0 +1,215 @@
+use ahash::AHashMap;
+use mir::{
+    strip_optbarrier, Block, Callee, FuncRef, Function, InlineHint, Inst, InstructionData, PhiMap,
+    PhiNode, SourceLoc, Value, ValueDef, ValueList,
+};
+use stdx::iter::zip;
+
+#[cfg(test)]
+mod tests;
+
+/// Functions with at most this many instructions are inlined
+/// if no explicit [`InlineHint`] was provided.
+pub const INLINE_THRESHOLD: usize = 64;
+
+fn should_inline(callee: &Callee, num_call_sites: usize) -> bool {
+    // the function references of a callee are meaningless inside of the caller
+    let func = callee.func;
+    let has_calls = func
+        .layout
+        .blocks()
+        .any(|bb| func.layout.block_insts(bb).any(|inst| func.dfg.insts[inst].opcode().is_call()));
+    if has_calls {
+        return false;
+    }
+
+    match callee.hint {
+        InlineHint::Always => true,
+        InlineHint::Never => false,
+        InlineHint::Auto => {
+            let num_insts: usize =
+                func.layout.blocks().map(|bb| func.layout.block_insts(bb).count()).sum();
+            num_call_sites == 1 || num_insts <= INLINE_THRESHOLD
+        }
+    }
+}
+
+/// Replaces calls within `func` with the body of the called function.
+///
+/// `callees` returns the body of the function called by a `FuncRef` (or `None` for
+/// callbacks that can not be inlined). Whether a call is inlined is decided by the
+/// [`InlineHint`] of the callee. Without an explicit hint small functions and functions
+/// that are only called once are inlined. Callees that contain calls themselves are never
+/// inlined.
+///
+/// The inlined code is placed between the block containing the call and a newly created block
+/// that contains all instructions after the call. Dead code (like the optbarriers of the returned
+/// values) is left behind for a following DCE pass.
+pub fn inline_calls<'a>(
+    func: &mut Function,
+    mut callees: impl FnMut(FuncRef) -> Option<Callee<'a>>,
+) {
+    let mut call_sites = Vec::new();
+    let mut num_call_sites: AHashMap<FuncRef, usize> = AHashMap::new();
+    for bb in func.layout.blocks() {
+        for inst in func.layout.block_insts(bb) {
+            if let InstructionData::Call { func_ref, .. } = func.dfg.insts[inst] {
+                call_sites.push((inst, func_ref));
+                *num_call_sites.entry(func_ref).or_default() += 1;
+            }
+        }
+    }
+
+    for (call, func_ref) in call_sites {
+        if let Some(callee) = callees(func_ref) {
+            if should_inline(&callee, num_call_sites[&func_ref]) {
+                inline_call(func, call, callee);
+            }
+        }
+    }
+}
+
+fn inline_call(func: &mut Function, call: Inst, callee: Callee) {
+    let bb = func.layout.inst_block(call).expect("call is attached");
+    let srcloc = func.srclocs.get(call).copied().unwrap_or_default();
+
+    // everything after the call is moved to a new block where the inlined function returns to
+    let cont = func.layout.make_block();
+    if let Some(next_inst) = func.layout.next_inst(call) {
+        func.split_block(cont, next_inst);
+    } else {
+        func.layout.insert_block_after(cont, bb);
+    }
+
+    let args = func.dfg.instr_args(call).to_vec();
+    let results = func.dfg.inst_results(call).to_vec();
+    func.dfg.zap_inst(call);
+    func.layout.remove_inst(call);
+
+    let mut inliner = Inliner {
+        func,
+        callee: callee.func,
+        args: &args,
+        srcloc,
+        val_map: AHashMap::new(),
+        block_map: AHashMap::new(),
+    };
+
+    let mut pos = bb;
+    for callee_bb in callee.func.layout.blocks() {
+        let new_bb = inliner.func.layout.make_block();
+        inliner.func.layout.insert_block_after(new_bb, pos);
+        inliner.block_map.insert(callee_bb, new_bb);
+        pos = new_bb;
+    }
+
+    let entry = inliner.block_map[&callee.func.layout.entry_block().unwrap()];
+    inliner.jump(bb, entry);
+
+    for callee_bb in callee.func.layout.blocks() {
+        let new_bb = inliner.block_map[&callee_bb];
+        for inst in callee.func.layout.block_insts(callee_bb) {
+            inliner.copy_inst(inst, new_bb);
+        }
+    }
+
+    // the exit block of a function has no terminator
+    let exit = inliner.block_map[&callee.func.layout.last_block().unwrap()];
+    inliner.jump(exit, cont);
+
+    for (&res, &ret) in zip(&results, callee.returns) {
+        let ret = strip_optbarrier(callee.func, ret);
+        let val = inliner.map_val(ret);
+        inliner.func.dfg.replace_uses(res, val);
+    }
+}
+
+struct Inliner<'a> {
+    func: &'a mut Function,
+    callee: &'a Function,
+    args: &'a [Value],
+    srcloc: SourceLoc,
+    val_map: AHashMap<Value, Value>,
+    block_map: AHashMap<Block, Block>,
+}
+
+impl Inliner<'_> {
+    fn map_val(&mut self, val: Value) -> Value {
+        if let Some(&mapped) = self.val_map.get(&val) {
+            return mapped;
+        }
+        let mapped = match self.callee.dfg.value_def(val) {
+            ValueDef::Param(param) => self.args[usize::from(param)],
+            ValueDef::Const(val) => self.func.dfg.values.make_const(val),
+            // forward reference that is replaced by the result of the instruction
+            // once it is copied
+            ValueDef::Result(..) | ValueDef::Invalid => self.func.dfg.make_invalid_value(),
+        };
+        self.val_map.insert(val, mapped);
+        mapped
+    }
+
+    fn copy_inst(&mut self, inst: Inst, bb: Block) {
+        let callee = self.callee;
+        let data = match callee.dfg.insts[inst] {
+            InstructionData::PhiNode(ref phi) => {
+                let mut phi_args = ValueList::new();
+                let mut blocks = PhiMap::new();
+                for (i, (pred, val)) in callee.dfg.phi_edges(phi).enumerate() {
+                    let val = self.map_val(val);
+                    phi_args.push(val, &mut self.func.dfg.insts.value_lists);
+                    blocks.insert(
+                        self.block_map[&pred],
+                        i as u32,
+                        &mut self.func.dfg.phi_forest,
+                        &(),
+                    );
+                }
+                PhiNode { args: phi_args, blocks }.into()
+            }
+            ref data => {
+                let args: Vec<_> =
+                    callee.dfg.instr_args(inst).iter().map(|&val| self.map_val(val)).collect();
+                let mut data = data.to_pool(
+                    &callee.dfg.insts.value_lists,
+                    &callee.dfg.phi_forest,
+                    &mut self.func.dfg.insts.value_lists,
+                    &mut self.func.dfg.phi_forest,
+                );
+                match &mut data {
+                    InstructionData::Branch { then_dst, else_dst, .. } => {
+                        *then_dst = self.block_map[then_dst];
+                        *else_dst = self.block_map[else_dst];
+                    }
+                    InstructionData::Jump { destination } => {
+                        *destination = self.block_map[destination];
+                    }
+                    _ => (),
+                }
+                data.arguments_mut(&mut self.func.dfg.insts.value_lists).copy_from_slice(&args);
+                data
+            }
+        };
+
+        let results: Vec<_> =
+            callee.dfg.inst_results(inst).iter().map(|&val| Some(self.map_val(val))).collect();
+        let new_inst = self.func.dfg.make_inst(data);
+        self.func.dfg.make_inst_results_reusing(new_inst, results.into_iter());
+        self.append_inst(new_inst, bb);
+    }
+
+    fn jump(&mut self, bb: Block, destination: Block) {
+        let inst = self.func.dfg.make_inst(InstructionData::Jump { destination });
+        self.append_inst(inst, bb);
+    }
+
+    fn append_inst(&mut self, inst: Inst, bb: Block) {
+        self.func.layout.append_inst_to_bb(inst, bb);
+        if self.func.srclocs.len() <= inst.into() {
+            self.func.srclocs.resize(inst.into(), SourceLoc::default());
+            self.func.srclocs.push(self.srcloc);
+        } else {
+            self.func.srclocs[inst] = self.srcloc;
+        }
+    }
+}
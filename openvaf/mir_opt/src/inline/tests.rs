@@ -0,0 +1,121 @@
+use expect_test::{expect, Expect};
+use mir::{Callee, InlineHint, Value};
+use mir_reader::parse_functions;
+
+use crate::inline_calls;
+
+fn check(src: &str, returns: &[&[u32]], hints: &[InlineHint], expect: Expect) {
+    let (mut funcs, _) = parse_functions(src).unwrap();
+    let mut func = funcs.remove(0);
+    let returns: Vec<Vec<Value>> =
+        returns.iter().map(|vals| vals.iter().map(|&val| val.into()).collect()).collect();
+    inline_calls(&mut func, |func_ref| {
+        let i = usize::from(func_ref);
+        Some(Callee { func: &funcs[i], returns: &returns[i], hint: hints[i] })
+    });
+    expect.assert_eq(&func.to_debug_string());
+}
+
+#[test]
+fn inline_branches() {
+    let src = r#"
+        function %bar(v10, v11) {
+            fn0 = const fn %clamp(2) -> 1
+
+        block0:
+            v12 = fmul v10, v11
+            v13 = call fn0 (v12, v11)
+            v14 = fadd v13, v10
+        }
+
+        function %clamp(v10, v11) {
+        block0:
+            v12 = fgt v10, v11
+            br v12, block1, block2
+
+        block1:
+            jmp block2
+
+        block2:
+            v13 = phi [v10, block0], [v11, block1]
+            v14 = optbarrier v13
+        }
+    "#;
+    let expect = expect![[r#"
+        function %bar(v10, v11) {
+            inst0 = const fn %clamp(2) -> 1
+
+        block0:
+            v12 = fmul v10, v11
+            jmp block2
+
+        block2:
+            v15 = fgt v12, v11
+            br v15, block3, block4
+
+        block3:
+            jmp block4
+
+        block4:
+            v16 = phi [v12, block2], [v11, block3]
+            v17 = optbarrier v16
+            jmp block1
+
+        block1:
+            v14 = fadd v16, v10
+        }
+    "#]];
+    check(src, &[&[14]], &[InlineHint::Auto], expect)
+}
+
+#[test]
+fn inline_hints() {
+    let src = r#"
+        function %bar(v10, v11) {
+            fn0 = const fn %sum_diff(2) -> 2
+            fn1 = const fn %square(1) -> 1
+
+        block0:
+            v12, v13 = call fn0 (v10, v11)
+            v14 = call fn1 (v12)
+            v15 = call fn1 (v13)
+            v16 = fmul v14, v15
+        }
+
+        function %sum_diff(v10, v11) {
+        block0:
+            v12 = fadd v10, v11
+            v13 = fsub v10, v11
+            v14 = optbarrier v12
+            v15 = optbarrier v13
+        }
+
+        function %square(v10) {
+        block0:
+            v11 = fmul v10, v10
+            v12 = optbarrier v11
+        }
+    "#;
+    let expect = expect![[r#"
+        function %bar(v10, v11) {
+            inst0 = const fn %sum_diff(2) -> 2
+            inst1 = const fn %square(1) -> 1
+
+        block0:
+            jmp block2
+
+        block2:
+            v17 = fadd v10, v11
+            v18 = fsub v10, v11
+            v19 = optbarrier v17
+            v20 = optbarrier v18
+            jmp block1
+
+        block1:
+            v14 = call fn1(v17)
+            v15 = call fn1(v18)
+            v16 = fmul v14, v15
+        }
+    "#]];
+    check(src, &[&[14, 15], &[12]], &[InlineHint::Always, InlineHint::Never], expect)
+}
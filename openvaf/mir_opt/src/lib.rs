@@ -3,7 +3,9 @@ mod const_prop;
 mod dead_code;
 mod dead_code_aggressive;
 mod global_value_numbering;
+mod inline;
 mod inst_combine;
+mod loop_invariant_code_motion;
 mod simplify;
 mod simplify_cfg;
 mod split_tainted;
@@ -12,6 +14,8 @@ pub use const_prop::sparse_conditional_constant_propagation;
 pub use dead_code::dead_code_elimination;
 pub use dead_code_aggressive::aggressive_dead_code_elimination;
 pub use global_value_numbering::{ClassId, GVN};
+pub use inline::{inline_calls, INLINE_THRESHOLD};
 pub use inst_combine::inst_combine;
+pub use loop_invariant_code_motion::loop_invariant_code_motion;
 pub use simplify_cfg::{simplify_cfg, simplify_cfg_no_phi_merge};
 pub use split_tainted::{propagate_direct_taint, propagate_taint};
@@ -0,0 +1,123 @@
+use bitset::BitSet;
+use mir::{Block, ControlFlowGraph, DominatorTree, Function, Inst, Opcode, ValueDef};
+
+#[cfg(test)]
+mod tests;
+
+/// Moves instructions that compute the same value in every iteration of a loop
+/// (all operands are defined outside of the loop) into the preheader of the loop.
+///
+/// Loops are detected as the natural loops of backedges (edges to a block that dominates its
+/// predecessor), so `dom_tree` must be up to date with `func` and `cfg`. Inner loops are
+/// processed before the loops containing them so that invariants are moved as far out as
+/// possible. Loops without a unique preheader (a predecessor of the header outside of the loop
+/// that has only the header as its successor) are left untouched.
+pub fn loop_invariant_code_motion(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    dom_tree: &DominatorTree,
+) {
+    let loop_blocks = BitSet::new_empty(func.layout.num_blocks());
+    let mut licm = LoopInvariantCodeMotion { func, cfg, dom_tree, loop_blocks, stack: Vec::new() };
+
+    // the postorder visits inner loop headers before the headers of the enclosing loop
+    for &header in dom_tree.cfg_postorder() {
+        licm.hoist_loop(header);
+    }
+}
+
+struct LoopInvariantCodeMotion<'a> {
+    func: &'a mut Function,
+    cfg: &'a ControlFlowGraph,
+    dom_tree: &'a DominatorTree,
+    loop_blocks: BitSet<Block>,
+    stack: Vec<Block>,
+}
+
+impl LoopInvariantCodeMotion<'_> {
+    fn hoist_loop(&mut self, header: Block) {
+        if !self.compute_loop_blocks(header) {
+            return;
+        }
+
+        let preheader = if let Some(preheader) = self.preheader(header) {
+            preheader
+        } else {
+            return;
+        };
+        let insert_pos = self.func.layout.block_terminator(preheader).unwrap();
+
+        // visiting the blocks in reverse postorder ensures that all (non phi) operands
+        // defined inside the loop are visited before their uses
+        for &bb in self.dom_tree.cfg_postorder().iter().rev() {
+            if !self.loop_blocks.contains(bb) {
+                continue;
+            }
+
+            let mut cursor = self.func.layout.block_inst_cursor(bb);
+            while let Some(inst) = cursor.next(&self.func.layout) {
+                if self.is_invariant(inst) {
+                    self.func.layout.remove_inst(inst);
+                    self.func.layout.prepend_inst(inst, insert_pos);
+                }
+            }
+        }
+    }
+
+    /// Computes the blocks of the natural loop with the header `header`.
+    /// Returns `false` if `header` is not a loop header.
+    fn compute_loop_blocks(&mut self, header: Block) -> bool {
+        self.loop_blocks.clear();
+        for pred in self.cfg.pred_iter(header) {
+            if self.dom_tree.dominates(pred, header) {
+                self.stack.push(pred);
+            }
+        }
+
+        if self.stack.is_empty() {
+            return false;
+        }
+
+        self.loop_blocks.insert(header);
+        while let Some(bb) = self.stack.pop() {
+            if self.loop_blocks.insert(bb) {
+                self.stack.extend(self.cfg.pred_iter(bb));
+            }
+        }
+
+        true
+    }
+
+    fn preheader(&self, header: Block) -> Option<Block> {
+        let mut outside_preds =
+            self.cfg.pred_iter(header).filter(|bb| !self.loop_blocks.contains(*bb));
+        let preheader = outside_preds.next()?;
+        if outside_preds.next().is_some() || self.cfg.unique_succ(preheader) != Some(header) {
+            return None;
+        }
+        Some(preheader)
+    }
+
+    fn is_invariant(&self, inst: Inst) -> bool {
+        match self.func.dfg.insts[inst].opcode() {
+            // control flow, phis and callbacks (which may have sideeffects or depend on
+            // the simulator state) are never moved
+            Opcode::Br | Opcode::Jmp | Opcode::Phi | Opcode::Call | Opcode::OptBarrier => {
+                return false
+            }
+            // integer division by zero is undefined behaviour. The division may be guarded
+            // by a condition inside the loop so it can not be executed speculatively
+            Opcode::Idiv | Opcode::Irem => return false,
+            _ => (),
+        }
+
+        self.func.dfg.instr_args(inst).iter().all(|&arg| match self.func.dfg.value_def(arg) {
+            ValueDef::Result(def, _) => {
+                let bb = self.func.layout.inst_block(def).unwrap();
+                !self.loop_blocks.contains(bb)
+            }
+            ValueDef::Param(_) | ValueDef::Const(_) => true,
+            ValueDef::Invalid => false,
+        })
+    }
+}
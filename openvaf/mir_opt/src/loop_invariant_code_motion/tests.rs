@@ -0,0 +1,201 @@
+use expect_test::{expect, Expect};
+use mir::{ControlFlowGraph, DominatorTree};
+use mir_reader::parse_function;
+
+use crate::loop_invariant_code_motion;
+
+fn check(src: &str, expect: Expect) {
+    let (mut func, _) = parse_function(src).unwrap();
+    let mut cfg = ControlFlowGraph::new();
+    cfg.compute(&func);
+    let mut dom_tree = DominatorTree::default();
+    dom_tree.compute(&func, &cfg, true, false, false);
+    loop_invariant_code_motion(&mut func, &cfg, &dom_tree);
+    expect.assert_eq(&func.to_debug_string());
+}
+
+#[test]
+fn while_loop() {
+    let src = r#"
+        function %bar(v10, v11, v12) {
+            v3 = fconst 0.0
+            v4 = fconst 0x1.0000000000000p0
+
+        block0:
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v23, block2]
+            v21 = flt v20, v12
+            br v21, block2[loop], block3
+
+        block2:
+            v22 = fmul v10, v11
+            v24 = exp v22
+            v25 = fmul v24, v20
+            v23 = fadd v25, v4
+            jmp block1
+
+        block3:
+            v26 = fadd v20, v4
+        }
+    "#;
+    let expect = expect![[r#"
+        function %bar(v10, v11, v12) {
+            v3 = fconst 0.0
+            v4 = fconst 0x1.0000000000000p0
+        block0:
+            v22 = fmul v10, v11
+            v24 = exp v22
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v23, block2]
+            v21 = flt v20, v12
+            br v21, block2[loop], block3
+
+        block2:
+            v25 = fmul v24, v20
+            v23 = fadd v25, v4
+            jmp block1
+
+        block3:
+            v26 = fadd v20, v4
+        }
+    "#]];
+    check(src, expect)
+}
+
+#[test]
+fn nested_loop() {
+    let src = r#"
+        function %bar(v10, v11, v12) {
+            v3 = fconst 0.0
+            v4 = fconst 0x1.0000000000000p0
+
+        block0:
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v30, block5]
+            v21 = flt v20, v12
+            br v21, block2[loop], block6
+
+        block2:
+            jmp block3
+
+        block3:
+            v30 = phi [v20, block2], [v33, block4]
+            v31 = flt v30, v11
+            br v31, block4[loop], block5
+
+        block4:
+            v32 = fmul v10, v10
+            v34 = fmul v32, v20
+            v33 = fadd v34, v30
+            jmp block3
+
+        block5:
+            jmp block1
+
+        block6:
+            v26 = fadd v20, v4
+        }
+    "#;
+    let expect = expect![[r#"
+        function %bar(v10, v11, v12) {
+            v3 = fconst 0.0
+            v4 = fconst 0x1.0000000000000p0
+        block0:
+            v32 = fmul v10, v10
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v30, block5]
+            v21 = flt v20, v12
+            br v21, block2[loop], block6
+
+        block2:
+            v34 = fmul v32, v20
+            jmp block3
+
+        block3:
+            v30 = phi [v20, block2], [v33, block4]
+            v31 = flt v30, v11
+            br v31, block4[loop], block5
+
+        block4:
+            v33 = fadd v34, v30
+            jmp block3
+
+        block5:
+            jmp block1
+
+        block6:
+            v26 = fadd v20, v4
+        }
+    "#]];
+    check(src, expect)
+}
+
+#[test]
+fn guarded_division() {
+    let src = r#"
+        function %bar(v10, v11) {
+            v3 = iconst 0
+            v4 = iconst 1
+
+        block0:
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v23, block3]
+            v21 = ilt v20, v10
+            br v21, block2[loop], block4
+
+        block2:
+            v22 = ine v11, v3
+            br v22, block5, block3
+
+        block5:
+            v24 = idiv v10, v11
+            jmp block3
+
+        block3:
+            v23 = iadd v20, v4
+            jmp block1
+
+        block4:
+            v26 = iadd v20, v4
+        }
+    "#;
+    let expect = expect![[r#"
+        function %bar(v10, v11) {
+            v3 = iconst 0
+            v4 = iconst 1
+        block0:
+            v22 = ine v11, v3
+            jmp block1
+
+        block1:
+            v20 = phi [v3, block0], [v23, block3]
+            v21 = ilt v20, v10
+            br v21, block2[loop], block4
+
+        block2:
+            br v22, block5, block3
+
+        block5:
+            v24 = idiv v10, v11
+            jmp block3
+
+        block3:
+            v23 = iadd v20, v4
+            jmp block1
+
+        block4:
+            v26 = iadd v20, v4
+        }
+    "#]];
+    check(src, expect)
+}
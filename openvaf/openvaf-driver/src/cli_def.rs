@@ -5,7 +5,10 @@ use anyhow::bail;
 use camino::Utf8Path;
 use clap::builder::{PossibleValue, PossibleValuesParser, ValueParser};
 use clap::{Arg, ArgAction, Command, ValueHint};
-use openvaf::{builtin_lints, get_target_names, host_triple, LintLevel};
+use openvaf::{
+    builtin_lints, get_target_names, host_triple, AutoDiffMode, Backend, EmitKind, FloatPolicy,
+    LinkerKind, LintLevel, Syntax, Target,
+};
 use path_absolutize::Absolutize;
 
 const ABOUT: &str = r"For further information visit https://openvaf.semimod.de.";
@@ -26,21 +29,93 @@ pub fn main_command() -> Command {
             output(),
             batchmode(),
             dry_run(),
-            cache_dir(),
+            cache_dir().requires(BATCHMODE),
+            cache_size().requires(BATCHMODE),
             opt_lvl(),
             target(),
             supported_targets(),
             target_cpu(),
             codegen_opts(),
+            linker(),
+            backend(),
+            reproducible(),
+            autodiff(),
+            autodiff_cse(),
+            fp_policy(),
+            check_fp(),
+            debug_info(),
+            symbolic_delays(),
+            sensitivity(),
+            hessian(),
+            module(),
+            split_modules(),
             interface(),
-            expand(),
             dump_json(),
+            emit(),
+            expand(),
+            dump_mir_json(),
+            manifest(),
             input(),
         ])
+        .subcommand(cache_command())
+        .subcommand(interpret_command())
+        .subcommand(equations_command())
         .subcommand_required(false)
+        .subcommand_negates_reqs(true)
         .arg_required_else_help(true)
 }
 
+fn cache_command() -> Command {
+    Command::new(CACHE)
+        .about("Manage the cache used in batchmode.")
+        .arg(cache_dir().global(true))
+        .subcommand(Command::new(CACHE_LIST).about("List all libraries in the cache."))
+        .subcommand(
+            Command::new(CACHE_CLEAN)
+                .about("Remove libraries from the cache.")
+                .long_about("Remove libraries from the cache.\nBy default all libraries are removed. If --cache-size is specified only the least\nrecently used libraries are removed until the cache is smaller than the given size.")
+                .arg(cache_size()),
+        )
+        .subcommand(
+            Command::new(CACHE_VERIFY)
+                .about("Check the integrity of the cache and remove broken libraries."),
+        )
+        .subcommand_required(true)
+}
+
+fn interpret_command() -> Command {
+    Command::new(INTERPRET)
+        .about("Evaluate a model with the MIR interpreter instead of compiling it.")
+        .long_about("Evaluate a model with the MIR interpreter instead of compiling it.\nThe model parameters are initialized, the instance is set up and eval is called once\n(DC operating point, limiting disabled). The residual, jacobian and operating point\nvariables are printed for every module. No native toolchain is required, which makes\nthis useful to cross-check the generated code and to debug models.")
+        .args([
+            def_arg(),
+            include_dir(),
+            module(),
+            autodiff(),
+            autodiff_cse(),
+            param(),
+            bias(),
+            temperature(),
+            input_file_path_arg(INPUT).help("The root Verilog-A file.").required(true),
+        ])
+}
+
+fn equations_command() -> Command {
+    Command::new(EQUATIONS)
+        .about("Print the equations of a model as readable expressions.")
+        .long_about("Print the equations of a model as readable expressions.\nThe residual and the nonzero jacobian entries of every module are printed after all\noptimizations. Values that only depend on parameters are computed during instance setup\nand printed first (as cache[<slot>]). Intermediate values that are used more than once\nare assigned to temporaries.")
+        .args([
+            def_arg(),
+            include_dir(),
+            module(),
+            autodiff(),
+            autodiff_cse(),
+            syntax(),
+            unknown(),
+            input_file_path_arg(INPUT).help("The root Verilog-A file.").required(true),
+        ])
+}
+
 pub const INTERFACE: &str = "interface";
 pub const BATCHMODE: &str = "batchmode";
 pub const DRYRUN: &str = "dry-run";
@@ -49,14 +124,42 @@ pub const SUPPORTED_TARGETS: &str = "supported-targets";
 pub const LINTS: &str = "lints";
 pub const TARGET_CPU: &str = "target_cpu";
 pub const CODEGEN: &str = "codegen";
+pub const LINKER: &str = "linker";
+pub const BACKEND: &str = "backend";
+pub const REPRODUCIBLE: &str = "reproducible";
+pub const AUTODIFF: &str = "autodiff";
+pub const AUTODIFF_CSE: &str = "autodiff-cse";
+pub const FP_POLICY: &str = "fp-policy";
+pub const CHECK_FP: &str = "check-fp";
+pub const DEBUG_INFO: &str = "debug-info";
+pub const SYMBOLIC_DELAYS: &str = "symbolic-delays";
+pub const SENSITIVITY: &str = "sensitivity";
+pub const HESSIAN: &str = "hessian";
+pub const MODULE: &str = "module";
+pub const SPLIT_MODULES: &str = "split-modules";
 pub const INPUT: &str = "input";
+pub const MANIFEST: &str = "manifest";
 pub const INCLUDE: &str = "include";
 pub const OUTPUT: &str = "output";
 pub const CACHE_DIR: &str = "cache-dir";
+pub const CACHE_SIZE: &str = "cache-size";
+pub const CACHE: &str = "cache";
+pub const CACHE_LIST: &str = "list";
+pub const CACHE_CLEAN: &str = "clean";
+pub const CACHE_VERIFY: &str = "verify";
+pub const INTERPRET: &str = "interpret";
+pub const PARAM: &str = "param";
+pub const BIAS: &str = "bias";
+pub const TEMPERATURE: &str = "temperature";
+pub const EQUATIONS: &str = "equations";
+pub const SYNTAX: &str = "syntax";
+pub const UNKNOWN: &str = "unknown";
 pub const OPT_LVL: &str = "opt_lvl";
 pub const DEFINE: &str = "define";
 pub const PRINT_EXPANSION: &str = "print-expansion";
 pub const DUMP_JSON: &str = "dump-json";
+pub const DUMP_MIR_JSON: &str = "dump-mir-json";
+pub const EMIT: &str = "emit";
 pub const ALLOW: &str = "allow";
 pub const WARN: &str = "warn";
 pub const DENY: &str = "deny";
@@ -74,6 +177,112 @@ fn interface() -> Arg {
         .required(false)
 }
 
+fn dump_json() -> Arg {
+    flag(DUMP_JSON, DUMP_JSON)
+        .help("Abort after lowering and write a JSON description of the model interface.")
+        .long_help("Abort after lowering and write a JSON description of the model interface.\nNo library is compiled. The file has the name of the output with the extension .json\nand lists the nodes, parameters (including defaults and bounds), operating point\nvariables, noise sources and collapsible node pairs of all compiled modules.")
+        .conflicts_with(BATCHMODE)
+}
+
+fn linker() -> Arg {
+    let internal = if LinkerKind::internal_available() {
+        "internal - LLD linked into openvaf, no C toolchain is required\n           (falls back to the external linker if LLD fails)"
+    } else {
+        "internal - LLD linked into openvaf (not available in this build,\n           the external linker is used instead)"
+    };
+    Arg::new(LINKER)
+        .long(LINKER)
+        .help("Linker used to create the shared library.")
+        .long_help(format!("Linker used to create the shared library.\n\npossible values:\nexternal - the system linker (ld, ld64 or link.exe)\n{internal}"))
+        .value_parser(PossibleValuesParser::new(LinkerKind::ALL.map(LinkerKind::name)))
+        .default_value(LinkerKind::External.name())
+        .value_name("LINKER")
+        .required(false)
+        .hide_possible_values(true)
+}
+
+fn backend() -> Arg {
+    Arg::new(BACKEND)
+        .long(BACKEND)
+        .help("Code generator used to compile the model.")
+        .long_help("Code generator used to compile the model.\n\npossible values:\nllvm - compile to a shared library (.osdi) with LLVM\nc - write portable C99 source code (.c) that implements the OSDI interface,\n    the output can be compiled into a shared library with any C compiler")
+        .value_parser(PossibleValuesParser::new(Backend::ALL.map(Backend::name)))
+        .default_value(Backend::Llvm.name())
+        .value_name("BACKEND")
+        .required(false)
+        .hide_possible_values(true)
+}
+
+fn reproducible() -> Arg {
+    flag(REPRODUCIBLE, REPRODUCIBLE)
+        .help("Produce byte-identical libraries on every machine.")
+        .long_help("Produce byte-identical libraries on every machine.\nCode is generated for the generic CPU of the target (instead of the host CPU) and\nthe linker is instructed not to embed timestamps or build ids.\nCan not be combined with -g or --check-fp because both embed the absolute paths of the\nsource files.")
+        .conflicts_with_all([TARGET_CPU, DEBUG_INFO, CHECK_FP])
+}
+
+fn autodiff() -> Arg {
+    Arg::new(AUTODIFF)
+        .long(AUTODIFF)
+        .help("How derivatives are computed.")
+        .long_help("How derivatives are computed.\n\npossible values:\nforward - derivatives are propagated forward one unknown at a time\nreverse - values that depend on many unknowns are differentiated in a single reverse sweep")
+        .value_parser(PossibleValuesParser::new(AutoDiffMode::ALL.map(AutoDiffMode::name)))
+        .default_value(AutoDiffMode::Forward.name())
+        .value_name("MODE")
+        .required(false)
+        .hide_possible_values(true)
+}
+
+fn autodiff_cse() -> Arg {
+    flag(AUTODIFF_CSE, AUTODIFF_CSE)
+        .help("Remove redundant instructions after derivatives were computed.")
+        .long_help("Remove redundant instructions after derivatives were computed.\nIdentical instructions created by automatic differentiation (like repeated chain rule\nproducts) are merged with global value numbering. Whether this reduces the size of the\ngenerated code or the compile time depends on the model.")
+}
+
+fn fp_policy() -> Arg {
+    Arg::new(FP_POLICY)
+        .long(FP_POLICY)
+        .help("Which fast-math optimizations are applied to floating point operations.")
+        .long_help("Which fast-math optimizations are applied to floating point operations.\nIndividual analog functions can overwrite this with the fp_policy attribute:\n(* fp_policy=\"strict\" *) analog function real f;\n\npossible values:\nstrict - IEEE 754 semantics, no fast-math optimizations\npartial - compiler generated code (derivatives, matrix loads) may be reassociated\n          and contracted, NaN and Inf are always preserved\nfast - all fast-math optimizations everywhere, assumes NaN and Inf never occur")
+        .value_parser(PossibleValuesParser::new(FloatPolicy::ALL.map(FloatPolicy::name)))
+        .default_value(FloatPolicy::Partial.name())
+        .value_name("POLICY")
+        .required(false)
+        .hide_possible_values(true)
+}
+
+fn check_fp() -> Arg {
+    flag(CHECK_FP, CHECK_FP)
+        .help("Report the first NaN/Inf computed by the model (debug mode).")
+        .long_help("Report the first NaN/Inf computed by the model (debug mode).\nThe result of every floating point operation is checked during setup and evaluation.\nThe first NaN or Inf produced by each call to setup_model, setup_instance or eval is reported with the location of the\nVerilog-A expression that computed it through the log callback of the simulator.\nThis makes the model considerably slower and implies --fp-policy partial (or strict).")
+}
+
+fn debug_info() -> Arg {
+    flag(DEBUG_INFO, DEBUG_INFO)
+        .short('g')
+        .help("Generate debug information for the Verilog-A source.")
+        .long_help("Generate debug information for the Verilog-A source.\nDebuggers like gdb and lldb can set breakpoints on lines of the Verilog-A source and\nshow the values of variables while a simulator evaluates the model. Optimizations\nmay remove variables or reorder lines, combine with -O0 for the best experience.")
+}
+
+fn symbolic_delays() -> Arg {
+    flag(SYMBOLIC_DELAYS, SYMBOLIC_DELAYS)
+        .help("Export absdelay symbolically for frequency domain simulators.")
+        .long_help("Export absdelay symbolically for frequency domain simulators.\nEach absdelay(x, td) adds an implicit equation y - x = 0 (an additional unknown) and is\nlisted with its delay time in the delays table of the OSDI descriptor, so that harmonic\nbalance simulators can apply the delay as a phase shift. The simulator has to apply the\ndelay in every analysis. Without this flag the delay is ignored and the nodes of the\nmodel do not change.")
+}
+
+fn emit() -> Arg {
+    Arg::new(EMIT)
+        .long(EMIT)
+        .help("Write intermediate representations next to the output.")
+        .long_help("Write intermediate representations next to the output.\nFiles are named <output>.<unit>[.<stage>].<ext> where unit is a compilation unit\n(or module for mir) and stage is pre_opt/post_opt for llvm-ir, llvm-bc and mir.\nFor mir the final eval, init and model_param_setup functions are written as well.\n\npossible values: llvm-ir, llvm-bc, asm, obj, mir")
+        .value_parser(PossibleValuesParser::new(EmitKind::ALL.map(EmitKind::name)))
+        .value_delimiter(',')
+        .value_name("KIND")
+        .action(ArgAction::Append)
+        .conflicts_with(BATCHMODE)
+        .required(false)
+        .hide_possible_values(true)
+}
+
 fn batchmode() -> Arg {
     flag(BATCHMODE, "batch").short('b').help("Enable batchmode compilation.").
         long_help("Enable batchmode compilation. In this mode files are only recompiled when required and the results are stored")
@@ -95,9 +304,9 @@ fn target() -> Arg {
         .long(TARGET)
         .help("Target triple for which the code is compiled.")
         .long_help(format!(
-            "Target triple for which the code is compiled.\n\npossible values:\n{vals}"
+            "Target triple for which the code is compiled.\nThe environment can be omitted where it is implied by the os (gnu on linux, msvc\non windows).\n\npossible values:\n{vals}"
         ))
-        .value_parser(PossibleValuesParser::new(get_target_names()))
+        .value_parser(ValueParser::new(target_triple))
         .default_value(host_triple())
         .value_name("TARGET")
         .required(false)
@@ -105,6 +314,13 @@ fn target() -> Arg {
         .hide_possible_values(true)
 }
 
+fn target_triple(raw: &str) -> anyhow::Result<String> {
+    match Target::search(raw) {
+        Some(_) => Ok(raw.to_owned()),
+        None => bail!("unsupported target (see --supported-targets)"),
+    }
+}
+
 fn supported_targets() -> Arg {
     flag(SUPPORTED_TARGETS, SUPPORTED_TARGETS)
         .help("Print target triples supported by OpenVAF.")
@@ -141,10 +357,135 @@ fn codegen_opts() -> Arg {
         .value_hint(ValueHint::Other)
 }
 
+fn sensitivity() -> Arg {
+    Arg::new(SENSITIVITY)
+        .long(SENSITIVITY)
+        .help("Compute the derivatives of the residual by this parameter.")
+        .long_help("Compute the derivatives of the residual by this parameter.\nThe parameter sensitivities (resistive and reactive) are exposed with the\nload_sensitivity function of the OSDI interface and can be used for gradient\nbased parameter extraction. Only real parameters are supported.")
+        .value_name("PARAM")
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn hessian() -> Arg {
+    Arg::new(HESSIAN)
+        .long(HESSIAN)
+        .help("Compute the second order derivatives of the residual of this node.")
+        .long_help("Compute the second order derivatives of the residual of this node.\nThe second order jacobian (resistive and reactive) by all pairs of unknowns is\nexposed with the load_hessian function of the OSDI interface and can be used for\ndistortion (Volterra) analysis. Only the Kirchhoff current law row of the node is\ndifferentiated (not the equations of voltage sources or implicit equations).")
+        .value_name("NODE")
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn module() -> Arg {
+    Arg::new(MODULE)
+        .long(MODULE)
+        .help("Only compile the module with this name.")
+        .long_help("Only compile the module with this name.\nCan be specified multiple times to compile multiple modules. By default all\nmodules are compiled.")
+        .value_name("NAME")
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn param() -> Arg {
+    Arg::new(PARAM)
+        .long(PARAM)
+        .short('p')
+        .help("Set a parameter of the model.")
+        .long_help("Set a parameter of the model.\nParameters that are not set use their default value. Builtin parameters are\ncalled $mfactor, $xposition, ...")
+        .value_name("NAME=VALUE")
+        .value_parser(ValueParser::new(assignment))
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn bias() -> Arg {
+    Arg::new(BIAS)
+        .long(BIAS)
+        .short('b')
+        .help("Set the potential of a node.")
+        .long_help("Set the potential of a node.\nAll other nodes are at 0V. Branch currents can be set with I(<branch>).")
+        .value_name("NODE=VALUE")
+        .value_parser(ValueParser::new(real_assignment))
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn temperature() -> Arg {
+    Arg::new(TEMPERATURE)
+        .long(TEMPERATURE)
+        .help("Temperature of the device in Kelvin.")
+        .long_help("Temperature of the device in Kelvin.\nDefaults to 300.15K (27°C).")
+        .value_name("KELVIN")
+        .value_parser(clap::value_parser!(f64))
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn syntax() -> Arg {
+    Arg::new(SYNTAX)
+        .long(SYNTAX)
+        .help("Language in which the equations are printed.")
+        .long_help("Language in which the equations are printed.\n\npossible values:\nverilog-a - Verilog-A expressions\nc - C99 expressions (names are turned into identifiers)\nnumpy - Python expressions using numpy\nlatex - LaTeX math")
+        .value_parser(PossibleValuesParser::new(Syntax::ALL.map(Syntax::name)))
+        .default_value(Syntax::VerilogA.name())
+        .value_name("SYNTAX")
+        .required(false)
+        .hide_possible_values(true)
+}
+
+fn unknown() -> Arg {
+    Arg::new(UNKNOWN)
+        .long(UNKNOWN)
+        .help("Only print the equations of this unknown.")
+        .long_help("Only print the equations of this unknown.\nThe residual and the jacobian row of the unknown are printed. Can be specified\nmultiple times. Branch currents are called I(<branch>).")
+        .value_name("NAME")
+        .action(ArgAction::Append)
+        .required(false)
+        .value_hint(ValueHint::Other)
+}
+
+fn assignment(raw: &str) -> anyhow::Result<(String, String)> {
+    match raw.split_once('=') {
+        Some((name, val)) => Ok((name.trim().to_owned(), val.trim().to_owned())),
+        None => bail!("expected NAME=VALUE"),
+    }
+}
+
+fn real_assignment(raw: &str) -> anyhow::Result<(String, f64)> {
+    let (name, val) = assignment(raw)?;
+    match val.parse() {
+        Ok(val) => Ok((name, val)),
+        Err(err) => bail!("invalid value \"{val}\": {err}"),
+    }
+}
+
+fn split_modules() -> Arg {
+    flag(SPLIT_MODULES, SPLIT_MODULES)
+        .help("Compile one library per module.")
+        .long_help("Compile one library per module.\nThe libraries are named <module>.osdi and placed in the directory of the output.")
+        .conflicts_with(BATCHMODE)
+}
+
 fn input() -> Arg {
     input_file_path_arg(INPUT)
-        .help("The root Verilog-A file.")
-        .required_unless_present_any([LINTS, SUPPORTED_TARGETS])
+        .help("The root Verilog-A file(s).")
+        .long_help("The root Verilog-A file(s).\nMultiple files are compiled in parallel and a summary is printed at the end.")
+        .action(ArgAction::Append)
+        .required_unless_present_any([LINTS, SUPPORTED_TARGETS, MANIFEST])
+}
+
+fn manifest() -> Arg {
+    input_file_path_arg(MANIFEST)
+        .long(MANIFEST)
+        .help("Compile all root files listed in this file.")
+        .long_help("Compile all root files listed in this file.\nThe file contains one path per line (relative to the directory of the manifest).\nEmpty lines and lines starting with # are ignored.")
+        .required(false)
 }
 
 fn include_dir() -> Arg {
@@ -174,7 +515,17 @@ fn cache_dir() -> Arg {
         .long(CACHE_DIR)
         .help("Directory where artifacts are stored in batchmode.")
         .required(false)
-        .requires("batchmode")
+}
+
+fn cache_size() -> Arg {
+    Arg::new(CACHE_SIZE)
+        .long(CACHE_SIZE)
+        .help("Maximum size of the cache in MiB.")
+        .long_help("Maximum size of the cache in MiB.\nWhen the libraries stored in the cache exceed this size, the least recently used\nlibraries are removed. Defaults to 1024 MiB.")
+        .value_name("MIB")
+        .value_parser(clap::value_parser!(u64))
+        .required(false)
+        .value_hint(ValueHint::Other)
 }
 
 fn dir_path_arg(name: &'static str) -> Arg {
@@ -267,8 +618,11 @@ directives (`include) resolved is emitted to stdout.",
         )
 }
 
-fn dump_json() -> Arg {
-    flag(DUMP_JSON, "dump-json").help("Abort after lowering and serialize MIR as json.")
+fn dump_mir_json() -> Arg {
+    flag(DUMP_MIR_JSON, DUMP_MIR_JSON)
+        .help("Abort after lowering and serialize MIR as json.")
+        .long_help("Abort after lowering and serialize the MIR of the operating point function as json.\nFor every module the file <output>_<module>.json is written next to the output.\nIt contains the control flow graph, instructions and values of the function together\nwith its inputs (parameters, voltages, currents, temperature, ...), outputs (all variables)\nand the callbacks it calls (ddt, ddx, noise sources, $limit, ...).")
+        .conflicts_with(BATCHMODE)
 }
 
 fn def_arg() -> Arg {
@@ -1,19 +1,30 @@
+use std::fs;
 use std::io::Write;
 use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Context, Result};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::parser::ValueSource;
 use clap::ArgMatches;
-use openvaf::{builtin_lints, get_target_names, host_triple, AbsPathBuf, LintLevel, OptLevel};
+use openvaf::{
+    builtin_lints, equations, get_target_names, host_triple, interpret, AbsPathBuf, AutoDiffMode,
+    AutoDiffOptions, Backend, Cache, EmitKind, FloatPolicy, LinkerKind, LintLevel, OperatingPoint,
+    OptLevel, Syntax, DEFAULT_CACHE_SIZE,
+};
 use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
 
 use crate::cli_def::{
-    ALLOW, BATCHMODE, CACHE_DIR, CODEGEN, DEFINE, DENY, DRYRUN, INCLUDE, INPUT, LINTS, OPT_LVL,
-    OUTPUT, SUPPORTED_TARGETS, TARGET, TARGET_CPU, WARN,
+    ALLOW, AUTODIFF, AUTODIFF_CSE, BACKEND, BATCHMODE, BIAS, CACHE_CLEAN, CACHE_DIR, CACHE_LIST,
+    CACHE_SIZE, CACHE_VERIFY, CHECK_FP, CODEGEN, DEBUG_INFO, DEFINE, DENY, DRYRUN, DUMP_JSON, EMIT,
+    FP_POLICY, HESSIAN, INCLUDE, INPUT, LINKER, LINTS, MANIFEST, MODULE, OPT_LVL, OUTPUT, PARAM,
+    REPRODUCIBLE, SENSITIVITY, SPLIT_MODULES, SUPPORTED_TARGETS, SYMBOLIC_DELAYS, SYNTAX, TARGET,
+    TARGET_CPU, TEMPERATURE, UNKNOWN, WARN,
 };
-use crate::{CompilationDestination, Opts};
+use crate::{CompilationDestination, Opts, DATA_ERROR};
 
-pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
+/// Returns the options for every input (in order)
+pub fn matches_to_opts(matches: ArgMatches) -> Result<Vec<Opts>> {
     if matches.get_flag(LINTS) {
         print_lints();
         exit(0)
@@ -23,7 +34,19 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         exit(0)
     }
 
-    let input: Utf8PathBuf = matches.get_one::<Utf8PathBuf>(INPUT).unwrap().clone();
+    let mut inputs: Vec<Utf8PathBuf> = matches
+        .get_many::<Utf8PathBuf>(INPUT)
+        .map_or_else(Vec::new, |inputs| inputs.cloned().collect());
+    if let Some(manifest) = matches.get_one::<Utf8PathBuf>(MANIFEST) {
+        inputs.extend(read_manifest(manifest)?);
+    }
+    if inputs.is_empty() {
+        bail!("no input files");
+    }
+    let output_file = matches.get_one::<Utf8PathBuf>(OUTPUT);
+    if inputs.len() > 1 && output_file.is_some() {
+        bail!("--output can not be used with multiple input files");
+    }
 
     let mut lints = Vec::new();
 
@@ -38,49 +61,48 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         lints.extend(deny.map(|lint| (lint.to_owned(), LintLevel::Deny)));
     }
 
-    let output = if matches.get_flag(BATCHMODE) {
-        let cache_dir = if let Some(val) = matches.get_one::<Utf8PathBuf>(CACHE_DIR) {
-            val.clone()
-        } else {
-            let path = directories_next::ProjectDirs::from("com", "semimod", "openvaf")
-                .context(
-                    "failed to find cache directory\nhelp: use --cache-dir to specify it manually",
-                )?
-                .cache_dir()
-                .to_owned();
-            if let Ok(res) = Utf8PathBuf::from_path_buf(path) {
-                res
-            } else {
-                bail!(
-                    "failed to find cache directory\nhelp: use --cache-dir to specify it manually",
-                )
-            }
-        };
-        CompilationDestination::Cache { cache_dir }
+    let cache = if matches.get_flag(BATCHMODE) {
+        let cache_dir = cache_dir(&matches)?;
+        let max_size = cache_size(&matches).unwrap_or(DEFAULT_CACHE_SIZE);
+        Some(CompilationDestination::Cache { cache_dir, max_size })
     } else {
-        let lib_file = if let Some(output) = matches.get_one::<Utf8PathBuf>(OUTPUT) {
-            output.clone()
-        } else {
-            input.with_extension("osdi")
-        };
-
-        CompilationDestination::Path { lib_file }
+        None
     };
 
     let codegen_opts = matches
         .get_many::<String>(CODEGEN)
         .map_or_else(Vec::new, |values| values.cloned().collect());
 
-    let defines = matches
-        .get_many::<String>(DEFINE)
+    let sensitivities = matches
+        .get_many::<String>(SENSITIVITY)
         .map_or_else(Vec::new, |values| values.cloned().collect());
 
-    let include: Result<_> = matches.get_many::<Utf8PathBuf>(INCLUDE).map_or_else(
-        || Ok(Vec::new()),
-        |include| include.map(|path| Ok(AbsPathBuf::assert(path.canonicalize()?))).collect(),
-    );
+    let hessian = matches
+        .get_many::<String>(HESSIAN)
+        .map_or_else(Vec::new, |values| values.cloned().collect());
 
-    let include = include?;
+    let linker = matches.get_one::<String>(LINKER).unwrap();
+    let linker = LinkerKind::ALL.into_iter().find(|kind| kind.name() == linker).unwrap();
+
+    let backend = matches.get_one::<String>(BACKEND).unwrap();
+    let backend = Backend::ALL.into_iter().find(|kind| kind.name() == backend).unwrap();
+    if backend == Backend::C {
+        // the C source is compiled by the user, these options only affect LLVM
+        for arg in [TARGET, TARGET_CPU, FP_POLICY, DEBUG_INFO] {
+            if matches.value_source(arg) == Some(ValueSource::CommandLine) {
+                bail!("--{arg} is not supported by the C backend");
+            }
+        }
+    }
+
+    let modules = modules(&matches);
+
+    let emit = matches.get_many::<String>(EMIT).map_or_else(Vec::new, |values| {
+        values.filter_map(|val| EmitKind::ALL.into_iter().find(|kind| kind.name() == val)).collect()
+    });
+
+    let defines = defines(&matches);
+    let include = include(&matches)?;
 
     let opt_lvl = match &**matches.get_one::<String>(OPT_LVL).unwrap() {
         "0" => OptLevel::None,
@@ -92,7 +114,6 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
 
     let host = host_triple();
     let target = matches.get_one::<String>(TARGET).cloned().unwrap_or_else(|| host.to_owned());
-    let default_cpu = if host != target { "generic" } else { "native" };
 
     let target = if let Some(target) = openvaf::Target::search(&target) {
         target
@@ -100,22 +121,279 @@ pub fn matches_to_opts(matches: ArgMatches) -> Result<Opts> {
         // should never happened but helpful to provide support just in case
         bail!("The target {target} is not supported by  this binary")
     };
+    // compare the targets instead of the triples to account for aliases
+    let is_host =
+        openvaf::Target::search(host).map_or(false, |host| host.llvm_target == target.llvm_target);
+    let default_cpu = if is_host { "native" } else { "generic" };
 
     let target_cpu: String =
         matches.get_one(TARGET_CPU).cloned().unwrap_or_else(|| default_cpu.to_owned());
 
-    Ok(Opts {
+    let dry_run = matches.get_flag(DRYRUN);
+    let dump_json = matches.get_flag(DUMP_JSON);
+    let split_modules = matches.get_flag(SPLIT_MODULES);
+    let reproducible = matches.get_flag(REPRODUCIBLE);
+    let autodiff = autodiff(&matches);
+    let fp_policy = FloatPolicy::from_name(matches.get_one::<String>(FP_POLICY).unwrap()).unwrap();
+    let check_fp = matches.get_flag(CHECK_FP);
+    let debug_info = matches.get_flag(DEBUG_INFO);
+    let symbolic_delays = matches.get_flag(SYMBOLIC_DELAYS);
+    let lib_ext = match backend {
+        Backend::C => "c",
+        Backend::Llvm if target.options.is_like_wasm => "wasm",
+        Backend::Llvm => "osdi",
+    };
+    let opts = inputs
+        .into_iter()
+        .map(|input| {
+            let output = cache.clone().unwrap_or_else(|| {
+                let lib_file =
+                    output_file.cloned().unwrap_or_else(|| input.with_extension(lib_ext));
+                CompilationDestination::Path { lib_file }
+            });
+            Opts {
+                input,
+                lints: lints.clone(),
+                codegen_opts: codegen_opts.clone(),
+                defines: defines.clone(),
+                include: include.clone(),
+                output,
+                opt_lvl,
+                target: target.clone(),
+                target_cpu: target_cpu.clone(),
+                dry_run,
+                sensitivities: sensitivities.clone(),
+                hessian: hessian.clone(),
+                dump_json,
+                emit: emit.clone(),
+                modules: modules.clone(),
+                split_modules,
+                linker,
+                reproducible,
+                autodiff,
+                fp_policy,
+                check_fp,
+                debug_info,
+                symbolic_delays,
+                backend,
+            }
+        })
+        .collect();
+    Ok(opts)
+}
+
+fn autodiff(matches: &ArgMatches) -> AutoDiffOptions {
+    let mode = matches.get_one::<String>(AUTODIFF).unwrap();
+    AutoDiffOptions {
+        mode: AutoDiffMode::ALL.into_iter().find(|it| it.name() == mode).unwrap(),
+        cse: matches.get_flag(AUTODIFF_CSE),
+    }
+}
+
+fn modules(matches: &ArgMatches) -> Vec<String> {
+    matches.get_many::<String>(MODULE).map_or_else(Vec::new, |values| values.cloned().collect())
+}
+
+fn defines(matches: &ArgMatches) -> Vec<String> {
+    matches.get_many::<String>(DEFINE).map_or_else(Vec::new, |values| values.cloned().collect())
+}
+
+fn include(matches: &ArgMatches) -> Result<Vec<AbsPathBuf>> {
+    matches.get_many::<Utf8PathBuf>(INCLUDE).map_or_else(
+        || Ok(Vec::new()),
+        |include| include.map(|path| Ok(AbsPathBuf::assert(path.canonicalize()?))).collect(),
+    )
+}
+
+fn read_manifest(manifest: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let contents =
+        fs::read_to_string(manifest).with_context(|| format!("failed to read {manifest}"))?;
+    let dir = manifest.parent().unwrap_or_else(|| Utf8Path::new(""));
+    let inputs = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect();
+    Ok(inputs)
+}
+
+fn cache_dir(matches: &ArgMatches) -> Result<Utf8PathBuf> {
+    if let Some(val) = matches.get_one::<Utf8PathBuf>(CACHE_DIR) {
+        return Ok(val.clone());
+    }
+    let path = directories_next::ProjectDirs::from("com", "semimod", "openvaf")
+        .context("failed to find cache directory\nhelp: use --cache-dir to specify it manually")?
+        .cache_dir()
+        .to_owned();
+    if let Ok(res) = Utf8PathBuf::from_path_buf(path) {
+        Ok(res)
+    } else {
+        bail!("failed to find cache directory\nhelp: use --cache-dir to specify it manually")
+    }
+}
+
+fn cache_size(matches: &ArgMatches) -> Option<u64> {
+    matches.get_one::<u64>(CACHE_SIZE).map(|mib| mib.saturating_mul(1 << 20))
+}
+
+/// Executes `openvaf cache <list|clean|verify>`.
+pub fn run_cache_command(matches: &ArgMatches) -> Result<i32> {
+    let (command, matches) = matches.subcommand().expect("clap ensures a subcommand is present");
+    let cache = Cache::new(cache_dir(matches)?);
+    let mut stdout = termcolor::StandardStream::stdout(ColorChoice::Auto);
+    match command {
+        CACHE_LIST => {
+            let entries = cache.entries()?;
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow))).unwrap();
+            writeln!(
+                &mut stdout,
+                "{:<26} {:>10} {:>10}  {:<32} {:<16} {:<3} INPUT",
+                "DIGEST", "SIZE", "LAST USED", "TARGET", "CPU", "OPT"
+            )?;
+            stdout.set_color(&ColorSpec::new()).unwrap();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+            for entry in entries.iter().rev() {
+                let field = |name| entry.key_field(name).unwrap_or("?");
+                let input = entry.inputs.first().map_or("?", |(path, _)| path);
+                writeln!(
+                    &mut stdout,
+                    "{:<26} {:>10} {:>10}  {:<32} {:<16} {:<3} {input}",
+                    entry.digest,
+                    format_size(entry.size),
+                    format_age(now.saturating_sub(entry.last_used)),
+                    field("target"),
+                    field("target_cpu"),
+                    field("opt_lvl"),
+                )?;
+            }
+            let size = entries.iter().map(|entry| entry.size).sum();
+            writeln!(
+                &mut stdout,
+                "{} libraries ({}) in {}",
+                entries.len(),
+                format_size(size),
+                cache.dir()
+            )?;
+        }
+        CACHE_CLEAN => {
+            let removed = if let Some(max_size) = cache_size(matches) {
+                cache.evict(max_size)?.len()
+            } else {
+                cache.clean()?
+            };
+            writeln!(&mut stdout, "removed {removed} libraries from {}", cache.dir())?;
+        }
+        CACHE_VERIFY => {
+            let broken = cache.verify()?;
+            for (digest, err) in &broken {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow))).unwrap();
+                write!(&mut stdout, "removed")?;
+                stdout.set_color(&ColorSpec::new()).unwrap();
+                writeln!(&mut stdout, " {digest}: {err}")?;
+            }
+            writeln!(&mut stdout, "found {} broken libraries in {}", broken.len(), cache.dir())?;
+        }
+        _ => unreachable!("unknown subcommand {command}"),
+    }
+    Ok(0)
+}
+
+/// The options of subcommands (like `interpret`) that only run the frontend
+fn frontend_opts(matches: &ArgMatches) -> Result<Opts> {
+    let input = matches.get_one::<Utf8PathBuf>(INPUT).unwrap().clone();
+    let target = match openvaf::Target::search(host_triple()) {
+        Some(target) => target,
+        None => bail!("The target {} is not supported by this binary", host_triple()),
+    };
+    let opts = Opts {
+        dry_run: true,
+        defines: defines(matches),
+        codegen_opts: Vec::new(),
+        lints: Vec::new(),
+        output: CompilationDestination::Path { lib_file: input.with_extension("osdi") },
         input,
-        lints,
-        codegen_opts,
-        defines,
-        include,
-        output,
-        opt_lvl,
+        include: include(matches)?,
+        opt_lvl: OptLevel::None,
         target,
-        target_cpu,
-        dry_run: matches.get_flag(DRYRUN),
-    })
+        target_cpu: "generic".to_owned(),
+        sensitivities: Vec::new(),
+        hessian: Vec::new(),
+        dump_json: false,
+        emit: Vec::new(),
+        modules: modules(matches),
+        split_modules: false,
+        linker: LinkerKind::External,
+        reproducible: false,
+        autodiff: autodiff(matches),
+        fp_policy: FloatPolicy::default(),
+        check_fp: false,
+        debug_info: false,
+        symbolic_delays: false,
+        backend: Backend::Llvm,
+    };
+    Ok(opts)
+}
+
+/// Executes `openvaf interpret`.
+pub fn run_interpret_command(matches: &ArgMatches) -> Result<i32> {
+    let opts = frontend_opts(matches)?;
+
+    let mut point = OperatingPoint::default();
+    if let Some(params) = matches.get_many::<(String, String)>(PARAM) {
+        point.params.extend(params.cloned());
+    }
+    if let Some(bias) = matches.get_many::<(String, f64)>(BIAS) {
+        point.bias.extend(bias.cloned());
+    }
+    if let Some(&temperature) = matches.get_one::<f64>(TEMPERATURE) {
+        point.temperature = temperature;
+    }
+
+    let evaluations = match interpret(&opts, &point)? {
+        Some(evaluations) => evaluations,
+        None => return Ok(DATA_ERROR),
+    };
+    for (i, evaluation) in evaluations.iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        print!("{evaluation}");
+    }
+    Ok(0)
+}
+
+/// Executes `openvaf equations`.
+pub fn run_equations_command(matches: &ArgMatches) -> Result<i32> {
+    let opts = frontend_opts(matches)?;
+    let name = matches.get_one::<String>(SYNTAX).unwrap();
+    let syntax = Syntax::ALL.into_iter().find(|syntax| syntax.name() == name).unwrap();
+    let unknowns: Vec<String> =
+        matches.get_many::<String>(UNKNOWN).map_or_else(Vec::new, |it| it.cloned().collect());
+    match equations(&opts, syntax, &unknowns)? {
+        Some(equations) => {
+            print!("{equations}");
+            Ok(0)
+        }
+        None => Ok(DATA_ERROR),
+    }
+}
+
+fn format_size(size: u64) -> String {
+    if size >= 1 << 20 {
+        format!("{:.1} MiB", size as f64 / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", size as f64 / (1 << 10) as f64)
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
 }
 
 fn print_lints() {
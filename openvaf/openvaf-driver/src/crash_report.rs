@@ -100,7 +100,7 @@ impl Report {
         let mut dst = String::new();
         let _ = writeln!(dst, "OpenVAF {}", env!("CARGO_PKG_VERSION"));
         if let Ok(args) = super::ARGS.lock() {
-            if let Some(args) = &*args {
+            for args in args.iter() {
                 let _ = writeln!(dst, "{:#?}", args);
             }
         }
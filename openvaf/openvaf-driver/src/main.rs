@@ -1,31 +1,39 @@
 use std::io::Write;
 use std::process::exit;
 use std::sync::Mutex;
+use std::time::Instant;
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use camino::Utf8PathBuf;
 use clap::ArgMatches;
 use mimalloc::MiMalloc;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 use cli_def::{main_command, INPUT};
-use openvaf::{compile, expand, CompilationDestination, CompilationTermination, Opts};
+use openvaf::{
+    compile, compile_batch, dump_mir_json, expand, BatchCompilation, CompilationDestination,
+    CompilationTermination, Opts,
+};
 
-use crate::cli_def::{DUMP_JSON, PRINT_EXPANSION};
-use crate::cli_process::matches_to_opts;
+use crate::cli_def::{CACHE, DUMP_MIR_JSON, EQUATIONS, INTERPRET, PRINT_EXPANSION};
+use crate::cli_process::{
+    matches_to_opts, run_cache_command, run_equations_command, run_interpret_command,
+};
 
 mod cli_def;
 mod cli_process;
 mod crash_report;
 
-static ARGS: Mutex<Option<Opts>> = Mutex::new(None);
+static ARGS: Mutex<Vec<Opts>> = Mutex::new(Vec::new());
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
 pub fn main() {
     let matches = main_command().get_matches();
     crash_report::install_panic_handler();
-    let input: Utf8PathBuf = matches.get_one(INPUT).cloned().unwrap_or_else(Utf8PathBuf::new);
+    let inputs: Vec<Utf8PathBuf> =
+        matches.get_many(INPUT).map_or_else(Vec::new, |inputs| inputs.cloned().collect());
+    let input = inputs.iter().map(Utf8PathBuf::as_str).collect::<Vec<_>>().join(", ");
     let env = env_logger::Env::default().filter("OPENVAF_LOG").write_style("OPENVAF_LOG_STYLE");
     env_logger::Builder::new()
         .format_timestamp(None)
@@ -60,35 +68,103 @@ pub fn main() {
 pub const DATA_ERROR: i32 = 65;
 
 fn wrapped_main(matches: ArgMatches) -> Result<i32> {
+    match matches.subcommand() {
+        Some((CACHE, matches)) => return run_cache_command(matches),
+        Some((INTERPRET, matches)) => return run_interpret_command(matches),
+        Some((EQUATIONS, matches)) => return run_equations_command(matches),
+        _ => (),
+    }
     let print_expansion = matches.get_flag(PRINT_EXPANSION);
-    let dump_json_ = matches.get_flag(DUMP_JSON);
+    let dump_mir_json_ = matches.get_flag(DUMP_MIR_JSON);
     let opts = matches_to_opts(matches)?;
-    *ARGS.lock().unwrap() = Some(opts.clone());
+    *ARGS.lock().unwrap() = opts.clone();
     if print_expansion {
-        let res = match expand(&opts)? {
-            CompilationTermination::Compiled { .. } => 0,
+        let mut res = 0;
+        for opts in &opts {
+            if let CompilationTermination::FatalDiagnostic = expand(opts)? {
+                res = DATA_ERROR
+            }
+        }
+        return Ok(res);
+    }
+    if dump_mir_json_ {
+        let mut res = 0;
+        for opts in &opts {
+            if let CompilationTermination::FatalDiagnostic = dump_mir_json(opts)? {
+                res = DATA_ERROR
+            }
+        }
+        return Ok(res);
+    }
+
+    if let [opts] = &*opts {
+        let res = match compile(opts)? {
+            CompilationTermination::Compiled { lib_file } => {
+                if matches!(opts.output, CompilationDestination::Cache { .. }) {
+                    println!("{lib_file}");
+                }
+                0
+            }
+            CompilationTermination::CompiledModules { .. } => 0,
             CompilationTermination::FatalDiagnostic => DATA_ERROR,
         };
         return Ok(res);
     }
-    if dump_json_ {
-        bail!("currently unimplemented");
-        // let res = match dump_json(&opts)? {
-        //     CompilationTermination::Compiled { .. } => 0,
-        //     CompilationTermination::FatalDiagnostic => DATA_ERROR,
-        // };
-        // return Ok(res);
-    }
 
-    let res = match compile(&opts)? {
-        CompilationTermination::Compiled { lib_file } => {
-            if matches!(opts.output, CompilationDestination::Cache { .. }) {
-                println!("{lib_file}");
+    let start = Instant::now();
+    let results = compile_batch(&opts);
+    print_summary(&results, start)?;
+    let failed = results
+        .iter()
+        .any(|res| matches!(res.result, Err(_) | Ok(CompilationTermination::FatalDiagnostic)));
+    Ok(if failed { DATA_ERROR } else { 0 })
+}
+
+fn print_summary(results: &[BatchCompilation], start: Instant) -> Result<()> {
+    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+    let width = results.iter().map(|res| res.input.as_str().len()).max().unwrap_or(0).max(5);
+    writeln!(&mut stderr)?;
+    stderr.set_color(ColorSpec::new().set_bold(true))?;
+    writeln!(&mut stderr, "{:<width$}  {:<6}  {:>8}  output", "input", "status", "time")?;
+    stderr.set_color(&ColorSpec::new())?;
+
+    let mut num_failed = 0;
+    for res in results {
+        write!(&mut stderr, "{:<width$}  ", res.input)?;
+        let (status, output) = match &res.result {
+            Ok(CompilationTermination::Compiled { lib_file }) => ("ok", lib_file.to_string()),
+            Ok(CompilationTermination::CompiledModules { lib_files }) => {
+                let lib_files: Vec<_> =
+                    lib_files.iter().map(|lib_file| lib_file.as_str()).collect();
+                ("ok", lib_files.join(", "))
             }
-            0
-        }
-        CompilationTermination::FatalDiagnostic => DATA_ERROR,
-    };
+            Ok(CompilationTermination::FatalDiagnostic) => {
+                ("failed", "see diagnostics above".to_owned())
+            }
+            Err(err) => ("error", format!("{err:#}")),
+        };
+        let color = if status == "ok" {
+            Color::Green
+        } else {
+            num_failed += 1;
+            Color::Red
+        };
+        stderr.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        write!(&mut stderr, "{status:<6}")?;
+        stderr.set_color(&ColorSpec::new())?;
+        writeln!(&mut stderr, "  {:>7.2}s  {output}", res.time.as_secs_f64())?;
+    }
 
-    Ok(res)
+    let seconds = start.elapsed().as_secs_f64();
+    let color = if num_failed == 0 { Color::Green } else { Color::Red };
+    stderr.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(&mut stderr, "Finished")?;
+    stderr.set_color(&ColorSpec::new())?;
+    writeln!(
+        &mut stderr,
+        " {} of {} files in {seconds:.2}s ({num_failed} failed)",
+        results.len() - num_failed,
+        results.len()
+    )?;
+    Ok(())
 }
@@ -36,6 +36,23 @@ fn smoke_test(args: &str) -> Result {
     Ok(())
 }
 
+/// Options that only affect LLVM are rejected with the C backend
+fn c_backend_rejects(args: &str) -> Result {
+    let args = args.split(' ');
+    let sh = xshell::Shell::new().unwrap();
+    sh.change_dir(project_root());
+    let openvaf = cargo_bin("openvaf");
+    let res = xshell::cmd!(
+        sh,
+        "{openvaf} --dry-run --backend c {args...} integration_tests/DIODE/diode.va"
+    )
+    .quiet()
+    .ignore_stderr()
+    .run();
+    assert!(res.is_err());
+    Ok(())
+}
+
 fn link_diode() -> Result {
     let sh = xshell::Shell::new().unwrap();
     sh.change_dir(project_root());
@@ -55,9 +72,20 @@ harness! {
             "--target_cpu generic",
             "--target_cpu skylake",
              "--dump-json",
+             "--dump-mir-json",
              "--supported-targets",
+             "--target x86_64-unknown-linux-musl",
+             "--target aarch64-unknown-linux-musl",
+             "--target riscv64gc-unknown-linux",
+             "--target powerpc64le-unknown-linux",
+             "--target riscv64gc-unknown-linux-gnu",
+             "--target powerpc64le-unknown-linux-gnu",
+             "--target x86_64-pc-windows-msvc",
+             "--target wasm32-unknown-unknown",
              "--batch",
              "--batch --cache-dir sourcegen",
+             "--batch --cache-size 64",
+             "--emit llvm-ir,llvm-bc,asm,obj,mir",
              "-O 0",
              "-O 1",
              "-O 2",
@@ -78,5 +106,16 @@ harness! {
              "-A all",
              "-A macro_overwritten",
         ]
+    ),
+    Test::from_list(
+        "cli::c_backend_rejects",
+         &c_backend_rejects,
+         &ignore_never,
+         &[
+             "--target x86_64-unknown-linux-gnu",
+             "--target_cpu generic",
+             "--fp-policy fast",
+             "-g",
+        ]
     )
 }
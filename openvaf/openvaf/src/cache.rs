@@ -1,55 +1,642 @@
-use core::slice;
-use std::mem::{size_of, size_of_val};
+//! Content addressed store for libraries compiled in batchmode.
+//!
+//! Every compilation is identified by a [`CacheKey`] that covers everything that affects the
+//! generated library: the preprocessed source, the compiler version, defines, lints, the target
+//! (triple, cpu and features), the optimization level and the codegen options. The md5 digest
+//! of the key addresses the entry: `<digest>.osdi` is the library and `<digest>.manifest`
+//! records the full key, the input files and when the entry was created and last used.
+//!
+//! Modifications of the cache are protected by an advisory lock of the operating system
+//! (`flock` on unix, an exclusive open on windows) so that concurrent invocations of
+//! `openvaf --batch` can share a cache directory. The lock is released by the operating
+//! system if the process is killed, so there are no stale locks.
 
-use basedb::lints::LintLevel;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
 use basedb::{BaseDB, VfsStorage};
+use camino::{Utf8Path, Utf8PathBuf};
 use hir::CompilationDB;
+use mir_llvm::LLVMBackend;
 
 use crate::Opts;
 
-// TODO: use high level hir API instead of low leve database API
-fn hash(db: &CompilationDB, defines: &[String]) -> md5::Digest {
-    let mut hash_builder = md5::Context::new();
-    let cu = db.compilation_unit();
+/// Incremented whenever the layout of the cache directory or the manifest changes.
+/// Entries created by other versions are ignored (and removed by `openvaf cache verify`).
+pub const CACHE_VERSION: u32 = 1;
+/// The default upper bound for the total size of all libraries in a cache (1 GiB).
+pub const DEFAULT_CACHE_SIZE: u64 = 1 << 30;
+
+const LOCK_FILE: &str = "cache.lock";
+const LIB_EXTENSION: &str = "osdi";
+const MANIFEST_EXTENSION: &str = "manifest";
+
+pub struct CacheKey {
+    digest: String,
+    fields: Vec<(String, String)>,
+    inputs: Vec<(String, String)>,
+}
+
+impl CacheKey {
+    pub fn new(db: &CompilationDB, opts: &Opts, back: &LLVMBackend) -> CacheKey {
+        let mut fields = Vec::new();
+        let mut field = |name: &str, val: String| fields.push((name.to_owned(), val));
+
+        field("openvaf", env!("CARGO_PKG_VERSION").to_owned());
+        for def in &opts.defines {
+            field("define", def.clone());
+        }
+        for (lint, lvl) in &opts.lints {
+            field("lint", format!("{lint}={lvl:?}"));
+        }
+        field("target", opts.target.llvm_target.clone());
+        // the resolved cpu and features so that `native` is never reused on another host
+        field("target_cpu", back.target_cpu().to_owned());
+        field("target_features", back.target_features().to_owned());
+        field("opt_lvl", (opts.opt_lvl as u32).to_string());
+        for opt in &opts.codegen_opts {
+            field("codegen", opt.clone());
+        }
+        for param in &opts.sensitivities {
+            field("sensitivity", param.clone());
+        }
+        for node in &opts.hessian {
+            field("hessian", node.clone());
+        }
+        field("linker", opts.linker.name().to_owned());
+        field("reproducible", opts.reproducible.to_string());
+        field("autodiff", opts.autodiff.mode.name().to_owned());
+        field("autodiff_cse", opts.autodiff.cse.to_string());
+        field("fp_policy", opts.fp_policy.name().to_owned());
+        field("check_fp", opts.check_fp.to_string());
+        field("debug_info", opts.debug_info.to_string());
+        field("symbolic_delays", opts.symbolic_delays.to_string());
+        field("backend", opts.backend.name().to_owned());
+        for module in &opts.modules {
+            field("module", module.clone());
+        }
+
+        // Hash the full preprocessor result
+        let mut hash_builder = md5::Context::new();
+        let mut files = Vec::new();
+        let cu = db.compilation_unit();
+        let preprocess = cu.preprocess(db);
+        let vfs = db.vfs().read();
+        for token in &*preprocess.ts {
+            if !token.kind.is_trivia() {
+                let filespan = token.span.to_file_span(&preprocess.sm);
+                let src = vfs.file_contents_unchecked(filespan.file);
+                hash_builder.consume(&src[filespan.range]);
+                hash_builder.consume(" ");
+                if !files.contains(&filespan.file) {
+                    files.push(filespan.file);
+                }
+            }
+        }
+        field("source", encode_digest(hash_builder.compute()));
+
+        let inputs = files
+            .into_iter()
+            .map(|file| {
+                let checksum = md5::compute(vfs.file_contents_unchecked(file));
+                (vfs.file_path(file).to_string(), encode_digest(checksum))
+            })
+            .collect();
 
-    // hash settings
-    hash_builder.consume(cu.root_file().0.to_ne_bytes());
+        let mut hash_builder = md5::Context::new();
+        hash_builder.consume(CACHE_VERSION.to_ne_bytes());
+        for (name, val) in &fields {
+            hash_builder.consume(name);
+            hash_builder.consume("=");
+            hash_builder.consume(val);
+            hash_builder.consume("\n");
+        }
+        let digest = encode_digest(hash_builder.compute());
+
+        CacheKey { digest, fields, inputs }
+    }
+
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+}
+
+fn encode_digest(digest: md5::Digest) -> String {
+    base_n::encode(u128::from_ne_bytes(*digest), base_n::CASE_INSENSITIVE)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// The manifest of a cache entry. Stored as `<digest>.manifest` next to the library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub digest: String,
+    /// Unix timestamp (in seconds) of the compilation that created this entry
+    pub created: u64,
+    /// Unix timestamp (in seconds) of the last compilation that used this entry
+    pub last_used: u64,
+    /// Size of the library in bytes
+    pub size: u64,
+    /// md5 digest of the library
+    pub checksum: String,
+    /// The full [`CacheKey`], this entry is only reused if all fields match
+    pub key: Vec<(String, String)>,
+    /// All files read during compilation and the md5 digest of their contents
+    pub inputs: Vec<(String, String)>,
+}
+
+impl CacheEntry {
+    /// Returns the first value of the key field `name`.
+    pub fn key_field(&self, name: &str) -> Option<&str> {
+        self.key.iter().find(|(field, _)| field == name).map(|(_, val)| &**val)
+    }
+
+    fn serialize(&self) -> String {
+        let mut res = String::new();
+        let _ = writeln!(res, "version = {CACHE_VERSION}");
+        let _ = writeln!(res, "digest = {}", self.digest);
+        let _ = writeln!(res, "created = {}", self.created);
+        let _ = writeln!(res, "last_used = {}", self.last_used);
+        let _ = writeln!(res, "size = {}", self.size);
+        let _ = writeln!(res, "checksum = {}", self.checksum);
+        res.push_str("\n[key]\n");
+        for (name, val) in &self.key {
+            let _ = writeln!(res, "{name} = {val}");
+        }
+        res.push_str("\n[inputs]\n");
+        for (path, checksum) in &self.inputs {
+            let _ = writeln!(res, "{checksum} = {path}");
+        }
+        res
+    }
 
-    hash_builder.consume(defines.len().to_ne_bytes());
-    for def in defines {
-        hash_builder.consume(def)
+    fn parse(src: &str) -> Result<CacheEntry> {
+        let mut entry = CacheEntry {
+            digest: String::new(),
+            created: 0,
+            last_used: 0,
+            size: 0,
+            checksum: String::new(),
+            key: Vec::new(),
+            inputs: Vec::new(),
+        };
+        let mut version = None;
+        let mut section = "";
+        for line in src.lines().filter(|line| !line.is_empty()) {
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name;
+                continue;
+            }
+            let (name, val) = if let Some(field) = line.split_once(" = ") {
+                field
+            } else {
+                bail!("invalid line {line:?}")
+            };
+            match (section, name) {
+                ("", "version") => version = Some(val.parse::<u32>()?),
+                ("", "digest") => entry.digest = val.to_owned(),
+                ("", "created") => entry.created = val.parse()?,
+                ("", "last_used") => entry.last_used = val.parse()?,
+                ("", "size") => entry.size = val.parse()?,
+                ("", "checksum") => entry.checksum = val.to_owned(),
+                ("key", _) => entry.key.push((name.to_owned(), val.to_owned())),
+                ("inputs", _) => entry.inputs.push((val.to_owned(), name.to_owned())),
+                _ => bail!("unknown field {name:?}"),
+            }
+        }
+        match version {
+            Some(CACHE_VERSION) => Ok(entry),
+            Some(version) => bail!("unsupported cache version {version}"),
+            None => bail!("missing cache version"),
+        }
     }
+}
+
+/// Held while the cache is modified, the lock is released when the lock file is closed on drop.
+/// The lock file itself is never removed (another process might be waiting for it).
+pub struct CacheLock {
+    path: Utf8PathBuf,
+    _file: File,
+}
+
+/// Blocks until this process holds the exclusive lock of `path`.
+#[cfg(unix)]
+fn lock_file(path: &Utf8Path) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
 
-    hash_builder.consume(env!("CARGO_PKG_VERSION"));
-    let lints = db.global_lint_overwrites(cu.root_file());
-    if cfg!(debug_assertions) && !lints.is_empty() {
-        assert_eq!(size_of::<Option<LintLevel>>(), size_of_val(&lints.raw[0]));
+    let file = OpenOptions::new().write(true).create(true).open(path)?;
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(file);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+        }
     }
-    let lints = unsafe {
-        slice::from_raw_parts(
-            lints.as_ptr() as *const u8,
-            size_of::<Option<LintLevel>>() * lints.len(),
-        )
-    };
-    hash_builder.consume(lints);
+}
+
+/// Blocks until this process holds the exclusive lock of `path`.
+#[cfg(windows)]
+fn lock_file(path: &Utf8Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::thread::sleep;
+    use std::time::Duration;
 
-    // Hash the full preprocessor result
-    let preprocess = cu.preprocess(db);
-    let vfs = db.vfs().read();
-    for token in &*preprocess.ts {
-        if !token.kind.is_trivia() {
-            let filespan = token.span.to_file_span(&preprocess.sm);
-            let src = vfs.file_contents_unchecked(filespan.file);
-            hash_builder.consume(&src[filespan.range]);
-            hash_builder.consume(" ");
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    loop {
+        // a file opened without sharing can't be opened by any other process
+        match OpenOptions::new().write(true).create(true).share_mode(0).open(path) {
+            Err(err) if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                sleep(Duration::from_millis(50))
+            }
+            res => return res,
         }
     }
+}
 
-    hash_builder.compute()
+pub struct Cache {
+    dir: Utf8PathBuf,
 }
 
-pub fn file_name(db: &CompilationDB, opts: &Opts) -> String {
-    let hash = u128::from_ne_bytes(*hash(db, &opts.defines));
-    let hash = base_n::encode(hash, base_n::CASE_INSENSITIVE);
-    format!("{}.osdi", hash)
+impl Cache {
+    pub fn new(dir: Utf8PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    pub fn lib_file(&self, digest: &str) -> Utf8PathBuf {
+        self.dir.join(format!("{digest}.{LIB_EXTENSION}"))
+    }
+
+    fn manifest_file(&self, digest: &str) -> Utf8PathBuf {
+        self.dir.join(format!("{digest}.{MANIFEST_EXTENSION}"))
+    }
+
+    /// The file that a new library is compiled to before it's moved into the cache
+    /// with [`Cache::insert`]. Unique for each process so that the (unlocked)
+    /// compilation doesn't interfere with other processes.
+    pub fn staging_file(&self, key: &CacheKey) -> Result<Utf8PathBuf> {
+        fs::create_dir_all(&self.dir).context("failed to create cache directory")?;
+        Ok(self.dir.join(format!("{}-{}.tmp.{LIB_EXTENSION}", key.digest, std::process::id())))
+    }
+
+    pub fn lock(&self) -> Result<CacheLock> {
+        fs::create_dir_all(&self.dir).context("failed to create cache directory")?;
+        let path = self.dir.join(LOCK_FILE);
+        let file = lock_file(&path).with_context(|| format!("failed to lock {path}"))?;
+        Ok(CacheLock { path, _file: file })
+    }
+
+    fn read_entry(&self, digest: &str) -> Result<CacheEntry> {
+        let path = self.manifest_file(digest);
+        let src = fs::read_to_string(&path).with_context(|| format!("failed to read {path}"))?;
+        CacheEntry::parse(&src).with_context(|| format!("invalid manifest {path}"))
+    }
+
+    fn write_entry(&self, entry: &CacheEntry) -> Result<()> {
+        let path = self.manifest_file(&entry.digest);
+        let tmp = path.with_extension(format!("{MANIFEST_EXTENSION}.tmp"));
+        fs::write(&tmp, entry.serialize()).with_context(|| format!("failed to write {tmp}"))?;
+        fs::rename(&tmp, &path).with_context(|| format!("failed to write {path}"))
+    }
+
+    fn remove_entry(&self, digest: &str) -> Result<()> {
+        for path in [self.lib_file(digest), self.manifest_file(digest)] {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(err).with_context(|| format!("failed to remove {path}"))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the library compiled for `key` if it's present in the cache.
+    pub fn lookup(&self, key: &CacheKey) -> Result<Option<Utf8PathBuf>> {
+        if !self.manifest_file(&key.digest).exists() {
+            return Ok(None);
+        }
+        let _lock = self.lock()?;
+        self.lookup_locked(key)
+    }
+
+    fn lookup_locked(&self, key: &CacheKey) -> Result<Option<Utf8PathBuf>> {
+        let mut entry = match self.read_entry(&key.digest) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+        // a modified (or truncated) library is never reused, `verify` removes it
+        if entry.key != key.fields || self.verify_entry(&key.digest, &entry).is_err() {
+            return Ok(None);
+        }
+        let lib_file = self.lib_file(&key.digest);
+        entry.last_used = now();
+        self.write_entry(&entry)?;
+        Ok(Some(lib_file))
+    }
+
+    /// Moves a library from its `staging_file` into the cache and evicts the least
+    /// recently used entries until the total size of the cache is below `max_size`.
+    pub fn insert(
+        &self,
+        key: &CacheKey,
+        staging_file: &Utf8Path,
+        max_size: u64,
+    ) -> Result<Utf8PathBuf> {
+        let _lock = self.lock()?;
+        // another process may have compiled the same library in the meantime
+        if let Some(lib_file) = self.lookup_locked(key)? {
+            let _ = fs::remove_file(staging_file);
+            return Ok(lib_file);
+        }
+
+        let contents =
+            fs::read(staging_file).with_context(|| format!("failed to read {staging_file}"))?;
+        let lib_file = self.lib_file(&key.digest);
+        fs::rename(staging_file, &lib_file)
+            .with_context(|| format!("failed to move {staging_file} to {lib_file}"))?;
+        let time = now();
+        let entry = CacheEntry {
+            digest: key.digest.clone(),
+            created: time,
+            last_used: time,
+            size: contents.len() as u64,
+            checksum: encode_digest(md5::compute(&contents)),
+            key: key.fields.clone(),
+            inputs: key.inputs.clone(),
+        };
+        self.write_entry(&entry)?;
+        self.evict_locked(max_size, Some(&key.digest))?;
+        Ok(lib_file)
+    }
+
+    /// Returns all entries in the cache, ordered from least to most recently used.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        Ok(self.read_entries()?.into_iter().filter_map(|(_, entry)| entry.ok()).collect())
+    }
+
+    fn read_entries(&self) -> Result<Vec<(String, Result<CacheEntry>)>> {
+        let mut res = Vec::new();
+        let dir = match self.dir.read_dir_utf8() {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(res),
+            Err(err) => return Err(err).with_context(|| format!("failed to read {}", self.dir)),
+        };
+        for file in dir {
+            let file = file.with_context(|| format!("failed to read {}", self.dir))?;
+            let path = file.path();
+            if path.extension() != Some(MANIFEST_EXTENSION) {
+                continue;
+            }
+            if let Some(digest) = path.file_stem() {
+                res.push((digest.to_owned(), self.read_entry(digest)));
+            }
+        }
+        res.sort_by_key(|(digest, entry)| {
+            (entry.as_ref().map_or(0, |entry| entry.last_used), digest.clone())
+        });
+        Ok(res)
+    }
+
+    /// Removes the least recently used entries until the total size of the cache is below
+    /// `max_size`. Returns the removed entries.
+    pub fn evict(&self, max_size: u64) -> Result<Vec<CacheEntry>> {
+        let _lock = self.lock()?;
+        self.evict_locked(max_size, None)
+    }
+
+    fn evict_locked(&self, max_size: u64, keep: Option<&str>) -> Result<Vec<CacheEntry>> {
+        let entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut evicted = Vec::new();
+        for entry in entries {
+            if size <= max_size {
+                break;
+            }
+            if Some(&*entry.digest) == keep {
+                continue;
+            }
+            self.remove_entry(&entry.digest)?;
+            size -= entry.size;
+            evicted.push(entry);
+        }
+        Ok(evicted)
+    }
+
+    /// Removes all files from the cache. Returns the number of removed entries.
+    pub fn clean(&self) -> Result<usize> {
+        let lock = self.lock()?;
+        let entries = self.read_entries()?.len();
+        for file in
+            self.dir.read_dir_utf8().with_context(|| format!("failed to read {}", self.dir))?
+        {
+            let file = file.with_context(|| format!("failed to read {}", self.dir))?;
+            if file.path() != lock.path.as_path()
+                && file.file_type().map_or(false, |ty| ty.is_file())
+            {
+                fs::remove_file(file.path())
+                    .with_context(|| format!("failed to remove {}", file.path()))?;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Checks the integrity of all entries and removes the broken ones.
+    /// Returns the digest of each removed entry and the reason why it was removed.
+    pub fn verify(&self) -> Result<Vec<(String, String)>> {
+        let _lock = self.lock()?;
+        let mut broken = Vec::new();
+        for (digest, entry) in self.read_entries()? {
+            let err = match entry {
+                Ok(entry) => self.verify_entry(&digest, &entry).err(),
+                Err(err) => Some(err),
+            };
+            if let Some(err) = err {
+                self.remove_entry(&digest)?;
+                broken.push((digest, format!("{err:#}")));
+            }
+        }
+
+        // libraries without a manifest
+        for file in
+            self.dir.read_dir_utf8().with_context(|| format!("failed to read {}", self.dir))?
+        {
+            let file = file.with_context(|| format!("failed to read {}", self.dir))?;
+            let path = file.path();
+            let digest = match path.file_stem() {
+                Some(digest) if path.extension() == Some(LIB_EXTENSION) => digest,
+                _ => continue,
+            };
+            // libraries that are currently compiled are staged with a `.tmp` suffix
+            if digest.ends_with(".tmp") || self.manifest_file(digest).exists() {
+                continue;
+            }
+            self.remove_entry(digest)?;
+            broken.push((digest.to_owned(), "missing manifest".to_owned()));
+        }
+        Ok(broken)
+    }
+
+    fn verify_entry(&self, digest: &str, entry: &CacheEntry) -> Result<()> {
+        if entry.digest != digest {
+            bail!("manifest belongs to {}", entry.digest)
+        }
+        let lib_file = self.lib_file(digest);
+        let contents = fs::read(&lib_file).with_context(|| format!("failed to read {lib_file}"))?;
+        if contents.len() as u64 != entry.size {
+            bail!("expected {} bytes but found {}", entry.size, contents.len())
+        }
+        if encode_digest(md5::compute(&contents)) != entry.checksum {
+            bail!("checksum mismatch")
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip() {
+        let entry = CacheEntry {
+            digest: "1x2y3z".to_owned(),
+            created: 1,
+            last_used: 2,
+            size: 3,
+            checksum: "4a5b6c".to_owned(),
+            key: vec![
+                ("openvaf".to_owned(), "23.5.0".to_owned()),
+                ("define".to_owned(), "FOO=1".to_owned()),
+                ("define".to_owned(), "BAR = 2".to_owned()),
+            ],
+            inputs: vec![("/tmp/foo bar.va".to_owned(), "7d8e9f".to_owned())],
+        };
+        assert_eq!(CacheEntry::parse(&entry.serialize()).unwrap(), entry);
+        assert!(CacheEntry::parse("version = 0\n").is_err());
+    }
+
+    fn test_cache(name: &str) -> Cache {
+        let dir = std::env::temp_dir().join(format!("openvaf_cache_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        Cache::new(Utf8PathBuf::from_path_buf(dir).unwrap())
+    }
+
+    fn test_key(digest: &str, opt_lvl: &str) -> CacheKey {
+        CacheKey {
+            digest: digest.to_owned(),
+            fields: vec![("opt_lvl".to_owned(), opt_lvl.to_owned())],
+            inputs: vec![("/tmp/foo.va".to_owned(), "7d8e9f".to_owned())],
+        }
+    }
+
+    fn insert(cache: &Cache, key: &CacheKey, contents: &str, max_size: u64) -> Utf8PathBuf {
+        let staging_file = cache.staging_file(key).unwrap();
+        fs::write(&staging_file, contents).unwrap();
+        let lib_file = cache.insert(key, &staging_file, max_size).unwrap();
+        assert!(!staging_file.exists());
+        lib_file
+    }
+
+    #[test]
+    fn hit_and_miss() {
+        let cache = test_cache("hit_and_miss");
+        let key = test_key("abc", "3");
+        assert_eq!(cache.lookup(&key).unwrap(), None);
+
+        let lib_file = insert(&cache, &key, "library", DEFAULT_CACHE_SIZE);
+        assert_eq!(lib_file, cache.lib_file("abc"));
+        assert_eq!(fs::read_to_string(&lib_file).unwrap(), "library");
+        assert_eq!(cache.lookup(&key).unwrap(), Some(lib_file));
+        assert_eq!(cache.lookup(&test_key("def", "3")).unwrap(), None);
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].size, 7);
+        assert_eq!(entries[0].key_field("opt_lvl"), Some("3"));
+        assert_eq!(entries[0].inputs, key.inputs);
+    }
+
+    #[test]
+    fn invalidation() {
+        let cache = test_cache("invalidation");
+        let key = test_key("abc", "3");
+        let lib_file = insert(&cache, &key, "library", DEFAULT_CACHE_SIZE);
+
+        // a digest collision must not reuse an entry compiled with different options
+        assert_eq!(cache.lookup(&test_key("abc", "0")).unwrap(), None);
+
+        // a modified library is never reused and removed by `verify`
+        fs::write(&lib_file, "libra").unwrap();
+        assert_eq!(cache.lookup(&key).unwrap(), None);
+        fs::write(&lib_file, "LIBRARY").unwrap();
+        assert_eq!(cache.lookup(&key).unwrap(), None);
+        let broken = cache.verify().unwrap();
+        assert_eq!(broken, [("abc".to_owned(), "checksum mismatch".to_owned())]);
+        assert!(!lib_file.exists());
+        assert_eq!(cache.lookup(&key).unwrap(), None);
+
+        // a new compilation replaces the broken entry
+        insert(&cache, &key, "library", DEFAULT_CACHE_SIZE);
+        assert!(cache.verify().unwrap().is_empty());
+        assert_eq!(cache.lookup(&key).unwrap(), Some(lib_file));
+    }
+
+    #[test]
+    fn eviction() {
+        let cache = test_cache("eviction");
+        let old = test_key("old", "3");
+        let new = test_key("new", "3");
+        insert(&cache, &old, "1234", 8);
+        insert(&cache, &new, "5678", 8);
+        assert_eq!(cache.entries().unwrap().len(), 2);
+
+        // the entry that is inserted is never evicted
+        let newest = test_key("newest", "3");
+        insert(&cache, &newest, "123456789", 8);
+        assert_eq!(cache.lookup(&old).unwrap(), None);
+        assert_eq!(cache.lookup(&new).unwrap(), None);
+        assert!(cache.lookup(&newest).unwrap().is_some());
+
+        assert_eq!(cache.clean().unwrap(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        use std::sync::mpsc::channel;
+        use std::sync::{Arc, Mutex};
+        use std::thread::spawn;
+
+        let cache = test_cache("lock");
+        let lock = cache.lock().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (started, wait_for_start) = channel();
+        let thread = {
+            let dir = cache.dir().to_owned();
+            let events = events.clone();
+            spawn(move || {
+                started.send(()).unwrap();
+                let _lock = Cache::new(dir).lock().unwrap();
+                events.lock().unwrap().push("locked by thread");
+            })
+        };
+        // the thread can only take the lock after it was released here
+        wait_for_start.recv().unwrap();
+        events.lock().unwrap().push("released by main");
+        drop(lock);
+        thread.join().unwrap();
+        assert_eq!(*events.lock().unwrap(), ["released by main", "locked by thread"]);
+    }
 }
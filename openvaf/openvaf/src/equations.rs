@@ -0,0 +1,151 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use hir::CompilationDB;
+use hir_lower::HirInterner;
+use lasso::Rodeo;
+use mir::write::{ExprWriter, Syntax};
+use mir::{ControlFlowGraph, FuncRef, Param, F_ZERO};
+use sim_back::dae::SimUnknown;
+use sim_back::CompiledModule;
+use typed_index_collections::TiVec;
+
+use crate::{lower_modules, mir_callback_name, mir_param_name, unknown_name, Opts};
+
+/// Prints the equations of the DAE system of the modules in `opts.input` (after all
+/// optimizations) as expressions in the given `syntax`:
+///
+/// * `I[<unknown>]`/`Q[<unknown>]`: the resistive and reactive residual of each unknown
+/// * `dI[<row>]/d[<col>]`/`dQ[<row>]/d[<col>]`: the nonzero entries of the jacobian
+///
+/// Values that only depend on parameters are computed once during instance setup and read
+/// from a cache (`cache[<slot>]`). The equations of all cached values are printed first.
+/// If `unknowns` is not empty, only the residuals and jacobian rows of these unknowns are
+/// printed. Returns `None` if the compilation failed (the diagnostics were already printed).
+pub fn equations(opts: &Opts, syntax: Syntax, unknowns: &[String]) -> Result<Option<String>> {
+    let (db, modules) = if let Some(res) = lower_modules(opts)? {
+        res
+    } else {
+        return Ok(None);
+    };
+
+    let mut literals = Rodeo::new();
+    let mut found = vec![false; unknowns.len()];
+    let mut res = String::new();
+    for (i, module) in modules.iter().enumerate() {
+        let module = CompiledModule::new(&db, module, &mut literals, false);
+        if i != 0 {
+            res.push('\n');
+        }
+        write_module(&db, &module, &literals, syntax, unknowns, &mut found, &mut res)?;
+    }
+
+    if let Some((name, _)) = unknowns.iter().zip(&found).find(|(_, found)| !**found) {
+        bail!("no unknown called \"{name}\" exists");
+    }
+    Ok(Some(res))
+}
+
+fn write_module(
+    db: &CompilationDB,
+    module: &CompiledModule,
+    literals: &Rodeo,
+    syntax: Syntax,
+    unknowns: &[String],
+    found: &mut [bool],
+    dst: &mut String,
+) -> fmt::Result {
+    let mut writer = ExprWriter::new(syntax, literals);
+    writer.write_comment(dst, &format!("module {}", module.info.module.name(db)))?;
+
+    let init = &module.init;
+    if !init.cached_vals.is_empty() {
+        writer.write_comment(dst, "instance setup")?;
+        let cfg = ControlFlowGraph::with_function(&init.func);
+        writer.write_expressions(
+            dst,
+            &init.func,
+            &cfg,
+            |param| param_name(db, &init.intern, param),
+            |func_ref| callback_name(db, &init.intern, literals, func_ref),
+            init.cached_vals.iter().map(|(&val, &slot)| (format!("cache[{}]", slot.0), val)),
+        )?;
+    }
+
+    let names: TiVec<SimUnknown, String> =
+        module.dae_system.unknowns.iter().map(|&unknown| unknown_name(db, unknown)).collect();
+    let mut selected: TiVec<SimUnknown, bool> = vec![unknowns.is_empty(); names.len()].into();
+    for (name, found) in unknowns.iter().zip(found) {
+        if let Some(unknown) = names.iter().position(|it| it == name) {
+            selected[SimUnknown::from(unknown)] = true;
+            *found = true;
+        }
+    }
+
+    let mut outputs = Vec::new();
+    for (unknown, residual) in module.dae_system.residual.iter_enumerated() {
+        if !selected[unknown] {
+            continue;
+        }
+        let name = &names[unknown];
+        if residual.resist != F_ZERO {
+            outputs.push((format!("I[{name}]"), residual.resist));
+        }
+        if residual.react != F_ZERO {
+            outputs.push((format!("Q[{name}]"), residual.react));
+        }
+    }
+    for entry in &module.dae_system.jacobian {
+        if !selected[entry.row] {
+            continue;
+        }
+        let (row, col) = (&names[entry.row], &names[entry.col]);
+        if entry.resist != F_ZERO {
+            outputs.push((format!("dI[{row}]/d[{col}]"), entry.resist));
+        }
+        if entry.react != F_ZERO {
+            outputs.push((format!("dQ[{row}]/d[{col}]"), entry.react));
+        }
+    }
+
+    writer.write_comment(dst, "eval")?;
+    let cfg = ControlFlowGraph::with_function(&module.eval);
+    writer.write_expressions(
+        dst,
+        &module.eval,
+        &cfg,
+        |param| param_name(db, &module.intern, param),
+        |func_ref| callback_name(db, &module.intern, literals, func_ref),
+        outputs,
+    )
+}
+
+/// Parameters of `eval` that are not part of the `intern` are read from the cache.
+fn param_name(db: &CompilationDB, intern: &HirInterner, param: Param) -> String {
+    let kind = match intern.params.get_index(param) {
+        Some((kind, _)) => kind,
+        None => return format!("cache[{}]", usize::from(param) - intern.params.len()),
+    };
+    let (category, name) = mir_param_name(db, kind);
+    match category {
+        "voltages" => format!("V{name}"),
+        "currents" => format!("I{name}"),
+        "param_given" => format!("$param_given({name})"),
+        "port_connected" => format!("$port_connected({name})"),
+        _ => name,
+    }
+}
+
+fn callback_name(
+    db: &CompilationDB,
+    intern: &HirInterner,
+    literals: &Rodeo,
+    func_ref: FuncRef,
+) -> String {
+    let (kind, name) = mir_callback_name(db, intern, literals, &intern.callbacks[func_ref]);
+    match kind {
+        "limit" | "analog_function" => name,
+        "analog_function_derivative" => format!("ddx_{name}"),
+        _ => kind.to_owned(),
+    }
+}
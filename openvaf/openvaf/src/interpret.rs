@@ -0,0 +1,574 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::fmt::{self, Display};
+use std::mem::take;
+
+use anyhow::{bail, Context, Result};
+use hir::{CompilationDB, ParamSysFun, Type};
+use hir_lower::fmt::{FmtArg, FmtArgKind};
+use hir_lower::{CallBackKind, CurrentKind, HirInterner, ParamInfoKind, ParamKind, PlaceKind};
+use lasso::{Rodeo, Spur};
+use mir::{FuncRef, Function, Param, Value};
+use mir_interpret::{Data, Func, Interpreter, InterpreterState};
+use sim_back::dae::SimUnknown;
+use sim_back::{CompiledModule, SimUnknownKind};
+use typed_index_collections::{TiSlice, TiVec};
+
+use crate::{lower_modules, unknown_name, Opts};
+
+/// The point at which [`interpret`] evaluates a module
+#[derive(Debug, Clone)]
+pub struct OperatingPoint {
+    /// Values of the parameters (by name or alias) that are not left at their default.
+    /// Builtin parameters are called `$mfactor`, `$xposition`, ...
+    pub params: Vec<(String, String)>,
+    /// Values of the unknowns of the DAE system (see [`Evaluation::unknowns`] for their names).
+    /// All other unknowns are zero.
+    pub bias: Vec<(String, f64)>,
+    /// `$temperature` in Kelvin
+    pub temperature: f64,
+}
+
+impl Default for OperatingPoint {
+    fn default() -> Self {
+        OperatingPoint { params: Vec::new(), bias: Vec::new(), temperature: 300.15 }
+    }
+}
+
+/// The result of a DC evaluation of a single module with [`interpret`]
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub module: String,
+    /// The names of the unknowns of the DAE system: node potentials are named after the node,
+    /// branch currents are written as `I(<branch>)` and implicit equations as `implicit_equation_<n>`
+    pub unknowns: Vec<String>,
+    /// The resistive and reactive residual of each unknown
+    pub residual: Vec<(f64, f64)>,
+    /// The resistive and reactive part of all entries `(row, column)` of the jacobian
+    pub jacobian: Vec<((usize, usize), (f64, f64))>,
+    /// The operating point variables (name, value, unit)
+    pub opvars: Vec<(String, f64, String)>,
+    /// Text printed by the model (`$strobe`, `$display`, ...)
+    pub messages: Vec<String>,
+}
+
+impl Evaluation {
+    pub fn residual(&self, unknown: &str) -> Option<(f64, f64)> {
+        let idx = self.unknowns.iter().position(|it| it == unknown)?;
+        Some(self.residual[idx])
+    }
+
+    pub fn jacobian(&self, row: &str, col: &str) -> Option<(f64, f64)> {
+        let row = self.unknowns.iter().position(|it| it == row)?;
+        let col = self.unknowns.iter().position(|it| it == col)?;
+        self.jacobian.iter().find(|(entry, _)| *entry == (row, col)).map(|(_, val)| *val)
+    }
+
+    pub fn opvar(&self, name: &str) -> Option<f64> {
+        self.opvars.iter().find(|(it, _, _)| it == name).map(|(_, val, _)| *val)
+    }
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for msg in &self.messages {
+            write!(f, "{msg}")?;
+        }
+
+        writeln!(f, "module {}", self.module)?;
+        let width = self.unknowns.iter().map(String::len).max().unwrap_or(0).max(7);
+        writeln!(f, "\n  {:<width$}  {:>14}  {:>14}", "residual", "resist", "react")?;
+        for (name, (resist, react)) in self.unknowns.iter().zip(&self.residual) {
+            writeln!(f, "  {name:<width$}  {resist:>14.6e}  {react:>14.6e}")?;
+        }
+
+        let entries: Vec<_> = self
+            .jacobian
+            .iter()
+            .map(|&((row, col), val)| {
+                (format!("({}, {})", self.unknowns[row], self.unknowns[col]), val)
+            })
+            .collect();
+        let width = entries.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(8);
+        writeln!(f, "\n  {:<width$}  {:>14}  {:>14}", "jacobian", "resist", "react")?;
+        for (name, (resist, react)) in &entries {
+            writeln!(f, "  {name:<width$}  {resist:>14.6e}  {react:>14.6e}")?;
+        }
+
+        if !self.opvars.is_empty() {
+            let width = self.opvars.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0).max(6);
+            writeln!(f, "\n  {:<width$}  {:>14}", "opvars", "value")?;
+            for (name, val, unit) in &self.opvars {
+                writeln!(f, "  {name:<width$}  {val:>14.6e}  {unit}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates the modules of `opts.input` at a DC operating point with the MIR interpreter
+/// instead of compiling them with LLVM. The model parameters are initialized, the
+/// instance is set up and the residual, jacobian and operating point variables are computed.
+/// Limiting is disabled and `analysis("dc")`/`analysis("static")` are true. Returns `None`
+/// if the compilation failed (the diagnostics were already printed).
+pub fn interpret(opts: &Opts, point: &OperatingPoint) -> Result<Option<Vec<Evaluation>>> {
+    let (db, modules) = if let Some(res) = lower_modules(opts)? {
+        res
+    } else {
+        return Ok(None);
+    };
+
+    let mut literals = Rodeo::new();
+    let mut found_params = vec![false; point.params.len()];
+    let mut found_bias = vec![false; point.bias.len()];
+    let mut res = Vec::with_capacity(modules.len());
+    for module in &modules {
+        let module = CompiledModule::new(&db, module, &mut literals, false);
+        let mut inputs = Inputs::new(&module, point.temperature);
+        for ((name, val), found) in point.params.iter().zip(&mut found_params) {
+            *found |= inputs.set_param(&db, &module, &mut literals, name, val)?;
+        }
+        for ((name, val), found) in point.bias.iter().zip(&mut found_bias) {
+            *found |= inputs.set_bias(&db, &module, name, *val);
+        }
+        let name = module.info.module.name(&db);
+        let evaluation = evaluate(&db, &module, &mut literals, &mut inputs)
+            .with_context(|| format!("failed to evaluate module {name}"))?;
+        res.push(evaluation);
+    }
+
+    if let Some(((name, _), _)) = point.params.iter().zip(&found_params).find(|(_, found)| !**found)
+    {
+        bail!("no parameter called \"{name}\" exists");
+    }
+    if let Some(((name, _), _)) = point.bias.iter().zip(&found_bias).find(|(_, found)| !**found) {
+        bail!("no unknown called \"{name}\" exists");
+    }
+
+    Ok(Some(res))
+}
+
+fn evaluate(
+    db: &CompilationDB,
+    module: &CompiledModule,
+    literals: &mut Rodeo,
+    inputs: &mut Inputs,
+) -> Result<Evaluation> {
+    let empty_str = literals.get_or_intern_static("");
+    let sim = Simulator {
+        db,
+        literals,
+        empty_str,
+        messages: RefCell::new(Vec::new()),
+        errors: RefCell::new(Vec::new()),
+    };
+
+    // model parameters (including the defaults of instance parameters)
+    let intern = &module.model_param_intern;
+    let args: TiVec<Param, Data> =
+        intern.params.iter().map(|(kind, _)| inputs.param(module, kind)).collect();
+    let state = sim.run(&module.model_param_setup, intern, &args)?;
+    inputs.read_params(module, intern, &state);
+
+    // instance setup, fills the cache that is used by eval
+    let intern = &module.init.intern;
+    let args: TiVec<Param, Data> =
+        intern.params.iter().map(|(kind, _)| inputs.param(module, kind)).collect();
+    let state = sim.run(&module.init.func, intern, &args)?;
+    inputs.read_params(module, intern, &state);
+    let mut cache = vec![Data::UNDEF; module.init.cache_slots.len()];
+    for (&val, &slot) in module.init.cached_vals.iter() {
+        cache[usize::from(slot)] = state.read(val);
+    }
+
+    let intern = &module.intern;
+    let args: TiVec<Param, Data> =
+        intern.params.iter().map(|(kind, _)| inputs.param(module, kind)).chain(cache).collect();
+    let state = sim.run(&module.eval, intern, &args)?;
+
+    let unknowns =
+        module.dae_system.unknowns.iter().map(|&unknown| unknown_name(db, unknown)).collect();
+    let residual = module
+        .dae_system
+        .residual
+        .iter()
+        .map(|residual| (state.read(residual.resist), state.read(residual.react)))
+        .collect();
+    let jacobian = module
+        .dae_system
+        .jacobian
+        .iter()
+        .map(|entry| {
+            let pos = (usize::from(entry.row), usize::from(entry.col));
+            (pos, (state.read(entry.resist), state.read(entry.react)))
+        })
+        .collect();
+    let opvars = module
+        .info
+        .op_vars
+        .iter()
+        .filter_map(|(&var, info)| {
+            let val = module.intern.outputs.get(&PlaceKind::Var(var))?.expand()?;
+            let val = match var.ty(db) {
+                Type::Real => state.read(val),
+                Type::Integer => state.read::<i32>(val) as f64,
+                _ => return None,
+            };
+            Some((var.name(db).to_string(), val, info.unit.clone()))
+        })
+        .collect();
+
+    Ok(Evaluation {
+        module: module.info.module.name(db),
+        unknowns,
+        residual,
+        jacobian,
+        opvars,
+        messages: sim.messages.into_inner(),
+    })
+}
+
+/// The values that the simulator would pass to the MIR functions of a module
+struct Inputs {
+    /// The value of each parameter of the module. Parameters that were not specified
+    /// are undefined until they were computed by the model parameter setup.
+    params: Vec<Data>,
+    given: Vec<bool>,
+    sys_fun: Vec<(ParamSysFun, f64)>,
+    unknowns: TiVec<SimUnknown, f64>,
+    temperature: f64,
+}
+
+impl Inputs {
+    fn new(module: &CompiledModule, temperature: f64) -> Inputs {
+        Inputs {
+            params: vec![Data::UNDEF; module.info.params.len()],
+            given: vec![false; module.info.params.len()],
+            sys_fun: Vec::new(),
+            unknowns: vec![0.0; module.dae_system.unknowns.len()].into(),
+            temperature,
+        }
+    }
+
+    /// Sets the parameter called `name` to `val`, returns `false` if the module
+    /// has no such parameter.
+    fn set_param(
+        &mut self,
+        db: &CompilationDB,
+        module: &CompiledModule,
+        literals: &mut Rodeo,
+        name: &str,
+        val: &str,
+    ) -> Result<bool> {
+        let sys_fun =
+            ParamSysFun::iter().find(|param| format!("${param:?}") == name).or_else(|| {
+                module.info.sys_fun_alias.iter().find_map(|(&param, alias)| {
+                    alias.iter().any(|alias| alias == name).then_some(param)
+                })
+            });
+        if let Some(param) = sys_fun {
+            let val = val
+                .parse()
+                .with_context(|| format!("invalid value \"{val}\" for parameter {name}"))?;
+            self.sys_fun.push((param, val));
+            return Ok(true);
+        }
+
+        let pos = module
+            .info
+            .params
+            .values()
+            .position(|info| info.name == name || info.alias.iter().any(|alias| alias == name));
+        let pos = if let Some(pos) = pos { pos } else { return Ok(false) };
+        let param = *module.info.params.get_index(pos).unwrap().0;
+        let err = || format!("invalid value \"{val}\" for parameter {name}");
+        self.params[pos] = match param.ty(db) {
+            Type::Real => val.parse::<f64>().with_context(err)?.into(),
+            Type::Integer => val.parse::<i32>().with_context(err)?.into(),
+            Type::String => literals.get_or_intern(val).into(),
+            ty => bail!("parameter {name} has type {ty:?} which is not supported"),
+        };
+        self.given[pos] = true;
+        Ok(true)
+    }
+
+    /// Sets the unknown called `name` to `val`, returns `false` if the module
+    /// has no such unknown.
+    fn set_bias(
+        &mut self,
+        db: &CompilationDB,
+        module: &CompiledModule,
+        name: &str,
+        val: f64,
+    ) -> bool {
+        let unknown = module
+            .dae_system
+            .unknowns
+            .iter_enumerated()
+            .find(|(_, unknown)| unknown_name(db, **unknown) == name);
+        if let Some((unknown, _)) = unknown {
+            self.unknowns[unknown] = val;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unknown(&self, module: &CompiledModule, unknown: SimUnknownKind) -> f64 {
+        // unknowns that are not part of the DAE system are always zero
+        module.dae_system.unknowns.index(&unknown).map_or(0.0, |unknown| self.unknowns[unknown])
+    }
+
+    fn param(&self, module: &CompiledModule, kind: &ParamKind) -> Data {
+        match *kind {
+            ParamKind::Param(param) => {
+                self.params[module.info.params.get_index_of(&param).unwrap()]
+            }
+            ParamKind::ParamGiven { param } => {
+                self.given[module.info.params.get_index_of(&param).unwrap()].into()
+            }
+            ParamKind::ParamSysFun(param) => {
+                let val = self.sys_fun.iter().rev().find(|(it, _)| *it == param);
+                val.map_or_else(|| param.default_value(), |(_, val)| *val).into()
+            }
+            ParamKind::Voltage { hi, lo } => {
+                let hi = self.unknown(module, SimUnknownKind::KirchoffLaw(hi));
+                let lo = lo.map_or(0.0, |lo| self.unknown(module, SimUnknownKind::KirchoffLaw(lo)));
+                (hi - lo).into()
+            }
+            ParamKind::Current(CurrentKind::Port(_)) => 0f64.into(),
+            ParamKind::Current(kind) => self.unknown(module, SimUnknownKind::Current(kind)).into(),
+            ParamKind::ImplicitUnknown(equation) => {
+                self.unknown(module, SimUnknownKind::Implicit(equation)).into()
+            }
+            ParamKind::Temperature => self.temperature.into(),
+            ParamKind::PortConnected { .. } => true.into(),
+            ParamKind::Abstime | ParamKind::PrevState(_) | ParamKind::NewState(_) => 0f64.into(),
+            ParamKind::EnableIntegration | ParamKind::EnableLim => false.into(),
+            ParamKind::HiddenState(_) | ParamKind::FunctionArg(_) => unreachable!(),
+        }
+    }
+
+    /// Reads the final value of all parameters that were initialized by `intern`
+    fn read_params(
+        &mut self,
+        module: &CompiledModule,
+        intern: &HirInterner,
+        state: &InterpreterState,
+    ) {
+        for (&param, dst) in module.info.params.keys().zip(&mut self.params) {
+            if let Some(val) =
+                intern.outputs.get(&PlaceKind::Param(param)).and_then(|it| it.expand())
+            {
+                *dst = state.read(val);
+            }
+        }
+    }
+}
+
+/// Implements the callbacks that the simulator provides to the compiled model
+struct Simulator<'a> {
+    db: &'a CompilationDB,
+    literals: &'a Rodeo,
+    empty_str: Spur,
+    messages: RefCell<Vec<String>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl Simulator<'_> {
+    fn run(
+        &self,
+        func: &Function,
+        intern: &HirInterner,
+        args: &TiSlice<Param, Data>,
+    ) -> Result<InterpreterState> {
+        let callbacks: TiVec<FuncRef, _> =
+            intern.callbacks.iter().map(|kind| Callback { kind, intern, sim: self }).collect();
+        let calls: TiVec<FuncRef, (Func, *mut c_void)> = callbacks
+            .iter()
+            .map(|cb| (call_callback as Func, cb as *const Callback as *mut c_void))
+            .collect();
+        let mut interpreter = Interpreter::new(func, &calls, args);
+        interpreter.run();
+
+        let errors = take(&mut *self.errors.borrow_mut());
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"))
+        }
+        Ok(interpreter.state)
+    }
+}
+
+struct Callback<'a> {
+    kind: &'a CallBackKind,
+    intern: &'a HirInterner,
+    sim: &'a Simulator<'a>,
+}
+
+fn call_callback(state: &mut InterpreterState, args: &[Value], rets: &[Value], data: *mut c_void) {
+    // Safety: data always points to a `Callback` that outlives the interpreter (see `Simulator::run`)
+    let cb = unsafe { &*(data as *const Callback) };
+    let sim = cb.sim;
+    match cb.kind {
+        // the derivatives were already computed during compilation, remaining calls are zero
+        CallBackKind::Derivative(_)
+        | CallBackKind::NodeDerivative(_)
+        | CallBackKind::TimeDerivative
+        // noise is not evaluated
+        | CallBackKind::WhiteNoise { .. }
+        | CallBackKind::FlickerNoise { .. }
+        | CallBackKind::NoiseTable(_) => {
+            for &ret in rets {
+                state.write(ret, 0f64)
+            }
+        }
+        CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) => {
+            sim.errors.borrow_mut().push(format!("parameter {} is out of bounds", param.name(sim.db)))
+        }
+        CallBackKind::ParamInfo(..)
+        | CallBackKind::CollapseHint(..)
+        | CallBackKind::LimDiscontinuity => (),
+        // limiting is disabled, the unlimited value is returned
+        CallBackKind::BuiltinLimit { .. } | CallBackKind::StoreLimit(_) => {
+            let val: Data = state.read(args[0]);
+            state.write(rets[0], val)
+        }
+        CallBackKind::Analysis => {
+            let name = &sim.literals[state.read::<Spur>(args[0])];
+            state.write(rets[0], matches!(name, "dc" | "static"))
+        }
+        CallBackKind::SimParam | CallBackKind::SimParamStr => {
+            let name = &sim.literals[state.read::<Spur>(args[0])];
+            sim.errors.borrow_mut().push(format!("unknown simulator parameter \"{name}\""));
+            if matches!(cb.kind, CallBackKind::SimParam) {
+                state.write(rets[0], 0f64)
+            } else {
+                state.write(rets[0], sim.empty_str)
+            }
+        }
+        CallBackKind::SimParamOpt => {
+            let default: Data = state.read(args[1]);
+            state.write(rets[0], default)
+        }
+        CallBackKind::Print { arg_tys, .. } => {
+            let fmt = &sim.literals[state.read::<Spur>(args[0])];
+            let args: Vec<Data> = args[1..].iter().map(|&arg| state.read(arg)).collect();
+            let msg = format_print(fmt, arg_tys, &args, sim.literals);
+            sim.messages.borrow_mut().push(msg);
+        }
+        CallBackKind::AnalogFunction { fun, .. } => {
+            let fun = &cb.intern.analog_functions[fun];
+            call_analog_function(state, &fun.func, &fun.returns, args, rets)
+        }
+        CallBackKind::AnalogFunctionDerivative { fun, .. } => {
+            let fun = &cb.intern.analog_functions[fun];
+            let (func, partials) =
+                fun.derivative.as_ref().expect("derivative of analog function was not generated");
+            call_analog_function(state, func, partials, args, rets)
+        }
+    }
+}
+
+/// Analog functions that were not inlined never call any callbacks
+fn call_analog_function(
+    state: &mut InterpreterState,
+    func: &Function,
+    returns: &[Value],
+    args: &[Value],
+    rets: &[Value],
+) {
+    let args: TiVec<Param, Data> = args.iter().map(|&arg| state.read(arg)).collect();
+    let mut interpreter = Interpreter::new(func, TiSlice::from_ref(&[]), &args);
+    interpreter.run();
+    for (&dst, &val) in rets.iter().zip(returns) {
+        let val: Data = interpreter.state.read(val);
+        state.write(dst, val)
+    }
+}
+
+/// Formats the arguments of a `$display` like call. `fmt` uses the printf syntax of C
+/// (see [`hir_lower::fmt`]). Flags and field widths are ignored.
+fn format_print(fmt: &str, arg_tys: &[FmtArg], args: &[Data], literals: &Rodeo) -> String {
+    let mut res = String::with_capacity(fmt.len());
+    let mut args = arg_tys.iter().zip(args);
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+
+        let mut precision = None;
+        let conv = loop {
+            match chars.next() {
+                Some('%') => break '%',
+                Some('.') => precision = Some(0),
+                Some(c @ '0'..='9') => {
+                    if let Some(precision) = &mut precision {
+                        *precision = *precision * 10 + c.to_digit(10).unwrap() as usize
+                    }
+                }
+                Some('*') => {
+                    let width = args.next().map_or(0, |(_, arg)| arg.i32().max(0) as usize);
+                    if let Some(precision) = &mut precision {
+                        *precision = width
+                    }
+                }
+                Some(c) if c.is_ascii_alphabetic() => break c,
+                Some(_) => (),
+                None => return res,
+            }
+        };
+        if conv == '%' {
+            res.push('%');
+            continue;
+        }
+
+        let (ty, &arg) = if let Some(arg) = args.next() { arg } else { break };
+        let precision = precision.unwrap_or(6);
+        match conv {
+            'f' | 'F' if ty.kind == FmtArgKind::EngineerReal => {
+                // followed by `%c` for the scale factor
+                chars.next();
+                chars.next();
+                res.push_str(&format_engineering(arg.f64(), precision))
+            }
+            's' if ty.kind == FmtArgKind::Binary => res.push_str(&format!("{:b}", arg.i32())),
+            's' => res.push_str(&literals[arg.str()]),
+            'd' | 'i' => res.push_str(&arg.i32().to_string()),
+            'x' => res.push_str(&format!("{:x}", arg.i32())),
+            'X' => res.push_str(&format!("{:X}", arg.i32())),
+            'o' => res.push_str(&format!("{:o}", arg.i32())),
+            'c' => res.extend(char::from_u32(arg.i32() as u32)),
+            'e' => res.push_str(&format!("{:.*e}", precision, arg.f64())),
+            'E' => res.push_str(&format!("{:.*E}", precision, arg.f64())),
+            'f' | 'F' => res.push_str(&format!("{:.*}", precision, arg.f64())),
+            _ => res.push_str(&arg.f64().to_string()),
+        }
+    }
+    res
+}
+
+/// Formats `val` with a scale factor (`1.5m` instead of `1.5e-3`)
+fn format_engineering(val: f64, precision: usize) -> String {
+    const SCALE_FACTORS: [(f64, &str); 11] = [
+        (1e12, "T"),
+        (1e9, "G"),
+        (1e6, "M"),
+        (1e3, "k"),
+        (1.0, ""),
+        (1e-3, "m"),
+        (1e-6, "u"),
+        (1e-9, "n"),
+        (1e-12, "p"),
+        (1e-15, "f"),
+        (1e-18, "a"),
+    ];
+    let (scale, suffix) = if val == 0.0 || !val.is_finite() {
+        (1.0, "")
+    } else {
+        let smallest = SCALE_FACTORS[SCALE_FACTORS.len() - 1];
+        SCALE_FACTORS.into_iter().find(|(scale, _)| val.abs() >= *scale).unwrap_or(smallest)
+    };
+    format!("{:.*}{suffix}", precision, val / scale)
+}
@@ -1,38 +1,87 @@
-use std::fs::{create_dir_all, remove_file};
+use std::fs::{remove_file, write};
 use std::io::Write;
-use std::time::Instant;
+use std::slice;
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use basedb::diagnostics::{ConsoleSink, DiagnosticSink};
-use basedb::BaseDB;
-use camino::Utf8PathBuf;
+use basedb::{BaseDB, FileId};
+use camino::{Utf8Path, Utf8PathBuf};
 use hir::CompilationDB;
+use hir_lower::{CallBackKind, CurrentKind, HirInterner, ParamKind, PlaceKind};
+use lasso::Rodeo;
 use linker::link;
 use mir_llvm::LLVMBackend;
-use sim_back::collect_modules;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use sim_back::{build_opvar_mir, collect_modules, ModuleInfo, SimUnknownKind};
+use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::cache::CacheKey;
 
 pub use basedb::lints::builtin as builtin_lints;
 pub use basedb::lints::LintLevel;
+pub use cache::{Cache, CacheEntry, DEFAULT_CACHE_SIZE};
+pub use equations::equations;
+pub use interpret::{interpret, Evaluation, OperatingPoint};
+pub use linker::LinkerKind;
 pub use llvm::OptLevel;
+pub use mir::write::Syntax;
+pub use mir_llvm::FloatPolicy;
+pub use osdi::EmitKind;
 pub use paths::AbsPathBuf;
+pub use sim_back::{AutoDiffMode, AutoDiffOptions};
 pub use target::host_triple;
 pub use target::spec::{get_target_names, Target};
 
 mod cache;
+mod equations;
+mod interpret;
 
 #[derive(Debug, Clone)]
 pub enum CompilationDestination {
-    Path { lib_file: Utf8PathBuf },
-    Cache { cache_dir: Utf8PathBuf },
+    Path {
+        lib_file: Utf8PathBuf,
+    },
+    /// Content addressed cache (batchmode), see [`Cache`]. When the libraries in the
+    /// cache exceed `max_size` bytes the least recently used ones are removed.
+    Cache {
+        cache_dir: Utf8PathBuf,
+        max_size: u64,
+    },
 }
 
 pub enum CompilationTermination {
-    Compiled { lib_file: Utf8PathBuf },
+    Compiled {
+        lib_file: Utf8PathBuf,
+    },
+    /// One library was compiled for each module (see [`Opts::split_modules`])
+    CompiledModules {
+        lib_files: Vec<Utf8PathBuf>,
+    },
     FatalDiagnostic,
 }
 
+/// The code generator used to compile the modules
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Backend {
+    /// Compile to a shared library with LLVM
+    #[default]
+    Llvm,
+    /// Emit portable C99 source code (see [`osdi::compile_c`]) that implements the OSDI
+    /// interface and can be compiled with any C compiler
+    C,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Llvm, Backend::C];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Llvm => "llvm",
+            Backend::C => "c",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Opts {
     pub dry_run: bool,
@@ -45,71 +94,203 @@ pub struct Opts {
     pub opt_lvl: OptLevel,
     pub target: Target,
     pub target_cpu: String,
+    /// Names of the parameters by which the residual is differentiated (parameter sensitivities)
+    pub sensitivities: Vec<String>,
+    /// Names of the nodes whose residual is differentiated twice (second order jacobian)
+    pub hessian: Vec<String>,
+    /// Only lower the modules and write a JSON description of their interface to the output
+    /// with the extension `.json` (see [`osdi::INTERFACE_VERSION`]) instead of a library
+    pub dump_json: bool,
+    /// Intermediate representations that are written next to the library (`<lib>.<unit>.<ext>`).
+    /// Ignored when compiling into the cache.
+    pub emit: Vec<EmitKind>,
+    /// Names of the modules that are compiled (all modules if empty)
+    pub modules: Vec<String>,
+    /// Compile one library per module instead of a single library for all modules. The
+    /// libraries are named after the modules (`<module>.osdi` or `<module>.wasm`) and placed
+    /// next to the output.
+    /// Not supported when compiling into the cache.
+    pub split_modules: bool,
+    /// Linker used to create the library (LLD linked into openvaf or the system linker)
+    pub linker: LinkerKind,
+    /// Produce byte-identical libraries on every machine: `target_cpu` is ignored (the generic
+    /// CPU of the target is used instead) and the linker does not embed timestamps or build ids.
+    /// Can not be combined with `debug_info` or `check_fp` (both embed absolute paths).
+    pub reproducible: bool,
+    /// How the derivatives of the residual are computed (see [`AutoDiffOptions`])
+    pub autodiff: AutoDiffOptions,
+    /// Which fast-math optimizations are allowed (see [`FloatPolicy`]). Analog functions
+    /// can overwrite this with the `fp_policy` attribute.
+    pub fp_policy: FloatPolicy,
+    /// Report the first `NaN`/`Inf` computed during each evaluation of a model together with
+    /// the location in the Verilog-A source that produced it (debug mode)
+    pub check_fp: bool,
+    /// Emit debug information so that debuggers can step trough the Verilog-A source
+    pub debug_info: bool,
+    /// Represent `absdelay` symbolically for frequency domain simulators: each call adds an
+    /// implicit equation and is listed in the `delays` table of the OSDI descriptor.
+    /// Otherwise the delay is ignored (which keeps the nodes of existing models unchanged).
+    pub symbolic_delays: bool,
+    /// The code generator, [`Backend::C`] writes C source code to the output instead of
+    /// a library
+    pub backend: Backend,
+}
+/// Serializes the MIR of the operating point function of every module as json (see
+/// [`mir::Function::to_json`] for the schema). The files are written next to the output as
+/// `<output>_<module>.json`. The inputs of the function are grouped into the following
+/// categories:
+///
+/// * `parameters`: model/instance parameters and builtin parameters like `$mfactor`
+/// * `param_given`: whether a parameter was explicitly specified (`$param_given`)
+/// * `port_connected`: whether a port is connected (`$port_connected`)
+/// * `voltages`: node potentials `(hi)` and potential differences `(hi, lo)`
+/// * `currents`: currents of named branches and unnamed branches `(hi, lo)`
+/// * `implicit_unknowns`: unknowns of implicit equations (e.g. from `idt`)
+/// * `sim_state`: `$temperature`, `$abstime` and the flags `$enable_integration` (false
+///   during DC/IC analysis, `idt` returns its initial condition) and `$enable_lim`
+///   (`$limit` may be applied)
+/// * `limit_states`: previous (`prev[..]`) and limited (`new[..]`) values of `$limit` calls
+/// * `hidden_state`: variables that retain their value between evaluations
+///
+/// All variables are exported as `outputs`.
+pub fn dump_mir_json(opts: &Opts) -> Result<CompilationTermination> {
+    let lib_file = match &opts.output {
+        CompilationDestination::Path { lib_file } => lib_file,
+        CompilationDestination::Cache { .. } => {
+            bail!("dumping MIR as json is not supported in batchmode")
+        }
+    };
+    let input = resolve_input(opts)?;
+    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+    let modules = if let Some(modules) = collect_modules(&db, true, &mut ConsoleSink::new(&db)) {
+        modules
+    } else {
+        return Ok(CompilationTermination::FatalDiagnostic);
+    };
+    let mut literals = Rodeo::new();
+    for module in &modules {
+        let (func, intern, cfg) = build_opvar_mir(&db, module, &mut literals);
+        let json = func.to_json(
+            &cfg,
+            &literals,
+            |param| mir_param_name(&db, intern.params.get_index(param).unwrap().0),
+            |func_ref| mir_callback_name(&db, &intern, &literals, &intern.callbacks[func_ref]),
+            intern.outputs.iter().filter_map(|(kind, val)| {
+                let name = match *kind {
+                    PlaceKind::Var(var) => var.name(&db).to_string(),
+                    _ => return None,
+                };
+                Some((name, val.expand()?))
+            }),
+        );
+        let path = lib_file.with_file_name(format!(
+            "{}_{}.json",
+            lib_file.file_stem().unwrap(),
+            module.module.name(&db)
+        ));
+        if !opts.dry_run {
+            write(&path, json).with_context(|| format!("failed to write {path}"))?;
+        }
+    }
+    Ok(CompilationTermination::Compiled { lib_file: Utf8PathBuf::default() })
+}
+
+fn mir_param_name(db: &CompilationDB, kind: &ParamKind) -> (&'static str, String) {
+    match *kind {
+        ParamKind::Param(param) => ("parameters", param.name(db)),
+        ParamKind::ParamSysFun(param) => ("parameters", format!("${param:?}")),
+        ParamKind::ParamGiven { param } => ("param_given", param.name(db)),
+        ParamKind::PortConnected { port } => ("port_connected", port.name(db).to_string()),
+        ParamKind::Voltage { hi, lo: Some(lo) } => {
+            ("voltages", format!("({}, {})", hi.name(db), lo.name(db)))
+        }
+        ParamKind::Voltage { hi, lo: None } => ("voltages", format!("({})", hi.name(db))),
+        ParamKind::Current(CurrentKind::Unnamed { hi, lo: Some(lo) }) => {
+            ("currents", format!("({}, {})", hi.name(db), lo.name(db)))
+        }
+        ParamKind::Current(CurrentKind::Unnamed { hi, lo: None }) => {
+            ("currents", format!("({})", hi.name(db)))
+        }
+        ParamKind::Current(CurrentKind::Branch(br)) => ("currents", br.name(db)),
+        ParamKind::Current(CurrentKind::Port(port)) => {
+            ("currents", format!("(<{}>)", port.name(db)))
+        }
+        ParamKind::ImplicitUnknown(equation) => ("implicit_unknowns", equation.to_string()),
+        ParamKind::Temperature => ("sim_state", "$temperature".to_owned()),
+        ParamKind::Abstime => ("sim_state", "$abstime".to_owned()),
+        ParamKind::EnableIntegration => ("sim_state", "$enable_integration".to_owned()),
+        ParamKind::EnableLim => ("sim_state", "$enable_lim".to_owned()),
+        ParamKind::PrevState(state) => ("limit_states", format!("prev[{state}]")),
+        ParamKind::NewState(state) => ("limit_states", format!("new[{state}]")),
+        ParamKind::HiddenState(var) => ("hidden_state", var.name(db).to_string()),
+        ParamKind::FunctionArg(arg) => {
+            ("function_args", format!("{}.{}", arg.function().name(db), arg.name(db)))
+        }
+    }
+}
+
+fn mir_callback_name(
+    db: &CompilationDB,
+    intern: &HirInterner,
+    literals: &Rodeo,
+    kind: &CallBackKind,
+) -> (&'static str, String) {
+    match kind {
+        CallBackKind::TimeDerivative => ("ddt", String::new()),
+        CallBackKind::Derivative(param) => {
+            ("ddx", mir_param_name(db, intern.params.get_index(*param).unwrap().0).1)
+        }
+        CallBackKind::NodeDerivative(node) => ("ddx", format!("({})", node.name(db))),
+        CallBackKind::WhiteNoise { name, .. } => ("white_noise", literals[*name].to_owned()),
+        CallBackKind::FlickerNoise { name, .. } => ("flicker_noise", literals[*name].to_owned()),
+        CallBackKind::NoiseTable(table) => {
+            let kind = if table.log { "noise_table_log" } else { "noise_table" };
+            (kind, literals[table.name].to_owned())
+        }
+        CallBackKind::BuiltinLimit { name, .. } => ("limit", literals[*name].to_owned()),
+        CallBackKind::StoreLimit(state) => ("store_limit", state.to_string()),
+        CallBackKind::LimDiscontinuity => ("discontinuity", "-1".to_owned()),
+        CallBackKind::Analysis => ("analysis", String::new()),
+        CallBackKind::SimParam => ("simparam", String::new()),
+        CallBackKind::SimParamOpt => ("simparam_opt", String::new()),
+        CallBackKind::SimParamStr => ("simparam_str", String::new()),
+        CallBackKind::ParamInfo(info, param) => {
+            ("param_info", format!("{info:?}({})", param.name(db)))
+        }
+        CallBackKind::CollapseHint(hi, Some(lo)) => {
+            ("collapse_hint", format!("({}, {})", hi.name(db), lo.name(db)))
+        }
+        CallBackKind::CollapseHint(hi, None) => ("collapse_hint", format!("({})", hi.name(db))),
+        CallBackKind::Print { kind, .. } => ("print", format!("{kind:?}")),
+        CallBackKind::AnalogFunction { fun, .. } => ("analog_function", fun.name(db)),
+        CallBackKind::AnalogFunctionDerivative { fun, .. } => {
+            ("analog_function_derivative", fun.name(db))
+        }
+    }
+}
+
+/// The name of `unknown` (node names and `I(<branch>)` for branch currents)
+fn unknown_name(db: &CompilationDB, unknown: SimUnknownKind) -> String {
+    match unknown {
+        SimUnknownKind::KirchoffLaw(node) => node.name(db).to_string(),
+        SimUnknownKind::Current(CurrentKind::Branch(br)) => format!("I({})", br.name(db)),
+        SimUnknownKind::Current(CurrentKind::Unnamed { hi, lo: Some(lo) }) => {
+            format!("I({}, {})", hi.name(db), lo.name(db))
+        }
+        SimUnknownKind::Current(CurrentKind::Unnamed { hi, lo: None }) => {
+            format!("I({})", hi.name(db))
+        }
+        SimUnknownKind::Current(CurrentKind::Port(port)) => format!("I(<{}>)", port.name(db)),
+        SimUnknownKind::Implicit(equation) => {
+            format!("implicit_equation_{}", u32::from(equation))
+        }
+    }
 }
-// pub fn dump_json(opts: &Opts) -> Result<CompilationTermination> {
-//     let input =
-//         opts.input.canonicalize().with_context(|| format!("failed to resolve {}", opts.input))?;
-//     let input = AbsPathBuf::assert(input);
-//     let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
-//     let modules = if let Some(modules) = collect_modules(&db, true, &mut ConsoleSink::new(&db)) {
-//         modules
-//     } else {
-//         return Ok(CompilationTermination::FatalDiagnostic);
-//     };
-//     for module in modules {
-//         let (func, intern, strings, cfg) = module.build_opvar_mir(&db);
-//         let json = func.to_json(
-//             &cfg,
-//             &strings,
-//             |param| match *intern.params.get_index(param).unwrap().0 {
-//                 ParamKind::Param(param) => ("parameters", param.name(&db)),
-//                 ParamKind::Abstime => ("sim_state", "$abstime".to_owned()),
-//                 ParamKind::EnableIntegration => todo!(),
-//                 ParamKind::Voltage { hi, lo: Some(lo) } => {
-//                     ("voltages", format!("({}, {})", &hi.name(&db), &lo.name(&db)))
-//                 }
-//                 ParamKind::Voltage { hi, lo: None } => ("voltages", format!("({})", &hi.name(&db))),
-//                 ParamKind::Current(hir_lower::CurrentKind::Unnamed { hi, lo: Some(lo) }) => {
-//                     ("currents", format!("({}, {})", &hi.name(&db), &lo.name(&db)))
-//                 }
-//                 ParamKind::Current(hir_lower::CurrentKind::Unnamed { hi, lo: None }) => {
-//                     ("currents", format!("({})", hi.name(&db)))
-//                 }
-//                 ParamKind::Current(hir_lower::CurrentKind::Branch(br)) => {
-//                     ("currents", br.name(&db))
-//                 }
-//                 ParamKind::Temperature => ("sim_state", "$temperature".to_owned()),
-//                 ParamKind::ParamGiven { param } => ("param_given", param.name(&db)),
-//                 ParamKind::PortConnected { port } => ("port_connected", port.name(&db).to_string()),
-//                 ParamKind::ParamSysFun(param) => ("params", format!("${param:?}")),
-//                 _ => unreachable!(),
-//             },
-//             intern.outputs.iter().filter_map(|(kind, val)| {
-//                 let name = match *kind {
-//                     PlaceKind::Var(var) => var.name(&db).to_string(),
-//                     _ => return None,
-//                 };
-//                 Some((name, val.expand()?))
-//             }),
-//         );
-//         let path = opts.input.with_file_name(format!(
-//             "{}_{}.json",
-//             opts.input.file_stem().unwrap(),
-//             module.module.name(&db)
-//         ));
-//         if !opts.dry_run {
-//             std::fs::write(path, json)?;
-//         }
-//     }
-//     Ok(CompilationTermination::Compiled { lib_file: Utf8PathBuf::default() })
-// }
 
 pub fn expand(opts: &Opts) -> Result<CompilationTermination> {
     let start = Instant::now();
 
-    let input =
-        opts.input.canonicalize().with_context(|| format!("failed to resolve {}", opts.input))?;
-    let input = AbsPathBuf::assert(input);
+    let input = resolve_input(opts)?;
     let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
     let cu = db.compilation_unit();
 
@@ -140,53 +321,294 @@ pub fn expand(opts: &Opts) -> Result<CompilationTermination> {
 
 pub fn compile(opts: &Opts) -> Result<CompilationTermination> {
     let start = Instant::now();
+    let input = resolve_input(opts)?;
+    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+    compile_db(&db, opts, start, &mut StandardStream::stderr(ColorChoice::Auto))
+}
+
+/// The result of compiling one input with [`compile_batch`]
+pub struct BatchCompilation {
+    pub input: Utf8PathBuf,
+    pub result: Result<CompilationTermination>,
+    /// Time spent compiling this input (inputs are compiled in parallel)
+    pub time: Duration,
+}
 
+/// Compiles multiple inputs in parallel. The `opts` must only differ in their `input` and
+/// `output`. All inputs are added to a single database so that files that are used by
+/// multiple inputs (like shared headers) are only read once. Errors are reported for each
+/// input individually and do not abort the remaining compilations. The diagnostics of each
+/// input are buffered and printed at once when its compilation finishes.
+pub fn compile_batch(opts: &[Opts]) -> Vec<BatchCompilation> {
+    let mut db: Option<CompilationDB> = None;
+    let roots: Vec<Result<FileId>> = opts
+        .iter()
+        .map(|opts| {
+            let input = resolve_input(opts)?;
+            if let Some(db) = &mut db {
+                return Ok(db.add_root_file(input));
+            }
+            let new_db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+            let root = new_db.root_file();
+            db = Some(new_db);
+            Ok(root)
+        })
+        .collect();
+
+    let mut results: Vec<Option<BatchCompilation>> = opts.iter().map(|_| None).collect();
+    rayon_core::scope(|scope| {
+        for ((opts, root), dst) in opts.iter().zip(roots).zip(&mut results) {
+            let db = root.map(|root| db.as_ref().unwrap().with_root_file(root));
+            scope.spawn(move |_| {
+                let start = Instant::now();
+                let stderr = BufferWriter::stderr(ColorChoice::Auto);
+                let mut buffer = stderr.buffer();
+                let result = db.and_then(|db| compile_db(&db, opts, start, &mut buffer));
+                // a failure to print diagnostics should not hide the result of the compilation
+                let _ = stderr.print(&buffer);
+                *dst = Some(BatchCompilation {
+                    input: opts.input.clone(),
+                    result,
+                    time: start.elapsed(),
+                });
+            });
+        }
+    });
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+fn resolve_input(opts: &Opts) -> Result<AbsPathBuf> {
     let input =
         opts.input.canonicalize().with_context(|| format!("failed to resolve {}", opts.input))?;
-    let input = AbsPathBuf::assert(input);
-    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+    Ok(AbsPathBuf::assert(input))
+}
 
+/// Compiles the root file of `db`, diagnostics and the final status are written to `stderr`
+fn compile_db(
+    db: &CompilationDB,
+    opts: &Opts,
+    start: Instant,
+    stderr: &mut dyn WriteColor,
+) -> Result<CompilationTermination> {
+    if opts.reproducible && (opts.debug_info || opts.check_fp) {
+        bail!(
+            "debug information and floating point checks embed absolute paths and are not supported by reproducible builds"
+        );
+    }
+    // `native` (and any other explicit cpu) would make the output depend on the host
+    let target_cpu = if opts.reproducible { "generic".to_owned() } else { opts.target_cpu.clone() };
+    let back = LLVMBackend::new(&opts.codegen_opts, &opts.target, target_cpu, &[]);
+    let mut cache_entry = None;
     let lib_file = match &opts.output {
-        CompilationDestination::Cache { cache_dir } => {
-            let file_name = cache::file_name(&db, opts);
-            let lib_file = cache_dir.join(file_name);
-            if cfg!(not(debug_assertions)) && lib_file.exists() {
-                return Ok(CompilationTermination::Compiled { lib_file });
+        CompilationDestination::Cache { .. } if opts.split_modules => {
+            bail!("compiling one library per module is not supported in batchmode")
+        }
+        CompilationDestination::Cache { .. } if opts.dump_json => {
+            bail!("dumping the model interface as json is not supported in batchmode")
+        }
+        CompilationDestination::Cache { cache_dir, max_size } => {
+            let cache = Cache::new(cache_dir.clone());
+            let key = CacheKey::new(db, opts, &back);
+            if cfg!(not(debug_assertions)) && !opts.dry_run {
+                if let Some(lib_file) = cache.lookup(&key)? {
+                    return Ok(CompilationTermination::Compiled { lib_file });
+                }
             }
-            create_dir_all(cache_dir).context("failed to create cache directory")?;
+            let lib_file = cache.lib_file(key.digest());
+            cache_entry = Some((cache, key, *max_size));
             lib_file
         }
         CompilationDestination::Path { lib_file } => lib_file.clone(),
     };
 
-    let modules = if let Some(modules) = collect_modules(&db, false, &mut ConsoleSink::new(&db)) {
+    let mut sink = ConsoleSink::new_with(db, Box::new(&mut *stderr));
+    let mut modules = if let Some(modules) = collect_modules(db, false, &mut sink) {
         modules
     } else {
         return Ok(CompilationTermination::FatalDiagnostic);
     };
 
-    let back = LLVMBackend::new(&opts.codegen_opts, &opts.target, opts.target_cpu.clone(), &[]);
-    if opts.dry_run {
-        return Ok(CompilationTermination::Compiled { lib_file });
+    select_modules(db, opts, &mut modules)?;
+
+    for module in &mut modules {
+        module.autodiff = opts.autodiff;
+        module.check_fp = opts.check_fp;
+        module.debug_info = opts.debug_info;
+        module.symbolic_delays = opts.symbolic_delays;
+    }
+
+    for param in &opts.sensitivities {
+        let mut found = false;
+        for module in &mut modules {
+            found |= module.add_sensitivity(db, param);
+        }
+        if !found {
+            bail!("failed to compute sensitivity: no real parameter called \"{param}\" exists");
+        }
+    }
+
+    for node in &opts.hessian {
+        let mut found = false;
+        for module in &mut modules {
+            found |= module.add_hessian(db, node);
+        }
+        if !found {
+            bail!("failed to compute hessian: no node called \"{node}\" exists");
+        }
     }
-    let paths = osdi::compile(&db, &modules, &lib_file, &opts.target, &back, true, opts.opt_lvl);
-    // TODO configure linker
-    link(None, &opts.target, lib_file.as_ref(), |linker| {
-        for path in &paths {
-            linker.add_object(path);
+
+    for module in &modules {
+        module.check_inlining(db, &mut sink);
+    }
+    if sink.summary(&opts.input.file_name().unwrap()) {
+        return Ok(CompilationTermination::FatalDiagnostic);
+    }
+    drop(sink);
+
+    if opts.dump_json {
+        let json_file = lib_file.with_extension("json");
+        if !opts.dry_run {
+            write(&json_file, osdi::interface_json(db, &modules))
+                .with_context(|| format!("failed to write {json_file}"))?;
         }
-    })?;
+        return Ok(CompilationTermination::Compiled { lib_file: json_file });
+    }
 
-    for obj_file in paths {
-        remove_file(obj_file).context("failed to delete intermediate compile artifact")?;
+    if opts.dry_run {
+        return Ok(CompilationTermination::Compiled { lib_file });
     }
 
+    let res = if opts.split_modules {
+        let mut lib_files = Vec::with_capacity(modules.len());
+        let ext = match opts.backend {
+            Backend::C => "c",
+            Backend::Llvm if opts.target.options.is_like_wasm => "wasm",
+            Backend::Llvm => "osdi",
+        };
+        for module in &modules {
+            let lib_file = lib_file.with_file_name(format!("{}.{ext}", module.module.name(db)));
+            build_library(db, opts, &back, slice::from_ref(module), &lib_file)?;
+            lib_files.push(lib_file);
+        }
+        CompilationTermination::CompiledModules { lib_files }
+    } else if let Some((cache, key, max_size)) = cache_entry {
+        let staging_file = cache.staging_file(&key)?;
+        build_library(db, opts, &back, &modules, &staging_file)?;
+        let lib_file = cache.insert(&key, &staging_file, max_size)?;
+        CompilationTermination::Compiled { lib_file }
+    } else {
+        build_library(db, opts, &back, &modules, &lib_file)?;
+        CompilationTermination::Compiled { lib_file }
+    };
+
     let seconds = Instant::elapsed(&start).as_secs_f64();
-    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
     stderr.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(&mut stderr, "Finished")?;
+    write!(stderr, "Finished")?;
     stderr.set_color(&ColorSpec::new())?;
-    writeln!(&mut stderr, " building {} in {:.2}s", opts.input.file_name().unwrap(), seconds)?;
+    writeln!(stderr, " building {} in {:.2}s", opts.input.file_name().unwrap(), seconds)?;
+
+    Ok(res)
+}
+
+/// Collects the modules of `opts.input` that were selected with [`Opts::modules`] for the
+/// commands that only lower the modules without compiling them. Returns `None` if the
+/// compilation failed (the diagnostics were already printed).
+fn lower_modules(opts: &Opts) -> Result<Option<(CompilationDB, Vec<ModuleInfo>)>> {
+    let input = resolve_input(opts)?;
+    let db = CompilationDB::new_fs(input, &opts.include, &opts.defines, &opts.lints)?;
+    let mut sink = ConsoleSink::new(&db);
+    let mut modules = if let Some(modules) = collect_modules(&db, false, &mut sink) {
+        modules
+    } else {
+        return Ok(None);
+    };
+    select_modules(&db, opts, &mut modules)?;
+    for module in &mut modules {
+        module.autodiff = opts.autodiff;
+        module.check_inlining(&db, &mut sink);
+    }
+    if sink.summary(&opts.input.file_name().unwrap()) {
+        return Ok(None);
+    }
+    drop(sink);
+    Ok(Some((db, modules)))
+}
 
-    Ok(CompilationTermination::Compiled { lib_file })
+/// Removes all modules that were not selected with [`Opts::modules`]
+fn select_modules(db: &CompilationDB, opts: &Opts, modules: &mut Vec<ModuleInfo>) -> Result<()> {
+    if opts.modules.is_empty() {
+        return Ok(());
+    }
+    for name in &opts.modules {
+        if !modules.iter().any(|module| module.module.name(db) == *name) {
+            let available: Vec<_> = modules.iter().map(|module| module.module.name(db)).collect();
+            bail!(
+                "no module called \"{name}\" exists\nhelp: available modules are {}",
+                available.join(", ")
+            );
+        }
+    }
+    modules.retain(|module| opts.modules.contains(&module.module.name(db)));
+    Ok(())
+}
+
+/// Compiles `modules` into the library `lib_file`
+fn build_library(
+    db: &CompilationDB,
+    opts: &Opts,
+    back: &LLVMBackend,
+    modules: &[ModuleInfo],
+    lib_file: &Utf8Path,
+) -> Result<()> {
+    if opts.backend == Backend::C {
+        if !opts.emit.is_empty() {
+            bail!("emitting intermediate representations is not supported by the C backend");
+        }
+        if opts.debug_info || opts.fp_policy != FloatPolicy::default() {
+            bail!(
+                "debug information and floating point policies are not supported by the C backend"
+            );
+        }
+        return write(lib_file, osdi::compile_c(db, modules))
+            .with_context(|| format!("failed to write {lib_file}"));
+    }
+
+    let emit: &[EmitKind] = match opts.output {
+        CompilationDestination::Path { .. } => &opts.emit,
+        CompilationDestination::Cache { .. } => &[],
+    };
+    let output = osdi::compile(
+        db,
+        modules,
+        lib_file,
+        &opts.target,
+        back,
+        Some(emit),
+        opts.opt_lvl,
+        opts.fp_policy,
+    )?;
+    // TODO configure linker path
+    link(
+        opts.linker,
+        None,
+        &opts.target,
+        lib_file,
+        opts.reproducible,
+        opts.debug_info,
+        |linker| {
+            for path in &output.objects {
+                linker.add_object(path);
+            }
+        },
+    )?;
+
+    // ld64 does not copy the DWARF sections into the library, debuggers read them
+    // from the objects instead
+    let keep_objects = opts.debug_info && opts.target.options.is_like_osx;
+    if !keep_objects {
+        for obj_file in output.objects {
+            remove_file(obj_file).context("failed to delete intermediate compile artifact")?;
+        }
+    }
+    Ok(())
 }
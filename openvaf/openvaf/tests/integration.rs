@@ -1,23 +1,64 @@
+use std::collections::HashMap;
 use std::f64::consts;
 use std::path::Path;
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use expect_test::expect_file;
 use float_cmp::assert_approx_eq;
 use llvm::OptLevel;
 use mini_harness::{harness, Result};
-use openvaf::{CompilationDestination, CompilationTermination};
+use openvaf::{
+    AutoDiffMode, AutoDiffOptions, Backend, CompilationDestination, CompilationTermination,
+    FloatPolicy, LinkerKind,
+};
 use stdx::{ignore_dev_tests, openvaf_test_data, project_root};
 use target::spec::Target;
 
-use crate::load::{load_osdi_lib, EvalFlags, OsdiDescriptor};
+use crate::load::{
+    load_osdi_lib, osdi_str, take_log, EvalFlags, EvalRetFlags, OsdiDescriptor, PARA_TY_MASK,
+    PARA_TY_REAL,
+};
 use crate::mock_sim::{MockSimulation, ALPHA};
 
 mod load;
 mod mock_sim;
 
+/// Tests that link a library are skipped in CI on windows as there is no toolchain available
+fn toolchain_available() -> bool {
+    !(stdx::IS_CI && cfg!(windows))
+}
+
+/// Returns an empty directory (in the temp dir) for the outputs of the test `name`
+fn out_dir(name: &str) -> Utf8PathBuf {
+    let dir = std::env::temp_dir().join(format!("openvaf_{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.try_into().unwrap()
+}
+
+/// Options that compile the test model `file` (in `test_data/osdi`) into `out_dir`
+fn test_opts(file: &str, out_dir: &Utf8Path) -> openvaf::Opts {
+    let root_file: Utf8PathBuf = openvaf_test_data("osdi").join(file).try_into().unwrap();
+    let lib_file = out_dir.join(root_file.file_stem().unwrap()).with_extension("osdi");
+    let mut opts = openvaf_opts(&root_file, &[]);
+    opts.output = CompilationDestination::Path { lib_file };
+    opts
+}
+
+/// Compiles a single library and returns its path
+fn compile_lib(opts: &openvaf::Opts) -> Result<Utf8PathBuf> {
+    match openvaf::compile(opts)? {
+        CompilationTermination::Compiled { lib_file } => Ok(lib_file),
+        _ => panic!("openvaf: compilation of {} failed", opts.input),
+    }
+}
+
 fn compile_and_load(root_file: &Utf8Path) -> &'static OsdiDescriptor {
-    let openvaf_opts = openvaf::Opts {
+    compile_and_load_with_sensitivities(root_file, &[])
+}
+
+fn openvaf_opts(root_file: &Utf8Path, sensitivities: &[&str]) -> openvaf::Opts {
+    openvaf::Opts {
         defines: Vec::new(),
         codegen_opts: Vec::new(),
         lints: Vec::new(),
@@ -28,11 +69,36 @@ fn compile_and_load(root_file: &Utf8Path) -> &'static OsdiDescriptor {
         target: Target::host_target().unwrap(),
         target_cpu: "native".to_owned(),
         dry_run: false,
-    };
+        sensitivities: sensitivities.iter().map(|&param| param.to_owned()).collect(),
+        hessian: Vec::new(),
+        dump_json: false,
+        emit: Vec::new(),
+        modules: Vec::new(),
+        split_modules: false,
+        linker: LinkerKind::External,
+        reproducible: false,
+        autodiff: AutoDiffOptions::default(),
+        fp_policy: FloatPolicy::default(),
+        check_fp: false,
+        debug_info: false,
+        symbolic_delays: false,
+        backend: Backend::Llvm,
+    }
+}
 
-    let res = openvaf::compile(&openvaf_opts).unwrap();
+fn compile_and_load_with_sensitivities(
+    root_file: &Utf8Path,
+    sensitivities: &[&str],
+) -> &'static OsdiDescriptor {
+    compile_and_load_with_opts(&openvaf_opts(root_file, sensitivities))
+}
+
+fn compile_and_load_with_opts(openvaf_opts: &openvaf::Opts) -> &'static OsdiDescriptor {
+    let root_file = &openvaf_opts.input;
+    let res = openvaf::compile(openvaf_opts).unwrap();
     let lib_file = match res {
         CompilationTermination::Compiled { lib_file } => lib_file,
+        CompilationTermination::CompiledModules { .. } => unreachable!(),
         CompilationTermination::FatalDiagnostic => {
             panic!("openvaf: compilation of {root_file} failed");
         }
@@ -58,6 +124,320 @@ fn integration_test(dir: &Path) -> Result {
     Ok(())
 }
 
+fn c_backend_integration_test(dir: &Path) -> Result {
+    if !toolchain_available() {
+        return Ok(());
+    }
+    let name = dir.file_name().unwrap().to_str().unwrap().to_lowercase();
+    let main_file = dir.join(format!("{name}.va"));
+    compare_c_backend(main_file.as_path().try_into().unwrap())
+}
+
+/// Compiles `root_file` with the C backend and builds the generated source into a
+/// shared library with the system C compiler (`$CC` or `cc`)
+fn compile_c_and_load(root_file: &Utf8Path, out_dir: &Utf8Path) -> Result<&'static OsdiDescriptor> {
+    let name = root_file.file_stem().unwrap();
+
+    let mut opts = openvaf_opts(root_file, &[]);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join(format!("{name}.c")) };
+    opts.backend = Backend::C;
+    let src_file = match openvaf::compile(&opts)? {
+        CompilationTermination::Compiled { lib_file } => lib_file,
+        _ => panic!("openvaf: compilation of {root_file} failed"),
+    };
+
+    let lib_file = src_file.with_extension(std::env::consts::DLL_EXTENSION);
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let status = std::process::Command::new(cc)
+        .args(["-std=c99", "-shared", "-fPIC", "-O1", "-o"])
+        .args([lib_file.as_str(), src_file.as_str(), "-lm"])
+        .status()?;
+    assert!(status.success(), "failed to compile {src_file}");
+
+    let libs = unsafe { load_osdi_lib(&lib_file)? };
+    assert_eq!(libs.len(), 1);
+    Ok(&libs[0])
+}
+
+/// Everything a simulator reads from a model after `setup_*` and `eval`
+struct EvalResult {
+    sim: MockSimulation,
+    opvars: Vec<f64>,
+    noise: Vec<f64>,
+    lim_rhs: (Vec<f64>, Vec<f64>),
+}
+
+/// Sets up the model and evaluates it at a deterministic operating point
+fn eval_at_test_point(desc: &'static OsdiDescriptor) -> Result<EvalResult> {
+    const FREQ: f64 = 1e3;
+
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    // a deterministic operating point that is different for every node (0 is ground)
+    for (i, voltage) in sim.solve.iter_mut().enumerate() {
+        *voltage = 0.1 * i as f64;
+    }
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    instance.load_dae(&model, &mut sim);
+    let lim_rhs = instance.load_limit_rhs(&model, &sim);
+    instance.load_noise(&model, &mut sim, FREQ);
+    let noise = sim.noise_dense.clone();
+    let opvars = (0..desc.num_opvars)
+        .filter(|&opvar| {
+            let flags = desc.params()[(desc.num_params + opvar) as usize].flags;
+            flags & PARA_TY_MASK == PARA_TY_REAL
+        })
+        .map(|opvar| instance.read_real_opvar(&model, opvar))
+        .collect();
+    Ok(EvalResult { sim, opvars, noise, lim_rhs })
+}
+
+/// Evaluates two compilations (`names`) of the same model with [`eval_at_test_point`] and
+/// checks that both produce the same setup errors, residual, jacobian, limiting rhs,
+/// operating point variables and noise
+fn compare_compilations(
+    names: (&str, &str),
+    (lhs, rhs): (&'static OsdiDescriptor, &'static OsdiDescriptor),
+) -> Result {
+    let (lhs_name, rhs_name) = names;
+    assert_eq!(osdi_str(lhs.name), osdi_str(rhs.name));
+    assert_eq!(lhs.num_nodes, rhs.num_nodes);
+    assert_eq!(lhs.num_params, rhs.num_params);
+    assert_eq!(lhs.num_opvars, rhs.num_opvars);
+    assert_eq!(lhs.num_noise_src, rhs.num_noise_src);
+
+    // errors during setup (for example parameters out of bounds) must be identical
+    let (lhs_res, rhs_res) = match (eval_at_test_point(lhs), eval_at_test_point(rhs)) {
+        (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+        (Err(lhs), Err(rhs)) => {
+            assert_eq!(lhs.to_string(), rhs.to_string());
+            return Ok(());
+        }
+        (lhs, rhs) => panic!(
+            "setup failed with only one compilation: {:?} ({lhs_name}) != {:?} ({rhs_name})",
+            lhs.err(),
+            rhs.err()
+        ),
+    };
+    let (lhs_sim, rhs_sim) = (&lhs_res.sim, &rhs_res.sim);
+    assert_eq!(lhs_sim.nodes, rhs_sim.nodes);
+    assert_eq!(lhs_sim.jacobian_info, rhs_sim.jacobian_info);
+
+    // LLVM may reassociate the generated derivatives (see FloatPolicy::Partial)
+    let check = |what: String, (lhs, rhs): (f64, f64)| {
+        let tol = 1e-6 * lhs.abs().max(rhs.abs()) + 1e-20;
+        assert!(
+            (lhs - rhs).abs() <= tol || (lhs.is_nan() && rhs.is_nan()),
+            "{what}: {lhs} ({lhs_name}) != {rhs} ({rhs_name})"
+        );
+    };
+    for (i, &node) in lhs_sim.nodes.iter().enumerate() {
+        let (lhs, rhs) = (lhs_sim.read_residual(node), rhs_sim.read_residual(node));
+        check(format!("resistive residual of {node}"), (lhs.0, rhs.0));
+        check(format!("reactive residual of {node}"), (lhs.1, rhs.1));
+        let (lhs, rhs) = (lhs_res.lim_rhs.0[i], rhs_res.lim_rhs.0[i]);
+        check(format!("resistive limiting rhs of {node}"), (lhs, rhs));
+        let (lhs, rhs) = (lhs_res.lim_rhs.1[i], rhs_res.lim_rhs.1[i]);
+        check(format!("reactive limiting rhs of {node}"), (lhs, rhs));
+    }
+    for &(hi, lo) in &lhs_sim.jacobian_info {
+        let (hi, lo) = (lhs_sim.nodes[hi as usize], lhs_sim.nodes[lo as usize]);
+        let (lhs, rhs) = (lhs_sim.read_jacobian(hi, lo), rhs_sim.read_jacobian(hi, lo));
+        check(format!("resistive jacobian entry ({hi}, {lo})"), (lhs.0, rhs.0));
+        check(format!("reactive jacobian entry ({hi}, {lo})"), (lhs.1, rhs.1));
+    }
+    for (i, (&lhs, &rhs)) in lhs_res.opvars.iter().zip(&rhs_res.opvars).enumerate() {
+        check(format!("operating point variable {i}"), (lhs, rhs));
+    }
+    for (i, (&lhs, &rhs)) in lhs_res.noise.iter().zip(&rhs_res.noise).enumerate() {
+        check(format!("noise source {i}"), (lhs, rhs));
+    }
+    Ok(())
+}
+
+/// Evaluates the model in `root_file` compiled with the LLVM and the C backend at the
+/// same operating point and checks that both produce the same results
+fn compare_c_backend(root_file: &Utf8Path) -> Result {
+    let name = root_file.file_stem().unwrap();
+    let out_dir = out_dir(&format!("c_backend_{name}"));
+
+    let mut opts = openvaf_opts(root_file, &[]);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join(format!("{name}.osdi")) };
+    let llvm = compile_and_load_with_opts(&opts);
+    let c = compile_c_and_load(root_file, &out_dir)?;
+    compare_compilations(("llvm", "c"), (llvm, c))
+}
+
+fn autodiff_integration_test(dir: &Path) -> Result {
+    if !toolchain_available() {
+        return Ok(());
+    }
+    let name = dir.file_name().unwrap().to_str().unwrap().to_lowercase();
+    let main_file = dir.join(format!("{name}.va"));
+    compare_autodiff(main_file.as_path().try_into().unwrap())
+}
+
+/// Checks that the model in `root_file` produces the same results (in particular the same
+/// jacobian) with every `--autodiff` mode and `--autodiff-cse` as with the default
+/// forward mode
+fn compare_autodiff(root_file: &Utf8Path) -> Result {
+    let name = root_file.file_stem().unwrap();
+    let out_dir = out_dir(&format!("autodiff_{name}"));
+    let compile = |autodiff: AutoDiffOptions| {
+        let mut opts = openvaf_opts(root_file, &[]);
+        let cse = if autodiff.cse { "_cse" } else { "" };
+        let lib_file = out_dir.join(format!("{name}_{}{cse}.osdi", autodiff.mode.name()));
+        opts.output = CompilationDestination::Path { lib_file };
+        opts.autodiff = autodiff;
+        compile_and_load_with_opts(&opts)
+    };
+
+    let forward = compile(AutoDiffOptions::default());
+    for mode in AutoDiffMode::ALL {
+        for cse in [false, true] {
+            let autodiff = AutoDiffOptions { mode, cse };
+            if autodiff == AutoDiffOptions::default() {
+                continue;
+            }
+            let desc = compile(autodiff);
+            let cse = if cse { " with cse" } else { "" };
+            let name = format!("{}{cse}", mode.name());
+            compare_compilations(("forward", name.as_str()), (forward, desc))?;
+        }
+    }
+    Ok(())
+}
+
+fn interpret_integration_test(dir: &Path) -> Result {
+    if !toolchain_available() {
+        return Ok(());
+    }
+    let name = dir.file_name().unwrap().to_str().unwrap().to_lowercase();
+    let main_file = dir.join(format!("{name}.va"));
+    compare_interpreter(main_file.as_path().try_into().unwrap())
+}
+
+/// The name of the unknown of an OSDI node in [`openvaf::Evaluation::unknowns`]
+fn interpreter_unknown(osdi_node: &str) -> String {
+    match osdi_node.strip_prefix("flow(") {
+        Some(branch) => format!("I({}", branch.replace(',', ", ")),
+        None => osdi_node.to_owned(),
+    }
+}
+
+/// Evaluates the model in `root_file` with the MIR interpreter and compiled with LLVM at
+/// the same DC operating point and checks that both produce the same residual and jacobian.
+/// The simulator collapses nodes, so the rows and columns of the interpreter that belong to
+/// the same simulator node are summed up.
+fn compare_interpreter(root_file: &Utf8Path) -> Result {
+    let name = root_file.file_stem().unwrap();
+    let out_dir = out_dir(&format!("interpret_{name}"));
+    let mut opts = openvaf_opts(root_file, &[]);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join(format!("{name}.osdi")) };
+    let desc = compile_and_load_with_opts(&opts);
+
+    let model = desc.new_model();
+    let mut instance = model.new_instance();
+    let setup = model
+        .process_params()
+        .and_then(|_| instance.mock_simulation(&model, desc.num_terminals, 300.0));
+    let mut sim = match setup {
+        Ok(sim) => sim,
+        Err(_) => {
+            let point = openvaf::OperatingPoint { temperature: 300.0, ..Default::default() };
+            assert!(openvaf::interpret(&opts, &point).is_err(), "setup only failed with LLVM");
+            return Ok(());
+        }
+    };
+    // a deterministic operating point that is different for every node (0 is ground)
+    for (i, voltage) in sim.solve.iter_mut().enumerate() {
+        *voltage = 0.1 * i as f64;
+    }
+    let flags = instance.eval(
+        &model,
+        &mut sim,
+        EvalFlags::CALC_OP | EvalFlags::ANALYSIS_DC | EvalFlags::ANALYSIS_STATIC,
+    );
+    instance.load_dae(&model, &mut sim);
+
+    // the simulator node (index into sim.nodes) of every unknown
+    let sim_nodes: HashMap<String, usize> = desc
+        .nodes()
+        .iter()
+        .zip(instance.node_mapping())
+        .map(|(node, sim_node)| {
+            (interpreter_unknown(unsafe { osdi_str(node.name) }), sim_node.get() as usize)
+        })
+        .collect();
+    let mut point = openvaf::OperatingPoint { temperature: 300.0, ..Default::default() };
+    for (unknown, &node) in &sim_nodes {
+        point.bias.push((unknown.clone(), sim.solve[node]));
+    }
+    let evaluation = match openvaf::interpret(&opts, &point) {
+        Ok(evaluations) => evaluations.unwrap().remove(0),
+        Err(err) => {
+            assert!(flags.contains(EvalRetFlags::EVAL_RET_FLAG_FATAL), "{err:?}");
+            return Ok(());
+        }
+    };
+
+    let sim_node = |unknown: &str| {
+        *sim_nodes.get(unknown).unwrap_or_else(|| panic!("no OSDI node for {unknown}"))
+    };
+    let mut residual = vec![(0.0, 0.0); sim.nodes.len()];
+    for (unknown, (resist, react)) in evaluation.unknowns.iter().zip(&evaluation.residual) {
+        let node = &mut residual[sim_node(unknown)];
+        node.0 += resist;
+        node.1 += react;
+    }
+    let mut jacobian = vec![(0.0, 0.0); sim.jacobian_info.len()];
+    for &((row, col), (resist, react)) in &evaluation.jacobian {
+        let entry = (
+            sim_node(&evaluation.unknowns[row]) as u32,
+            sim_node(&evaluation.unknowns[col]) as u32,
+        );
+        if entry.0 == 0 || entry.1 == 0 {
+            continue;
+        }
+        let i = sim.jacobian_info.get_index_of(&entry);
+        let i = i.unwrap_or_else(|| panic!("jacobian entry {entry:?} missing in OSDI"));
+        jacobian[i].0 += resist;
+        jacobian[i].1 += react;
+    }
+
+    // contributions of collapsed nodes are summed in a different order
+    let check = |what: String, (llvm, interp): (f64, f64), scale: f64| {
+        let tol = 1e-6 * llvm.abs().max(interp.abs()) + 1e-12 * scale;
+        assert!(
+            (llvm - interp).abs() <= tol || (llvm.is_nan() && interp.is_nan()),
+            "{what} of {name}: {llvm} (llvm) != {interp} (interpreter)"
+        );
+    };
+    let scale = |vals: &[(f64, f64)]| {
+        vals.iter().fold(0f64, |acc, val| acc.max(val.0.abs()).max(val.1.abs()))
+    };
+    let residual_scale = scale(&residual);
+    for (i, node) in sim.nodes.iter().enumerate().skip(1) {
+        let llvm = (sim.residual_resist[i], sim.residual_react[i]);
+        check(format!("resistive residual of {node}"), (llvm.0, residual[i].0), residual_scale);
+        check(format!("reactive residual of {node}"), (llvm.1, residual[i].1), residual_scale);
+    }
+    let jacobian_scale = scale(&jacobian);
+    for (i, &(row, col)) in sim.jacobian_info.iter().enumerate() {
+        if row == 0 || col == 0 {
+            continue;
+        }
+        let (row, col) = (sim.nodes[row as usize], sim.nodes[col as usize]);
+        let llvm = sim.read_jacobian(row, col);
+        let entry = format!("({row}, {col})");
+        check(format!("resistive jacobian entry {entry}"), (llvm.0, jacobian[i].0), jacobian_scale);
+        check(format!("reactive jacobian entry {entry}"), (llvm.1, jacobian[i].1), jacobian_scale);
+    }
+    Ok(())
+}
+
 fn test_descriptor(main_file: &Path) -> Result<&'static OsdiDescriptor> {
     let main_file: &Utf8Path = main_file.try_into().unwrap();
     let name = main_file.file_stem().unwrap();
@@ -212,8 +592,775 @@ fn test_noise() -> Result<()> {
     Ok(())
 }
 
+fn test_bound_step() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    const TMAX: f64 = 1e-9;
+
+    let desc = compile_and_load_with_opts(&test_opts("bound_step.va", &out_dir("bound_step")));
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+
+    instance.eval(&model, &mut sim, EvalFlags::empty());
+    assert_eq!(instance.bound_step(), Some(TMAX));
+    assert_eq!(instance.discontinuity(), Some(-1));
+
+    // the smallest step size and the lowest discontinuity order win
+    sim.next_iter();
+    sim.set_voltage("a", 1.5);
+    instance.eval(&model, &mut sim, EvalFlags::empty());
+    assert_approx_eq!(instance.bound_step().unwrap(), TMAX / 10.0);
+    assert_eq!(instance.discontinuity(), Some(1));
+
+    sim.next_iter();
+    sim.set_voltage("a", 3.0);
+    instance.eval(&model, &mut sim, EvalFlags::empty());
+    assert_approx_eq!(instance.bound_step().unwrap(), TMAX / 10.0);
+    assert_eq!(instance.discontinuity(), Some(0));
+
+    // both outputs are reset at the start of each evaluation
+    sim.next_iter();
+    sim.set_voltage("a", 0.0);
+    instance.eval(&model, &mut sim, EvalFlags::empty());
+    assert_eq!(instance.bound_step(), Some(TMAX));
+    assert_eq!(instance.discontinuity(), Some(-1));
+    Ok(())
+}
+
+fn test_opvar_derivative() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    const K: f64 = 2.0;
+    const VGS: f64 = 1.5;
+    const VDS: f64 = 2.0;
+
+    let opts = test_opts("opvar_derivative.va", &out_dir("opvar_derivative"));
+    let desc = compile_and_load_with_opts(&opts);
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+
+    sim.set_voltage("g", VGS);
+    sim.set_voltage("d", VDS);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    assert_approx_eq!(instance.read_real_opvar(&model, 0), K * VGS * VGS * (1.0 + 0.1 * VDS));
+    assert_approx_eq!(instance.read_real_opvar(&model, 1), 2.0 * K * VGS * (1.0 + 0.1 * VDS));
+    assert_approx_eq!(instance.read_real_opvar(&model, 2), 0.1 * K * VGS * VGS);
+    Ok(())
+}
+
+fn test_absdelay() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("absdelay");
+    let mut opts = test_opts("absdelay.va", &out_dir);
+    // by default the delay is ignored and the interface of the model does not change
+    let ignored = compile_and_load_with_opts(&opts);
+    assert!(ignored.delays().is_empty());
+
+    opts.symbolic_delays = true;
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join("symbolic.osdi") };
+    let desc = compile_and_load_with_opts(&opts);
+    assert_eq!(desc.delays().len(), 1);
+    assert_eq!(desc.num_nodes, ignored.num_nodes + 1);
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+
+    sim.set_voltage("a", 1.0);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_RESIST_RESIDUAL | EvalFlags::ANALYSIS_HB);
+    assert_eq!(instance.delays(), vec![1e-9]);
+    Ok(())
+}
+
+fn test_analysis() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let desc = compile_and_load_with_opts(&test_opts("analysis.va", &out_dir("analysis")));
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP | EvalFlags::ANALYSIS_HB);
+    assert_eq!(instance.read_real_opvar(&model, 0), 1.0);
+    assert_eq!(instance.read_real_opvar(&model, 1), 0.0);
+
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP | EvalFlags::ANALYSIS_DC);
+    assert_eq!(instance.read_real_opvar(&model, 0), 0.0);
+    assert_eq!(instance.read_real_opvar(&model, 1), 1.0);
+    Ok(())
+}
+
+fn test_sensitivity() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    const V: f64 = 0.7;
+    const R: f64 = 2.0;
+
+    let mut opts = test_opts("sensitivity.va", &out_dir("sensitivity"));
+    opts.sensitivities = vec!["r".to_owned(), "c0".to_owned()];
+    let desc = compile_and_load_with_opts(&opts);
+    let names: Vec<_> = desc
+        .sensitivity_params()
+        .iter()
+        .map(|&param| unsafe { osdi_str(*desc.params()[param as usize].name) })
+        .collect();
+    assert_eq!(names, ["r", "c0"]);
+
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    sim.set_voltage("a", V);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_SENSITIVITY);
+    let sensitivities = instance.load_sensitivity(&model, &sim);
+
+    let a = sim.nodes.get_index_of("a").unwrap();
+    let c = sim.nodes.get_index_of("c").unwrap();
+    let (resist_r, react_r) = &sensitivities[0];
+    assert_approx_eq!(resist_r[a], -V / (R * R));
+    assert_approx_eq!(resist_r[c], V / (R * R));
+    assert_eq!(react_r[a], 0.0);
+    let (resist_c0, react_c0) = &sensitivities[1];
+    assert_eq!(resist_c0[a], 0.0);
+    assert_approx_eq!(react_c0[a], V);
+    assert_approx_eq!(react_c0[c], -V);
+    Ok(())
+}
+
+fn test_hessian() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    const V: f64 = 0.7;
+    const K: f64 = 2.0;
+    const C0: f64 = 1e-3;
+
+    let mut opts = test_opts("hessian.va", &out_dir("hessian"));
+    opts.hessian = vec!["a".to_owned()];
+    let desc = compile_and_load_with_opts(&opts);
+    let entries: Vec<_> = desc
+        .hessian_entries()
+        .iter()
+        .map(|entry| unsafe {
+            let nodes = desc.nodes();
+            (
+                osdi_str(nodes[entry.node as usize].name),
+                osdi_str(nodes[entry.nodes.node_1 as usize].name),
+                osdi_str(nodes[entry.nodes.node_2 as usize].name),
+            )
+        })
+        .collect();
+    assert_eq!(entries, [("a", "a", "a"), ("a", "a", "c"), ("a", "c", "c")]);
+
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    sim.set_voltage("a", V);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP | EvalFlags::CALC_HESSIAN);
+    let (resist, react) = instance.load_hessian(&model);
+    assert_approx_eq!(resist[0], 6.0 * K * V);
+    assert_approx_eq!(resist[1], -6.0 * K * V);
+    assert_approx_eq!(resist[2], 6.0 * K * V);
+    assert_approx_eq!(react[0], 2.0 * C0);
+    assert_approx_eq!(react[1], -2.0 * C0);
+    assert_approx_eq!(react[2], 2.0 * C0);
+    // nested ddx
+    assert_approx_eq!(instance.read_real_opvar(&model, 0), 6.0 * K * V);
+    Ok(())
+}
+
+fn test_noinline() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    const VA: f64 = 0.3;
+    const VB: f64 = 0.5;
+    const K: f64 = 2.0;
+
+    let out_dir = out_dir("noinline");
+    let eval = |inline: bool| -> Result<MockSimulation> {
+        let mut opts = test_opts("noinline.va", &out_dir);
+        if inline {
+            opts.defines = vec!["INLINE".to_owned()];
+            opts.output = CompilationDestination::Path { lib_file: out_dir.join("inline.osdi") };
+        }
+        let desc = compile_and_load_with_opts(&opts);
+        let model = desc.new_model();
+        model.process_params()?;
+        let mut instance = model.new_instance();
+        let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+        sim.set_voltage("a", VA);
+        sim.set_voltage("b", VB);
+        instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+        instance.load_dae(&model, &mut sim);
+        Ok(sim)
+    };
+
+    let noinline = eval(false)?;
+    let inline = eval(true)?;
+    assert_eq!(noinline.jacobian_info, inline.jacobian_info);
+    for &(hi, lo) in &inline.jacobian_info {
+        let (hi, lo) = (inline.nodes[hi as usize], inline.nodes[lo as usize]);
+        assert_approx_eq!(noinline.read_jacobian(hi, lo).0, inline.read_jacobian(hi, lo).0);
+    }
+    assert_approx_eq!(noinline.read_jacobian("a", "a").0, 3.0 * K * VA * VA + VA.exp());
+    // second derivative of h trough a (* noinline *) call
+    assert_approx_eq!(noinline.read_jacobian("b", "b").0, 6.0 * K * VB + VB.exp());
+    Ok(())
+}
+
+fn test_fp_policy() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("fp_policy");
+
+    // all voltages are zero so every expression produces an infinity or NaN
+    let mut opts = test_opts("fp_policy.va", &out_dir);
+    let mut eval = |fp_policy: FloatPolicy, name: &str| -> Result<Vec<f64>> {
+        opts.fp_policy = fp_policy;
+        opts.output = CompilationDestination::Path { lib_file: out_dir.join(name) };
+        let desc = compile_and_load_with_opts(&opts);
+        let model = desc.new_model();
+        model.process_params()?;
+        let mut instance = model.new_instance();
+        let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+        instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+        Ok((0..desc.num_opvars).map(|opvar| instance.read_real_opvar(&model, opvar)).collect())
+    };
+
+    let opvars = eval(FloatPolicy::Strict, "strict.osdi")?;
+    assert_eq!(opvars[0], f64::INFINITY);
+    assert_eq!(opvars[1], f64::NEG_INFINITY);
+    assert!(opvars[2].is_nan());
+    assert_eq!(opvars[3], 1.0);
+    assert_eq!(opvars[4], f64::INFINITY);
+    assert_eq!(opvars[5], 1.0);
+
+    // partial only relaxes generated code (the derivative of sqrt) and never assumes that
+    // values are finite, so it produces the same infinities and NaNs as strict
+    let opvars = eval(FloatPolicy::Partial, "partial.osdi")?;
+    assert_eq!(opvars[0], f64::INFINITY);
+    assert_eq!(opvars[1], f64::NEG_INFINITY);
+    assert!(opvars[2].is_nan());
+    assert_eq!(opvars[3], 1.0);
+    assert_eq!(opvars[4], f64::INFINITY);
+    assert_eq!(opvars[5], 1.0);
+
+    // the fp_policy attribute of the analog function overwrites the policy of the module
+    let opvars = eval(FloatPolicy::Fast, "fast.osdi")?;
+    assert_eq!(opvars[5], 1.0);
+
+    // second order derivatives are not propagated trough the call of a strict function
+    // and it can not be inlined without dropping its policy
+    let res = openvaf::compile(&test_opts("fp_policy_conflict.va", &out_dir))?;
+    assert!(matches!(res, CompilationTermination::FatalDiagnostic));
+    Ok(())
+}
+
+fn test_check_fp() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let mut opts = test_opts("check_fp.va", &out_dir("check_fp"));
+    opts.check_fp = true;
+    let desc = compile_and_load_with_opts(&opts);
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    take_log();
+
+    // the derivative of sqrt is infinite at zero
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    let log = take_log();
+    assert_eq!(log.len(), 1, "{log:?}");
+    assert!(log[0].starts_with("err foo - Inf produced by a derivative of the expression at "));
+    assert!(log[0].ends_with("check_fp.va:7:25"), "{}", log[0]);
+
+    // only the first NaN is reported (and not every value computed from it)
+    sim.set_voltage("a", -1.0);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    let log = take_log();
+    assert_eq!(log.len(), 1, "{log:?}");
+    assert!(log[0].starts_with("err foo - NaN produced by the expression at "));
+    assert!(log[0].ends_with("check_fp.va:7:25"), "{}", log[0]);
+
+    sim.set_voltage("a", 1.0);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    assert_eq!(take_log(), Vec::<String>::new());
+    Ok(())
+}
+
+fn test_check_fp_setup() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let mut opts = test_opts("check_fp_setup.va", &out_dir("check_fp_setup"));
+    opts.check_fp = true;
+    let desc = compile_and_load_with_opts(&opts);
+    take_log();
+
+    // the default value of q is computed by setup_model
+    let model = desc.new_model();
+    model.process_params()?;
+    let log = take_log();
+    assert_eq!(log.len(), 1, "{log:?}");
+    assert!(log[0].starts_with("err foo - NaN produced by the expression at "));
+    assert!(log[0].ends_with("check_fp_setup.va:6:24"), "{}", log[0]);
+
+    // ln(p - 2) only depends on parameters and is computed by setup_instance
+    let mut instance = model.new_instance();
+    instance.process_params(&model, desc.num_terminals, 300.0)?;
+    let log = take_log();
+    assert_eq!(log.len(), 1, "{log:?}");
+    assert!(log[0].starts_with("err foo - NaN produced by the expression at "));
+    assert!(log[0].ends_with("check_fp_setup.va:8:20"), "{}", log[0]);
+    Ok(())
+}
+
+fn test_debug_info() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("debug_info");
+    let mut opts = test_opts("debug_info.va", &out_dir);
+    opts.emit = vec![openvaf::EmitKind::LlvmIr];
+    opts.opt_lvl = OptLevel::None;
+    opts.debug_info = true;
+    let desc = compile_and_load_with_opts(&opts);
+
+    let read_ir = |unit: &str| -> Result<String> {
+        let path = out_dir.join(format!("debug_info.{unit}_debug_info.post_opt.ll"));
+        Ok(std::fs::read_to_string(path)?)
+    };
+    let eval_ir = read_ir("eval")?;
+    for expected in [
+        "DIFile(filename: \"debug_info.va\"",
+        "DISubprogram(name: \"eval_",
+        "DILocation(line: 18, column: 13",
+        "DILocation(line: 19, column: 20",
+        "call void @llvm.dbg.value(",
+        // analog functions that are not inlined are placed at their declaration
+        "DISubprogram(name: \"scale\"",
+        "DILocation(line: 13, column: 21",
+    ] {
+        assert!(eval_ir.contains(expected), "{expected} missing from\n{eval_ir}");
+    }
+    // variables are declared where they are declared in the source code
+    let var = eval_ir
+        .lines()
+        .find(|line| line.contains("DILocalVariable(name: \"v\""))
+        .expect("debug information of v is missing");
+    assert!(var.contains("line: 6,"), "{var}");
+
+    for unit in ["setup_model", "setup_instance"] {
+        let ir = read_ir(unit)?;
+        let expected = format!("DISubprogram(name: \"{unit}_debug_info\"");
+        assert!(ir.contains(&expected), "{expected} missing from\n{ir}");
+    }
+
+    // the debug information must survive linking
+    if cfg!(target_os = "linux") {
+        let lib = std::fs::read(out_dir.join("debug_info.osdi"))?;
+        for section in [".debug_info", ".debug_line"] {
+            assert!(
+                lib.windows(section.len()).any(|window| window == section.as_bytes()),
+                "{section} missing from the library"
+            );
+        }
+    }
+
+    // debug information must not change the behaviour of the model
+    let model = desc.new_model();
+    model.process_params()?;
+    let mut instance = model.new_instance();
+    let mut sim = instance.mock_simulation(&model, desc.num_terminals, 300.0)?;
+    sim.set_voltage("a", 1.0);
+    instance.eval(&model, &mut sim, EvalFlags::CALC_OP);
+    assert_eq!(instance.read_real_opvar(&model, 0), 2.0);
+    Ok(())
+}
+
+fn test_interface() -> Result<()> {
+    let out_dir = out_dir("interface");
+    let mut opts = test_opts("interface.va", &out_dir);
+    opts.dump_json = true;
+    let json_file = compile_lib(&opts)?;
+    assert_eq!(json_file, out_dir.join("interface.json"));
+    // the description is written without compiling a library
+    assert!(!out_dir.join("interface.osdi").exists());
+    let interface = std::fs::read_to_string(&json_file)?;
+    expect_file![openvaf_test_data("osdi").join("interface.snap")].assert_eq(&interface);
+
+    // the ids must match the descriptor of the library
+    if toolchain_available() {
+        opts.dump_json = false;
+        let desc = compile_and_load_with_opts(&opts);
+        for (id, param) in desc.params().iter().enumerate() {
+            let name = unsafe { osdi_str(*param.name) };
+            let expected = format!("\"id\": {id},\n{:20}\"name\": \"{name}\"", "");
+            assert!(interface.contains(&expected), "parameter {id} is not {name}");
+        }
+    }
+    Ok(())
+}
+
+fn test_dump_mir_json() -> Result<()> {
+    let out_dir = out_dir("dump_mir_json");
+    let root_file = openvaf_test_data("osdi").join("dump_json.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let mut opts = openvaf_opts(root_file, &[]);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join("dump_json.osdi") };
+    let res = openvaf::dump_mir_json(&opts)?;
+    assert!(matches!(res, CompilationTermination::Compiled { .. }));
+    let json = std::fs::read_to_string(out_dir.join("dump_json_dump_json.json"))?;
+    for expected in [
+        "\"parameters\": {",
+        "\"c0\": ",
+        "\"voltages\": {",
+        "\"(a, c)\": ",
+        "\"$enable_integration\": ",
+        "\"kind\": \"ddt\"",
+        "\"kind\": \"ddx\"",
+        "\"outputs\": {",
+        "\"gq\": ",
+        "\"flux\": ",
+    ] {
+        assert!(json.contains(expected), "{expected} missing from MIR json:\n{json}");
+    }
+    Ok(())
+}
+
+fn test_interpret() -> Result<()> {
+    const K: f64 = 3.0;
+    const VGS: f64 = 1.5;
+    const VDS: f64 = 2.0;
+
+    let root_file = openvaf_test_data("osdi").join("opvar_derivative.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let point = openvaf::OperatingPoint {
+        params: vec![("k".to_owned(), K.to_string())],
+        bias: vec![("g".to_owned(), VGS), ("d".to_owned(), VDS)],
+        ..Default::default()
+    };
+    let evaluations = openvaf::interpret(&openvaf_opts(root_file, &[]), &point)?.unwrap();
+    assert_eq!(evaluations.len(), 1);
+    let evaluation = &evaluations[0];
+
+    let ids = K * VGS * VGS * (1.0 + 0.1 * VDS);
+    let gm = 2.0 * K * VGS * (1.0 + 0.1 * VDS);
+    assert_approx_eq!(evaluation.opvar("ids").unwrap(), ids);
+    assert_approx_eq!(evaluation.opvar("gm").unwrap(), gm);
+    assert_approx_eq!(evaluation.opvar("gds").unwrap(), 0.1 * K * VGS * VGS);
+    assert_approx_eq!(evaluation.residual("d").unwrap().0, ids);
+    assert_approx_eq!(evaluation.residual("s").unwrap().0, -ids);
+    assert_approx_eq!(evaluation.jacobian("d", "g").unwrap().0, gm);
+    Ok(())
+}
+
+fn test_equations() -> Result<()> {
+    let root_file = openvaf_test_data("osdi").join("opvar_derivative.va");
+    let root_file: &Utf8Path = root_file.as_path().try_into().unwrap();
+    let opts = openvaf_opts(root_file, &[]);
+    for syntax in openvaf::Syntax::ALL {
+        let equations = openvaf::equations(&opts, syntax, &[])?.unwrap();
+        assert!(equations.contains("module opvar_derivative"), "{equations}");
+    }
+
+    let equations = openvaf::equations(&opts, openvaf::Syntax::VerilogA, &["d".to_owned()])?;
+    let equations = equations.unwrap();
+    for expected in ["\\I[d] =", "\\dI[d]/d[g] =", "\\dI[d]/d[d] ="] {
+        assert!(equations.contains(expected), "{expected} missing from:\n{equations}");
+    }
+    assert!(!equations.contains("\\I[s] ="), "{equations}");
+
+    let err = openvaf::equations(&opts, openvaf::Syntax::VerilogA, &["x".to_owned()]);
+    assert!(err.is_err());
+    Ok(())
+}
+
+fn test_emit() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("emit");
+    let mut opts = test_opts("sensitivity.va", &out_dir);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join("emit.osdi") };
+    opts.emit = openvaf::EmitKind::ALL.to_vec();
+    compile_lib(&opts)?;
+
+    let mut files: Vec<_> = std::fs::read_dir(&out_dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<std::io::Result<_>>()?;
+    files.sort();
+    for (prefix, suffix) in [
+        ("emit.sensitivity.", "pre_opt.mir"),
+        ("emit.sensitivity.", "post_opt.mir"),
+        ("emit.sensitivity.", "eval.mir"),
+        ("emit.eval_", ".pre_opt.ll"),
+        ("emit.eval_", ".post_opt.ll"),
+        ("emit.eval_", ".post_opt.bc"),
+        ("emit.setup_model_", ".s"),
+        ("emit.access_", ".o"),
+        ("emit.emit.", "post_opt.ll"),
+    ] {
+        assert!(
+            files.iter().any(|file| file.starts_with(prefix) && file.ends_with(suffix)),
+            "{prefix}*{suffix} was not emitted: {files:?}"
+        );
+    }
+    Ok(())
+}
+
+fn test_compile_batch() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("compile_batch");
+    let opts: Vec<_> = ["sensitivity.va", "missing.va", "interface.va"]
+        .iter()
+        .map(|file| test_opts(file, &out_dir))
+        .collect();
+    let results = openvaf::compile_batch(&opts);
+    assert_eq!(results.len(), 3);
+    for (res, opts) in results.iter().zip(&opts) {
+        assert_eq!(res.input, opts.input);
+    }
+    assert!(matches!(results[0].result, Ok(CompilationTermination::Compiled { .. })));
+    assert!(results[1].result.is_err());
+    assert!(matches!(results[2].result, Ok(CompilationTermination::Compiled { .. })));
+
+    for res in &results {
+        if let Ok(CompilationTermination::Compiled { lib_file }) = &res.result {
+            let libs = unsafe { load_osdi_lib(lib_file)? };
+            assert_eq!(libs.len(), 1);
+        }
+    }
+    Ok(())
+}
+
+fn test_modules() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("modules");
+    let mut opts = test_opts("modules.va", &out_dir);
+    opts.modules = vec!["resistor_a".to_owned(), "resistor_b".to_owned()];
+    let lib_file = compile_lib(&opts)?;
+    let libs = unsafe { load_osdi_lib(&lib_file)? };
+    let names: Vec<_> = libs.iter().map(|desc| unsafe { osdi_str(desc.name) }).collect();
+    assert_eq!(names, ["resistor_a", "resistor_b"]);
+
+    opts.split_modules = true;
+    let res = openvaf::compile(&opts)?;
+    let lib_files = match res {
+        CompilationTermination::CompiledModules { lib_files } => lib_files,
+        _ => panic!("openvaf: compilation of {} failed", opts.input),
+    };
+    assert_eq!(lib_files, [out_dir.join("resistor_a.osdi"), out_dir.join("resistor_b.osdi")]);
+    for (lib_file, name) in lib_files.iter().zip(names) {
+        let libs = unsafe { load_osdi_lib(lib_file)? };
+        assert_eq!(libs.len(), 1);
+        assert_eq!(unsafe { osdi_str(libs[0].name) }, name);
+    }
+
+    opts.modules = vec!["resistor_c".to_owned()];
+    let err = openvaf::compile(&opts).err().expect("unknown module must be rejected");
+    assert!(err.to_string().contains("no module called \"resistor_c\" exists"));
+    Ok(())
+}
+
+fn test_internal_linker() -> Result<()> {
+    let mut opts = test_opts("sensitivity.va", &out_dir("internal_linker"));
+    opts.linker = LinkerKind::Internal;
+    // without LLD the external linker is used instead
+    if !LinkerKind::internal_available() && !toolchain_available() {
+        return Ok(());
+    }
+
+    let lib_file = compile_lib(&opts)?;
+    let libs = unsafe { load_osdi_lib(&lib_file)? };
+    assert_eq!(libs.len(), 1);
+    Ok(())
+}
+
+fn test_reproducible() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("reproducible");
+    let opts = |file: &str, dir: &str| {
+        std::fs::create_dir_all(out_dir.join(dir)).unwrap();
+        let mut opts = test_opts(file, &out_dir);
+        opts.output = CompilationDestination::Path { lib_file: out_dir.join(dir).join("lib.osdi") };
+        opts.reproducible = true;
+        opts
+    };
+
+    // compiling another file first with the same database changes the ids of the
+    // modules in the database, the output must not depend on that
+    let first = opts("modules.va", "a");
+    openvaf::compile(&first)?;
+    let results = openvaf::compile_batch(&[opts("noise.va", "b"), opts("modules.va", "c")]);
+    for res in results {
+        assert!(matches!(res.result, Ok(CompilationTermination::Compiled { .. })));
+    }
+
+    let hash = |dir: &str| -> Result<md5::Digest> {
+        Ok(md5::compute(std::fs::read(out_dir.join(dir).join("lib.osdi"))?))
+    };
+    assert_eq!(hash("a")?, hash("c")?);
+
+    // debug information and floating point checks embed the absolute path of the source
+    let mut debug_info = opts("modules.va", "d");
+    debug_info.debug_info = true;
+    assert!(openvaf::compile(&debug_info).is_err());
+    let mut check_fp = opts("modules.va", "e");
+    check_fp.check_fp = true;
+    assert!(openvaf::compile(&check_fp).is_err());
+    Ok(())
+}
+
+fn test_c_backend() -> Result<()> {
+    if !toolchain_available() {
+        return Ok(());
+    }
+
+    let test_dir = openvaf_test_data("osdi");
+    for file in ["diode_lim.va", "noise.va", "opvar_derivative.va", "sensitivity.va"] {
+        compare_c_backend(test_dir.join(file).as_path().try_into().unwrap())?;
+    }
+    Ok(())
+}
+
+fn test_wasm() -> Result<()> {
+    // wasm-ld is usually not installed, the wasm module can only be linked with LLD
+    if !LinkerKind::internal_available() {
+        return Ok(());
+    }
+
+    let out_dir = out_dir("wasm");
+    let mut opts = test_opts("wasm.va", &out_dir);
+    opts.output = CompilationDestination::Path { lib_file: out_dir.join("wasm.wasm") };
+    opts.target = Target::search("wasm32-unknown-unknown").unwrap();
+    opts.target_cpu = "generic".to_owned();
+    opts.linker = LinkerKind::Internal;
+    let lib_file = compile_lib(&opts)?;
+    let module = std::fs::read(&lib_file)?;
+    assert_eq!(&module[..4], b"\0asm");
+
+    // run the module with the JS runtime (osdi/wasm/runtime.mjs) if node is installed
+    let node = std::process::Command::new("node").arg("--version").output();
+    if !node.map_or(false, |out| out.status.success()) {
+        return Ok(());
+    }
+    const ITERATIONS: u32 = 100;
+    let (va, vc) = (0.6, 0.1);
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasm/eval.mjs");
+    let out = std::process::Command::new("node")
+        .arg(script)
+        .arg(lib_file.as_str())
+        .args([ITERATIONS.to_string(), va.to_string(), vc.to_string()])
+        .output()?;
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let out = String::from_utf8(out.stdout)?;
+    let mut residual = HashMap::new();
+    let mut jacobian = HashMap::new();
+    let mut logs = None;
+    let mut heap_growth = None;
+    for line in out.lines() {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match *words.as_slice() {
+            ["residual", node, val] => {
+                residual.insert(node.parse::<u32>()?, val.parse::<f64>()?);
+            }
+            ["jacobian", node1, node2, val] => {
+                jacobian
+                    .insert((node1.parse::<u32>()?, node2.parse::<u32>()?), val.parse::<f64>()?);
+            }
+            ["logs", num] => logs = Some(num.parse::<u32>()?),
+            ["heap_growth", bytes] => heap_growth = Some(bytes.parse::<i64>()?),
+            _ => panic!("unexpected output {line}"),
+        }
+    }
+
+    let (r, is, vt) = (2.0, 1e-12, 0.025);
+    let vd: f64 = va - vc;
+    let current = vd / r + is * ((vd / vt).exp() - 1.0);
+    let conductance = 1.0 / r + is / vt * (vd / vt).exp();
+    let (a, c) = (0, 1);
+    float_cmp::assert_approx_eq!(f64, residual[&a], current, epsilon = 1e-12);
+    float_cmp::assert_approx_eq!(f64, residual[&c], -current, epsilon = 1e-12);
+    float_cmp::assert_approx_eq!(f64, jacobian[&(a, a)], conductance, epsilon = 1e-12);
+    float_cmp::assert_approx_eq!(f64, jacobian[&(a, c)], -conductance, epsilon = 1e-12);
+    float_cmp::assert_approx_eq!(f64, jacobian[&(c, a)], -conductance, epsilon = 1e-12);
+    float_cmp::assert_approx_eq!(f64, jacobian[&(c, c)], conductance, epsilon = 1e-12);
+
+    // every eval logs with $strobe, the messages must be freed and reused
+    assert_eq!(logs, Some(ITERATIONS));
+    assert_eq!(heap_growth, Some(0));
+    Ok(())
+}
+
 harness! {
     // TODO: run this in CI, somehow this test is flakey tough regarding the linker invocation (and really slow)
     Test::from_dir("integration", &integration_test, &ignore_dev_tests, &project_root().join("integration_tests")),
-    [Test::new("$limit", &test_limit),Test::new("noise", &test_noise)]
+    Test::from_dir("c_backend", &c_backend_integration_test, &ignore_dev_tests, &project_root().join("integration_tests")),
+    Test::from_dir("interpret", &interpret_integration_test, &ignore_dev_tests, &project_root().join("integration_tests")),
+    Test::from_dir("autodiff", &autodiff_integration_test, &ignore_dev_tests, &project_root().join("integration_tests")),
+    [
+        Test::new("$limit", &test_limit),
+        Test::new("noise", &test_noise),
+        Test::new("$bound_step", &test_bound_step),
+        Test::new("opvar derivative", &test_opvar_derivative),
+        Test::new("absdelay", &test_absdelay),
+        Test::new("analysis", &test_analysis),
+        Test::new("sensitivity", &test_sensitivity),
+        Test::new("hessian", &test_hessian),
+        Test::new("noinline", &test_noinline),
+        Test::new("fp_policy", &test_fp_policy),
+        Test::new("check_fp", &test_check_fp),
+        Test::new("check_fp_setup", &test_check_fp_setup),
+        Test::new("debug_info", &test_debug_info),
+        Test::new("interface", &test_interface),
+        Test::new("dump_mir_json", &test_dump_mir_json),
+        Test::new("interpret", &test_interpret),
+        Test::new("equations", &test_equations),
+        Test::new("emit", &test_emit),
+        Test::new("compile_batch", &test_compile_batch),
+        Test::new("modules", &test_modules),
+        Test::new("internal_linker", &test_internal_linker),
+        Test::new("reproducible", &test_reproducible),
+        Test::new("c_backend", &test_c_backend),
+        Test::new("wasm", &test_wasm),
+    ]
 }
@@ -1,5 +1,5 @@
 use std::alloc::{alloc_zeroed, handle_alloc_error, Layout};
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::ffi::{c_char, CStr};
 use std::fmt::Debug;
 use std::mem::{align_of, swap};
@@ -43,6 +43,23 @@ impl OsdiDescriptor {
         unsafe { slice::from_raw_parts(self.noise_sources, self.num_noise_src as usize) }
     }
 
+    pub fn delays(&self) -> &[OsdiDelay] {
+        // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+        unsafe { slice::from_raw_parts(self.delays, self.num_delays as usize) }
+    }
+
+    pub fn sensitivity_params(&self) -> &[u32] {
+        // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+        unsafe {
+            slice::from_raw_parts(self.sensitivity_params, self.num_sensitivity_params as usize)
+        }
+    }
+
+    pub fn hessian_entries(&self) -> &[OsdiHessianEntry] {
+        // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+        unsafe { slice::from_raw_parts(self.hessian_entries, self.num_hessian_entries as usize) }
+    }
+
     pub fn matrix_entries(&self) -> &[OsdiJacobianEntry] {
         // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
         unsafe { slice::from_raw_parts(self.jacobian_entries, self.num_jacobian_entries as usize) }
@@ -224,6 +241,47 @@ impl OsdiInstance {
         }
     }
 
+    pub fn bound_step(&self) -> Option<f64> {
+        let off = self.descriptor.bound_step_offset;
+        if off == u32::MAX {
+            return None;
+        }
+        let ptr = self.data as *mut u8;
+        // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+        unsafe { Some(ptr.add(off as usize).cast::<f64>().read()) }
+    }
+
+    pub fn discontinuity(&self) -> Option<i32> {
+        let off = self.descriptor.discontinuity_offset;
+        if off == u32::MAX {
+            return None;
+        }
+        let ptr = self.data as *mut u8;
+        // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+        unsafe { Some(ptr.add(off as usize).cast::<i32>().read()) }
+    }
+
+    pub fn delays(&self) -> Vec<f64> {
+        let ptr = self.data as *mut u8;
+        self.descriptor
+            .delays()
+            .iter()
+            // SAFETY: self.data is a valid allocation and the descriptor is assumed valid
+            .map(|delay| unsafe { ptr.add(delay.delay_offset as usize).cast::<f64>().read() })
+            .collect()
+    }
+
+    pub fn read_real_opvar(&self, model: &OsdiModel, opvar: u32) -> f64 {
+        let id = self.descriptor.num_params + opvar;
+        let flags = ACCESS_FLAG_READ | ACCESS_FLAG_INSTANCE;
+        let ptr = self.descriptor.access(self.data, model.data, id, flags);
+        let ptr = ptr as *mut f64;
+        if ptr.is_null() {
+            unreachable!("invalid opvar access")
+        }
+        unsafe { ptr.read() }
+    }
+
     pub fn collapse_nodes(&self, connected_terminals: u32) -> Vec<u32> {
         let collapsed = self.collapsed();
         let node_mapping = self.node_mapping();
@@ -359,6 +417,16 @@ pub unsafe fn load_osdi_lib(path: &Utf8Path) -> Result<&'static [OsdiDescriptor]
     Ok(descriptors)
 }
 
+thread_local! {
+    /// Messages logged by the models (with `osdi_log`) on the current thread
+    static LOG: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Returns (and clears) all messages logged by the models on the current thread
+pub fn take_log() -> Vec<String> {
+    LOG.with(|log| log.take())
+}
+
 unsafe extern "C" fn osdi_log(handle: *mut c_void, msg: *const c_char, lvl: u32) {
     let _ = catch_unwind(|| osdi_log_impl(handle, msg, lvl));
 }
@@ -397,19 +465,21 @@ unsafe fn osdi_log_impl(handle: *mut c_void, msg: *const c_char, lvl: u32) {
     let instance = CStr::from_ptr(instance).to_str().expect("all OSDI strings must be valid utf-8");
     let msg = CStr::from_ptr(msg).to_str().expect("all OSDI strings must be valid utf-8");
 
-    if (lvl & LOG_FMT_ERR) == 0 {
+    let line = if (lvl & LOG_FMT_ERR) == 0 {
         match lvl & LOG_LVL_MASK {
-            LOG_LVL_DEBUG => println!("debug {instance} - {msg}"),
-            LOG_LVL_DISPLAY => println!("display {instance} - {msg}"),
-            LOG_LVL_INFO => println!("info {instance} - {msg}"),
-            LOG_LVL_WARN => println!("warn {instance} - {msg}"),
-            LOG_LVL_ERR => println!("err {instance} - {msg}"),
-            LOG_LVL_FATAL => println!("fatal {instance} - FATAL {msg}"),
-            _ => println!("{instance} - UNKNOWN_LOG_LVL {msg}"),
+            LOG_LVL_DEBUG => format!("debug {instance} - {msg}"),
+            LOG_LVL_DISPLAY => format!("display {instance} - {msg}"),
+            LOG_LVL_INFO => format!("info {instance} - {msg}"),
+            LOG_LVL_WARN => format!("warn {instance} - {msg}"),
+            LOG_LVL_ERR => format!("err {instance} - {msg}"),
+            LOG_LVL_FATAL => format!("fatal {instance} - FATAL {msg}"),
+            _ => format!("{instance} - UNKNOWN_LOG_LVL {msg}"),
         }
     } else {
-        println!("{instance} - failed to format\"{msg}\"")
-    }
+        format!("{instance} - failed to format\"{msg}\"")
+    };
+    println!("{line}");
+    LOG.with(|log| log.borrow_mut().push(line));
 }
 
 impl Debug for OsdiDescriptor {
@@ -489,8 +559,23 @@ impl Debug for OsdiDescriptor {
                 };
                 wn!("noise {:?} ({}, {})", osdi_str(*name), osdi_str(hi), lo);
             }
+            for delay in self.delays() {
+                let node = self.nodes()[delay.node as usize].name;
+                wn!("delay {}", osdi_str(node));
+            }
+            for &param in self.sensitivity_params() {
+                let param = &self.params()[param as usize];
+                wn!("sensitivity {:?}", osdi_str(*param.name));
+            }
+            for entry in self.hessian_entries() {
+                let node = osdi_str(self.nodes()[entry.node as usize].name);
+                let node_1 = osdi_str(self.nodes()[entry.nodes.node_1 as usize].name);
+                let node_2 = osdi_str(self.nodes()[entry.nodes.node_2 as usize].name);
+                wn!("hessian {} ({}, {})", node, node_1, node_2);
+            }
             wn!("{} states", self.num_states);
             wn!("has bound_step {}", self.bound_step_offset != u32::MAX);
+            wn!("has discontinuity {}", self.discontinuity_offset != u32::MAX);
             wn!("instance size {}", self.instance_size);
             wn!("model size {}", self.model_size);
             Ok(())
@@ -540,6 +625,9 @@ bitflags! {
         const ANALYSIS_IC = ANALYSIS_IC;
         const ANALYSIS_STATIC = ANALYSIS_STATIC;
         const ANALYSIS_NODESET = ANALYSIS_NODESET;
+        const ANALYSIS_HB = ANALYSIS_HB;
+        const CALC_SENSITIVITY = CALC_SENSITIVITY;
+        const CALC_HESSIAN = CALC_HESSIAN;
     }
 }
 
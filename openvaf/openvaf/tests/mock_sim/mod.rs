@@ -218,6 +218,54 @@ impl OsdiInstance {
         self.descriptor.load_jacobian_resist(self.data, model.data);
         self.descriptor.load_jacobian_react(self.data, model.data, 1.0);
     }
+    /// Returns the resistive and reactive right hand side of limiting
+    /// (indexed like the residual of `sim`).
+    pub fn load_limit_rhs(&self, model: &OsdiModel, sim: &MockSimulation) -> (Vec<f64>, Vec<f64>) {
+        let len = sim.residual_resist.len();
+        let mut resist = vec![0.0; len];
+        let mut react = vec![0.0; len];
+        self.descriptor.load_limit_rhs_resist(self.data, model.data, resist.as_mut_ptr());
+        self.descriptor.load_limit_rhs_react(self.data, model.data, react.as_mut_ptr());
+        (resist, react)
+    }
+
+    /// Returns the resistive and reactive derivatives of the residual by each parameter
+    /// selected for sensitivity analysis (indexed like the residual of `sim`).
+    pub fn load_sensitivity(
+        &self,
+        model: &OsdiModel,
+        sim: &MockSimulation,
+    ) -> Vec<(Vec<f64>, Vec<f64>)> {
+        let num_params = self.descriptor.num_sensitivity_params as usize;
+        let len = sim.residual_resist.len();
+        let mut resist = vec![vec![0.0; len]; num_params];
+        let mut react = vec![vec![0.0; len]; num_params];
+        let mut resist_ptrs: Vec<_> = resist.iter_mut().map(|dst| dst.as_mut_ptr()).collect();
+        let mut react_ptrs: Vec<_> = react.iter_mut().map(|dst| dst.as_mut_ptr()).collect();
+        self.descriptor.load_sensitivity(
+            self.data,
+            model.data,
+            resist_ptrs.as_mut_ptr(),
+            react_ptrs.as_mut_ptr(),
+        );
+        zip(resist, react).collect()
+    }
+
+    /// Returns the resistive and reactive second order derivatives of the residual
+    /// (indexed like the `hessian_entries` of the descriptor).
+    pub fn load_hessian(&self, model: &OsdiModel) -> (Vec<f64>, Vec<f64>) {
+        let len = self.descriptor.num_hessian_entries as usize;
+        let mut resist = vec![0.0; len];
+        let mut react = vec![0.0; len];
+        self.descriptor.load_hessian(
+            self.data,
+            model.data,
+            resist.as_mut_ptr(),
+            react.as_mut_ptr(),
+        );
+        (resist, react)
+    }
+
     pub fn eval(
         &self,
         model: &OsdiModel,
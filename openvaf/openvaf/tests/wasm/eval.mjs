@@ -0,0 +1,109 @@
+// Runs an OSDI library compiled for wasm32 with the runtime in osdi/wasm (used by the
+// `wasm` test in integration.rs). The first module of the library is set up with its default
+// parameters and evaluated `iterations` times at the given node voltages. Afterwards the
+// resistive residual and jacobian, the number of logged messages and how much the heap
+// grew after the first evaluation are printed.
+//
+// Usage: node eval.mjs <lib.wasm> <iterations> <voltage of node 0> <voltage of node 1> ...
+
+import fs from "node:fs";
+
+import { instantiateOsdi } from "../../../osdi/wasm/runtime.mjs";
+
+const CALC_RESIST_RESIDUAL = 1;
+const CALC_RESIST_JACOBIAN = 4;
+const CALC_OP = 32;
+const ANALYSIS_DC = 2048;
+const EVAL_RET_FLAG_FATAL = 2;
+
+// sizes of the OSDI structs on wasm32 (4 byte pointers)
+const SIM_PARAS_SIZE = 16;
+const SIM_INFO_SIZE = 40;
+const INIT_INFO_SIZE = 12;
+const JACOBIAN_ENTRY_SIZE = 16;
+
+const [file, iterations, ...voltages] = process.argv.slice(2);
+
+let numLogs = 0;
+const lib = await instantiateOsdi(fs.readFileSync(file), { log: () => numLogs++ });
+const [desc] = lib.descriptors();
+// the buffer is replaced whenever the memory grows
+const view = () => new DataView(lib.memory.buffer);
+const call = (fun, ...args) => lib.table.get(fun)(...args);
+
+const alloc = (size) => {
+  const ptr = lib.malloc(size);
+  new Uint8Array(lib.memory.buffer).fill(0, ptr, ptr + size);
+  return ptr;
+};
+
+// the runtime does not use the handle
+const handle = alloc(1);
+const model = alloc(desc.model_size);
+const inst = alloc(desc.instance_size);
+
+// no simulator parameters: both name lists only contain the terminating null pointer
+const names = alloc(4);
+const simParas = alloc(SIM_PARAS_SIZE);
+view().setUint32(simParas, names, true);
+view().setUint32(simParas + 8, names, true);
+
+const initInfo = alloc(INIT_INFO_SIZE);
+const checkInit = (fun) => {
+  const flags = view().getUint32(initInfo, true);
+  const numErrors = view().getUint32(initInfo + 4, true);
+  lib.free(view().getUint32(initInfo + 8, true));
+  if (numErrors !== 0 || (flags & EVAL_RET_FLAG_FATAL) !== 0) {
+    throw new Error(`${fun} failed with ${numErrors} errors`);
+  }
+};
+call(desc.setup_model, handle, model, simParas, initInfo);
+checkInit("setup_model");
+call(desc.setup_instance, handle, inst, model, 300.0, desc.num_terminals, simParas, initInfo);
+checkInit("setup_instance");
+
+const numNodes = desc.num_nodes;
+const numEntries = desc.num_jacobian_entries;
+const prevSolve = alloc(8 * numNodes);
+const residual = alloc(8 * numNodes);
+const jacobian = alloc(8 * numEntries);
+for (let i = 0; i < numNodes; i++) {
+  view().setUint32(inst + desc.node_mapping_offset + 4 * i, i, true);
+  view().setFloat64(prevSolve + 8 * i, Number(voltages[i] || 0), true);
+}
+for (let i = 0; i < numEntries; i++) {
+  view().setUint32(inst + desc.jacobian_ptr_resist_offset + 4 * i, jacobian + 8 * i, true);
+}
+
+const simInfo = alloc(SIM_INFO_SIZE);
+new Uint8Array(lib.memory.buffer).copyWithin(simInfo, simParas, simParas + SIM_PARAS_SIZE);
+view().setUint32(simInfo + 24, prevSolve, true);
+const flags = CALC_RESIST_RESIDUAL | CALC_RESIST_JACOBIAN | CALC_OP | ANALYSIS_DC;
+view().setUint32(simInfo + 36, flags, true);
+
+let heapTop;
+for (let i = 0; i < Number(iterations); i++) {
+  new Uint8Array(lib.memory.buffer).fill(0, residual, residual + 8 * numNodes);
+  new Uint8Array(lib.memory.buffer).fill(0, jacobian, jacobian + 8 * numEntries);
+  const ret = call(desc.eval, handle, inst, model, simInfo);
+  if ((ret & EVAL_RET_FLAG_FATAL) !== 0) {
+    throw new Error("eval failed");
+  }
+  call(desc.load_residual_resist, inst, model, residual);
+  call(desc.load_jacobian_resist, inst, model);
+  if (i === 0) {
+    heapTop = lib.heapTop();
+  }
+}
+
+for (let i = 0; i < numNodes; i++) {
+  console.log(`residual ${i} ${view().getFloat64(residual + 8 * i, true)}`);
+}
+for (let i = 0; i < numEntries; i++) {
+  const entry = desc.jacobian_entries + JACOBIAN_ENTRY_SIZE * i;
+  const node1 = view().getUint32(entry, true);
+  const node2 = view().getUint32(entry + 4, true);
+  console.log(`jacobian ${node1} ${node2} ${view().getFloat64(jacobian + 8 * i, true)}`);
+}
+console.log(`logs ${numLogs}`);
+console.log(`heap_growth ${lib.heapTop() - heapTop}`);
@@ -48,7 +48,8 @@ fn main() {
                 println!("cargo:rerun-if-changed={}", file.display());
 
                 let mut cmd = cmd!(sh, "clang -emit-llvm -O3 -D{def_name} -DNO_STD -o {out_file} -c {src_file} -target {target_name}");
-                if !target.options.is_like_windows {
+                // wasm has no shared libraries, everything is linked into a single module
+                if !target.options.is_like_windows && !target.options.is_like_wasm {
                     cmd = cmd.arg("-fPIC");
                 }
                 cmd.run().expect("failed to generate bitcode");
@@ -45,6 +45,9 @@
 #define ANALYSIS_IC 16384
 #define ANALYSIS_STATIC 32768
 #define ANALYSIS_NODESET 65536
+#define ANALYSIS_HB 131072
+#define CALC_SENSITIVITY 262144
+#define CALC_HESSIAN 524288
 
 #define EVAL_RET_FLAG_LIM 1
 #define EVAL_RET_FLAG_FATAL 2
@@ -138,6 +141,16 @@ typedef struct OsdiNoiseSource {
   OsdiNodePair nodes;
 }OsdiNoiseSource;
 
+typedef struct OsdiDelay {
+  uint32_t node;
+  uint32_t delay_offset;
+}OsdiDelay;
+
+typedef struct OsdiHessianEntry {
+  uint32_t node;
+  OsdiNodePair nodes;
+}OsdiHessianEntry;
+
 typedef struct OsdiDescriptor {
   char *name;
 
@@ -192,6 +205,21 @@ typedef struct OsdiDescriptor {
   void (*load_jacobian_resist)(void *inst, void* model);
   void (*load_jacobian_react)(void *inst, void* model, double alpha);
   void (*load_jacobian_tran)(void *inst, void* model, double alpha);
+
+  uint32_t discontinuity_offset;
+
+  uint32_t num_delays;
+  OsdiDelay *delays;
+
+  uint32_t num_sensitivity_params;
+  uint32_t *sensitivity_params;
+  void (*load_sensitivity)(void *inst, void *model, double **dst_resist,
+                           double **dst_react);
+
+  uint32_t num_hessian_entries;
+  OsdiHessianEntry *hessian_entries;
+  void (*load_hessian)(void *inst, void *model, double *dst_resist,
+                       double *dst_react);
 }OsdiDescriptor;
 
 
@@ -0,0 +1,558 @@
+//! Generates portable C99 source code that implements the OSDI interface.
+//!
+//! The generated code mirrors the code produced by the LLVM backend: The instance and model
+//! data are C structs with the same fields (and offsets computed with `offsetof`), the MIR
+//! functions are translated with [`mir_c`] and the descriptor is a statically initialized
+//! array of `OsdiDescriptor`. The output only requires a C99 compiler and the C standard
+//! library.
+
+use std::fmt::Write;
+
+use ahash::RandomState;
+use hir::{CompilationDB, Parameter, Type};
+use hir_lower::fmt::{DisplayKind, FmtArg, FmtArgKind};
+use hir_lower::{CallBackKind, HirInterner};
+use indexmap::IndexMap;
+use lasso::Rodeo;
+use mir::{FuncRef, Function, Value};
+use mir_c::{Builder, CType, CValue, CallbackFun, FpCheck};
+use sim_back::{CompiledModule, ModuleInfo};
+use typed_index_collections::TiVec;
+use typed_indexmap::TiSet;
+
+use crate::compilation_unit::OsdiModule;
+use crate::inst_data::{EvalOutput, EvalOutputSlot, InstanceLayout, OsdiInstanceParam};
+use crate::metadata::OsdiLimFunction;
+use crate::{ty_len, OsdiLimId, OSDI_VERSION};
+
+mod access;
+mod descriptor;
+mod eval;
+mod load;
+mod setup;
+
+const HEADER: &str = include_str!("../header/osdi_0_3.h");
+const STDLIB: &str = include_str!("../stdlib.c");
+
+/// Generates a single C source file that contains the OSDI descriptors of all `modules`.
+pub fn compile_c(db: &CompilationDB, modules: &[ModuleInfo]) -> String {
+    let mut literals = Rodeo::new();
+    let mut lim_table = TiSet::default();
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|module| {
+            let mir = CompiledModule::new(db, module, &mut literals, false);
+            for cb in mir.intern.callbacks.iter() {
+                if let CallBackKind::BuiltinLimit { name, num_args } = *cb {
+                    lim_table.ensure(OsdiLimFunction { name, num_args: num_args - 2 });
+                }
+            }
+            mir
+        })
+        .collect();
+    let modules: Vec<_> = modules
+        .iter()
+        .enumerate()
+        .map(|(i, module)| OsdiModule::new(module, i, &lim_table))
+        .collect();
+
+    let mut out = String::new();
+    prelude(&mut out);
+    if !lim_table.is_empty() {
+        out.push_str("OSDI_EXPORT OsdiLimFunction OSDI_LIM_TABLE[] = {\n");
+        for entry in lim_table.iter() {
+            let name = mir_c::const_str(literals.resolve(&entry.name));
+            writeln!(out, "    {{{name}, {}, NULL}},", entry.num_args).unwrap();
+        }
+        out.push_str("};\n");
+        writeln!(out, "OSDI_EXPORT const uint32_t OSDI_LIM_TABLE_LEN = {};\n", lim_table.len())
+            .unwrap();
+    }
+
+    let descriptors: Vec<_> = modules
+        .iter()
+        .map(|module| {
+            let mut cmodule = CModule::new(db, module, &literals);
+            cmodule.data_structs();
+            cmodule.access();
+            cmodule.setup_model();
+            cmodule.setup_instance();
+            cmodule.eval();
+            cmodule.load_functions();
+            let descriptor = cmodule.descriptor();
+            out.push_str(&cmodule.out);
+            descriptor
+        })
+        .collect();
+
+    out.push_str("OSDI_EXPORT const OsdiDescriptor OSDI_DESCRIPTORS[] = {\n");
+    for descriptor in descriptors {
+        out.push_str(&descriptor);
+    }
+    out.push_str("};\n");
+    writeln!(out, "OSDI_EXPORT const uint32_t OSDI_NUM_DESCRIPTORS = {};", modules.len()).unwrap();
+    writeln!(out, "OSDI_EXPORT const uint32_t OSDI_VERSION_MAJOR = {};", OSDI_VERSION.0).unwrap();
+    writeln!(out, "OSDI_EXPORT const uint32_t OSDI_VERSION_MINOR = {};", OSDI_VERSION.1).unwrap();
+    out
+}
+
+/// Includes, the OSDI header and the runtime functions (`stdlib.c`) used by the generated code
+fn prelude(out: &mut String) {
+    out.push_str("/* Generated by OpenVAF. Do not edit. */\n\n#define OSDI_0_3\n");
+    out.push_str(mir_c::PRELUDE);
+    out.push_str(
+        r#"#include <stddef.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#ifndef __GNUC__
+#define __builtin_isnan(x) isnan(x)
+#define __builtin_isinf(x) isinf(x)
+#define __builtin_clz(x) (32 - mir_c_clog2((int32_t)(x)))
+#endif
+
+#if defined(_WIN32)
+#define OSDI_EXPORT __declspec(dllexport)
+#elif defined(__GNUC__)
+#define OSDI_EXPORT __attribute__((visibility("default")))
+#else
+#define OSDI_EXPORT
+#endif
+
+"#,
+    );
+    out.push_str(HEADER.trim_start_matches("#pragma once").trim_start());
+    out.push('\n');
+    out.push_str(STDLIB);
+    out.push_str("\nOSDI_EXPORT osdi_log_ptr osdi_log = NULL;\n\n");
+}
+
+/// The type of a field in the instance or model data struct
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CField {
+    ty: CType,
+    /// number of elements if the field is an array
+    len: Option<u32>,
+}
+
+impl CField {
+    const REAL: CField = CField { ty: CType::Real, len: None };
+
+    fn new(ty: &Type) -> CField {
+        let base = match ty.base_type() {
+            Type::Real => CType::Real,
+            Type::Integer | Type::EmptyArray => CType::Int,
+            Type::String => CType::Str,
+            Type::Bool => CType::Bool,
+            Type::Void | Type::Err | Type::Array { .. } => unreachable!(),
+        };
+        CField { ty: base, len: ty_len(ty) }
+    }
+
+    fn declare(self, name: &str) -> String {
+        match self.len {
+            // C does not allow arrays without elements
+            Some(len) => format!("{}[{}]", self.ty.declare(name), len.max(1)),
+            None => self.ty.declare(name),
+        }
+    }
+}
+
+/// Generates the code for a single module
+struct CModule<'a, 'b> {
+    db: &'a CompilationDB,
+    module: &'a OsdiModule<'b>,
+    literals: &'a Rodeo,
+    inst: InstanceLayout<CField>,
+    model_params: IndexMap<Parameter, CField, RandomState>,
+    /// `struct osdi_inst_data_<sym>`
+    inst_ty: String,
+    /// `struct osdi_model_data_<sym>`
+    model_ty: String,
+    /// number of helper functions (callbacks) generated so far
+    num_helpers: u32,
+    out: String,
+}
+
+impl<'a, 'b> CModule<'a, 'b> {
+    fn new(db: &'a CompilationDB, module: &'a OsdiModule<'b>, literals: &'a Rodeo) -> Self {
+        let inst = InstanceLayout::new(db, module, CField::REAL, CField::new);
+        let model_params = module
+            .info
+            .params
+            .keys()
+            .filter(|param| !inst.params.contains_key(&OsdiInstanceParam::User(**param)))
+            .map(|&param| (param, CField::new(&param.ty(db))))
+            .collect();
+        CModule {
+            db,
+            module,
+            literals,
+            inst,
+            model_params,
+            inst_ty: format!("struct osdi_inst_data_{}", module.sym),
+            model_ty: format!("struct osdi_model_data_{}", module.sym),
+            num_helpers: 0,
+            out: String::new(),
+        }
+    }
+
+    fn data_structs(&mut self) {
+        let module = self.module;
+        let num_params = self.inst.params.len() + self.model_params.len();
+        let mut fields = vec![
+            format!("uint32_t param_given[{}]", bitwords(self.inst.params.len())),
+            format!("double *jacobian_ptr_resist[{}]", module.dae_system.jacobian.len().max(1)),
+            format!("double *jacobian_ptr_react[{}]", self.inst.num_react.max(1)),
+            format!("uint32_t node_mapping[{}]", module.dae_system.unknowns.len().max(1)),
+            format!("bool collapsed[{}]", module.node_collapse.num_pairs().max(1)),
+            "double temperature".to_owned(),
+            "uint32_t connected_ports".to_owned(),
+            format!("int32_t state_idx[{}]", module.intern.lim_state.len().max(1)),
+        ];
+        for (i, (param, field)) in self.inst.params.iter().enumerate() {
+            let name = self.inst_param_name(*param);
+            fields.push(format!("{}; /* {name} */", field.declare(&format!("param_{i}"))));
+        }
+        for (slot, field) in self.inst.cache_slots.iter_enumerated() {
+            fields.push(field.declare(&format!("cache_{}", u32::from(slot))));
+        }
+        for (slot, (_, field)) in self.inst.eval_outputs.raw.iter().enumerate() {
+            fields.push(field.declare(&format!("out_{slot}")));
+        }
+        self.write_struct(&self.inst_ty.clone(), &fields);
+
+        let mut fields = vec![format!("uint32_t param_given[{}]", bitwords(num_params))];
+        for (i, (param, field)) in self.model_params.iter().enumerate() {
+            let name = &module.info.params[param].name;
+            fields.push(format!("{}; /* {name} */", field.declare(&format!("param_{i}"))));
+        }
+        for (i, (param, field)) in self.inst.params.iter().enumerate() {
+            let name = self.inst_param_name(*param);
+            fields.push(format!("{}; /* {name} */", field.declare(&format!("inst_param_{i}"))));
+        }
+        self.write_struct(&self.model_ty.clone(), &fields);
+    }
+
+    fn write_struct(&mut self, name: &str, fields: &[String]) {
+        writeln!(self.out, "{name} {{").unwrap();
+        for field in fields {
+            if field.ends_with("*/") {
+                writeln!(self.out, "    {field}").unwrap();
+            } else {
+                writeln!(self.out, "    {field};").unwrap();
+            }
+        }
+        self.out.push_str("};\n\n");
+    }
+
+    fn inst_param_name(&self, param: OsdiInstanceParam) -> String {
+        match param {
+            OsdiInstanceParam::Builtin(builtin) => format!("${builtin:?}"),
+            OsdiInstanceParam::User(param) => self.module.info.params[&param].name.to_string(),
+        }
+    }
+
+    /// The field of the instance or model data that stores `param`
+    fn param(&self, param: Parameter) -> CValue {
+        match self.inst.params.get_full(&OsdiInstanceParam::User(param)) {
+            Some((pos, _, field)) => CValue::new(format!("inst->param_{pos}"), field.ty),
+            None => {
+                let (pos, _, field) = self.model_params.get_full(&param).unwrap();
+                CValue::new(format!("model->param_{pos}"), field.ty)
+            }
+        }
+    }
+
+    fn builtin_param(&self, param: OsdiInstanceParam) -> CValue {
+        let pos = self.inst.params.get_index_of(&param).unwrap();
+        CValue::new(format!("inst->param_{pos}"), CType::Real)
+    }
+
+    fn eval_output_slot(&self, slot: EvalOutputSlot) -> CValue {
+        let ty = self.inst.eval_outputs.get_index(slot).unwrap().1.ty;
+        CValue::new(format!("inst->out_{}", u32::from(slot)), ty)
+    }
+
+    /// The value of an output of `eval` (after `eval` was called)
+    fn eval_output(&self, output: EvalOutput) -> CValue {
+        match output {
+            EvalOutput::Calculated(slot) => self.eval_output_slot(slot),
+            EvalOutput::Const(val, _) => CValue::from_const(&val, self.literals),
+            EvalOutput::Param(param) => self.eval_param(param),
+            EvalOutput::Cache(slot) => {
+                let ty = self.inst.cache_slots[slot].ty;
+                CValue::new(format!("inst->cache_{}", u32::from(slot)), ty)
+            }
+        }
+    }
+
+    /// The memory location that stores the parameter `param` of the eval function
+    fn eval_param(&self, param: mir::Param) -> CValue {
+        let (kind, _) = self.module.intern.params.get_index(param).unwrap();
+        match *kind {
+            hir_lower::ParamKind::Param(param) => self.param(param),
+            hir_lower::ParamKind::Temperature => CValue::new("inst->temperature", CType::Real),
+            hir_lower::ParamKind::ParamSysFun(func) => {
+                self.builtin_param(OsdiInstanceParam::Builtin(func))
+            }
+            // `sim_back` replaces all uses of hidden state with the initial value of the
+            // variable (`HirInterner::insert_var_init`) so eval never reads it
+            _ => unreachable!(),
+        }
+    }
+
+    fn helper_name(&mut self) -> String {
+        self.num_helpers += 1;
+        format!("cb_{}_{}", self.module.sym, self.num_helpers - 1)
+    }
+
+    /// Callbacks that are shared by all functions. Helper functions required by the
+    /// callbacks are written to the output immediately.
+    fn general_callbacks(
+        &mut self,
+        intern: &HirInterner,
+        handle: &str,
+        simparam: &str,
+    ) -> TiVec<FuncRef, Option<CallbackFun>> {
+        let simparam_state = || -> Box<[String]> {
+            Box::new([simparam.to_owned(), handle.to_owned(), "&ret_flags".to_owned()])
+        };
+        intern
+            .callbacks
+            .raw
+            .iter()
+            .map(|call| {
+                let cb = match call {
+                    CallBackKind::SimParam => {
+                        CallbackFun::new("simparam", simparam_state(), vec![CType::Real])
+                    }
+                    CallBackKind::SimParamOpt => CallbackFun::new(
+                        "simparam_opt",
+                        Box::new([simparam.to_owned()]),
+                        vec![CType::Real],
+                    ),
+                    CallBackKind::SimParamStr => {
+                        CallbackFun::new("simparam_str", simparam_state(), vec![CType::Str])
+                    }
+                    // If these derivative were non zero they would have been removed
+                    CallBackKind::Derivative(_) | CallBackKind::NodeDerivative(_) => {
+                        CallbackFun::Const(CValue::real(0.0))
+                    }
+                    CallBackKind::ParamInfo(_, _)
+                    | CallBackKind::CollapseHint(_, _)
+                    | CallBackKind::BuiltinLimit { .. }
+                    | CallBackKind::StoreLimit(_)
+                    | CallBackKind::LimDiscontinuity
+                    | CallBackKind::Analysis
+                    | CallBackKind::NoiseTable(_)
+                    | CallBackKind::WhiteNoise { .. }
+                    | CallBackKind::FlickerNoise { .. }
+                    | CallBackKind::TimeDerivative => return None,
+
+                    CallBackKind::Print { kind, arg_tys } => {
+                        let fun = self.print_callback(*kind, arg_tys);
+                        CallbackFun::new(fun, Box::new([handle.to_owned()]), Vec::new())
+                    }
+                    CallBackKind::AnalogFunction { fun, .. } => {
+                        let analog_function = &intern.analog_functions[fun];
+                        let return_tys: Vec<_> = analog_function
+                            .return_tys
+                            .iter()
+                            .map(|ty| CField::new(ty).ty)
+                            .collect();
+                        self.analog_function_callback(
+                            &analog_function.func,
+                            &analog_function.returns,
+                            &analog_function.param_tys,
+                            &return_tys,
+                        )
+                    }
+                    CallBackKind::AnalogFunctionDerivative { fun, .. } => {
+                        let analog_function = &intern.analog_functions[fun];
+                        let (func, partials) = analog_function
+                            .derivative
+                            .as_ref()
+                            .expect("derivative of analog function was not generated");
+                        let return_tys = vec![CType::Real; partials.len()];
+                        self.analog_function_callback(
+                            func,
+                            partials,
+                            &analog_function.param_tys,
+                            &return_tys,
+                        )
+                    }
+                };
+                Some(cb)
+            })
+            .collect()
+    }
+
+    /// Compiles an analog function (that was not inlined) to a static function.
+    /// Functions with multiple return values write them to pointers passed as the last arguments.
+    fn analog_function_callback(
+        &mut self,
+        func: &Function,
+        returns: &[Value],
+        param_tys: &[Type],
+        return_tys: &[CType],
+    ) -> CallbackFun {
+        let name = self.helper_name();
+        let mut builder = Builder::new(func, self.literals);
+        let mut params: Vec<_> = param_tys
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| CValue::new(format!("p{i}"), CField::new(ty).ty))
+            .collect();
+        builder.params = params.iter().cloned().map(Some).collect();
+        builder.build_consts();
+        builder.build_func();
+
+        let exit_bb = func.layout.last_block().unwrap();
+        let ret_ty = match *returns {
+            [val] => {
+                let ret = format!("return {};", value(&builder, val));
+                builder.append(exit_bb, ret);
+                return_tys[0].name()
+            }
+            _ => {
+                for (i, (&val, ty)) in returns.iter().zip(return_tys).enumerate() {
+                    let store = format!("*r{i} = {};", value(&builder, val));
+                    builder.append(exit_bb, store);
+                    params.push(CValue::new(format!("*r{i}"), *ty));
+                }
+                builder.append(exit_bb, "return;");
+                "void"
+            }
+        };
+
+        let params: Vec<_> = params.iter().map(|param| param.ty.declare(&param.expr)).collect();
+        let params = if params.is_empty() { "void".to_owned() } else { params.join(", ") };
+        writeln!(self.out, "static {ret_ty} {name}({params}) {{\n{}}}\n", builder.finish())
+            .unwrap();
+
+        CallbackFun::new(name, Box::new([]), return_tys.to_vec())
+    }
+
+    /// Generates a function that formats a message with `snprintf` and passes it to `osdi_log`
+    fn print_callback(&mut self, kind: DisplayKind, arg_tys: &[FmtArg]) -> String {
+        let name = self.helper_name();
+        let mut params = vec!["void *handle".to_owned(), "char *fmt".to_owned()];
+        let mut body = String::new();
+        let mut args = String::new();
+        let mut free = Vec::new();
+        for (i, arg) in arg_tys.iter().enumerate() {
+            params.push(CField::new(&arg.ty).ty.declare(&format!("a{i}")));
+            match arg.kind {
+                FmtArgKind::Binary => {
+                    writeln!(body, "    char *bin{i} = fmt_binary(a{i});").unwrap();
+                    write!(args, ", bin{i}").unwrap();
+                    free.push(format!("bin{i}"));
+                }
+                FmtArgKind::EngineerReal => {
+                    writeln!(body, "    int idx{i} = fmt_char_idx(a{i});").unwrap();
+                    write!(args, ", a{i} * EXP[idx{i}], FMT_CHARS[idx{i}]").unwrap();
+                }
+                FmtArgKind::Other => write!(args, ", a{i}").unwrap(),
+            }
+        }
+
+        let lvl = match kind {
+            DisplayKind::Debug => "LOG_LVL_DEBUG",
+            DisplayKind::Display | DisplayKind::Monitor => "LOG_LVL_DISPLAY",
+            DisplayKind::Info => "LOG_LVL_INFO",
+            DisplayKind::Warn => "LOG_LVL_WARN",
+            DisplayKind::Error => "LOG_LVL_ERR",
+            DisplayKind::Fatal => "LOG_LVL_FATAL",
+        };
+
+        writeln!(
+            body,
+            "    int len = snprintf(NULL, 0, fmt{args});
+    char *msg = len < 0 ? NULL : malloc((size_t)len + 1);
+    if (msg != NULL && snprintf(msg, (size_t)len + 1, fmt{args}) >= 0) {{
+        osdi_log(handle, msg, {lvl});
+    }} else {{
+        free(msg);
+        osdi_log(handle, fmt, {lvl} | LOG_FMT_ERR);
+    }}"
+        )
+        .unwrap();
+        for ptr in free {
+            writeln!(body, "    free({ptr});").unwrap();
+        }
+        writeln!(self.out, "static void {name}({}) {{\n{body}}}\n", params.join(", ")).unwrap();
+        name
+    }
+
+    /// Generates the function called by `lim_<sym>_<id>` callbacks: calls the limit function
+    /// registered by the simulator in `OSDI_LIM_TABLE` and sets `EVAL_RET_FLAG_LIM` if it
+    /// changed the value.
+    fn lim_func(&mut self, id: OsdiLimId, num_args: u32) -> String {
+        let name = format!("lim_{}_{}", self.module.sym, u32::from(id));
+        let params: Vec<_> = (0..num_args + 2).map(|i| format!("double a{i}")).collect();
+        let args: Vec<_> = (0..num_args + 2).map(|i| format!("a{i}")).collect();
+        let fun_args = vec!["double"; num_args as usize + 2].join(", ");
+        let fun_ty = format!("double (*)(bool, bool *, {fun_args})");
+        writeln!(
+            self.out,
+            "static double {name}(OsdiSimInfo *sim_info, uint32_t *ret_flags, {}) {{
+    bool changed = false;
+    double (*fun)(bool, bool *, {fun_args}) = ({fun_ty})OSDI_LIM_TABLE[{}].func_ptr;
+    double res = fun((sim_info->flags & INIT_LIM) != 0, &changed, {});
+    if (changed) {{
+        *ret_flags |= EVAL_RET_FLAG_LIM;
+    }}
+    return res;
+}}
+",
+            params.join(", "),
+            u32::from(id),
+            args.join(", ")
+        )
+        .unwrap();
+        name
+    }
+}
+
+/// The C expression of `val` (`0` if `val` is undefined)
+/// Reports NaN/Inf values to the simulator with `check_fp`. The generated function must
+/// declare `int reported = 0;` so that only the first NaN/Inf of each call is reported.
+fn fp_check(intern: &HirInterner) -> FpCheck {
+    let locations =
+        intern.source_locations.iter().map(|loc| mir_c::const_str(&loc.to_string())).collect();
+    FpCheck {
+        fun: "check_fp".to_owned(),
+        state: Box::new(["handle".to_owned(), "&reported".to_owned()]),
+        locations,
+    }
+}
+
+fn value(builder: &Builder<'_>, val: Value) -> String {
+    builder.values[val].as_ref().map_or_else(|| "0".to_owned(), |val| val.expr.clone())
+}
+
+/// The number of 32 bit words required to store `len` bits (at least one)
+fn bitwords(len: usize) -> usize {
+    ((len + 31) / 32).max(1)
+}
+
+/// Whether bit `pos` of the `param_given` bitfield of `ptr` is set
+fn is_given(ptr: &str, pos: usize) -> String {
+    format!("({ptr}->param_given[{}] & {:#x}u) != 0", pos / 32, 1u32 << (pos % 32))
+}
+
+fn set_given(ptr: &str, pos: usize) -> String {
+    format!("{ptr}->param_given[{}] |= {:#x}u;", pos / 32, 1u32 << (pos % 32))
+}
+
+/// `a | b | c` for all flags in `names` that are set in `flags`
+fn flag_names(flags: u32, names: &[(u32, &str)]) -> String {
+    let names: Vec<_> =
+        names.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect();
+    if names.is_empty() {
+        "0".to_owned()
+    } else {
+        names.join(" | ")
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+use mir_c::CValue;
+
+use crate::c_backend::{set_given, CModule};
+use crate::inst_data::EvalOutput;
+
+impl CModule<'_, '_> {
+    pub(super) fn access(&mut self) {
+        let sym = &self.module.sym;
+        let n_inst = self.inst.params.len();
+        let n_model = self.model_params.len();
+
+        let case = |body: &mut String, id: usize, ptr: &str, given: usize, field: &str| {
+            writeln!(
+                body,
+                "        case {id}:
+            if (flags & ACCESS_FLAG_SET) {{
+                {}
+            }}
+            return &{ptr}->{field};",
+                set_given(ptr, given)
+            )
+            .unwrap();
+        };
+
+        let mut body = String::new();
+        body.push_str("    if (flags & ACCESS_FLAG_INSTANCE) {\n        switch (id) {\n");
+        for i in 0..n_inst {
+            case(&mut body, i, "inst", i, &format!("param_{i}"));
+        }
+        body.push_str("        }\n    } else {\n        switch (id) {\n");
+        // inst param model default values
+        for i in 0..n_inst {
+            case(&mut body, i, "model", n_model + i, &format!("inst_param_{i}"));
+        }
+        for j in 0..n_model {
+            case(&mut body, n_inst + j, "model", j, &format!("param_{j}"));
+        }
+        body.push_str("        }\n    }\n\n    switch (id) {\n");
+
+        for (k, &output) in self.inst.opvars.values().enumerate() {
+            let ptr = match output {
+                EvalOutput::Calculated(slot) => format!("&inst->out_{}", u32::from(slot)),
+                EvalOutput::Const(val, slot) => {
+                    let slot = u32::from(slot.unwrap());
+                    let val = CValue::from_const(&val, self.literals);
+                    format!("(inst->out_{slot} = {val}, &inst->out_{slot})")
+                }
+                EvalOutput::Param(param) => format!("&{}", self.eval_param(param).operand()),
+                EvalOutput::Cache(slot) => format!("&inst->cache_{}", u32::from(slot)),
+            };
+            writeln!(body, "    case {}:\n        return {ptr};", n_model + n_inst + k).unwrap();
+        }
+        //return NULL on unknown id
+        body.push_str("    }\n    return NULL;\n");
+
+        let (inst_ty, model_ty) = (&self.inst_ty, &self.model_ty);
+        writeln!(
+            self.out,
+            "static void *access_{sym}(void *inst_, void *model_, uint32_t id, uint32_t flags) {{
+    {inst_ty} *inst = inst_;
+    {model_ty} *model = model_;
+{body}}}
+"
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::Write;
+
+use mir::F_ZERO;
+use mir_c::const_str;
+
+use crate::c_backend::{flag_names, CModule};
+use crate::inst_data::{EvalOutputSlot, OsdiInstanceParam};
+use crate::metadata::osdi_0_3::{
+    JACOBIAN_ENTRY_REACT, JACOBIAN_ENTRY_REACT_CONST, JACOBIAN_ENTRY_RESIST,
+    JACOBIAN_ENTRY_RESIST_CONST, PARA_KIND_INST, PARA_KIND_MASK, PARA_KIND_MODEL, PARA_TY_INT,
+    PARA_TY_MASK, PARA_TY_REAL,
+};
+use crate::metadata::sim_unknown_info;
+
+impl CModule<'_, '_> {
+    /// Writes the (static) arrays referenced by the descriptor and returns the initializer
+    /// of the descriptor
+    pub(super) fn descriptor(&mut self) -> String {
+        let module = self.module;
+        let db = self.db;
+        let sym = &module.sym;
+        let inst_ty = self.inst_ty.clone();
+        let offset = |field: &str| format!("(uint32_t)offsetof({inst_ty}, {field})");
+        let slot_offset = |slot: Option<EvalOutputSlot>| match slot {
+            Some(slot) => offset(&format!("out_{}", u32::from(slot))),
+            None => "UINT32_MAX".to_owned(),
+        };
+
+        let param_opvar = module.param_opvar(
+            db,
+            self.inst.params.keys(),
+            self.model_params.keys(),
+            self.inst.opvars.keys(),
+        );
+        let mut names = Vec::new();
+        let entries: Vec<_> = param_opvar
+            .iter()
+            .map(|param| {
+                let ty = match param.flags & PARA_TY_MASK {
+                    PARA_TY_REAL => "PARA_TY_REAL",
+                    PARA_TY_INT => "PARA_TY_INT",
+                    _ => "PARA_TY_STR",
+                };
+                let kind = match param.flags & PARA_KIND_MASK {
+                    PARA_KIND_MODEL => "PARA_KIND_MODEL",
+                    PARA_KIND_INST => "PARA_KIND_INST",
+                    _ => "PARA_KIND_OPVAR",
+                };
+                let entry = format!(
+                    "{{&param_names_{sym}[{}], {}, {}, {}, {ty} | {kind}, {}}}",
+                    names.len(),
+                    param.num_alias,
+                    const_str(&param.description),
+                    const_str(&param.units),
+                    param.len
+                );
+                names.extend(param.name.iter().map(|name| const_str(name)));
+                entry
+            })
+            .collect();
+        self.static_array("char *", &format!("param_names_{sym}"), &names);
+        let param_opvar =
+            self.static_array("OsdiParamOpvar", &format!("param_opvar_{sym}"), &entries);
+
+        let nodes: Vec<_> = module
+            .dae_system
+            .unknowns
+            .iter_enumerated()
+            .map(|(id, unknown)| {
+                let (name, units, is_flow) = sim_unknown_info(*unknown, db);
+                let residual = &self.inst.residual[id];
+                format!(
+                    "{{{}, {}, \"\", {}, {}, {}, {}, {is_flow}}}",
+                    const_str(&name),
+                    const_str(&units),
+                    slot_offset(residual.resist.expand()),
+                    slot_offset(residual.react.expand()),
+                    slot_offset(residual.resist_lim_rhs.expand()),
+                    slot_offset(residual.react_lim_rhs.expand()),
+                )
+            })
+            .collect();
+        let nodes = self.static_array("OsdiNode", &format!("nodes_{sym}"), &nodes);
+
+        let mut num_react = 0;
+        let jacobian_entries: Vec<_> = module
+            .dae_system
+            .jacobian
+            .iter()
+            .map(|entry| {
+                let flags = flag_names(
+                    module.jacobian_flags(entry),
+                    &[
+                        (JACOBIAN_ENTRY_RESIST_CONST, "JACOBIAN_ENTRY_RESIST_CONST"),
+                        (JACOBIAN_ENTRY_REACT_CONST, "JACOBIAN_ENTRY_REACT_CONST"),
+                        (JACOBIAN_ENTRY_RESIST, "JACOBIAN_ENTRY_RESIST"),
+                        (JACOBIAN_ENTRY_REACT, "JACOBIAN_ENTRY_REACT"),
+                    ],
+                );
+                let react_ptr_off = if entry.react != F_ZERO {
+                    num_react += 1;
+                    format!(
+                        "{} + {} * sizeof(double *)",
+                        offset("jacobian_ptr_react"),
+                        num_react - 1
+                    )
+                } else {
+                    "UINT32_MAX".to_owned()
+                };
+                format!(
+                    "{{{{{}, {}}}, {react_ptr_off}, {flags}}}",
+                    u32::from(entry.row),
+                    u32::from(entry.col)
+                )
+            })
+            .collect();
+        let jacobian_entries = self.static_array(
+            "OsdiJacobianEntry",
+            &format!("jacobian_entries_{sym}"),
+            &jacobian_entries,
+        );
+
+        let collapsible: Vec<_> = module
+            .collapsible()
+            .iter()
+            .map(|pair| format!("{{{}, {}}}", pair.node_1, node(pair.node_2)))
+            .collect();
+        let num_collapsible = collapsible.len();
+        let collapsible =
+            self.static_array("OsdiNodePair", &format!("collapsible_{sym}"), &collapsible);
+
+        let noise_sources: Vec<_> = module
+            .dae_system
+            .noise_sources
+            .iter()
+            .map(|source| {
+                let name = const_str(self.literals.resolve(&source.name));
+                let node_2 = source.lo.map_or(u32::MAX, u32::from);
+                format!("{{{name}, {{{}, {}}}}}", u32::from(source.hi), node(node_2))
+            })
+            .collect();
+        let num_noise_src = noise_sources.len();
+        let noise_sources =
+            self.static_array("OsdiNoiseSource", &format!("noise_sources_{sym}"), &noise_sources);
+
+        let delays: Vec<_> = self
+            .inst
+            .delays
+            .iter()
+            .map(|&(unknown, slot)| {
+                format!("{{{}, {}}}", u32::from(unknown), slot_offset(Some(slot)))
+            })
+            .collect();
+        let num_delays = delays.len();
+        let delays = self.static_array("OsdiDelay", &format!("delays_{sym}"), &delays);
+
+        // ids (index into param_opvar) of the parameters selected for sensitivity analysis
+        let sensitivity_params: Vec<_> = module
+            .info
+            .sensitivities
+            .iter()
+            .map(|&param| {
+                let pos = self.inst.params.get_index_of(&OsdiInstanceParam::User(param));
+                let pos = pos.unwrap_or_else(|| {
+                    self.inst.params.len() + self.model_params.get_index_of(&param).unwrap()
+                });
+                pos.to_string()
+            })
+            .collect();
+        let num_sensitivity_params = sensitivity_params.len();
+        let sensitivity_params = self.static_array(
+            "uint32_t",
+            &format!("sensitivity_params_{sym}"),
+            &sensitivity_params,
+        );
+
+        let hessian_entries: Vec<_> = module
+            .dae_system
+            .hessian
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{{{}, {{{}, {}}}}}",
+                    u32::from(entry.row),
+                    u32::from(entry.col1),
+                    u32::from(entry.col2)
+                )
+            })
+            .collect();
+        let num_hessian_entries = hessian_entries.len();
+        let hessian_entries = self.static_array(
+            "OsdiHessianEntry",
+            &format!("hessian_entries_{sym}"),
+            &hessian_entries,
+        );
+
+        let model_ty = &self.model_ty;
+        let fields = [
+            ("name", const_str(&module.info.module.name(db))),
+            ("num_nodes", module.dae_system.unknowns.len().to_string()),
+            ("num_terminals", module.info.module.ports(db).len().to_string()),
+            ("nodes", nodes),
+            ("num_jacobian_entries", module.dae_system.jacobian.len().to_string()),
+            ("jacobian_entries", jacobian_entries),
+            ("num_collapsible", num_collapsible.to_string()),
+            ("collapsible", collapsible),
+            ("collapsed_offset", offset("collapsed")),
+            ("noise_sources", noise_sources),
+            ("num_noise_src", num_noise_src.to_string()),
+            ("num_params", (self.inst.params.len() + self.model_params.len()).to_string()),
+            ("num_instance_params", self.inst.params.len().to_string()),
+            ("num_opvars", self.inst.opvars.len().to_string()),
+            ("param_opvar", param_opvar),
+            ("node_mapping_offset", offset("node_mapping")),
+            ("jacobian_ptr_resist_offset", offset("jacobian_ptr_resist")),
+            ("num_states", module.intern.lim_state.len().to_string()),
+            ("state_idx_off", offset("state_idx")),
+            ("bound_step_offset", slot_offset(self.inst.bound_step)),
+            ("instance_size", format!("(uint32_t)sizeof({inst_ty})")),
+            ("model_size", format!("(uint32_t)sizeof({model_ty})")),
+            ("access", format!("access_{sym}")),
+            ("setup_model", format!("setup_model_{sym}")),
+            ("setup_instance", format!("setup_instance_{sym}")),
+            ("eval", format!("eval_{sym}")),
+            ("load_noise", format!("load_noise_{sym}")),
+            ("load_residual_resist", format!("load_residual_resist_{sym}")),
+            ("load_residual_react", format!("load_residual_react_{sym}")),
+            ("load_limit_rhs_resist", format!("load_lim_rhs_resist_{sym}")),
+            ("load_limit_rhs_react", format!("load_lim_rhs_react_{sym}")),
+            ("load_spice_rhs_dc", format!("load_spice_rhs_dc_{sym}")),
+            ("load_spice_rhs_tran", format!("load_spice_rhs_tran_{sym}")),
+            ("load_jacobian_resist", format!("load_jacobian_resist_{sym}")),
+            ("load_jacobian_react", format!("load_jacobian_react_{sym}")),
+            ("load_jacobian_tran", format!("load_jacobian_tran_{sym}")),
+            ("discontinuity_offset", slot_offset(self.inst.discontinuity)),
+            ("num_delays", num_delays.to_string()),
+            ("delays", delays),
+            ("num_sensitivity_params", num_sensitivity_params.to_string()),
+            ("sensitivity_params", sensitivity_params),
+            ("load_sensitivity", format!("load_sensitivity_{sym}")),
+            ("num_hessian_entries", num_hessian_entries.to_string()),
+            ("hessian_entries", hessian_entries),
+            ("load_hessian", format!("load_hessian_{sym}")),
+        ];
+
+        let mut descriptor = String::from("    {\n");
+        for (field, val) in fields {
+            writeln!(descriptor, "        .{field} = {val},").unwrap();
+        }
+        descriptor.push_str("    },\n");
+        descriptor
+    }
+
+    /// Writes a static array called `name` and returns an expression that points to it
+    /// (`NULL` for empty arrays)
+    fn static_array(&mut self, ty: &str, name: &str, entries: &[String]) -> String {
+        if entries.is_empty() {
+            return "NULL".to_owned();
+        }
+        let sep = if ty.ends_with('*') { "" } else { " " };
+        writeln!(self.out, "static {ty}{sep}{name}[] = {{").unwrap();
+        for entry in entries {
+            writeln!(self.out, "    {entry},").unwrap();
+        }
+        self.out.push_str("};\n\n");
+        name.to_owned()
+    }
+}
+
+/// A node index in a descriptor (`UINT32_MAX` if the node does not exist)
+fn node(node: u32) -> String {
+    if node == u32::MAX {
+        "UINT32_MAX".to_owned()
+    } else {
+        node.to_string()
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt::Write;
+
+use hir_lower::{CallBackKind, CurrentKind, ParamKind};
+use mir_c::{Builder, CType, CValue, CallbackFun};
+use sim_back::SimUnknownKind;
+use typed_index_collections::TiVec;
+
+use crate::c_backend::{fp_check, value, CModule};
+use crate::inst_data::{EvalOutput, EvalOutputSlot, OsdiInstanceParam};
+use crate::metadata::OsdiLimFunction;
+
+impl CModule<'_, '_> {
+    pub(super) fn eval(&mut self) {
+        let module = self.module;
+        let func = module.eval;
+        let intern = module.intern;
+        let sym = &module.sym;
+        let n_model = self.model_params.len();
+
+        let prev_solve = |unknown: SimUnknownKind| match module.dae_system.unknowns.index(&unknown)
+        {
+            Some(node) => format!("prev_solve[inst->node_mapping[{}]]", u32::from(node)),
+            None => "0.0".to_owned(),
+        };
+
+        let mut params: TiVec<_, _> = intern
+            .params
+            .raw
+            .iter()
+            .map(|(kind, val)| {
+                if func.dfg.value_dead(*val) && !self.inst.eval_outputs.contains_key(val) {
+                    return None;
+                }
+
+                let val = match *kind {
+                    ParamKind::Param(param) => self.param(param),
+                    ParamKind::Voltage { hi, lo } => {
+                        let hi = prev_solve(SimUnknownKind::KirchoffLaw(hi));
+                        let expr = match lo {
+                            Some(lo) => {
+                                format!("{hi} - {}", prev_solve(SimUnknownKind::KirchoffLaw(lo)))
+                            }
+                            None => hi,
+                        };
+                        CValue::new(expr, CType::Real)
+                    }
+                    // TODO support abstime
+                    ParamKind::Current(CurrentKind::Port(_)) => CValue::real(0.0),
+                    ParamKind::Abstime => CValue::new("sim_info->abstime", CType::Real),
+                    ParamKind::Current(kind) => {
+                        CValue::new(prev_solve(SimUnknownKind::Current(kind)), CType::Real)
+                    }
+                    ParamKind::ImplicitUnknown(equation) => {
+                        CValue::new(prev_solve(SimUnknownKind::Implicit(equation)), CType::Real)
+                    }
+                    ParamKind::Temperature => CValue::new("inst->temperature", CType::Real),
+                    ParamKind::ParamGiven { param } => {
+                        let expr =
+                            match self.inst.params.get_index_of(&OsdiInstanceParam::User(param)) {
+                                Some(pos) => format!(
+                                    "({}) || ({})",
+                                    super::is_given("inst", pos),
+                                    super::is_given("model", n_model + pos)
+                                ),
+                                None => {
+                                    let pos = self.model_params.get_index_of(&param).unwrap();
+                                    super::is_given("model", pos)
+                                }
+                            };
+                        CValue::new(expr, CType::Bool)
+                    }
+                    ParamKind::PortConnected { port } => {
+                        let id = module
+                            .dae_system
+                            .unknowns
+                            .unwrap_index(&SimUnknownKind::KirchoffLaw(port));
+                        let expr = format!("{}u < inst->connected_ports", u32::from(id));
+                        CValue::new(expr, CType::Bool)
+                    }
+                    ParamKind::ParamSysFun(param) => {
+                        self.builtin_param(OsdiInstanceParam::Builtin(param))
+                    }
+                    // replaced with the initial value of the variable by `insert_var_init`
+                    ParamKind::HiddenState(_) => unreachable!(),
+                    ParamKind::FunctionArg(_) => unreachable!(),
+                    ParamKind::EnableIntegration => CValue::new(
+                        "(sim_info->flags & CALC_REACT_JACOBIAN) != 0 \
+                         && (sim_info->flags & ANALYSIS_IC) == 0",
+                        CType::Bool,
+                    ),
+                    ParamKind::PrevState(state) => CValue::new(
+                        format!("sim_info->prev_state[inst->state_idx[{}]]", u32::from(state)),
+                        CType::Real,
+                    ),
+                    ParamKind::NewState(state) => CValue::new(
+                        format!("sim_info->next_state[inst->state_idx[{}]]", u32::from(state)),
+                        CType::Real,
+                    ),
+                    ParamKind::EnableLim => {
+                        CValue::new("(sim_info->flags & ENABLE_LIM) != 0", CType::Bool)
+                    }
+                };
+                Some(val)
+            })
+            .collect();
+
+        let cache_vals = self.inst.cache_slots.iter_enumerated().map(|(slot, field)| {
+            Some(CValue::new(format!("inst->cache_{}", u32::from(slot)), field.ty))
+        });
+        params.extend(cache_vals);
+
+        let mut callbacks = self.general_callbacks(intern, "handle", "&sim_info->paras");
+        let mut lim_funcs = Vec::new();
+        for (func_ref, kind) in intern.callbacks.iter_enumerated() {
+            let cb = match *kind {
+                CallBackKind::BuiltinLimit { name, num_args } => {
+                    let id = module
+                        .lim_table
+                        .unwrap_index(&OsdiLimFunction { name, num_args: num_args - 2 });
+                    let name = match lim_funcs.iter().find(|(lim, _)| *lim == id) {
+                        Some((_, name)) => name.clone(),
+                        None => {
+                            let name = self.lim_func(id, num_args - 2);
+                            lim_funcs.push((id, name.clone()));
+                            name
+                        }
+                    };
+                    let state = Box::new(["sim_info".to_owned(), "&ret_flags".to_owned()]);
+                    CallbackFun::new(name, state, vec![CType::Real])
+                }
+                CallBackKind::StoreLimit(state) => {
+                    let state = Box::new([
+                        "sim_info".to_owned(),
+                        format!("inst->state_idx[{}]", u32::from(state)),
+                    ]);
+                    CallbackFun::new("store_lim", state, vec![CType::Real])
+                }
+                CallBackKind::LimDiscontinuity => CallbackFun::new(
+                    "lim_discontinuity",
+                    Box::new(["(int *)&ret_flags".to_owned()]),
+                    Vec::new(),
+                ),
+                CallBackKind::Analysis => CallbackFun::new(
+                    "analysis",
+                    Box::new(["sim_info".to_owned()]),
+                    vec![CType::Int],
+                ),
+                _ => continue,
+            };
+            callbacks[func_ref] = Some(cb);
+        }
+
+        let mut builder = Builder::new(func, self.literals);
+        builder.params = params;
+        builder.callbacks = callbacks;
+        if module.info.check_fp {
+            builder.fp_check = Some(fp_check(intern));
+        }
+        builder.build_consts();
+        builder.build_func();
+
+        let exit_bb = func.layout.last_block().unwrap();
+        let store_slot = |slot: EvalOutputSlot| {
+            let (&val, _) = self.inst.eval_outputs.get_index(slot).unwrap();
+            format!("inst->out_{} = {};", u32::from(slot), value(&builder, val))
+        };
+        let store_output = |output: EvalOutput| match output {
+            EvalOutput::Calculated(slot) => Some(store_slot(slot)),
+            _ => None,
+        };
+
+        let mut groups: Vec<(&str, Vec<String>)> = Vec::new();
+        for reactive in [false, true] {
+            let (jacobian_flag, residual_flag, lim_rhs_flag) = if reactive {
+                ("CALC_REACT_JACOBIAN", "CALC_REACT_RESIDUAL", "CALC_REACT_LIM_RHS")
+            } else {
+                ("CALC_RESIST_JACOBIAN", "CALC_RESIST_RESIDUAL", "CALC_RESIST_LIM_RHS")
+            };
+
+            let jacobian = self
+                .inst
+                .jacobian
+                .iter()
+                .filter_map(|entry| if reactive { entry.react } else { entry.resist })
+                .filter_map(store_output)
+                .collect();
+            groups.push((jacobian_flag, jacobian));
+
+            let residual = self
+                .inst
+                .residual
+                .iter()
+                .filter_map(|residual| {
+                    let slot = if reactive { residual.react } else { residual.resist };
+                    slot.expand()
+                })
+                .map(store_slot)
+                .collect();
+            groups.push((residual_flag, residual));
+
+            let lim_rhs = self
+                .inst
+                .residual
+                .iter()
+                .filter_map(|residual| {
+                    let slot =
+                        if reactive { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+                    slot.expand()
+                })
+                .map(store_slot)
+                .collect();
+            groups.push((lim_rhs_flag, lim_rhs));
+        }
+
+        let opvars = self.inst.opvars.values().copied().filter_map(store_output).collect();
+        groups.push(("CALC_OP", opvars));
+        let noise = self
+            .inst
+            .noise
+            .iter()
+            .flat_map(|source| source.eval_outputs())
+            .filter_map(store_output)
+            .collect();
+        groups.push(("CALC_NOISE", noise));
+        let sensitivities = self
+            .inst
+            .sensitivities
+            .iter()
+            .flat_map(|sensitivity| sensitivity.eval_outputs())
+            .filter_map(store_output)
+            .collect();
+        groups.push(("CALC_SENSITIVITY", sensitivities));
+        let hessian = self
+            .inst
+            .hessian
+            .iter()
+            .flat_map(|entry| entry.eval_outputs())
+            .filter_map(store_output)
+            .collect();
+        groups.push(("CALC_HESSIAN", hessian));
+
+        let unconditional: Vec<_> = self
+            .inst
+            .bound_step
+            .iter()
+            .chain(&self.inst.discontinuity)
+            .chain(self.inst.delays.iter().map(|(_, slot)| slot))
+            .map(|&slot| store_slot(slot))
+            .collect();
+
+        for (flag, stmts) in groups {
+            if stmts.is_empty() {
+                continue;
+            }
+            builder.append(exit_bb, format!("if (sim_info->flags & {flag}) {{"));
+            for stmt in stmts {
+                builder.append(exit_bb, format!("    {stmt}"));
+            }
+            builder.append(exit_bb, "}");
+        }
+        for stmt in unconditional {
+            builder.append(exit_bb, stmt);
+        }
+        builder.append(exit_bb, "return ret_flags;");
+
+        let (inst_ty, model_ty) = (&self.inst_ty, &self.model_ty);
+        let mut body = format!(
+            "    {inst_ty} *inst = inst_;
+    {model_ty} *model = model_;
+    double *prev_solve = sim_info->prev_solve;
+    uint32_t ret_flags = 0;
+"
+        );
+        if module.info.check_fp {
+            body.push_str("    int reported = 0;\n");
+        }
+        body.push_str("    (void)inst;\n    (void)model;\n    (void)prev_solve;\n\n");
+        body.push_str(&builder.finish());
+        writeln!(
+            self.out,
+            "static uint32_t eval_{sym}(void *handle, void *inst_, void *model_, \
+             OsdiSimInfo *sim_info) {{\n{body}}}\n"
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::Write;
+
+use sim_back::dae::{NoiseSourceKind, SimUnknown};
+use stdx::iter::zip;
+use typed_index_collections::TiVec;
+
+use crate::c_backend::CModule;
+use crate::inst_data::EvalOutput;
+
+/// The destination of a contribution to the residual or the right hand side
+fn contrib(dst: &str, node: SimUnknown) -> String {
+    format!("{dst}[inst->node_mapping[{}]]", u32::from(node))
+}
+
+impl CModule<'_, '_> {
+    pub(super) fn load_functions(&mut self) {
+        self.load_noise();
+        for reactive in [false, true] {
+            self.load_residual(reactive);
+            self.load_lim_rhs(reactive);
+        }
+        self.load_spice_rhs(false);
+        self.load_spice_rhs(true);
+        self.load_jacobian("resist");
+        self.load_jacobian("react");
+        self.load_jacobian("tran");
+        self.load_sensitivity();
+        self.load_hessian();
+    }
+
+    fn load_function(&mut self, name: &str, params: &str, body: &str) {
+        let (sym, inst_ty, model_ty) = (&self.module.sym, &self.inst_ty, &self.model_ty);
+        writeln!(
+            self.out,
+            "static void {name}_{sym}(void *inst_, void *model_{params}) {{
+    {inst_ty} *inst = inst_;
+    {model_ty} *model = model_;
+    (void)inst;
+    (void)model;
+{body}}}
+"
+        )
+        .unwrap();
+    }
+
+    fn load_output(&self, output: EvalOutput) -> String {
+        self.eval_output(output).operand()
+    }
+
+    fn load_noise(&mut self) {
+        let mut body = String::new();
+        for (i, (src, eval_outputs)) in
+            zip(&self.module.dae_system.noise_sources, &self.inst.noise).enumerate()
+        {
+            let fac = self.load_output(eval_outputs.factor);
+            let pwr = match src.kind {
+                NoiseSourceKind::WhiteNoise { .. } => self.load_output(eval_outputs.args[0]),
+                NoiseSourceKind::FlickerNoise { .. } => {
+                    let pwr = self.load_output(eval_outputs.args[0]);
+                    let exp = self.eval_output(eval_outputs.args[1]);
+                    format!("{pwr} / pow(freq, {exp})")
+                }
+                NoiseSourceKind::NoiseTable { .. } => unimplemented!("noise tables"),
+            };
+            writeln!(body, "    noise_dens[{i}] = {pwr} * {fac};").unwrap();
+        }
+        self.load_function("load_noise", ", double freq, double *noise_dens", &body);
+    }
+
+    fn load_residual(&mut self, reactive: bool) {
+        let mut body = String::new();
+        for (node, residual) in self.inst.residual.iter_enumerated() {
+            let slot = if reactive { residual.react } else { residual.resist };
+            if let Some(slot) = slot.expand() {
+                let val = self.eval_output_slot(slot);
+                writeln!(body, "    {} += {val};", contrib("dst", node)).unwrap();
+            }
+        }
+        let name = format!("load_residual_{}", if reactive { "react" } else { "resist" });
+        self.load_function(&name, ", double *dst", &body);
+    }
+
+    fn load_lim_rhs(&mut self, reactive: bool) {
+        let mut body = String::new();
+        for (node, residual) in self.inst.residual.iter_enumerated() {
+            let slot = if reactive { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+            if let Some(slot) = slot.expand() {
+                let val = self.eval_output_slot(slot);
+                writeln!(body, "    {} -= {val};", contrib("dst", node)).unwrap();
+            }
+        }
+        let name = format!("load_lim_rhs_{}", if reactive { "react" } else { "resist" });
+        self.load_function(&name, ", double *dst", &body);
+    }
+
+    fn load_spice_rhs_(&self, tran: bool, body: &mut String) {
+        let dae_system = &self.module.dae_system;
+        let mut node_derivatives = TiVec::from(vec![Vec::new(); dae_system.unknowns.len()]);
+        for (id, entry) in dae_system.jacobian.iter_enumerated() {
+            node_derivatives[entry.row].push(id)
+        }
+
+        for node in dae_system.unknowns.indices() {
+            let mut res: Option<String> = None;
+            for &entry in &node_derivatives[node] {
+                let node_deriv = dae_system.jacobian[entry].col;
+                let entry = &self.inst.jacobian[entry];
+                let ddx = match if tran { entry.react } else { entry.resist } {
+                    Some(ddx) => self.load_output(ddx),
+                    None => continue,
+                };
+                let val = format!("{ddx} * {}", contrib("prev_solve", node_deriv));
+                res = Some(match res {
+                    Some(old) => format!("{old} + {val}"),
+                    None => val,
+                });
+            }
+
+            if !tran {
+                if let Some(slot) = self.inst.residual[node].resist.expand() {
+                    let contrib = self.eval_output_slot(slot);
+                    let old = res.unwrap_or_else(|| "0.0".to_owned());
+                    res = Some(format!("{old} - {contrib}"));
+                }
+            }
+            if let Some(mut res) = res {
+                let residual = &self.inst.residual[node];
+                let lim_rhs = if tran { residual.react_lim_rhs } else { residual.resist_lim_rhs };
+                if let Some(slot) = lim_rhs.expand() {
+                    res = format!("{res} + {}", self.eval_output_slot(slot));
+                }
+                if tran {
+                    res = format!("({res}) * alpha");
+                }
+                writeln!(body, "    {} += {res};", contrib("dst", node)).unwrap();
+            }
+        }
+    }
+
+    fn load_spice_rhs(&mut self, tran: bool) {
+        let mut body = String::new();
+        self.load_spice_rhs_(false, &mut body);
+        if tran {
+            self.load_spice_rhs_(true, &mut body);
+        }
+        let (name, params) = if tran {
+            ("load_spice_rhs_tran", ", double *dst, double *prev_solve, double alpha")
+        } else {
+            ("load_spice_rhs_dc", ", double *dst, double *prev_solve")
+        };
+        self.load_function(name, params, &body);
+    }
+
+    /// `kind` is one of `resist`, `react` or `tran`
+    fn load_jacobian(&mut self, kind: &str) {
+        let read_resistive = kind != "react";
+        let read_reactive = kind != "resist";
+        let mut body = String::new();
+        for (id, entry) in self.inst.jacobian.iter_enumerated() {
+            let resist = entry.resist.filter(|_| read_resistive).map(|val| self.load_output(val));
+            let react = entry
+                .react
+                .filter(|_| read_reactive)
+                .map(|val| format!("{} * alpha", self.load_output(val)));
+            let res = match (resist, react) {
+                (Some(resist), Some(react)) => format!("{resist} + {react}"),
+                (Some(res), None) | (None, Some(res)) => res,
+                (None, None) => continue,
+            };
+            let dst = if kind == "react" {
+                format!("jacobian_ptr_react[{}]", u32::from(entry.react_off.unwrap()))
+            } else {
+                format!("jacobian_ptr_resist[{}]", u32::from(id))
+            };
+            writeln!(body, "    *inst->{dst} += {res};").unwrap();
+        }
+        let params = if read_reactive { ", double alpha" } else { "" };
+        self.load_function(&format!("load_jacobian_{kind}"), params, &body);
+    }
+
+    /// Adds the derivatives of the residual by the i-th parameter selected for
+    /// sensitivity analysis to `dst_resist[i]` and `dst_react[i]`.
+    fn load_sensitivity(&mut self) {
+        let module = self.module;
+        let mut body = String::new();
+        for (entry, sensitivity) in zip(&module.dae_system.sensitivities, &self.inst.sensitivities)
+        {
+            let pos = module.info.sensitivities.iter().position(|&it| it == entry.param).unwrap();
+            for (val, dst) in [(sensitivity.resist, "dst_resist"), (sensitivity.react, "dst_react")]
+            {
+                if let Some(val) = val {
+                    let dst = contrib(&format!("{dst}[{pos}]"), entry.row);
+                    writeln!(body, "    {dst} += {};", self.eval_output(val)).unwrap();
+                }
+            }
+        }
+        self.load_function("load_sensitivity", ", double **dst_resist, double **dst_react", &body);
+    }
+
+    /// Adds the i-th entry of the second order jacobian to `dst_resist[i]` and `dst_react[i]`.
+    fn load_hessian(&mut self) {
+        let mut body = String::new();
+        for (pos, entry) in self.inst.hessian.iter().enumerate() {
+            for (val, dst) in [(entry.resist, "dst_resist"), (entry.react, "dst_react")] {
+                if let Some(val) = val {
+                    writeln!(body, "    {dst}[{pos}] += {};", self.eval_output(val)).unwrap();
+                }
+            }
+        }
+        self.load_function("load_hessian", ", double *dst_resist, double *dst_react", &body);
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt::Write;
+
+use hir_lower::{CallBackKind, ParamInfoKind, ParamKind, PlaceKind};
+use mir::{Block, ControlFlowGraph, Function, Param, Value};
+use mir_c::{Builder, CType, CValue, CallbackFun};
+use sim_back::SimUnknownKind;
+use typed_index_collections::TiVec;
+
+use crate::c_backend::{fp_check, is_given, value, CModule};
+use crate::inst_data::OsdiInstanceParam;
+
+/// Initializes the `OsdiInitInfo` returned by the setup functions
+const INIT_RES: &str = "    uint32_t ret_flags = 0;
+    uint32_t err_cap = 0;
+    res->flags = 0;
+    res->num_errors = 0;
+    res->errors = NULL;
+    (void)ret_flags;
+    (void)err_cap;
+";
+
+impl CModule<'_, '_> {
+    fn invalid_param_err(id: usize) -> CallbackFun {
+        let state = Box::new([
+            "(void **)&res->errors".to_owned(),
+            "&res->num_errors".to_owned(),
+            "&err_cap".to_owned(),
+            format!("{id}u"),
+        ]);
+        CallbackFun::new("push_invalid_param_err", state, Vec::new())
+    }
+
+    pub(super) fn setup_model(&mut self) {
+        let module = self.module;
+        let func = module.model_param_setup;
+        let intern = module.model_param_intern;
+        let n_model = self.model_params.len();
+
+        let mut params: TiVec<Param, _> = vec![None; intern.params.len()].into();
+        for (i, (&param, field)) in self.model_params.iter().enumerate() {
+            let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+            params[dst] = Some(CValue::new(format!("model->param_{i}"), field.ty));
+
+            let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+            params[dst] = Some(CValue::new(is_given("model", i), CType::Bool));
+        }
+
+        for (i, (param, field)) in self.inst.params.iter().enumerate() {
+            let given = is_given("model", n_model + i);
+            let val = format!("model->inst_param_{i}");
+
+            match *param {
+                OsdiInstanceParam::Builtin(builtin) => {
+                    if let Some(dst) = intern.params.index(&ParamKind::ParamSysFun(builtin)) {
+                        let default_val = mir_c::const_real(builtin.default_value());
+                        let val = format!("({given}) ? {val} : {default_val}");
+                        params[dst] = Some(CValue::new(val, CType::Real));
+                    }
+                }
+                OsdiInstanceParam::User(param) => {
+                    let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+                    params[dst] = Some(CValue::new(val, field.ty));
+                    let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+                    params[dst] = Some(CValue::new(given, CType::Bool));
+                }
+            }
+        }
+
+        let mut callbacks = self.general_callbacks(intern, "handle", "simparam");
+        for (call_id, call) in intern.callbacks.iter_enumerated() {
+            if let CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) = call {
+                if !module.info.params[param].is_instance {
+                    let id =
+                        self.model_params.get_index_of(param).unwrap() + self.inst.params.len();
+                    callbacks[call_id] = Some(Self::invalid_param_err(id));
+                }
+            }
+        }
+
+        let mut builder = Builder::new(func, self.literals);
+        builder.params = params;
+        builder.callbacks = callbacks;
+        if module.info.check_fp {
+            builder.fp_check = Some(fp_check(intern));
+        }
+        builder.build_consts();
+        builder.build_func();
+
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+        let exit_bb = cfg
+            .postorder(func)
+            .find(|&bb| {
+                func.layout.last_inst(bb).map_or(true, |term| !func.dfg.insts[term].is_terminator())
+            })
+            .unwrap();
+
+        // store parameters
+        for (i, param) in self.model_params.keys().enumerate() {
+            let val = intern.outputs[&PlaceKind::Param(*param)].unwrap_unchecked();
+            let stmt = format!("model->param_{i} = {};", value(&builder, val));
+            builder.append(def_block(func, val), stmt);
+        }
+        builder.append(exit_bb, "return;");
+
+        let (sym, model_ty) = (&module.sym, &self.model_ty);
+        let reported = if module.info.check_fp { "    int reported = 0;\n" } else { "" };
+        writeln!(
+            self.out,
+            "static void setup_model_{sym}(void *handle, void *model_, OsdiSimParas *simparam, \
+             OsdiInitInfo *res) {{
+    {model_ty} *model = model_;
+{INIT_RES}{reported}
+{}}}
+",
+            builder.finish()
+        )
+        .unwrap();
+    }
+
+    pub(super) fn setup_instance(&mut self) {
+        let module = self.module;
+        let func = &module.init.func;
+        let intern = &module.init.intern;
+        let n_model = self.model_params.len();
+        let sym = &module.sym;
+        let (inst_ty, model_ty) = (self.inst_ty.clone(), &self.model_ty);
+
+        let mut prologue = format!(
+            "    {inst_ty} *inst = inst_;
+    {model_ty} *model = model_;
+"
+        );
+        let mut params: TiVec<Param, _> = vec![None; intern.params.len()].into();
+
+        for (i, (param, field)) in self.inst.params.iter().enumerate() {
+            writeln!(
+                prologue,
+                "    bool inst_given_{i} = {};
+    bool given_{i} = inst_given_{i} || ({});
+    {} = inst_given_{i} ? inst->param_{i} : model->inst_param_{i};",
+                is_given("inst", i),
+                is_given("model", n_model + i),
+                field.ty.declare(&format!("val_{i}")),
+            )
+            .unwrap();
+
+            let val = CValue::new(format!("val_{i}"), field.ty);
+            match *param {
+                OsdiInstanceParam::Builtin(builtin) => {
+                    let default_val = mir_c::const_real(builtin.default_value());
+                    writeln!(
+                        prologue,
+                        "    val_{i} = given_{i} ? val_{i} : {default_val};
+    inst->param_{i} = val_{i};"
+                    )
+                    .unwrap();
+                    if let Some(dst) = intern.params.index(&ParamKind::ParamSysFun(builtin)) {
+                        params[dst] = Some(val);
+                    }
+                }
+                OsdiInstanceParam::User(param) => {
+                    let dst = intern.params.unwrap_index(&ParamKind::Param(param));
+                    params[dst] = Some(val);
+                    let dst = intern.params.unwrap_index(&ParamKind::ParamGiven { param });
+                    params[dst] = Some(CValue::new(format!("given_{i}"), CType::Bool));
+                }
+            }
+        }
+
+        for (i, (&param, field)) in self.model_params.iter().enumerate() {
+            if let Some(dst) = intern.params.index(&ParamKind::Param(param)) {
+                params[dst] = Some(CValue::new(format!("model->param_{i}"), field.ty));
+            }
+
+            if let Some(dst) = intern.params.index(&ParamKind::ParamGiven { param }) {
+                params[dst] = Some(CValue::new(is_given("model", i), CType::Bool));
+            }
+        }
+
+        if let Some(dst) = intern.params.index(&ParamKind::Temperature) {
+            params[dst] = Some(CValue::new("temperature", CType::Real));
+        }
+
+        for (node_id, unknown) in module.dae_system.unknowns.iter_enumerated() {
+            if let SimUnknownKind::KirchoffLaw(node) = unknown {
+                if let Some((dst, val)) =
+                    intern.params.index_and_val(&ParamKind::PortConnected { port: *node })
+                {
+                    if func.dfg.value_dead(*val) {
+                        continue;
+                    }
+
+                    let expr = format!("{} < (int32_t)num_terminals", u32::from(node_id));
+                    params[dst] = Some(CValue::new(expr, CType::Bool));
+                }
+            }
+        }
+
+        // store for use in eval() function
+        prologue.push_str(
+            "    inst->temperature = temperature;
+    inst->connected_ports = num_terminals;
+",
+        );
+        prologue.push_str(INIT_RES);
+        if module.info.check_fp {
+            prologue.push_str("    int reported = 0;\n");
+        }
+
+        let mut callbacks = self.general_callbacks(intern, "handle", "simparam");
+        let mut uses_collapse = false;
+        for (call_id, call) in intern.callbacks.iter_enumerated() {
+            let cb = match call {
+                CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) => {
+                    match self.inst.params.get_index_of(&OsdiInstanceParam::User(*param)) {
+                        Some(id) => Self::invalid_param_err(id),
+                        None => continue,
+                    }
+                }
+                CallBackKind::CollapseHint(node1, node2) => {
+                    let node1 = module
+                        .dae_system
+                        .unknowns
+                        .unwrap_index(&SimUnknownKind::KirchoffLaw(*node1));
+                    let node2 = node2.map(|node2| {
+                        module.dae_system.unknowns.unwrap_index(&SimUnknownKind::KirchoffLaw(node2))
+                    });
+                    let mut state = vec![];
+                    module.node_collapse.hint(node1, node2, |pair| {
+                        state.push("inst".to_owned());
+                        state.push(format!("{}u", u32::from(pair)));
+                    });
+                    uses_collapse = true;
+                    CallbackFun::Fun {
+                        fun: format!("collapse_{sym}"),
+                        state: state.into_boxed_slice(),
+                        num_state: 2,
+                        returns: Vec::new(),
+                    }
+                }
+                _ => continue,
+            };
+            callbacks[call_id] = Some(cb);
+        }
+
+        let mut builder = Builder::new(func, self.literals);
+        builder.params = params;
+        builder.callbacks = callbacks;
+        if module.info.check_fp {
+            builder.fp_check = Some(fp_check(intern));
+        }
+        builder.build_consts();
+        builder.build_func();
+        let exit_bb = func.layout.last_block().unwrap();
+
+        // store parameters
+        for (i, param) in self.inst.params.keys().enumerate() {
+            let val = match param {
+                OsdiInstanceParam::Builtin(_) => continue,
+                OsdiInstanceParam::User(param) => {
+                    intern.outputs[&PlaceKind::Param(*param)].unwrap_unchecked()
+                }
+            };
+            let stmt = format!("inst->param_{i} = {};", value(&builder, val));
+            builder.append(def_block(func, val), stmt);
+        }
+
+        for (&val, &slot) in module.init.cached_vals.iter() {
+            let stmt = format!("inst->cache_{} = {};", u32::from(slot), value(&builder, val));
+            builder.append(def_block(func, val), stmt);
+        }
+
+        for (&kind, val) in intern.outputs.iter() {
+            if let PlaceKind::CollapseImplicitEquation(eq) = kind {
+                let should_collapse = value(&builder, val.unwrap_unchecked());
+                let eq = module.dae_system.unknowns.unwrap_index(&SimUnknownKind::Implicit(eq));
+                builder.append(exit_bb, format!("if ({should_collapse}) {{"));
+                module.node_collapse.hint(eq, None, |pair| {
+                    let stmt = format!("    inst->collapsed[{}] = true;", u32::from(pair));
+                    builder.append(exit_bb, stmt);
+                });
+                builder.append(exit_bb, "}");
+            }
+        }
+        builder.append(exit_bb, "return;");
+
+        if uses_collapse {
+            writeln!(
+                self.out,
+                "static void collapse_{sym}({inst_ty} *inst, uint32_t idx) {{
+    inst->collapsed[idx] = true;
+}}
+"
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            self.out,
+            "static void setup_instance_{sym}(void *handle, void *inst_, void *model_, \
+             double temperature, uint32_t num_terminals, OsdiSimParas *simparam, \
+             OsdiInitInfo *res) {{
+{prologue}
+{}}}
+",
+            builder.finish()
+        )
+        .unwrap();
+    }
+}
+
+/// The block that contains the instruction that defines `val`
+fn def_block(func: &Function, val: Value) -> Block {
+    let inst = func.dfg.value_def(val).unwrap_inst();
+    func.layout.inst_block(inst).unwrap()
+}
@@ -1,17 +1,22 @@
-use hir::CompilationDB;
+use ahash::AHashMap;
+use hir::{CompilationDB, SourceLocation, Type};
 use hir_lower::fmt::{DisplayKind, FmtArg, FmtArgKind};
-use hir_lower::{CallBackKind, HirInterner};
+use hir_lower::{CallBackKind, HirInterner, PlaceKind};
+use indexmap::IndexSet;
 use lasso::Rodeo;
 use llvm::Linkage;
 use llvm::{
     IntPredicate, LLVMAddIncoming, LLVMAppendBasicBlockInContext, LLVMBuildAdd,
     LLVMBuildArrayMalloc, LLVMBuildBr, LLVMBuildCall2, LLVMBuildCondBr, LLVMBuildFMul,
-    LLVMBuildFree, LLVMBuildICmp, LLVMBuildInBoundsGEP2, LLVMBuildLoad2, LLVMBuildPhi,
-    LLVMGetParam, LLVMIsDeclaration, LLVMPositionBuilderAtEnd, LLVMSetLinkage,
-    LLVMSetUnnamedAddress, UnnamedAddr, UNNAMED,
+    LLVMBuildFree, LLVMBuildICmp, LLVMBuildInBoundsGEP2, LLVMBuildInsertValue, LLVMBuildLoad2,
+    LLVMBuildPhi, LLVMGetParam, LLVMGetUndef, LLVMIsDeclaration, LLVMPositionBuilderAtEnd,
+    LLVMSetLinkage, LLVMSetUnnamedAddress, UnnamedAddr, UNNAMED,
+};
+use mir::{FuncRef, Function, Tag};
+use mir_llvm::{
+    BuilderVal, CallbackFun, CodegenCx, DebugInfoBuilder, FloatPolicy, FpCheck, FunctionDebugInfo,
+    LLVMBackend, ModuleLlvm,
 };
-use mir::{FuncRef, Function};
-use mir_llvm::{CallbackFun, CodegenCx, LLVMBackend, ModuleLlvm};
 use sim_back::dae::DaeSystem;
 use sim_back::init::Initialization;
 use sim_back::node_collapse::NodeCollapse;
@@ -32,8 +37,10 @@ pub fn new_codegen<'a, 'll>(
     back: &'a LLVMBackend,
     llmod: &'ll ModuleLlvm,
     literals: &'a Rodeo,
+    fp_policy: FloatPolicy,
 ) -> CodegenCx<'a, 'll> {
-    let cx = unsafe { back.new_ctx(literals, llmod) };
+    let mut cx = unsafe { back.new_ctx(literals, llmod) };
+    cx.fp_policy = fp_policy;
     cx.include_bitcode(stdlib_bitcode(back.target()));
 
     for fun in llvm::function_iter(llmod.llmod()) {
@@ -117,12 +124,15 @@ pub struct OsdiModule<'a> {
 }
 
 impl<'a> OsdiModule<'a> {
+    /// `idx` is the position of the module within the library. It is used to derive the
+    /// symbol names so that they do not depend on the order in which the database interned
+    /// the modules (required for reproducible builds).
     pub fn new(
-        db: &'a CompilationDB,
         module: &'a CompiledModule,
+        idx: usize,
         lim_table: &'a TiSet<OsdiLimId, OsdiLimFunction>,
     ) -> Self {
-        let sym = base_n::encode(module.info.module.uuid(db) as u128, base_n::CASE_INSENSITIVE);
+        let sym = base_n::encode(idx as u128, base_n::CASE_INSENSITIVE);
         let CompiledModule {
             info,
             dae_system,
@@ -132,6 +142,7 @@ impl<'a> OsdiModule<'a> {
             model_param_setup,
             model_param_intern,
             node_collapse,
+            ..
         } = module;
         OsdiModule {
             sym,
@@ -148,7 +159,80 @@ impl<'a> OsdiModule<'a> {
     }
 }
 
+/// Debug information for the function `fun` called `name` that is generated from a MIR
+/// function lowered with the [`HirInterner`] `intern`. The source locations of the function
+/// refer to `source_locations` (usually [`HirInterner::source_locations`] of `intern`).
+pub fn function_debug_info<'a, 'll>(
+    db: &CompilationDB,
+    builder: &'a DebugInfoBuilder<'ll>,
+    fun: &'ll llvm::Value,
+    name: &str,
+    intern: &HirInterner,
+    source_locations: &IndexSet<SourceLocation, ahash::RandomState>,
+) -> FunctionDebugInfo<'a, 'll> {
+    let root_file = db.compilation_unit().path(db);
+    let line = source_locations.iter().find(|loc| loc.file == root_file).map_or(0, |loc| loc.line);
+    let mut debug_info = source_debug_info(builder, fun, name, &root_file, line, source_locations);
+
+    // writes to variables are tagged with the index of their place
+    debug_info.variables = intern
+        .outputs
+        .keys()
+        .enumerate()
+        .filter_map(|(place, kind)| {
+            let var = match *kind {
+                PlaceKind::Var(var) => var,
+                _ => return None,
+            };
+            let ty = match var.ty(db) {
+                Type::Real => builder.ty_real(),
+                Type::Integer => builder.ty_integer(),
+                _ => return None,
+            };
+            let decl = var.location(db);
+            let file = builder.file(&decl.file);
+            let var = builder.variable(debug_info.scope, &var.name(db), file, decl.line, ty);
+            Some((Tag::from(place), var))
+        })
+        .collect();
+
+    debug_info
+}
+
+/// Debug information (without variables) for the function `fun` called `name` that is
+/// declared at `line` of `file`. The source locations of the MIR function refer to
+/// `source_locations`.
+fn source_debug_info<'a, 'll>(
+    builder: &'a DebugInfoBuilder<'ll>,
+    fun: &'ll llvm::Value,
+    name: &str,
+    file: &str,
+    line: u32,
+    source_locations: &IndexSet<SourceLocation, ahash::RandomState>,
+) -> FunctionDebugInfo<'a, 'll> {
+    let scope = builder.function(fun, name, builder.file(file), line);
+
+    // code from other (included) files needs a dedicated scope
+    let mut file_scopes = AHashMap::new();
+    let locations = source_locations
+        .iter()
+        .map(|loc| {
+            let scope = if loc.file == file {
+                scope
+            } else {
+                *file_scopes
+                    .entry(&loc.file)
+                    .or_insert_with(|| builder.file_scope(scope, builder.file(&loc.file)))
+            };
+            builder.location(scope, loc.line, loc.col)
+        })
+        .collect();
+
+    FunctionDebugInfo { builder, scope, locations, variables: AHashMap::new() }
+}
+
 pub fn general_callbacks<'ll>(
+    db: &CompilationDB,
     intern: &HirInterner,
     builder: &mut mir_llvm::Builder<'_, '_, 'll>,
     ret_flags: &'ll llvm::Value,
@@ -156,6 +240,7 @@ pub fn general_callbacks<'ll>(
     simparam: &'ll llvm::Value,
 ) -> TiVec<FuncRef, Option<CallbackFun<'ll>>> {
     let ptr_ty = builder.cx.ty_ptr();
+    let debug_info = builder.debug_info.as_ref().map(|debug_info| debug_info.builder);
     intern
         .callbacks
         .raw
@@ -230,12 +315,129 @@ pub fn general_callbacks<'ll>(
                     let (fun, fun_ty) = print_callback(builder.cx, *kind, arg_tys);
                     CallbackFun { fun_ty, fun, state: Box::new([handle]), num_state: 0 }
                 }
+
+                CallBackKind::AnalogFunction { fun, .. } => {
+                    let analog_function = &intern.analog_functions[fun];
+                    let return_tys: Vec<_> = analog_function
+                        .return_tys
+                        .iter()
+                        .map(|ty| lltype(ty, builder.cx))
+                        .collect();
+                    let debug_info = debug_info.map(|debug_info| {
+                        (debug_info, fun.location(db), &analog_function.source_locations)
+                    });
+                    analog_function_callback(
+                        builder.cx,
+                        &analog_function.func,
+                        &analog_function.returns,
+                        &analog_function.param_tys,
+                        &return_tys,
+                        analog_function.fp_policy.unwrap_or(builder.fp_policy),
+                        debug_info,
+                    )
+                }
+                CallBackKind::AnalogFunctionDerivative { fun, .. } => {
+                    let analog_function = &intern.analog_functions[fun];
+                    let (func, partials) = analog_function
+                        .derivative
+                        .as_ref()
+                        .expect("derivative of analog function was not generated");
+                    let return_tys = vec![builder.cx.ty_double(); partials.len()];
+                    analog_function_callback(
+                        builder.cx,
+                        func,
+                        partials,
+                        &analog_function.param_tys,
+                        &return_tys,
+                        analog_function.fp_policy.unwrap_or(builder.fp_policy),
+                        None,
+                    )
+                }
             };
             Some(cb)
         })
         .collect()
 }
 
+/// Reports NaN/Inf values produced by `builder` to the simulator with the stdlib function
+/// `check_fp`. Only the first NaN/Inf produced during each call of the function is reported.
+/// The source locations of the compiled function must refer to `source_locations`.
+pub fn fp_check<'ll>(
+    builder: &mir_llvm::Builder<'_, '_, 'll>,
+    handle: &'ll llvm::Value,
+    source_locations: &IndexSet<SourceLocation, ahash::RandomState>,
+) -> FpCheck<'ll> {
+    let cx = builder.cx;
+    let reported = unsafe { builder.alloca(cx.ty_int()) };
+    unsafe { builder.store(reported, cx.const_int(0)) };
+    let fun = cx.get_func_by_name("check_fp").expect("stdlib function check_fp is missing");
+    let fun_ty = cx.ty_func(
+        &[cx.ty_ptr(), cx.ty_ptr(), cx.ty_ptr(), cx.ty_int(), cx.ty_double()],
+        cx.ty_void(),
+    );
+    let locations =
+        source_locations.iter().map(|loc| cx.const_str_uninterned(&loc.to_string())).collect();
+    FpCheck {
+        callback: CallbackFun { fun_ty, fun, state: Box::new([handle, reported]), num_state: 0 },
+        locations,
+    }
+}
+
+/// Compiles an analog function (that was not inlined) to an internal function.
+/// Functions with multiple return values return a struct. If `debug_info` is passed
+/// the function is placed at its declaration and `func` refers to the source locations
+/// of the analog function.
+fn analog_function_callback<'ll>(
+    cx: &CodegenCx<'_, 'll>,
+    func: &Function,
+    returns: &[mir::Value],
+    param_tys: &[Type],
+    return_tys: &[&'ll llvm::Type],
+    fp_policy: FloatPolicy,
+    debug_info: Option<(
+        &DebugInfoBuilder<'ll>,
+        SourceLocation,
+        &IndexSet<SourceLocation, ahash::RandomState>,
+    )>,
+) -> CallbackFun<'ll> {
+    let params: Vec<_> = param_tys.iter().map(|ty| lltype(ty, cx)).collect();
+    let ret_ty = match *return_tys {
+        [ty] => ty,
+        _ => cx.ty_struct("analog_function_ret", return_tys),
+    };
+    let fun_ty = cx.ty_func(&params, ret_ty);
+    let name = cx.local_callback_name();
+    let fun = cx.declare_int_fn(&name, fun_ty);
+
+    let mut builder = mir_llvm::Builder::new(cx, func, fun);
+    builder.fp_policy = fp_policy;
+    builder.debug_info = debug_info.map(|(debug_info, decl, source_locations)| {
+        source_debug_info(debug_info, fun, &func.name, &decl.file, decl.line, source_locations)
+    });
+    builder.params = (0..params.len())
+        .map(|i| BuilderVal::Eager(unsafe { LLVMGetParam(fun, i as u32) }))
+        .collect();
+    builder.build_consts();
+    unsafe {
+        builder.build_func();
+        builder.select_bb(func.layout.last_block().unwrap());
+        let ret = match *returns {
+            [val] => builder.values[val].get(&builder),
+            _ => {
+                let mut ret = LLVMGetUndef(ret_ty);
+                for (i, &val) in returns.iter().enumerate() {
+                    let val = builder.values[val].get(&builder);
+                    ret = LLVMBuildInsertValue(builder.llbuilder, ret, val, i as u32, UNNAMED);
+                }
+                ret
+            }
+        };
+        builder.ret(ret);
+    }
+
+    CallbackFun { fun_ty, fun, state: Box::new([]), num_state: 0 }
+}
+
 fn print_callback<'ll>(
     cx: &CodegenCx<'_, 'll>,
     kind: hir_lower::fmt::DisplayKind,
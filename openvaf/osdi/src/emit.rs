@@ -0,0 +1,122 @@
+use std::fmt::Display;
+use std::{fs, io};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use mir::Function;
+use mir_llvm::ModuleLlvm;
+
+/// Intermediate representations that can be written next to the library in addition to
+/// the library itself (mainly for investigating the generated code).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EmitKind {
+    /// Textual LLVM IR of every compilation unit before and after optimization
+    LlvmIr,
+    /// LLVM bitcode of every compilation unit before and after optimization
+    LlvmBc,
+    /// Target assembly of every compilation unit
+    Asm,
+    /// Object file of every compilation unit (these are normally deleted after linking)
+    Obj,
+    /// MIR of every module before and after `mir_opt` and the final functions of `sim_back`
+    Mir,
+}
+
+impl EmitKind {
+    pub const ALL: [EmitKind; 5] =
+        [EmitKind::LlvmIr, EmitKind::LlvmBc, EmitKind::Asm, EmitKind::Obj, EmitKind::Mir];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EmitKind::LlvmIr => "llvm-ir",
+            EmitKind::LlvmBc => "llvm-bc",
+            EmitKind::Asm => "asm",
+            EmitKind::Obj => "obj",
+            EmitKind::Mir => "mir",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            EmitKind::LlvmIr => "ll",
+            EmitKind::LlvmBc => "bc",
+            EmitKind::Asm => "s",
+            EmitKind::Obj => "o",
+            EmitKind::Mir => "mir",
+        }
+    }
+}
+
+/// Writes the requested [`EmitKind`]s of a compilation unit to
+/// `<lib>.<unit>[.<stage>].<ext>` next to the library.
+#[derive(Clone, Copy)]
+pub(crate) struct Artifacts<'a> {
+    pub(crate) dst: &'a Utf8Path,
+    pub(crate) kinds: &'a [EmitKind],
+}
+
+impl Artifacts<'_> {
+    pub(crate) fn emits(&self, kind: EmitKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    fn path(&self, unit: &str, stage: Option<&str>, kind: EmitKind) -> Utf8PathBuf {
+        let stem = self.dst.file_stem().expect("destination is a file");
+        let ext = kind.extension();
+        let name = match stage {
+            Some(stage) => format!("{stem}.{unit}.{stage}.{ext}"),
+            None => format!("{stem}.{unit}.{ext}"),
+        };
+        self.dst.with_file_name(name)
+    }
+
+    pub(crate) fn emit_mir(&self, unit: &str, stage: &str, func: &Function) -> io::Result<()> {
+        if self.emits(EmitKind::Mir) {
+            let path = self.path(unit, Some(stage), EmitKind::Mir);
+            fs::write(&path, format!("{func:?}")).map_err(|err| write_error(&path, err))?;
+        }
+        Ok(())
+    }
+
+    /// Optimizes `llmod` (if `optimize` is set) and writes it to the object file `obj`
+    /// that is linked into the library. The requested LLVM artifacts are written along the way.
+    pub(crate) fn emit_llvm(
+        &self,
+        llmod: &ModuleLlvm,
+        unit: &str,
+        optimize: bool,
+        obj: &Utf8Path,
+    ) -> io::Result<()> {
+        self.emit_llvm_stage(llmod, unit, "pre_opt")?;
+        if optimize {
+            llmod.optimize();
+        }
+        self.emit_llvm_stage(llmod, unit, "post_opt")?;
+
+        llmod.emit_object(obj.as_ref()).map_err(|err| write_error(obj, err))?;
+        if self.emits(EmitKind::Asm) {
+            let path = self.path(unit, None, EmitKind::Asm);
+            llmod.emit_asm(path.as_ref()).map_err(|err| write_error(&path, err))?;
+        }
+        if self.emits(EmitKind::Obj) {
+            let path = self.path(unit, None, EmitKind::Obj);
+            fs::copy(obj, &path).map_err(|err| write_error(&path, err))?;
+        }
+        Ok(())
+    }
+
+    fn emit_llvm_stage(&self, llmod: &ModuleLlvm, unit: &str, stage: &str) -> io::Result<()> {
+        if self.emits(EmitKind::LlvmIr) {
+            let path = self.path(unit, Some(stage), EmitKind::LlvmIr);
+            llmod.emit_llvm_ir(path.as_ref()).map_err(|err| write_error(&path, err))?;
+        }
+        if self.emits(EmitKind::LlvmBc) {
+            let path = self.path(unit, Some(stage), EmitKind::LlvmBc);
+            llmod.emit_llvm_bc(path.as_ref()).map_err(|err| write_error(&path, err))?;
+        }
+        Ok(())
+    }
+}
+
+fn write_error(path: &Utf8Path, err: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("failed to write {path}: {err}"))
+}
@@ -7,17 +7,19 @@ use llvm::{
     LLVMGetParam, LLVMPositionBuilderAtEnd, UNNAMED,
 };
 use log::info;
-use mir_llvm::{Builder, BuilderVal, CallbackFun, MemLoc};
+use mir_llvm::{Builder, BuilderVal, CallbackFun, DebugInfoBuilder, MemLoc};
 use sim_back::SimUnknownKind;
 use typed_index_collections::TiVec;
 
 use crate::bitfield::{is_flag_set, is_flag_set_mem, is_flag_unset};
-use crate::compilation_unit::{general_callbacks, OsdiCompilationUnit};
+use crate::compilation_unit::{
+    fp_check, function_debug_info, general_callbacks, OsdiCompilationUnit,
+};
 use crate::inst_data::OsdiInstanceParam;
 use crate::metadata::osdi_0_3::{
-    ANALYSIS_IC, CALC_NOISE, CALC_OP, CALC_REACT_JACOBIAN, CALC_REACT_LIM_RHS, CALC_REACT_RESIDUAL,
-    CALC_RESIST_JACOBIAN, CALC_RESIST_LIM_RHS, CALC_RESIST_RESIDUAL, ENABLE_LIM, EVAL_RET_FLAG_LIM,
-    INIT_LIM,
+    ANALYSIS_IC, CALC_HESSIAN, CALC_NOISE, CALC_OP, CALC_REACT_JACOBIAN, CALC_REACT_LIM_RHS,
+    CALC_REACT_RESIDUAL, CALC_RESIST_JACOBIAN, CALC_RESIST_LIM_RHS, CALC_RESIST_RESIDUAL,
+    CALC_SENSITIVITY, ENABLE_LIM, EVAL_RET_FLAG_LIM, INIT_LIM,
 };
 use crate::metadata::OsdiLimFunction;
 use crate::OsdiLimId;
@@ -33,7 +35,7 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         cx.declare_ext_fn(name, fun_ty)
     }
 
-    pub fn eval(&self) -> &'ll llvm::Value {
+    pub fn eval(&self, debug_info: Option<&DebugInfoBuilder<'ll>>) -> &'ll llvm::Value {
         let llfunc = self.eval_prototype();
         let OsdiCompilationUnit { inst_data, model_data, cx, module, .. } = self;
 
@@ -41,6 +43,17 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         let intern = module.intern;
 
         let mut builder = Builder::new(cx, func, llfunc);
+        builder.debug_info = debug_info.map(|debug_info| {
+            let name = format!("eval_{}", &module.sym);
+            function_debug_info(
+                self.db,
+                debug_info,
+                llfunc,
+                &name,
+                intern,
+                &intern.source_locations,
+            )
+        });
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
         let instance = unsafe { llvm::LLVMGetParam(llfunc, 1) };
@@ -183,6 +196,7 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                             )
                             .unwrap(),
                         ParamKind::HiddenState(_) => unreachable!(), // TODO  hidden state
+                        ParamKind::FunctionArg(_) => unreachable!(),
                         ParamKind::EnableIntegration => {
                             let flags = flags.read(builder.llbuilder);
                             let is_not_dc =
@@ -232,7 +246,12 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         params.extend(cache_vals);
         builder.params = params;
 
-        builder.callbacks = general_callbacks(intern, &mut builder, ret_flags, handle, simparam);
+        builder.callbacks =
+            general_callbacks(self.db, intern, &mut builder, ret_flags, handle, simparam);
+
+        if module.info.check_fp {
+            builder.fp_check = Some(fp_check(&builder, handle, &intern.source_locations));
+        }
 
         for (func, kind) in intern.callbacks.iter_enumerated() {
             let cb = match *kind {
@@ -330,8 +349,32 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                 }
             };
             Self::build_store_results(&builder, llfunc, &flags, CALC_NOISE, &store_noise);
+            let store_sensitivities = |builder: &Builder<'_, '_, 'll>| {
+                for sensitivity in &inst_data.sensitivities {
+                    for eval_output in sensitivity.eval_outputs() {
+                        inst_data.store_eval_output(eval_output, instance, builder)
+                    }
+                }
+            };
+            Self::build_store_results(
+                &builder,
+                llfunc,
+                &flags,
+                CALC_SENSITIVITY,
+                &store_sensitivities,
+            );
+            let store_hessian = |builder: &Builder<'_, '_, 'll>| {
+                for entry in &inst_data.hessian {
+                    for eval_output in entry.eval_outputs() {
+                        inst_data.store_eval_output(eval_output, instance, builder)
+                    }
+                }
+            };
+            Self::build_store_results(&builder, llfunc, &flags, CALC_HESSIAN, &store_hessian);
 
             inst_data.store_bound_step(instance, &builder);
+            inst_data.store_discontinuity(instance, &builder);
+            inst_data.store_delays(instance, &builder);
 
             let ret_flags = builder.load(cx.ty_int(), ret_flags);
             builder.ret(ret_flags);
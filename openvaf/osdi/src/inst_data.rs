@@ -5,12 +5,13 @@ use indexmap::IndexMap;
 use llvm::{
     IntPredicate, LLVMBuildFAdd, LLVMBuildFSub, LLVMBuildGEP2, LLVMBuildICmp, LLVMBuildIntCast2,
     LLVMBuildLoad2, LLVMBuildStore, LLVMBuildStructGEP2, LLVMConstInt, LLVMOffsetOfElement,
-    LLVMSetFastMath, TargetData, UNNAMED,
+    TargetData, UNNAMED,
 };
 use mir::{strip_optbarrier, Const, Function, Param, ValueDef, F_ZERO};
 use mir_llvm::{CodegenCx, MemLoc};
 use sim_back::dae::{self, MatrixEntryId, SimUnknown};
 use sim_back::init::CacheSlot;
+use sim_back::SimUnknownKind;
 use stdx::packed_option::PackedOption;
 use stdx::{impl_debug_display, impl_idx_from};
 use typed_index_collections::TiVec;
@@ -46,12 +47,12 @@ pub enum EvalOutput {
 impl EvalOutput {
     const NONE: EvalOutput = EvalOutput::Cache(CacheSlot(u32::MAX));
 
-    fn new<'ll>(
+    fn new<T>(
         module: &OsdiModule<'_>,
         val: mir::Value,
-        eval_outputs: &mut TiMap<EvalOutputSlot, mir::Value, &'ll llvm::Type>,
+        eval_outputs: &mut TiMap<EvalOutputSlot, mir::Value, T>,
         requires_slot: bool,
-        ty: &'ll llvm::Type,
+        ty: T,
     ) -> EvalOutput {
         match module.eval.dfg.value_def(val) {
             ValueDef::Result(_, _) => (),
@@ -96,10 +97,10 @@ pub struct Residual {
 }
 
 impl Residual {
-    pub fn new<'ll>(
+    pub fn new<T: Copy>(
         residual: &dae::Residual,
-        slots: &mut TiMap<EvalOutputSlot, mir::Value, &'ll llvm::Type>,
-        ty_real: &'ll llvm::Type,
+        slots: &mut TiMap<EvalOutputSlot, mir::Value, T>,
+        ty_real: T,
         func: &Function,
     ) -> Residual {
         let mut get_slot = |mut val| {
@@ -127,11 +128,11 @@ pub struct MatrixEntry {
 }
 
 impl MatrixEntry {
-    pub fn new<'ll>(
+    pub fn new<T: Copy>(
         entry: &dae::MatrixEntry,
         module: &OsdiModule<'_>,
-        slots: &mut TiMap<EvalOutputSlot, mir::Value, &'ll llvm::Type>,
-        ty_real: &'ll llvm::Type,
+        slots: &mut TiMap<EvalOutputSlot, mir::Value, T>,
+        ty_real: T,
         num_react: &mut u32,
     ) -> MatrixEntry {
         let mut get_output = |mut val| {
@@ -156,6 +157,38 @@ impl MatrixEntry {
     }
 }
 
+/// A derivative of the residual that is not part of the jacobian
+/// (parameter sensitivities and the second order jacobian)
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ResidualDerivative {
+    pub resist: Option<EvalOutput>,
+    pub react: Option<EvalOutput>,
+}
+
+impl ResidualDerivative {
+    pub fn new<T: Copy>(
+        resist: mir::Value,
+        react: mir::Value,
+        module: &OsdiModule<'_>,
+        slots: &mut TiMap<EvalOutputSlot, mir::Value, T>,
+        ty_real: T,
+    ) -> ResidualDerivative {
+        let mut get_output = |mut val| {
+            val = strip_optbarrier(module.eval, val);
+            if val == F_ZERO {
+                None
+            } else {
+                Some(EvalOutput::new(module, val, slots, false, ty_real))
+            }
+        };
+        ResidualDerivative { resist: get_output(resist), react: get_output(react) }
+    }
+
+    pub fn eval_outputs(&self) -> impl Iterator<Item = EvalOutput> {
+        self.resist.into_iter().chain(self.react)
+    }
+}
+
 #[derive(Debug)]
 pub struct NoiseSource {
     pub factor: EvalOutput,
@@ -164,11 +197,11 @@ pub struct NoiseSource {
 }
 
 impl NoiseSource {
-    pub fn new<'ll>(
+    pub fn new<T: Copy>(
         source: &dae::NoiseSource,
         module: &OsdiModule<'_>,
-        slots: &mut TiMap<EvalOutputSlot, mir::Value, &'ll llvm::Type>,
-        ty_real: &'ll llvm::Type,
+        slots: &mut TiMap<EvalOutputSlot, mir::Value, T>,
+        ty_real: T,
     ) -> NoiseSource {
         let mut get_output = |mut val| {
             val = strip_optbarrier(module.eval, val);
@@ -208,14 +241,43 @@ pub struct OsdiInstanceData<'ll> {
     pub noise: Vec<NoiseSource>,
     pub opvars: IndexMap<Variable, EvalOutput, RandomState>,
     pub jacobian: TiVec<MatrixEntryId, MatrixEntry>,
+    /// one entry for each [`dae::SensitivityEntry`]
+    pub sensitivities: Vec<ResidualDerivative>,
+    /// one entry for each [`dae::HessianEntry`]
+    pub hessian: Vec<ResidualDerivative>,
     pub bound_step: Option<EvalOutputSlot>,
+    pub discontinuity: Option<EvalOutputSlot>,
+    /// The delay times of all `absdelay` operators (and the unknown of the corresponding
+    /// implicit equation)
+    pub delays: Vec<(SimUnknown, EvalOutputSlot)>,
 }
 
-impl<'ll> OsdiInstanceData<'ll> {
-    pub fn new(db: &CompilationDB, module: &OsdiModule<'_>, cx: &CodegenCx<'_, 'll>) -> Self {
-        let ty_f64 = cx.ty_double();
-        let ty_u32 = cx.ty_int();
+/// The fields of the instance data struct (and the outputs of `eval` stored in them) that
+/// depend on the compiled module. `T` is the type of a field in the generated code.
+pub struct InstanceLayout<T> {
+    pub params: IndexMap<OsdiInstanceParam, T, RandomState>,
+    pub eval_outputs: TiMap<EvalOutputSlot, mir::Value, T>,
+    pub cache_slots: TiVec<CacheSlot, T>,
+    pub residual: TiVec<SimUnknown, Residual>,
+    pub noise: Vec<NoiseSource>,
+    pub opvars: IndexMap<Variable, EvalOutput, RandomState>,
+    pub jacobian: TiVec<MatrixEntryId, MatrixEntry>,
+    pub sensitivities: Vec<ResidualDerivative>,
+    pub hessian: Vec<ResidualDerivative>,
+    pub bound_step: Option<EvalOutputSlot>,
+    pub discontinuity: Option<EvalOutputSlot>,
+    pub delays: Vec<(SimUnknown, EvalOutputSlot)>,
+    /// number of jacobian entries with a reactive component
+    pub num_react: u32,
+}
 
+impl<T: Copy> InstanceLayout<T> {
+    pub fn new(
+        db: &CompilationDB,
+        module: &OsdiModule<'_>,
+        ty_f64: T,
+        ty: impl Fn(&hir::Type) -> T,
+    ) -> Self {
         let builtin_inst_params = ParamSysFun::iter().filter_map(|param| {
             let is_live = |intern: &HirInterner, func| {
                 intern.is_param_live(func, &ParamKind::ParamSysFun(param))
@@ -230,7 +292,7 @@ impl<'ll> OsdiInstanceData<'ll> {
             .keys()
             .map(|param| (OsdiInstanceParam::Builtin(*param), ty_f64));
         let user_inst_params = module.info.params.iter().filter_map(|(param, info)| {
-            info.is_instance.then(|| (OsdiInstanceParam::User(*param), lltype(&param.ty(db), cx)))
+            info.is_instance.then(|| (OsdiInstanceParam::User(*param), ty(&param.ty(db))))
         });
         let params: IndexMap<_, _, _> =
             builtin_inst_params.chain(alias_inst_params).chain(user_inst_params).collect();
@@ -242,7 +304,7 @@ impl<'ll> OsdiInstanceData<'ll> {
             .keys()
             .map(|var| {
                 let val = module.intern.outputs[&PlaceKind::Var(*var)].unwrap_unchecked();
-                let ty = lltype(&var.ty(db), cx);
+                let ty = ty(&var.ty(db));
                 let pos = EvalOutput::new(module, val, &mut eval_outputs, true, ty);
                 (*var, pos)
             })
@@ -260,6 +322,34 @@ impl<'ll> OsdiInstanceData<'ll> {
             .iter()
             .map(|entry| MatrixEntry::new(entry, module, &mut eval_outputs, ty_f64, &mut num_react))
             .collect();
+        let sensitivities = module
+            .dae_system
+            .sensitivities
+            .iter()
+            .map(|entry| {
+                ResidualDerivative::new(
+                    entry.resist,
+                    entry.react,
+                    module,
+                    &mut eval_outputs,
+                    ty_f64,
+                )
+            })
+            .collect();
+        let hessian = module
+            .dae_system
+            .hessian
+            .iter()
+            .map(|entry| {
+                ResidualDerivative::new(
+                    entry.resist,
+                    entry.react,
+                    module,
+                    &mut eval_outputs,
+                    ty_f64,
+                )
+            })
+            .collect();
         let noise = module
             .dae_system
             .noise_sources
@@ -272,6 +362,70 @@ impl<'ll> OsdiInstanceData<'ll> {
             let slot = eval_outputs.insert_full(val, ty_f64).0;
             Some(slot)
         });
+        let discontinuity = module.intern.outputs.get(&PlaceKind::Discontinuity).and_then(|val| {
+            let mut val = val.expand()?;
+            val = strip_optbarrier(module.eval, val);
+            // the lowest order is signed (-1 if no discontinuity was announced)
+            let slot = eval_outputs.insert_full(val, ty(&hir::Type::Integer)).0;
+            Some(slot)
+        });
+        let delays = module
+            .intern
+            .outputs
+            .iter()
+            .filter_map(|(kind, val)| {
+                let equation = if let PlaceKind::Delay(equation) = *kind {
+                    equation
+                } else {
+                    return None;
+                };
+                let unknown =
+                    module.dae_system.unknowns.index(&SimUnknownKind::Implicit(equation))?;
+                let val = strip_optbarrier(module.eval, val.expand()?);
+                let slot = eval_outputs.insert_full(val, ty_f64).0;
+                Some((unknown, slot))
+            })
+            .collect();
+
+        let cache_slots = module.init.cache_slots.raw.values().map(ty).collect();
+
+        InstanceLayout {
+            params,
+            eval_outputs,
+            cache_slots,
+            residual,
+            noise,
+            opvars,
+            jacobian,
+            sensitivities,
+            hessian,
+            bound_step,
+            discontinuity,
+            delays,
+            num_react,
+        }
+    }
+}
+
+impl<'ll> OsdiInstanceData<'ll> {
+    pub fn new(db: &CompilationDB, module: &OsdiModule<'_>, cx: &CodegenCx<'_, 'll>) -> Self {
+        let ty_f64 = cx.ty_double();
+        let ty_u32 = cx.ty_int();
+        let InstanceLayout {
+            params,
+            eval_outputs,
+            cache_slots,
+            residual,
+            noise,
+            opvars,
+            jacobian,
+            sensitivities,
+            hessian,
+            bound_step,
+            discontinuity,
+            delays,
+            num_react,
+        } = InstanceLayout::new(db, module, ty_f64, |ty| lltype(ty, cx));
 
         let param_given = bitfield::arr_ty(params.len() as u32, cx);
         let jacobian_ptr = cx.ty_array(cx.ty_ptr(), module.dae_system.jacobian.len() as u32);
@@ -281,9 +435,6 @@ impl<'ll> OsdiInstanceData<'ll> {
         let temperature = cx.ty_double();
         let connected_ports = cx.ty_int();
 
-        let cache_slots: TiVec<_, _> =
-            module.init.cache_slots.raw.values().map(|ty| lltype(ty, cx)).collect();
-
         let state_idx = cx.ty_array(cx.ty_int(), module.intern.lim_state.len() as u32);
         let static_fields: [_; NUM_CONST_FIELDS as usize] = [
             param_given,
@@ -322,7 +473,11 @@ impl<'ll> OsdiInstanceData<'ll> {
             noise,
             opvars,
             jacobian,
+            sensitivities,
+            hessian,
             bound_step,
+            discontinuity,
+            delays,
         }
     }
 
@@ -341,6 +496,35 @@ impl<'ll> OsdiInstanceData<'ll> {
         Some(elem)
     }
 
+    pub unsafe fn store_discontinuity(
+        &self,
+        ptr: &'ll llvm::Value,
+        builder: &mir_llvm::Builder<'_, '_, 'll>,
+    ) {
+        if let Some(slot) = self.discontinuity {
+            self.store_eval_output_slot(slot, ptr, builder);
+        }
+    }
+
+    pub fn discontinuity_elem(&self) -> Option<u32> {
+        let elem = self.eval_output_slot_elem(self.discontinuity?);
+        Some(elem)
+    }
+
+    pub unsafe fn store_delays(
+        &self,
+        ptr: &'ll llvm::Value,
+        builder: &mir_llvm::Builder<'_, '_, 'll>,
+    ) {
+        for &(_, slot) in &self.delays {
+            self.store_eval_output_slot(slot, ptr, builder);
+        }
+    }
+
+    pub fn delay_elems(&self) -> impl Iterator<Item = (SimUnknown, u32)> + '_ {
+        self.delays.iter().map(|&(unknown, slot)| (unknown, self.eval_output_slot_elem(slot)))
+    }
+
     pub unsafe fn param_ptr(
         &self,
         param: OsdiInstanceParam,
@@ -706,7 +890,7 @@ impl<'ll> OsdiInstanceData<'ll> {
         } else {
             LLVMBuildFAdd(llbuilder, old, contrib, UNNAMED)
         };
-        LLVMSetFastMath(val);
+        cx.set_fast_math(val);
         LLVMBuildStore(llbuilder, val, dst);
     }
 
@@ -747,7 +931,7 @@ impl<'ll> OsdiInstanceData<'ll> {
         let dst = LLVMBuildLoad2(llbuilder, cx.ty_ptr(), ptr, UNNAMED);
         let old = LLVMBuildLoad2(llbuilder, cx.ty_double(), dst, UNNAMED);
         let val = LLVMBuildFAdd(llbuilder, old, val, UNNAMED);
-        LLVMSetFastMath(val);
+        cx.set_fast_math(val);
         LLVMBuildStore(llbuilder, val, dst);
     }
 
@@ -912,7 +1096,8 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                     | ParamKind::EnableLim
                     | ParamKind::PrevState(_)
                     | ParamKind::NewState(_)
-                    | ParamKind::ImplicitUnknown(_) => unreachable!(),
+                    | ParamKind::ImplicitUnknown(_)
+                    | ParamKind::FunctionArg(_) => unreachable!(),
                 }
             }
             EvalOutput::Cache(slot) => inst_data.cache_slot_ptr(llbuilder, slot, inst_ptr),
@@ -986,7 +1171,8 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                     | ParamKind::EnableLim
                     | ParamKind::PrevState(_)
                     | ParamKind::NewState(_)
-                    | ParamKind::ImplicitUnknown(_) => unreachable!(),
+                    | ParamKind::ImplicitUnknown(_)
+                    | ParamKind::FunctionArg(_) => unreachable!(),
                 }
             }
             EvalOutput::Cache(slot) => inst_data.cache_slot_ptr(llbuilder, slot, inst_ptr),
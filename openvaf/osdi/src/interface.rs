@@ -0,0 +1,353 @@
+//! Machine readable (JSON) description of the interface of the compiled models.
+//!
+//! The description mirrors the `OsdiDescriptor`s of the library (parameter ids and node
+//! indices are identical) so that tools can inspect a model without loading the library.
+//! It also contains information that OSDI does not expose, like parameter defaults and
+//! bounds. These are emitted as Verilog-A source text because they may be arbitrary
+//! expressions (of other parameters). The schema is versioned with [`INTERFACE_VERSION`]:
+//!
+//! ```text
+//! {
+//!     "version": 1,
+//!     "osdi_version": "0.3",
+//!     "modules": [{
+//!         "name": "diode",
+//!         "nodes": [{ "name": "A", "units": "V", "terminal": true, "is_flow": false }],
+//!         "parameters": [{
+//!             "id": 0,                        // index into param_opvar
+//!             "name": "Rs",
+//!             "aliases": [],
+//!             "kind": "model",                // "model" or "instance"
+//!             "type": "real",                 // "real", "integer" or "string"
+//!             "len": 0,                       // array length, 0 for scalars
+//!             "units": "Ohm",
+//!             "description": "Series resistance",
+//!             "group": "",
+//!             "default": "1e-3",
+//!             "bounds": [
+//!                 { "kind": "from", "lower": "0", "lower_inclusive": true,
+//!                   "upper": "inf", "upper_inclusive": false },
+//!                 { "kind": "exclude", "value": "1" }
+//!             ]
+//!         }],
+//!         "opvars": [{ "id": 5, "name": "gd", "type": "real", "units": "S", "description": "",
+//!                      "derivative": { "of": "id", "wrt": "A" } }],
+//!         "noise_sources": [{ "name": "thermal", "nodes": ["A", "CI"] }],
+//!         "collapsible": [["CI", "C"], ["X", null]],   // null is the ground node
+//!         "sensitivity_params": [0],
+//!         "hessian_entries": [["A", "A", "CI"]]     // residual node, first and second unknown
+//!     }]
+//! }
+//! ```
+//!
+//! New fields may be added without incrementing the version, removing or changing
+//! existing fields requires a new version.
+
+use std::fmt::{self, Display, Write};
+
+use hir::{AstCache, CompilationDB, ParamSysFun};
+use hir_lower::CallBackKind;
+use lasso::Rodeo;
+use mir::JsonEscaped;
+use sim_back::{CompiledModule, ModuleInfo};
+use syntax::ast::{self, ConstraintKind, ConstraintValue};
+use syntax::AstNode;
+use typed_indexmap::TiSet;
+
+use crate::compilation_unit::OsdiModule;
+use crate::inst_data::{InstanceLayout, OsdiInstanceParam};
+use crate::metadata::osdi_0_3::{
+    PARA_KIND_INST, PARA_KIND_MASK, PARA_TY_INT, PARA_TY_MASK, PARA_TY_REAL,
+};
+use crate::metadata::{sim_unknown_info, OsdiLimFunction};
+use crate::OSDI_VERSION;
+
+/// Version of the schema of the interface description.
+pub const INTERFACE_VERSION: u32 = 1;
+
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Num(u64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    fn str(val: impl Into<String>) -> Json {
+        Json::Str(val.into())
+    }
+
+    fn write(&self, dst: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        match self {
+            Json::Null => write!(dst, "null"),
+            Json::Bool(val) => write!(dst, "{val}"),
+            Json::Num(val) => write!(dst, "{val}"),
+            Json::Str(val) => write!(dst, "\"{}\"", JsonEscaped(val)),
+            Json::Arr(vals) if vals.is_empty() => write!(dst, "[]"),
+            Json::Arr(vals) => {
+                dst.write_char('[')?;
+                for (i, val) in vals.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(dst, "{sep}\n{:width$}", "", width = 4 * (indent + 1))?;
+                    val.write(dst, indent + 1)?;
+                }
+                write!(dst, "\n{:width$}]", "", width = 4 * indent)
+            }
+            Json::Obj(fields) if fields.is_empty() => write!(dst, "{{}}"),
+            Json::Obj(fields) => {
+                dst.write_char('{')?;
+                for (i, (key, val)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(dst, "{sep}\n{:width$}\"{key}\": ", "", width = 4 * (indent + 1))?;
+                    val.write(dst, indent + 1)?;
+                }
+                write!(dst, "\n{:width$}}}", "", width = 4 * indent)
+            }
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Returns the JSON description of the interface of `modules` (see [`INTERFACE_VERSION`]).
+/// The modules are only lowered to MIR, no code is generated.
+pub fn interface_json(db: &CompilationDB, modules: &[ModuleInfo]) -> String {
+    let mut literals = Rodeo::new();
+    let mut lim_table = TiSet::default();
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|module| {
+            let mir = CompiledModule::new(db, module, &mut literals, false);
+            for cb in mir.intern.callbacks.iter() {
+                if let CallBackKind::BuiltinLimit { name, num_args } = *cb {
+                    lim_table.ensure(OsdiLimFunction { name, num_args: num_args - 2 });
+                }
+            }
+            mir
+        })
+        .collect();
+    let ast = db.compilation_unit().ast(db);
+    let modules = modules
+        .iter()
+        .enumerate()
+        .map(|(i, module)| OsdiModule::new(module, i, &lim_table).interface(db, &literals, &ast))
+        .collect();
+
+    let (major, minor) = OSDI_VERSION;
+    let interface = Json::Obj(vec![
+        ("version", Json::Num(INTERFACE_VERSION.into())),
+        ("osdi_version", Json::Str(format!("{major}.{minor}"))),
+        ("modules", Json::Arr(modules)),
+    ]);
+    format!("{interface}\n")
+}
+
+fn syntax_text(node: Option<impl AstNode>) -> Json {
+    node.map_or(Json::Null, |node| Json::Str(node.syntax().text().to_string().trim().to_owned()))
+}
+
+fn bounds(param: &ast::Param) -> Json {
+    let bounds = param
+        .constraints()
+        .filter_map(|constraint| {
+            let kind = match constraint.kind()? {
+                ConstraintKind::From => "from",
+                ConstraintKind::Exclude => "exclude",
+            };
+            let mut fields = vec![("kind", Json::str(kind))];
+            match constraint.val()? {
+                ConstraintValue::Range(range) => {
+                    fields.push(("lower", syntax_text(range.start())));
+                    fields.push(("lower_inclusive", Json::Bool(range.start_inclusive())));
+                    fields.push(("upper", syntax_text(range.end())));
+                    fields.push(("upper_inclusive", Json::Bool(range.end_inclusive())));
+                }
+                ConstraintValue::Val(val) => fields.push(("value", syntax_text(Some(val)))),
+            }
+            Some(Json::Obj(fields))
+        })
+        .collect();
+    Json::Arr(bounds)
+}
+
+impl OsdiModule<'_> {
+    fn interface(&self, db: &CompilationDB, literals: &Rodeo, ast: &AstCache) -> Json {
+        // the same layout as the instance/model data so that the ids match the descriptor
+        let inst = InstanceLayout::new(db, self, (), |_| ());
+        let model_params: Vec<_> = self
+            .info
+            .params
+            .keys()
+            .filter(|param| !inst.params.contains_key(&OsdiInstanceParam::User(**param)))
+            .copied()
+            .collect();
+
+        let unknowns = &self.dae_system.unknowns;
+        let node = |node: u32| {
+            unknowns
+                .raw
+                .get_index(node as usize)
+                .map_or(Json::Null, |&unknown| Json::Str(sim_unknown_info(unknown, db).0))
+        };
+
+        let num_terminals = self.info.module.ports(db).len();
+        let nodes = unknowns
+            .iter()
+            .enumerate()
+            .map(|(i, &unknown)| {
+                let (name, units, is_flow) = sim_unknown_info(unknown, db);
+                Json::Obj(vec![
+                    ("name", Json::Str(name)),
+                    ("units", Json::Str(units)),
+                    ("terminal", Json::Bool(i < num_terminals)),
+                    ("is_flow", Json::Bool(is_flow)),
+                ])
+            })
+            .collect();
+
+        let param_opvar =
+            self.param_opvar(db, inst.params.keys(), model_params.iter(), inst.opvars.keys());
+
+        // same order as param_opvar
+        let params = inst
+            .params
+            .keys()
+            .map(|param| match *param {
+                OsdiInstanceParam::Builtin(builtin) => Err(builtin),
+                OsdiInstanceParam::User(param) => Ok(param),
+            })
+            .chain(model_params.iter().map(|&param| Ok(param)));
+        let params = params
+            .zip(&param_opvar)
+            .enumerate()
+            .map(|(id, (param, osdi_param))| {
+                let kind = if osdi_param.flags & PARA_KIND_MASK == PARA_KIND_INST {
+                    "instance"
+                } else {
+                    "model"
+                };
+                let (group, default, bounds) = match param {
+                    Ok(param) => {
+                        let decl = param.ast(db, ast);
+                        let group = self.info.params[&param].group.clone();
+                        (group, syntax_text(decl.default()), bounds(&decl))
+                    }
+                    Err(builtin) => (
+                        String::new(),
+                        Json::Str(ParamSysFun::default_value(builtin).to_string()),
+                        Json::Arr(Vec::new()),
+                    ),
+                };
+                Json::Obj(vec![
+                    ("id", Json::Num(id as u64)),
+                    ("name", Json::str(&osdi_param.name[0])),
+                    ("aliases", Json::Arr(osdi_param.name[1..].iter().map(Json::str).collect())),
+                    ("kind", Json::str(kind)),
+                    ("type", Json::str(para_ty(osdi_param.flags))),
+                    ("len", Json::Num(osdi_param.len.into())),
+                    ("units", Json::str(&osdi_param.units)),
+                    ("description", Json::str(&osdi_param.description)),
+                    ("group", Json::Str(group)),
+                    ("default", default),
+                    ("bounds", bounds),
+                ])
+            })
+            .collect();
+
+        let num_params = inst.params.len() + model_params.len();
+        let opvars = inst
+            .opvars
+            .keys()
+            .zip(&param_opvar[num_params..])
+            .enumerate()
+            .map(|(i, (var, osdi_opvar))| {
+                let mut fields = vec![
+                    ("id", Json::Num((num_params + i) as u64)),
+                    ("name", Json::str(&osdi_opvar.name[0])),
+                    ("type", Json::str(para_ty(osdi_opvar.flags))),
+                    ("units", Json::str(&osdi_opvar.units)),
+                    ("description", Json::str(&osdi_opvar.description)),
+                ];
+                if let Some(derivative) = self.info.op_vars[var].derivative {
+                    let derivative = Json::Obj(vec![
+                        ("of", Json::Str(derivative.of.name(db).to_string())),
+                        ("wrt", Json::Str(derivative.wrt.name(db).to_string())),
+                    ]);
+                    fields.push(("derivative", derivative));
+                }
+                Json::Obj(fields)
+            })
+            .collect();
+
+        let noise_sources = self
+            .dae_system
+            .noise_sources
+            .iter()
+            .map(|source| {
+                let node_2 = source.lo.map_or(u32::MAX, u32::from);
+                Json::Obj(vec![
+                    ("name", Json::str(literals.resolve(&source.name))),
+                    ("nodes", Json::Arr(vec![node(source.hi.into()), node(node_2)])),
+                ])
+            })
+            .collect();
+
+        let collapsible = self
+            .collapsible()
+            .iter()
+            .map(|pair| Json::Arr(vec![node(pair.node_1), node(pair.node_2)]))
+            .collect();
+
+        // ids (index into param_opvar) of the parameters selected for sensitivity analysis
+        let sensitivity_params = self
+            .info
+            .sensitivities
+            .iter()
+            .map(|&param| {
+                let pos = inst.params.get_index_of(&OsdiInstanceParam::User(param));
+                let pos = pos.unwrap_or_else(|| {
+                    inst.params.len() + model_params.iter().position(|&it| it == param).unwrap()
+                });
+                Json::Num(pos as u64)
+            })
+            .collect();
+
+        let hessian_entries = self
+            .dae_system
+            .hessian
+            .iter()
+            .map(|entry| {
+                Json::Arr(vec![
+                    node(entry.row.into()),
+                    node(entry.col1.into()),
+                    node(entry.col2.into()),
+                ])
+            })
+            .collect();
+
+        Json::Obj(vec![
+            ("name", Json::Str(self.info.module.name(db))),
+            ("nodes", Json::Arr(nodes)),
+            ("parameters", Json::Arr(params)),
+            ("opvars", Json::Arr(opvars)),
+            ("noise_sources", Json::Arr(noise_sources)),
+            ("collapsible", Json::Arr(collapsible)),
+            ("sensitivity_params", Json::Arr(sensitivity_params)),
+            ("hessian_entries", Json::Arr(hessian_entries)),
+        ])
+    }
+}
+
+fn para_ty(flags: u32) -> &'static str {
+    match flags & PARA_TY_MASK {
+        PARA_TY_REAL => "real",
+        PARA_TY_INT => "integer",
+        _ => "string",
+    }
+}
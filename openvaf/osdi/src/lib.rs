@@ -4,7 +4,7 @@ use hir::{CompilationDB, ParamSysFun, Type};
 use hir_lower::{CallBackKind, HirInterner, ParamKind};
 use lasso::Rodeo;
 use llvm::{LLVMDisposeTargetData, OptLevel};
-use mir_llvm::{CodegenCx, LLVMBackend};
+use mir_llvm::{CodegenCx, DebugInfoBuilder, FloatPolicy, LLVMBackend};
 use salsa::ParallelDatabase;
 use sim_back::{CompiledModule, ModuleInfo};
 use stdx::{impl_debug_display, impl_idx_from};
@@ -12,15 +12,21 @@ use target::spec::Target;
 use typed_indexmap::TiSet;
 
 use std::ffi::CString;
+use std::io;
+use std::sync::Mutex;
 
 use crate::compilation_unit::{new_codegen, OsdiCompilationUnit, OsdiModule};
+use crate::emit::Artifacts;
 use crate::metadata::osdi_0_3::OsdiTys;
 use crate::metadata::OsdiLimFunction;
 
 mod access;
 mod bitfield;
+mod c_backend;
 mod compilation_unit;
+mod emit;
 mod inst_data;
+mod interface;
 mod metadata;
 mod model_data;
 
@@ -29,31 +35,53 @@ mod load;
 mod noise;
 mod setup;
 
+pub use c_backend::compile_c;
+pub use emit::EmitKind;
+pub use interface::{interface_json, INTERFACE_VERSION};
+
 const OSDI_VERSION: (u32, u32) = (0, 3);
 
+pub struct OsdiOutput {
+    /// Object files that need to be linked into the library
+    pub objects: Vec<Utf8PathBuf>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn compile(
     db: &CompilationDB,
     modules: &[ModuleInfo],
     dst: &Utf8Path,
     target: &Target,
     back: &LLVMBackend,
-    emit: bool,
+    emit: Option<&[EmitKind]>,
     opt_lvl: OptLevel,
-) -> Vec<Utf8PathBuf> {
+    fp_policy: FloatPolicy,
+) -> io::Result<OsdiOutput> {
+    let artifacts = emit.map(|kinds| Artifacts { dst, kinds });
+    let dump_mir = artifacts.map_or(false, |artifacts| artifacts.emits(EmitKind::Mir));
     let mut literals = Rodeo::new();
     let mut lim_table = TiSet::default();
-    let modules: Vec<_> = modules
+    let modules = modules
         .iter()
         .map(|module| {
-            let mir = CompiledModule::new(db, module, &mut literals);
+            let mir = CompiledModule::new(db, module, &mut literals, dump_mir);
+            if let Some(artifacts) = artifacts {
+                let name = module.module.name(db);
+                for (stage, func) in &mir.mir_stages {
+                    artifacts.emit_mir(&name, stage, func)?;
+                }
+                artifacts.emit_mir(&name, "eval", &mir.eval)?;
+                artifacts.emit_mir(&name, "init", &mir.init.func)?;
+                artifacts.emit_mir(&name, "model_param_setup", &mir.model_param_setup)?;
+            }
             for cb in mir.intern.callbacks.iter() {
                 if let CallBackKind::BuiltinLimit { name, num_args } = *cb {
                     lim_table.ensure(OsdiLimFunction { name, num_args: num_args - 2 });
                 }
             }
-            mir
+            Ok(mir)
         })
-        .collect();
+        .collect::<io::Result<Vec<_>>>()?;
     let name = dst.file_stem().expect("destition is a file").to_owned();
 
     let mut paths: Vec<Utf8PathBuf> = (0..modules.len() * 4)
@@ -71,39 +99,47 @@ pub fn compile(
 
     let modules: Vec<_> = modules
         .iter()
-        .map(|module| {
-            let unit = OsdiModule::new(db, module, &lim_table);
+        .enumerate()
+        .map(|(i, module)| {
+            let unit = OsdiModule::new(module, i, &lim_table);
             unit.intern_names(&mut literals, db);
             unit
         })
         .collect();
 
+    let root_file = db.compilation_unit().path(db);
     let db = db.snapshot();
 
     let main_file = dst.with_extension("o");
+    // the first error encountered while writing the outputs of the compilation units
+    let error = Mutex::new(None);
 
     rayon_core::scope(|scope| {
         let db = db;
         let literals_ = &literals;
         let target_data_ = &target_data;
         let paths = &paths;
+        let root_file = &root_file;
+        let report = |res: io::Result<()>| {
+            if let Err(err) = res {
+                error.lock().unwrap().get_or_insert(err);
+            }
+        };
 
         for (i, module) in modules.iter().enumerate() {
             let _db = db.snapshot();
             scope.spawn(move |_| {
                 let access = format!("access_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&access, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, fp_policy);
                 let tys = OsdiTys::new(&cx, target_data_);
                 let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
 
                 cguint.access_function();
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4];
-                    llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                if let Some(artifacts) = artifacts {
+                    report(artifacts.emit_llvm(&llmod, &access, true, &paths[i * 4]));
                 }
             });
 
@@ -111,17 +147,22 @@ pub fn compile(
             scope.spawn(move |_| {
                 let name = format!("setup_model_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, fp_policy);
                 let tys = OsdiTys::new(&cx, target_data_);
                 let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
-
-                cguint.setup_model();
+                let debug_info = module
+                    .info
+                    .debug_info
+                    .then(|| DebugInfoBuilder::new(&cx, root_file, opt_lvl != OptLevel::None));
+
+                cguint.setup_model(debug_info.as_ref());
+                if let Some(debug_info) = debug_info {
+                    debug_info.finalize();
+                }
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 1];
-                    // llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                if let Some(artifacts) = artifacts {
+                    report(artifacts.emit_llvm(&llmod, &name, false, &paths[i * 4 + 1]));
                 }
             });
 
@@ -129,17 +170,22 @@ pub fn compile(
             scope.spawn(move |_| {
                 let name = format!("setup_instance_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, fp_policy);
                 let tys = OsdiTys::new(&cx, target_data_);
                 let mut cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, false);
-
-                cguint.setup_instance();
+                let debug_info = module
+                    .info
+                    .debug_info
+                    .then(|| DebugInfoBuilder::new(&cx, root_file, opt_lvl != OptLevel::None));
+
+                cguint.setup_instance(debug_info.as_ref());
+                if let Some(debug_info) = debug_info {
+                    debug_info.finalize();
+                }
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 2];
-                    llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                if let Some(artifacts) = artifacts {
+                    report(artifacts.emit_llvm(&llmod, &name, true, &paths[i * 4 + 2]));
                 }
             });
 
@@ -147,25 +193,30 @@ pub fn compile(
             scope.spawn(move |_| {
                 let access = format!("eval_{}", &module.sym);
                 let llmod = unsafe { back.new_module(&access, opt_lvl).unwrap() };
-                let cx = new_codegen(back, &llmod, literals_);
+                let cx = new_codegen(back, &llmod, literals_, fp_policy);
                 let tys = OsdiTys::new(&cx, target_data_);
                 let cguint = OsdiCompilationUnit::new(&_db, module, &cx, &tys, true);
+                let debug_info = module
+                    .info
+                    .debug_info
+                    .then(|| DebugInfoBuilder::new(&cx, root_file, opt_lvl != OptLevel::None));
 
                 // println!("{:?}", module.eval);
-                cguint.eval();
+                cguint.eval(debug_info.as_ref());
+                if let Some(debug_info) = debug_info {
+                    debug_info.finalize();
+                }
                 // println!("{}", llmod.to_str());
                 debug_assert!(llmod.verify_and_print());
 
-                if emit {
-                    let path = &paths[i * 4 + 3];
-                    llmod.optimize();
-                    assert_eq!(llmod.emit_object(path.as_ref()), Ok(()))
+                if let Some(artifacts) = artifacts {
+                    report(artifacts.emit_llvm(&llmod, &access, true, &paths[i * 4 + 3]));
                 }
             });
         }
 
         let llmod = unsafe { back.new_module(&name, opt_lvl).unwrap() };
-        let cx = new_codegen(back, &llmod, &literals);
+        let cx = new_codegen(back, &llmod, &literals, fp_policy);
         let tys = OsdiTys::new(&cx, target_data);
 
         let descriptors: Vec<_> = modules
@@ -220,17 +271,17 @@ pub fn compile(
 
         debug_assert!(llmod.verify_and_print());
 
-        if emit {
-            // println!("{}", llmod.to_str());
-            llmod.optimize();
-            // println!("{}", llmod.to_str());
-            assert_eq!(llmod.emit_object(main_file.as_ref()), Ok(()))
+        if let Some(artifacts) = artifacts {
+            report(artifacts.emit_llvm(&llmod, &name, true, &main_file));
         }
     });
 
-    paths.push(main_file);
     unsafe { LLVMDisposeTargetData(target_data) };
-    paths
+    if let Some(err) = error.into_inner().unwrap() {
+        return Err(err);
+    }
+    paths.push(main_file);
+    Ok(OsdiOutput { objects: paths })
 }
 
 impl OsdiModule<'_> {
@@ -275,6 +326,13 @@ impl OsdiModule<'_> {
                 literals.get_or_intern(format!("${param:?}"));
             }
         }
+
+        if self.info.check_fp {
+            let interners = [self.intern, self.model_param_intern, &self.init.intern];
+            for loc in interners.iter().flat_map(|intern| &intern.source_locations) {
+                literals.get_or_intern(loc.to_string());
+            }
+        }
     }
 }
 
@@ -1,8 +1,8 @@
 use llvm::{
     LLVMAppendBasicBlockInContext, LLVMBuildCall2, LLVMBuildFAdd, LLVMBuildFDiv, LLVMBuildFMul,
-    LLVMBuildFSub, LLVMBuildGEP2, LLVMBuildRetVoid, LLVMBuildStore, LLVMCreateBuilderInContext,
-    LLVMDisposeBuilder, LLVMGetParam, LLVMPositionBuilderAtEnd, LLVMSetFastMath,
-    LLVMSetPartialFastMath, UNNAMED,
+    LLVMBuildFSub, LLVMBuildGEP2, LLVMBuildLoad2, LLVMBuildRetVoid, LLVMBuildStore,
+    LLVMCreateBuilderInContext, LLVMDisposeBuilder, LLVMGetParam, LLVMPositionBuilderAtEnd,
+    UNNAMED,
 };
 use sim_back::dae::NoiseSourceKind;
 use stdx::iter::zip;
@@ -76,15 +76,15 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                             .unwrap_or_else(|| unreachable!("intrinsic {} not found", name));
                         let freq_exp =
                             LLVMBuildCall2(llbuilder, ty, fun, [freq, exp].as_ptr(), 2, UNNAMED);
-                        LLVMSetPartialFastMath(freq_exp);
+                        cx.set_fast_math(freq_exp);
                         pwr = LLVMBuildFDiv(llbuilder, pwr, freq_exp, UNNAMED);
-                        LLVMSetFastMath(pwr);
+                        cx.set_fast_math(pwr);
                         pwr
                     }
                     NoiseSourceKind::NoiseTable { .. } => unimplemented!("noise tables"),
                 };
                 pwr = LLVMBuildFMul(llbuilder, pwr, fac, UNNAMED);
-                LLVMSetFastMath(pwr);
+                cx.set_fast_math(pwr);
                 let dst = LLVMBuildGEP2(
                     llbuilder,
                     cx.ty_double(),
@@ -135,6 +135,96 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         llfunc
     }
 
+    /// Adds the derivatives of the residual by the i-th parameter selected for
+    /// sensitivity analysis to `dst_resist[i]` and `dst_react[i]`.
+    pub fn load_sensitivity(&self) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { inst_data, cx, module, .. } = self;
+        let ptr_ty = cx.ty_ptr();
+        let fun_ty = cx.ty_func(&[ptr_ty, ptr_ty, ptr_ty, ptr_ty], cx.ty_void());
+        let name = &format!("load_sensitivity_{}", module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+
+            // get params
+            let inst = LLVMGetParam(llfunc, 0);
+            let model = LLVMGetParam(llfunc, 1);
+            let dst_resist = LLVMGetParam(llfunc, 2);
+            let dst_react = LLVMGetParam(llfunc, 3);
+
+            for (entry, sensitivity) in
+                zip(&module.dae_system.sensitivities, &inst_data.sensitivities)
+            {
+                let pos = module.info.sensitivities.iter().position(|&it| it == entry.param);
+                let pos = cx.const_unsigned_int(pos.unwrap() as u32);
+                for (val, dst) in [(sensitivity.resist, dst_resist), (sensitivity.react, dst_react)]
+                {
+                    let val = if let Some(val) = val {
+                        self.load_eval_output(val, inst, model, llbuilder)
+                    } else {
+                        continue;
+                    };
+                    let dst = LLVMBuildGEP2(llbuilder, ptr_ty, dst, [pos].as_ptr(), 1, UNNAMED);
+                    let dst = LLVMBuildLoad2(llbuilder, ptr_ty, dst, UNNAMED);
+                    inst_data.store_contrib(cx, entry.row, inst, dst, val, llbuilder, false);
+                }
+            }
+
+            LLVMBuildRetVoid(llbuilder);
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
+
+    /// Adds the i-th entry of the second order jacobian to `dst_resist[i]` and `dst_react[i]`.
+    pub fn load_hessian(&self) -> &'ll llvm::Value {
+        let OsdiCompilationUnit { inst_data, cx, module, .. } = self;
+        let ptr_ty = cx.ty_ptr();
+        let fun_ty = cx.ty_func(&[ptr_ty, ptr_ty, ptr_ty, ptr_ty], cx.ty_void());
+        let name = &format!("load_hessian_{}", module.sym);
+        let llfunc = cx.declare_int_c_fn(name, fun_ty);
+
+        unsafe {
+            let entry = LLVMAppendBasicBlockInContext(cx.llcx, llfunc, UNNAMED);
+            let llbuilder = LLVMCreateBuilderInContext(cx.llcx);
+
+            LLVMPositionBuilderAtEnd(llbuilder, entry);
+
+            // get params
+            let inst = LLVMGetParam(llfunc, 0);
+            let model = LLVMGetParam(llfunc, 1);
+            let dst_resist = LLVMGetParam(llfunc, 2);
+            let dst_react = LLVMGetParam(llfunc, 3);
+
+            for (pos, entry) in inst_data.hessian.iter().enumerate() {
+                let pos = cx.const_unsigned_int(pos as u32);
+                for (val, dst) in [(entry.resist, dst_resist), (entry.react, dst_react)] {
+                    let val = if let Some(val) = val {
+                        self.load_eval_output(val, inst, model, llbuilder)
+                    } else {
+                        continue;
+                    };
+                    let dst =
+                        LLVMBuildGEP2(llbuilder, cx.ty_double(), dst, [pos].as_ptr(), 1, UNNAMED);
+                    let old = LLVMBuildLoad2(llbuilder, cx.ty_double(), dst, UNNAMED);
+                    let val = LLVMBuildFAdd(llbuilder, old, val, UNNAMED);
+                    cx.set_fast_math(val);
+                    LLVMBuildStore(llbuilder, val, dst);
+                }
+            }
+
+            LLVMBuildRetVoid(llbuilder);
+            LLVMDisposeBuilder(llbuilder);
+        }
+
+        llfunc
+    }
+
     pub fn load_lim_rhs(&self, reactive: bool) -> &'ll llvm::Value {
         let OsdiCompilationUnit { inst_data, cx, module, .. } = self;
         let void_ptr = cx.ty_ptr();
@@ -201,11 +291,11 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                         .inst_data
                         .read_node_voltage(self.cx, node_deriv, inst, prev_solve, llbuilder);
                     let val = LLVMBuildFMul(llbuilder, ddx, voltage, UNNAMED);
-                    LLVMSetFastMath(val);
+                    self.cx.set_fast_math(val);
                     res = match res {
                         Some(old) => {
                             let val = LLVMBuildFAdd(llbuilder, old, val, UNNAMED);
-                            LLVMSetFastMath(val);
+                            self.cx.set_fast_math(val);
                             Some(val)
                         }
                         None => Some(val),
@@ -221,7 +311,7 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                             contrib,
                             UNNAMED,
                         );
-                        LLVMSetFastMath(val);
+                        cx.set_fast_math(val);
                         res = Some(val);
                     }
                 }
@@ -231,7 +321,7 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                     }
                     if tran {
                         res = LLVMBuildFMul(llbuilder, res, alpha, UNNAMED);
-                        LLVMSetFastMath(res);
+                        cx.set_fast_math(res);
                     }
                     inst_data.store_contrib(cx, node, inst, dst, res, llbuilder, false);
                 }
@@ -304,11 +394,11 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                         self.load_jacobian_entry(entry, inst, model, llbuilder, true)
                     {
                         val = LLVMBuildFMul(llbuilder, val, alpha, UNNAMED);
-                        LLVMSetFastMath(val);
+                        cx.set_fast_math(val);
                         val = match res {
                             Some(resist) => {
                                 let val = LLVMBuildFAdd(llbuilder, resist, val, UNNAMED);
-                                LLVMSetFastMath(val);
+                                cx.set_fast_math(val);
                                 val
                             }
                             None => val,
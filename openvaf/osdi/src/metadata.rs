@@ -1,6 +1,6 @@
 use std::iter::once;
 
-use hir::{CompilationDB, ParamSysFun, Type};
+use hir::{CompilationDB, ParamSysFun, Parameter, Type, Variable};
 use hir_lower::CurrentKind;
 use lasso::{Rodeo, Spur};
 use llvm::{LLVMABISizeOfType, LLVMOffsetOfElement, TargetData};
@@ -16,10 +16,10 @@ use crate::inst_data::{
 };
 use crate::load::JacobianLoadType;
 use crate::metadata::osdi_0_3::{
-    OsdiDescriptor, OsdiJacobianEntry, OsdiNode, OsdiNodePair, OsdiNoiseSource, OsdiParamOpvar,
-    OsdiTys, JACOBIAN_ENTRY_REACT, JACOBIAN_ENTRY_REACT_CONST, JACOBIAN_ENTRY_RESIST,
-    JACOBIAN_ENTRY_RESIST_CONST, PARA_KIND_INST, PARA_KIND_MODEL, PARA_KIND_OPVAR, PARA_TY_INT,
-    PARA_TY_REAL, PARA_TY_STR,
+    OsdiDelay, OsdiDescriptor, OsdiHessianEntry, OsdiJacobianEntry, OsdiNode, OsdiNodePair,
+    OsdiNoiseSource, OsdiParamOpvar, OsdiTys, JACOBIAN_ENTRY_REACT, JACOBIAN_ENTRY_REACT_CONST,
+    JACOBIAN_ENTRY_RESIST, JACOBIAN_ENTRY_RESIST_CONST, PARA_KIND_INST, PARA_KIND_MODEL,
+    PARA_KIND_OPVAR, PARA_TY_INT, PARA_TY_REAL, PARA_TY_STR,
 };
 use crate::ty_len;
 
@@ -45,94 +45,13 @@ impl OsdiLimFunction {
 
 impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
     pub fn param_opvar(&self) -> Vec<OsdiParamOpvar> {
-        let OsdiCompilationUnit { inst_data, model_data, module, .. } = self;
-        fn para_ty_flags(ty: &Type) -> u32 {
-            match ty.base_type() {
-                Type::Real => PARA_TY_REAL,
-                Type::Integer => PARA_TY_INT,
-                Type::String => PARA_TY_STR,
-                _ => unreachable!(),
-            }
-        }
-
-        let inst_params = inst_data.params.keys().map(|param| match param {
-            OsdiInstanceParam::Builtin(builtin) => {
-                let mut name = vec![format!("${builtin:?}")];
-                if let Some(alias) = self.module.info.sys_fun_alias.get(builtin) {
-                    name.extend(alias.iter().map(SmolStr::to_string))
-                }
-                OsdiParamOpvar {
-                    num_alias: name.len() as u32 - 1,
-                    name,
-                    description: match builtin {
-                        ParamSysFun::mfactor => "Multiplier (Verilog-A $mfactor)".to_owned(),
-                        _ => "".to_owned(),
-                    },
-                    units: match builtin {
-                        ParamSysFun::yposition | ParamSysFun::xposition => "m".to_owned(),
-                        ParamSysFun::angle => "deg".to_owned(),
-                        _ => "".to_owned(),
-                    },
-                    flags: PARA_TY_REAL | PARA_KIND_INST,
-                    len: 0,
-                }
-            }
-            OsdiInstanceParam::User(param) => {
-                let param_info = &module.info.params[param];
-                let ty = param.ty(self.db);
-
-                let flags = para_ty_flags(&ty) | PARA_KIND_INST;
-                OsdiParamOpvar {
-                    name: once(&param_info.name)
-                        .chain(&*param_info.alias)
-                        .map(SmolStr::to_string)
-                        .collect(),
-                    num_alias: param_info.alias.len() as u32,
-                    description: param_info.description.clone(),
-                    units: param_info.unit.clone(),
-                    flags,
-                    len: ty_len(&ty).unwrap_or(0),
-                }
-            }
-        });
-
-        let model_params = model_data.params.keys().filter_map(|param| {
-            let param_info = &module.info.params[param];
-            if param_info.is_instance {
-                return None;
-            }
-            let ty = param.ty(self.db);
-            let flags = para_ty_flags(&ty) | PARA_KIND_MODEL;
-            let param_opvar = OsdiParamOpvar {
-                name: once(&param_info.name)
-                    .chain(&*param_info.alias)
-                    .map(SmolStr::to_string)
-                    .collect(),
-                num_alias: param_info.alias.len() as u32,
-                description: param_info.description.clone(),
-                units: param_info.unit.clone(),
-                flags,
-                len: ty_len(&ty).unwrap_or(0),
-            };
-            Some(param_opvar)
-        });
-
-        let opvars = inst_data.opvars.keys().map(|opvar| {
-            let opvar_info = &module.info.op_vars[opvar];
-            // TODO inst params
-            let ty = opvar.ty(self.db);
-            let flags = para_ty_flags(&ty) | PARA_KIND_OPVAR;
-            OsdiParamOpvar {
-                name: vec![opvar.name(self.db).to_string()],
-                num_alias: 0,
-                description: opvar_info.description.clone(),
-                units: opvar_info.unit.clone(),
-                flags,
-                len: ty_len(&ty).unwrap_or(0),
-            }
-        });
-
-        inst_params.chain(model_params).chain(opvars).collect()
+        let OsdiCompilationUnit { inst_data, model_data, module, db, .. } = self;
+        module.param_opvar(
+            db,
+            inst_data.params.keys(),
+            model_data.params.keys(),
+            inst_data.opvars.keys(),
+        )
     }
 
     pub fn nodes(&self, target_data: &TargetData, db: &CompilationDB) -> Vec<OsdiNode> {
@@ -166,21 +85,6 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             .collect()
     }
 
-    fn is_const(&self, entry: &MatrixEntry, reactive: bool) -> bool {
-        let entry = if reactive { entry.react } else { entry.resist };
-        match self.module.eval.dfg.value_def(entry) {
-            ValueDef::Result(_, _) => false,
-            ValueDef::Param(param) => self
-                .module
-                .intern
-                .params
-                .get_index(param)
-                .map_or(true, |(kind, _)| !kind.op_dependent()),
-            ValueDef::Const(_) => true,
-            ValueDef::Invalid => unreachable!(),
-        }
-    }
-
     pub fn jacobian_entries(&self, target_data: &TargetData) -> Vec<OsdiJacobianEntry> {
         let OsdiCompilationUnit { inst_data, module, .. } = self;
         let mut jacobian_ptr_react_offset =
@@ -191,23 +95,10 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             .jacobian
             .iter()
             .map(|entry| {
-                let mut flags = 0;
+                let flags = module.jacobian_flags(entry);
                 let mut react_ptr_off = u32::MAX;
 
-                if self.is_const(entry, false) {
-                    flags |= JACOBIAN_ENTRY_RESIST_CONST
-                }
-
-                if self.is_const(entry, true) {
-                    flags |= JACOBIAN_ENTRY_REACT_CONST
-                }
-
-                if entry.resist != F_ZERO {
-                    flags |= JACOBIAN_ENTRY_RESIST;
-                }
-
                 if entry.react != F_ZERO {
-                    flags |= JACOBIAN_ENTRY_REACT;
                     react_ptr_off = jacobian_ptr_react_offset;
                     jacobian_ptr_react_offset += 8;
                 }
@@ -220,23 +111,12 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             .collect()
     }
 
-    pub fn collapsible(&self) -> Vec<OsdiNodePair> {
-        self.module
-            .node_collapse
-            .pairs()
-            .map(|(_, node1, node2)| OsdiNodePair {
-                node_1: node1.into(),
-                node_2: node2.map_or(u32::MAX, u32::from),
-            })
-            .collect()
-    }
-
     pub fn descriptor(
         &self,
         target_data: &llvm::TargetData,
         db: &CompilationDB,
     ) -> OsdiDescriptor<'ll> {
-        let collapsible = self.collapsible();
+        let collapsible = self.module.collapsible();
         let OsdiCompilationUnit { ref inst_data, ref model_data, module, cx, .. } = *self;
 
         unsafe {
@@ -249,6 +129,41 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
             let bound_step_offset = inst_data.bound_step_elem().map_or(u32::MAX, |elem| {
                 LLVMOffsetOfElement(target_data, inst_data.ty, elem) as u32
             });
+            let discontinuity_offset = inst_data.discontinuity_elem().map_or(u32::MAX, |elem| {
+                LLVMOffsetOfElement(target_data, inst_data.ty, elem) as u32
+            });
+
+            let delays: Vec<_> = inst_data
+                .delay_elems()
+                .map(|(unknown, elem)| OsdiDelay {
+                    node: unknown.into(),
+                    delay_offset: LLVMOffsetOfElement(target_data, inst_data.ty, elem) as u32,
+                })
+                .collect();
+
+            // ids (index into param_opvar) of the parameters selected for sensitivity analysis
+            let sensitivity_params: Vec<u32> = module
+                .info
+                .sensitivities
+                .iter()
+                .map(|&param| {
+                    let pos = inst_data.params.get_index_of(&OsdiInstanceParam::User(param));
+                    let pos = pos.unwrap_or_else(|| {
+                        inst_data.params.len() + model_data.params.get_index_of(&param).unwrap()
+                    });
+                    pos as u32
+                })
+                .collect();
+
+            let hessian_entries: Vec<_> = module
+                .dae_system
+                .hessian
+                .iter()
+                .map(|entry| OsdiHessianEntry {
+                    node: entry.row.into(),
+                    nodes: OsdiNodePair { node_1: entry.col1.into(), node_2: entry.col2.into() },
+                })
+                .collect();
 
             let state_idx_off = LLVMOffsetOfElement(target_data, inst_data.ty, STATE_IDX) as u32;
 
@@ -308,12 +223,162 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
                 num_states: self.module.intern.lim_state.len() as u32,
                 load_limit_rhs_resist: self.load_lim_rhs(false),
                 load_limit_rhs_react: self.load_lim_rhs(true),
+                discontinuity_offset,
+                num_delays: delays.len() as u32,
+                delays,
+                num_sensitivity_params: sensitivity_params.len() as u32,
+                sensitivity_params,
+                load_sensitivity: self.load_sensitivity(),
+                num_hessian_entries: hessian_entries.len() as u32,
+                hessian_entries,
+                load_hessian: self.load_hessian(),
             }
         }
     }
 }
 
 impl OsdiModule<'_> {
+    pub fn param_opvar<'a>(
+        &self,
+        db: &CompilationDB,
+        inst_params: impl Iterator<Item = &'a OsdiInstanceParam>,
+        model_params: impl Iterator<Item = &'a Parameter>,
+        opvars: impl Iterator<Item = &'a Variable>,
+    ) -> Vec<OsdiParamOpvar> {
+        fn para_ty_flags(ty: &Type) -> u32 {
+            match ty.base_type() {
+                Type::Real => PARA_TY_REAL,
+                Type::Integer => PARA_TY_INT,
+                Type::String => PARA_TY_STR,
+                _ => unreachable!(),
+            }
+        }
+
+        let inst_params = inst_params.map(|param| match param {
+            OsdiInstanceParam::Builtin(builtin) => {
+                let mut name = vec![format!("${builtin:?}")];
+                if let Some(alias) = self.info.sys_fun_alias.get(builtin) {
+                    name.extend(alias.iter().map(SmolStr::to_string))
+                }
+                OsdiParamOpvar {
+                    num_alias: name.len() as u32 - 1,
+                    name,
+                    description: match builtin {
+                        ParamSysFun::mfactor => "Multiplier (Verilog-A $mfactor)".to_owned(),
+                        _ => "".to_owned(),
+                    },
+                    units: match builtin {
+                        ParamSysFun::yposition | ParamSysFun::xposition => "m".to_owned(),
+                        ParamSysFun::angle => "deg".to_owned(),
+                        _ => "".to_owned(),
+                    },
+                    flags: PARA_TY_REAL | PARA_KIND_INST,
+                    len: 0,
+                }
+            }
+            OsdiInstanceParam::User(param) => {
+                let param_info = &self.info.params[param];
+                let ty = param.ty(db);
+
+                let flags = para_ty_flags(&ty) | PARA_KIND_INST;
+                OsdiParamOpvar {
+                    name: once(&param_info.name)
+                        .chain(&*param_info.alias)
+                        .map(SmolStr::to_string)
+                        .collect(),
+                    num_alias: param_info.alias.len() as u32,
+                    description: param_info.description.clone(),
+                    units: param_info.unit.clone(),
+                    flags,
+                    len: ty_len(&ty).unwrap_or(0),
+                }
+            }
+        });
+
+        let model_params = model_params.filter_map(|param| {
+            let param_info = &self.info.params[param];
+            if param_info.is_instance {
+                return None;
+            }
+            let ty = param.ty(db);
+            let flags = para_ty_flags(&ty) | PARA_KIND_MODEL;
+            let param_opvar = OsdiParamOpvar {
+                name: once(&param_info.name)
+                    .chain(&*param_info.alias)
+                    .map(SmolStr::to_string)
+                    .collect(),
+                num_alias: param_info.alias.len() as u32,
+                description: param_info.description.clone(),
+                units: param_info.unit.clone(),
+                flags,
+                len: ty_len(&ty).unwrap_or(0),
+            };
+            Some(param_opvar)
+        });
+
+        let opvars = opvars.map(|opvar| {
+            let opvar_info = &self.info.op_vars[opvar];
+            // TODO inst params
+            let ty = opvar.ty(db);
+            let flags = para_ty_flags(&ty) | PARA_KIND_OPVAR;
+            OsdiParamOpvar {
+                name: vec![opvar.name(db).to_string()],
+                num_alias: 0,
+                description: opvar_info.description.clone(),
+                units: opvar_info.unit.clone(),
+                flags,
+                len: ty_len(&ty).unwrap_or(0),
+            }
+        });
+
+        inst_params.chain(model_params).chain(opvars).collect()
+    }
+
+    fn is_const(&self, entry: &MatrixEntry, reactive: bool) -> bool {
+        let entry = if reactive { entry.react } else { entry.resist };
+        match self.eval.dfg.value_def(entry) {
+            ValueDef::Result(_, _) => false,
+            ValueDef::Param(param) => {
+                self.intern.params.get_index(param).map_or(true, |(kind, _)| !kind.op_dependent())
+            }
+            ValueDef::Const(_) => true,
+            ValueDef::Invalid => unreachable!(),
+        }
+    }
+
+    /// The `JACOBIAN_ENTRY_*` flags of `entry`
+    pub fn jacobian_flags(&self, entry: &MatrixEntry) -> u32 {
+        let mut flags = 0;
+
+        if self.is_const(entry, false) {
+            flags |= JACOBIAN_ENTRY_RESIST_CONST
+        }
+
+        if self.is_const(entry, true) {
+            flags |= JACOBIAN_ENTRY_REACT_CONST
+        }
+
+        if entry.resist != F_ZERO {
+            flags |= JACOBIAN_ENTRY_RESIST;
+        }
+
+        if entry.react != F_ZERO {
+            flags |= JACOBIAN_ENTRY_REACT;
+        }
+
+        flags
+    }
+
+    pub fn collapsible(&self) -> Vec<OsdiNodePair> {
+        self.node_collapse
+            .pairs()
+            .map(|(_, node1, node2)| OsdiNodePair {
+                node_1: node1.into(),
+                node_2: node2.map_or(u32::MAX, u32::from),
+            })
+            .collect()
+    }
+
     pub fn intern_node_strs(&self, intern: &mut Rodeo, db: &CompilationDB) {
         for &unknown in self.dae_system.unknowns.iter() {
             let (name, units, _) = sim_unknown_info(unknown, db);
@@ -323,7 +388,10 @@ impl OsdiModule<'_> {
     }
 }
 
-fn sim_unknown_info(unknown: SimUnknownKind, db: &CompilationDB) -> (String, String, bool) {
+pub(crate) fn sim_unknown_info(
+    unknown: SimUnknownKind,
+    db: &CompilationDB,
+) -> (String, String, bool) {
     let name;
     let discipline;
     let is_flow;
@@ -14,6 +14,16 @@ const STDLIB_BITCODE_AARCH64_PC_WINDOWS_MSVC: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_aarch64-pc-windows-msvc.bc"));
 const STDLIB_BITCODE_ARM64_APPLE_MACOSX11_0_0: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_arm64-apple-macosx11.0.0.bc"));
+const STDLIB_BITCODE_X86_64_UNKNOWN_LINUX_MUSL: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_x86_64-unknown-linux-musl.bc"));
+const STDLIB_BITCODE_AARCH64_UNKNOWN_LINUX_MUSL: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_aarch64-unknown-linux-musl.bc"));
+const STDLIB_BITCODE_RISCV64_UNKNOWN_LINUX_GNU: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_riscv64-unknown-linux-gnu.bc"));
+const STDLIB_BITCODE_POWERPC64LE_UNKNOWN_LINUX_GNU: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_powerpc64le-unknown-linux-gnu.bc"));
+const STDLIB_BITCODE_WASM32_UNKNOWN_UNKNOWN: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stdlib_0_3_wasm32-unknown-unknown.bc"));
 pub fn stdlib_bitcode(target: &target::spec::Target) -> &'static [u8] {
     match &*target.llvm_target {
         "x86_64-unknown-linux-gnu" => STDLIB_BITCODE_X86_64_UNKNOWN_LINUX_GNU,
@@ -22,6 +32,11 @@ pub fn stdlib_bitcode(target: &target::spec::Target) -> &'static [u8] {
         "aarch64-unknown-linux-gnu" => STDLIB_BITCODE_AARCH64_UNKNOWN_LINUX_GNU,
         "aarch64-pc-windows-msvc" => STDLIB_BITCODE_AARCH64_PC_WINDOWS_MSVC,
         "arm64-apple-macosx11.0.0" => STDLIB_BITCODE_ARM64_APPLE_MACOSX11_0_0,
+        "x86_64-unknown-linux-musl" => STDLIB_BITCODE_X86_64_UNKNOWN_LINUX_MUSL,
+        "aarch64-unknown-linux-musl" => STDLIB_BITCODE_AARCH64_UNKNOWN_LINUX_MUSL,
+        "riscv64-unknown-linux-gnu" => STDLIB_BITCODE_RISCV64_UNKNOWN_LINUX_GNU,
+        "powerpc64le-unknown-linux-gnu" => STDLIB_BITCODE_POWERPC64LE_UNKNOWN_LINUX_GNU,
+        "wasm32-unknown-unknown" => STDLIB_BITCODE_WASM32_UNKNOWN_UNKNOWN,
         triple => unreachable!("unknown target triple {triple}"),
     }
 }
@@ -59,6 +74,9 @@ pub const ANALYSIS_TRAN: u32 = 8192;
 pub const ANALYSIS_IC: u32 = 16384;
 pub const ANALYSIS_STATIC: u32 = 32768;
 pub const ANALYSIS_NODESET: u32 = 65536;
+pub const ANALYSIS_HB: u32 = 131072;
+pub const CALC_SENSITIVITY: u32 = 262144;
+pub const CALC_HESSIAN: u32 = 524288;
 pub const EVAL_RET_FLAG_LIM: u32 = 1;
 pub const EVAL_RET_FLAG_FATAL: u32 = 2;
 pub const EVAL_RET_FLAG_FINISH: u32 = 4;
@@ -292,6 +310,44 @@ impl OsdiTyBuilder<'_, '_, '_> {
         self.osdi_noise_source = Some(ty);
     }
 }
+pub struct OsdiDelay {
+    pub node: u32,
+    pub delay_offset: u32,
+}
+impl OsdiDelay {
+    pub fn to_ll_val<'ll>(&self, ctx: &CodegenCx<'_, 'll>, tys: &'ll OsdiTys) -> &'ll llvm::Value {
+        let fields = [ctx.const_unsigned_int(self.node), ctx.const_unsigned_int(self.delay_offset)];
+        let ty = tys.osdi_delay;
+        ctx.const_struct(ty, &fields)
+    }
+}
+impl OsdiTyBuilder<'_, '_, '_> {
+    fn osdi_delay(&mut self) {
+        let ctx = self.ctx;
+        let fields = [ctx.ty_int(), ctx.ty_int()];
+        let ty = ctx.ty_struct("OsdiDelay", &fields);
+        self.osdi_delay = Some(ty);
+    }
+}
+pub struct OsdiHessianEntry {
+    pub node: u32,
+    pub nodes: OsdiNodePair,
+}
+impl OsdiHessianEntry {
+    pub fn to_ll_val<'ll>(&self, ctx: &CodegenCx<'_, 'll>, tys: &'ll OsdiTys) -> &'ll llvm::Value {
+        let fields = [ctx.const_unsigned_int(self.node), self.nodes.to_ll_val(ctx, tys)];
+        let ty = tys.osdi_hessian_entry;
+        ctx.const_struct(ty, &fields)
+    }
+}
+impl OsdiTyBuilder<'_, '_, '_> {
+    fn osdi_hessian_entry(&mut self) {
+        let ctx = self.ctx;
+        let fields = [ctx.ty_int(), self.osdi_node_pair.unwrap()];
+        let ty = ctx.ty_struct("OsdiHessianEntry", &fields);
+        self.osdi_hessian_entry = Some(ty);
+    }
+}
 pub struct OsdiDescriptor<'ll> {
     pub name: String,
     pub num_nodes: u32,
@@ -329,6 +385,15 @@ pub struct OsdiDescriptor<'ll> {
     pub load_jacobian_resist: &'ll llvm::Value,
     pub load_jacobian_react: &'ll llvm::Value,
     pub load_jacobian_tran: &'ll llvm::Value,
+    pub discontinuity_offset: u32,
+    pub num_delays: u32,
+    pub delays: Vec<OsdiDelay>,
+    pub num_sensitivity_params: u32,
+    pub sensitivity_params: Vec<u32>,
+    pub load_sensitivity: &'ll llvm::Value,
+    pub num_hessian_entries: u32,
+    pub hessian_entries: Vec<OsdiHessianEntry>,
+    pub load_hessian: &'ll llvm::Value,
 }
 impl<'ll> OsdiDescriptor<'ll> {
     pub fn to_ll_val(&self, ctx: &CodegenCx<'_, 'll>, tys: &'ll OsdiTys) -> &'ll llvm::Value {
@@ -337,6 +402,10 @@ impl<'ll> OsdiDescriptor<'ll> {
         let arr_7: Vec<_> = self.collapsible.iter().map(|it| it.to_ll_val(ctx, tys)).collect();
         let arr_9: Vec<_> = self.noise_sources.iter().map(|it| it.to_ll_val(ctx, tys)).collect();
         let arr_14: Vec<_> = self.param_opvar.iter().map(|it| it.to_ll_val(ctx, tys)).collect();
+        let arr_38: Vec<_> = self.delays.iter().map(|it| it.to_ll_val(ctx, tys)).collect();
+        let arr_40: Vec<_> =
+            self.sensitivity_params.iter().map(|it| ctx.const_unsigned_int(*it)).collect();
+        let arr_43: Vec<_> = self.hessian_entries.iter().map(|it| it.to_ll_val(ctx, tys)).collect();
         let fields = [
             ctx.const_str_uninterned(&self.name),
             ctx.const_unsigned_int(self.num_nodes),
@@ -374,6 +443,15 @@ impl<'ll> OsdiDescriptor<'ll> {
             self.load_jacobian_resist,
             self.load_jacobian_react,
             self.load_jacobian_tran,
+            ctx.const_unsigned_int(self.discontinuity_offset),
+            ctx.const_unsigned_int(self.num_delays),
+            ctx.const_arr_ptr(tys.osdi_delay, &arr_38),
+            ctx.const_unsigned_int(self.num_sensitivity_params),
+            ctx.const_arr_ptr(ctx.ty_int(), &arr_40),
+            self.load_sensitivity,
+            ctx.const_unsigned_int(self.num_hessian_entries),
+            ctx.const_arr_ptr(tys.osdi_hessian_entry, &arr_43),
+            self.load_hessian,
         ];
         let ty = tys.osdi_descriptor;
         ctx.const_struct(ty, &fields)
@@ -419,6 +497,15 @@ impl OsdiTyBuilder<'_, '_, '_> {
             ctx.ty_ptr(),
             ctx.ty_ptr(),
             ctx.ty_ptr(),
+            ctx.ty_int(),
+            ctx.ty_int(),
+            ctx.ty_ptr(),
+            ctx.ty_int(),
+            ctx.ty_ptr(),
+            ctx.ty_ptr(),
+            ctx.ty_int(),
+            ctx.ty_ptr(),
+            ctx.ty_ptr(),
         ];
         let ty = ctx.ty_struct("OsdiDescriptor", &fields);
         self.osdi_descriptor = Some(ty);
@@ -437,6 +524,8 @@ pub struct OsdiTys<'ll> {
     pub osdi_node: &'ll llvm::Type,
     pub osdi_param_opvar: &'ll llvm::Type,
     pub osdi_noise_source: &'ll llvm::Type,
+    pub osdi_delay: &'ll llvm::Type,
+    pub osdi_hessian_entry: &'ll llvm::Type,
     pub osdi_descriptor: &'ll llvm::Type,
 }
 impl<'ll> OsdiTys<'ll> {
@@ -455,6 +544,8 @@ impl<'ll> OsdiTys<'ll> {
             osdi_node: None,
             osdi_param_opvar: None,
             osdi_noise_source: None,
+            osdi_delay: None,
+            osdi_hessian_entry: None,
             osdi_descriptor: None,
         };
         builder.osdi_lim_function();
@@ -468,6 +559,8 @@ impl<'ll> OsdiTys<'ll> {
         builder.osdi_node();
         builder.osdi_param_opvar();
         builder.osdi_noise_source();
+        builder.osdi_delay();
+        builder.osdi_hessian_entry();
         builder.osdi_descriptor();
         builder.finish()
     }
@@ -486,6 +579,8 @@ struct OsdiTyBuilder<'a, 'b, 'll> {
     osdi_node: Option<&'ll llvm::Type>,
     osdi_param_opvar: Option<&'ll llvm::Type>,
     osdi_noise_source: Option<&'ll llvm::Type>,
+    osdi_delay: Option<&'ll llvm::Type>,
+    osdi_hessian_entry: Option<&'ll llvm::Type>,
     osdi_descriptor: Option<&'ll llvm::Type>,
 }
 impl<'ll> OsdiTyBuilder<'_, '_, 'll> {
@@ -502,6 +597,8 @@ impl<'ll> OsdiTyBuilder<'_, '_, 'll> {
             osdi_node: self.osdi_node.unwrap(),
             osdi_param_opvar: self.osdi_param_opvar.unwrap(),
             osdi_noise_source: self.osdi_noise_source.unwrap(),
+            osdi_delay: self.osdi_delay.unwrap(),
+            osdi_hessian_entry: self.osdi_hessian_entry.unwrap(),
             osdi_descriptor: self.osdi_descriptor.unwrap(),
         }
     }
@@ -7,10 +7,12 @@ use llvm::{
     UNNAMED,
 };
 use mir::ControlFlowGraph;
-use mir_llvm::{Builder, BuilderVal, CallbackFun, CodegenCx};
+use mir_llvm::{Builder, BuilderVal, CallbackFun, CodegenCx, DebugInfoBuilder};
 use sim_back::SimUnknownKind;
 
-use crate::compilation_unit::{general_callbacks, OsdiCompilationUnit};
+use crate::compilation_unit::{
+    fp_check, function_debug_info, general_callbacks, OsdiCompilationUnit,
+};
 use crate::inst_data::OsdiInstanceParam;
 
 impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
@@ -57,16 +59,20 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         cx.declare_ext_fn(name, fun_ty)
     }
 
-    pub fn setup_model(&self) -> &'ll llvm::Value {
+    pub fn setup_model(&self, debug_info: Option<&DebugInfoBuilder<'ll>>) -> &'ll llvm::Value {
         let llfunc = self.setup_model_prototype();
-        let OsdiCompilationUnit { inst_data, model_data, tys, cx, .. } = self;
+        let OsdiCompilationUnit { db, inst_data, model_data, tys, cx, module, .. } = self;
 
-        let func = &self.module.model_param_setup;
-        let intern = &self.module.model_param_intern;
+        let func = &module.model_param_setup;
+        let intern = &module.model_param_intern;
 
         let mut cfg = ControlFlowGraph::new();
         cfg.compute(func);
         let mut builder = Builder::new(cx, func, llfunc);
+        builder.debug_info = debug_info.map(|debug_info| {
+            let name = format!("setup_model_{}", &module.sym);
+            function_debug_info(db, debug_info, llfunc, &name, intern, &intern.source_locations)
+        });
         let postorder: Vec<_> = cfg.postorder(func).collect();
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
@@ -138,7 +144,11 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         let ret_flags = unsafe { builder.alloca(cx.ty_int()) };
         unsafe { builder.store(ret_flags, cx.const_int(0)) };
 
-        builder.callbacks = general_callbacks(intern, &mut builder, ret_flags, handle, simparam);
+        builder.callbacks =
+            general_callbacks(db, intern, &mut builder, ret_flags, handle, simparam);
+        if self.module.info.check_fp {
+            builder.fp_check = Some(fp_check(&builder, handle, &intern.source_locations));
+        }
         for (call_id, call) in intern.callbacks.iter_enumerated() {
             if let CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) = call {
                 if !self.module.info.params[param].is_instance {
@@ -212,14 +222,24 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         cx.declare_ext_fn(name, fun_ty)
     }
 
-    pub fn setup_instance(&mut self) -> &'ll llvm::Value {
+    pub fn setup_instance(
+        &mut self,
+        debug_info: Option<&DebugInfoBuilder<'ll>>,
+    ) -> &'ll llvm::Value {
         let mark_collapsed = self.mark_collapsed();
         let llfunc = self.setup_instance_prototype();
-        let OsdiCompilationUnit { inst_data, model_data, tys, cx, module, .. } = self;
+        let OsdiCompilationUnit { db, inst_data, model_data, tys, cx, module, .. } = self;
 
         let func = &module.init.func;
         let intern = &module.init.intern;
         let mut builder = Builder::new(cx, func, llfunc);
+        // the instructions of the init function (including their tags) were copied from the
+        // eval function, the parameter initialization added further source locations
+        builder.debug_info = debug_info.map(|debug_info| {
+            let name = format!("setup_instance_{}", &module.sym);
+            let source_locations = &intern.source_locations;
+            function_debug_info(db, debug_info, llfunc, &name, module.intern, source_locations)
+        });
 
         let handle = unsafe { llvm::LLVMGetParam(llfunc, 0) };
         let instance = unsafe { llvm::LLVMGetParam(llfunc, 1) };
@@ -331,7 +351,11 @@ impl<'ll> OsdiCompilationUnit<'_, '_, 'll> {
         }
 
         let invalid_param_err = Self::invalid_param_err(cx);
-        builder.callbacks = general_callbacks(intern, &mut builder, ret_flags, handle, simparam);
+        builder.callbacks =
+            general_callbacks(db, intern, &mut builder, ret_flags, handle, simparam);
+        if self.module.info.check_fp {
+            builder.fp_check = Some(fp_check(&builder, handle, &intern.source_locations));
+        }
         for (call_id, call) in intern.callbacks.iter_enumerated() {
             let cb = match call {
                 CallBackKind::ParamInfo(ParamInfoKind::Invalid, param) => {
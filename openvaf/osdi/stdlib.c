@@ -90,6 +90,33 @@ char *simparam_str(void *params_, void *handle, uint32_t *flags, char *name) {
   return "�";
 }
 
+// Called with the result of every floating point operation when the model was compiled
+// with --check-fp. Only the first NaN/Inf produced by each call to eval, setup_model or
+// setup_instance is reported.
+void check_fp(void *handle, int *reported, char *loc, int derivative,
+              double val) {
+  if (*reported || !(__builtin_isnan(val) || __builtin_isinf(val))) {
+    return;
+  }
+  *reported = 1;
+
+  const char *prefix;
+  if (__builtin_isnan(val)) {
+    prefix = derivative ? "NaN produced by a derivative of the expression at "
+                        : "NaN produced by the expression at ";
+  } else {
+    prefix = derivative ? "Inf produced by a derivative of the expression at "
+                        : "Inf produced by the expression at ";
+  }
+  char *msg = concat(prefix, loc);
+  if (msg == NULL) {
+    osdi_log(handle, "NaN/Inf produced by the expression at %s",
+             LOG_LVL_ERR | LOG_FMT_ERR);
+  } else {
+    osdi_log(handle, msg, LOG_LVL_ERR);
+  }
+}
+
 void push_error(OsdiInitError **dst, uint32_t *len, uint32_t *cap,
                 OsdiInitError err) {
   if (*dst == NULL) {
@@ -168,13 +195,14 @@ double store_lim(void *sim_info_, int idx, double val) {
 int analysis(void *sim_info_, char *name) {
   OsdiSimInfo *sim_info = (OsdiSimInfo *)sim_info_;
   uint32_t flags = sim_info->flags;
-  return ((flags & ANALYSIS_AC) && strcmp(name, "ac")) ||
-         ((flags & ANALYSIS_DC) && strcmp(name, "dc")) ||
-         ((flags & ANALYSIS_NOISE) && strcmp(name, "noise")) ||
-         ((flags & ANALYSIS_TRAN) && strcmp(name, "tran")) ||
-         ((flags & ANALYSIS_IC) && strcmp(name, "ic")) ||
-         ((flags & ANALYSIS_STATIC) && strcmp(name, "static")) ||
-         ((flags & ANALYSIS_NODESET) && strcmp(name, "nodeset"));
+  return ((flags & ANALYSIS_AC) && strcmp(name, "ac") == 0) ||
+         ((flags & ANALYSIS_DC) && strcmp(name, "dc") == 0) ||
+         ((flags & ANALYSIS_NOISE) && strcmp(name, "noise") == 0) ||
+         ((flags & ANALYSIS_TRAN) && strcmp(name, "tran") == 0) ||
+         ((flags & ANALYSIS_IC) && strcmp(name, "ic") == 0) ||
+         ((flags & ANALYSIS_STATIC) && strcmp(name, "static") == 0) ||
+         ((flags & ANALYSIS_NODESET) && strcmp(name, "nodeset") == 0) ||
+         ((flags & ANALYSIS_HB) && strcmp(name, "hb") == 0);
 }
 
 double store_delay(void *sim_info_, double *dst, double val) {
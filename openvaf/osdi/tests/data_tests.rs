@@ -5,7 +5,7 @@ use hir::diagnostics::ConsoleSink;
 use hir::CompilationDB;
 use llvm::OptLevel;
 use mini_harness::{harness, Result};
-use mir_llvm::LLVMBackend;
+use mir_llvm::{FloatPolicy, LLVMBackend};
 use paths::AbsPathBuf;
 use sim_back::collect_modules;
 use stdx::{ignore_slow_tests, project_root};
@@ -17,8 +17,18 @@ fn test_compile(root_file: &Path) {
     let modules = collect_modules(&db, false, &mut ConsoleSink::new(&db)).unwrap();
     let target = Target::host_target().unwrap();
     let back = LLVMBackend::new(&[], &target, "native".to_owned(), &[]);
-    let emit = !stdx::IS_CI;
-    osdi::compile(&db, &modules, Utf8Path::new("foo.o"), &target, &back, emit, OptLevel::None);
+    let emit = if stdx::IS_CI { None } else { Some(&[][..]) };
+    osdi::compile(
+        &db,
+        &modules,
+        Utf8Path::new("foo.o"),
+        &target,
+        &back,
+        emit,
+        OptLevel::None,
+        FloatPolicy::default(),
+    )
+    .unwrap();
 }
 
 fn integration_test(dir: &Path) -> Result {
@@ -0,0 +1,438 @@
+// Host runtime for OSDI libraries compiled for `wasm32-unknown-unknown`.
+//
+// wasm modules produced by OpenVAF do not contain a libc. Instead the math functions
+// (libm), the few string and memory functions used by the OSDI stdlib and `snprintf`
+// (used for `$display`/`$strobe`) are imported from the `env` module and provided here.
+// Memory is managed by a small first fit allocator starting at `__heap_base`. Freed blocks
+// are reused, so the messages allocated for every `osdi_log` call (which are freed by the
+// runtime once `options.log` returns) and the errors returned by the setup functions
+// (which must be released with `lib.free`) do not grow the memory.
+//
+// Usage:
+//
+//   import { instantiateOsdi } from "./runtime.mjs";
+//   const lib = await instantiateOsdi(fs.readFileSync("model.wasm"), {
+//     log: (msg, lvl) => console.log(msg),
+//   });
+//   for (const descriptor of lib.descriptors()) {
+//     console.log(descriptor.name, descriptor.num_nodes);
+//   }
+//
+// The returned object exposes the memory and the function table so that the function
+// pointers in the descriptors (e.g. `descriptor.eval`) can be called with
+// `lib.table.get(descriptor.eval)(...)`.
+
+const PAGE_SIZE = 65536;
+// Every allocation is preceded by a header that stores the size of the block. The header
+// is as large as the alignment (16 byte, like the stack) so that all blocks stay aligned.
+const HEADER_SIZE = 16;
+// osdi_log: `msg` is a static format string and is not owned by the callee
+const LOG_FMT_ERR = 16;
+
+// Fields of `OsdiDescriptor` (see header/osdi_0_3.h) in declaration order. On wasm32
+// all pointers are 32 bit wide, so every field occupies exactly 4 bytes.
+const DESCRIPTOR_FIELDS = [
+  "name",
+  "num_nodes",
+  "num_terminals",
+  "nodes",
+  "num_jacobian_entries",
+  "jacobian_entries",
+  "num_collapsible",
+  "collapsible",
+  "collapsed_offset",
+  "noise_sources",
+  "num_noise_src",
+  "num_params",
+  "num_instance_params",
+  "num_opvars",
+  "param_opvar",
+  "node_mapping_offset",
+  "jacobian_ptr_resist_offset",
+  "num_states",
+  "state_idx_off",
+  "bound_step_offset",
+  "instance_size",
+  "model_size",
+  "access",
+  "setup_model",
+  "setup_instance",
+  "eval",
+  "load_noise",
+  "load_residual_resist",
+  "load_residual_react",
+  "load_limit_rhs_resist",
+  "load_limit_rhs_react",
+  "load_spice_rhs_dc",
+  "load_spice_rhs_tran",
+  "load_jacobian_resist",
+  "load_jacobian_react",
+  "load_jacobian_tran",
+  "discontinuity_offset",
+  "num_delays",
+  "delays",
+  "num_sensitivity_params",
+  "sensitivity_params",
+  "load_sensitivity",
+  "num_hessian_entries",
+  "hessian_entries",
+  "load_hessian",
+];
+const DESCRIPTOR_SIZE = DESCRIPTOR_FIELDS.length * 4;
+
+// A minimal wasm module that re-exports an imported `(i32, i32, i32) -> ()` function.
+// Functions created by JS can only be stored in a wasm table if they are wasm functions,
+// this is used to turn the `log` callback into something that can be assigned to `osdi_log`.
+const LOG_TRAMPOLINE = new Uint8Array([
+  0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic + version
+  0x01, 0x07, 0x01, 0x60, 0x03, 0x7f, 0x7f, 0x7f, 0x00, // type: (i32, i32, i32) -> ()
+  0x02, 0x07, 0x01, 0x01, 0x65, 0x01, 0x66, 0x00, 0x00, // import "e" "f"
+  0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export "f"
+]);
+
+class Runtime {
+  constructor() {
+    this.instance = null;
+    this.heapTop = 0;
+    // blocks that were freed but are not at the top of the heap
+    this.freeBlocks = [];
+  }
+
+  get memory() {
+    return this.instance.exports.memory;
+  }
+
+  u8() {
+    return new Uint8Array(this.memory.buffer);
+  }
+
+  view() {
+    return new DataView(this.memory.buffer);
+  }
+
+  readCString(ptr) {
+    const mem = this.u8();
+    let end = ptr;
+    while (mem[end] !== 0) {
+      end++;
+    }
+    return new TextDecoder().decode(mem.subarray(ptr, end));
+  }
+
+  writeCString(ptr, size, str) {
+    if (size === 0) {
+      return;
+    }
+    const bytes = new TextEncoder().encode(str).subarray(0, size - 1);
+    const mem = this.u8();
+    mem.set(bytes, ptr);
+    mem[ptr + bytes.length] = 0;
+  }
+
+  blockSize(ptr) {
+    return this.view().getUint32(ptr - HEADER_SIZE, true);
+  }
+
+  malloc(size) {
+    size = (size + HEADER_SIZE - 1) & ~(HEADER_SIZE - 1);
+    const idx = this.freeBlocks.findIndex((block) => this.blockSize(block) >= size);
+    if (idx !== -1) {
+      return this.freeBlocks.splice(idx, 1)[0];
+    }
+    const ptr = this.heapTop + HEADER_SIZE;
+    const end = ptr + size;
+    const available = this.memory.buffer.byteLength;
+    if (end > available) {
+      this.memory.grow(Math.ceil((end - available) / PAGE_SIZE));
+    }
+    this.view().setUint32(ptr - HEADER_SIZE, size, true);
+    this.heapTop = end;
+    return ptr;
+  }
+
+  realloc(ptr, size) {
+    if (ptr === 0) {
+      return this.malloc(size);
+    }
+    const old = this.blockSize(ptr);
+    if (old >= size) {
+      return ptr;
+    }
+    const res = this.malloc(size);
+    this.u8().copyWithin(res, ptr, ptr + old);
+    this.free(ptr);
+    return res;
+  }
+
+  free(ptr) {
+    if (ptr === 0) {
+      return;
+    }
+    this.freeBlocks.push(ptr);
+    // shrink the heap while its topmost block is free
+    for (;;) {
+      const idx = this.freeBlocks.findIndex(
+        (block) => block + this.blockSize(block) === this.heapTop
+      );
+      if (idx === -1) {
+        break;
+      }
+      this.heapTop = this.freeBlocks.splice(idx, 1)[0] - HEADER_SIZE;
+    }
+  }
+
+  // Formats a C format string. Variadic arguments are passed by wasm32 clang as a
+  // pointer to a buffer where every argument is stored with its natural alignment.
+  format(fmt, args) {
+    const view = this.view();
+    const next = (size) => {
+      args = (args + size - 1) & ~(size - 1);
+      const ptr = args;
+      args += size;
+      return ptr;
+    };
+    return fmt.replace(
+      /%([-+ #0]*)(\*|\d+)?(?:\.(\*|\d+))?(hh|h|ll|l|z|j|t|L)?([diouxXeEfFgGcsp%])/g,
+      (_match, flags, width, precision, length, conv) => {
+        if (conv === "%") {
+          return "%";
+        }
+        if (width === "*") {
+          width = view.getInt32(next(4), true);
+        }
+        if (precision === "*") {
+          precision = view.getInt32(next(4), true);
+        }
+        width = width === undefined ? 0 : Number(width);
+        precision = precision === undefined ? undefined : Number(precision);
+
+        let res;
+        switch (conv) {
+          case "d":
+          case "i":
+            res = length === "ll" ? view.getBigInt64(next(8), true) : view.getInt32(next(4), true);
+            res = res.toString();
+            break;
+          case "u":
+          case "o":
+          case "x":
+          case "X": {
+            const val =
+              length === "ll" ? view.getBigUint64(next(8), true) : view.getUint32(next(4), true);
+            const radix = conv === "u" ? 10 : conv === "o" ? 8 : 16;
+            res = val.toString(radix);
+            if (conv === "X") {
+              res = res.toUpperCase();
+            }
+            break;
+          }
+          case "c":
+            res = String.fromCharCode(view.getInt32(next(4), true));
+            break;
+          case "s":
+            res = this.readCString(view.getUint32(next(4), true));
+            if (precision !== undefined) {
+              res = res.slice(0, precision);
+            }
+            break;
+          case "p":
+            res = "0x" + view.getUint32(next(4), true).toString(16);
+            break;
+          default:
+            res = formatFloat(view.getFloat64(next(8), true), conv, precision, flags);
+        }
+        if (flags.includes("+") && /^[0-9]/.test(res) && "dieEfFgG".includes(conv)) {
+          res = "+" + res;
+        }
+        if (res.length < width) {
+          if (flags.includes("-")) {
+            res = res.padEnd(width);
+          } else if (flags.includes("0") && /^[-+]?[0-9]/.test(res)) {
+            const sign = /^[-+]/.test(res) ? res[0] : "";
+            res = sign + res.slice(sign.length).padStart(width - sign.length, "0");
+          } else {
+            res = res.padStart(width);
+          }
+        }
+        return res;
+      }
+    );
+  }
+
+  imports() {
+    const rt = this;
+    const env = {
+      // libm
+      pow: Math.pow,
+      exp: Math.exp,
+      log: Math.log,
+      log10: Math.log10,
+      log2: Math.log2,
+      sqrt: Math.sqrt,
+      floor: Math.floor,
+      sin: Math.sin,
+      cos: Math.cos,
+      tan: Math.tan,
+      asin: Math.asin,
+      acos: Math.acos,
+      atan: Math.atan,
+      atan2: Math.atan2,
+      sinh: Math.sinh,
+      cosh: Math.cosh,
+      tanh: Math.tanh,
+      asinh: Math.asinh,
+      acosh: Math.acosh,
+      atanh: Math.atanh,
+      hypot: Math.hypot,
+      fmod: (x, y) => x % y,
+      // C rounds half away from zero, Math.round rounds half towards +inf
+      lround: (x) => Math.sign(x) * Math.round(Math.abs(x)),
+
+      // string.h
+      strlen: (ptr) => {
+        const mem = rt.u8();
+        let end = ptr;
+        while (mem[end] !== 0) {
+          end++;
+        }
+        return end - ptr;
+      },
+      strcmp: (a, b) => {
+        const mem = rt.u8();
+        while (mem[a] !== 0 && mem[a] === mem[b]) {
+          a++;
+          b++;
+        }
+        return mem[a] - mem[b];
+      },
+      memcmp: (a, b, len) => {
+        const mem = rt.u8();
+        for (let i = 0; i < len; i++) {
+          if (mem[a + i] !== mem[b + i]) {
+            return mem[a + i] - mem[b + i];
+          }
+        }
+        return 0;
+      },
+      memcpy: (dst, src, len) => {
+        rt.u8().copyWithin(dst, src, src + len);
+        return dst;
+      },
+      memmove: (dst, src, len) => {
+        rt.u8().copyWithin(dst, src, src + len);
+        return dst;
+      },
+      memset: (dst, val, len) => {
+        rt.u8().fill(val, dst, dst + len);
+        return dst;
+      },
+
+      // stdlib.h
+      malloc: (size) => rt.malloc(size),
+      realloc: (ptr, size) => rt.realloc(ptr, size),
+      free: (ptr) => rt.free(ptr),
+
+      // stdio.h
+      snprintf: (dst, size, fmt, args) => {
+        const str = rt.format(rt.readCString(fmt), args);
+        rt.writeCString(dst, size, str);
+        return new TextEncoder().encode(str).length;
+      },
+    };
+    return { env };
+  }
+}
+
+function formatFloat(val, conv, precision, flags) {
+  if (!Number.isFinite(val)) {
+    const res = Number.isNaN(val) ? "nan" : val > 0 ? "inf" : "-inf";
+    return conv === conv.toUpperCase() ? res.toUpperCase() : res;
+  }
+  precision = precision === undefined ? 6 : precision;
+  let res;
+  switch (conv.toLowerCase()) {
+    case "f":
+      res = val.toFixed(precision);
+      break;
+    case "e":
+      res = exponential(val, precision);
+      break;
+    default: {
+      // %g: use the shorter of %e and %f and strip trailing zeros
+      const p = precision === 0 ? 1 : precision;
+      const exp = val === 0 ? 0 : Math.floor(Math.log10(Math.abs(Number(val.toPrecision(p)))));
+      res = exp < -4 || exp >= p ? exponential(val, p - 1) : val.toFixed(p - 1 - exp);
+      if (!flags.includes("#")) {
+        res = res.replace(/\.?0+(e|$)/, "$1");
+      }
+    }
+  }
+  return conv === conv.toUpperCase() ? res.toUpperCase() : res;
+}
+
+// like Number.toExponential but with (at least) two digits in the exponent as in C
+function exponential(val, precision) {
+  return val.toExponential(precision).replace(/e([+-])(\d)$/, "e$10$2");
+}
+
+// Instantiates an OSDI library compiled for wasm32. `options.log(msg, lvl)` is called for
+// every message the model logs (`osdi_log`), the message is freed afterwards.
+export async function instantiateOsdi(bytes, options = {}) {
+  const runtime = new Runtime();
+  const log = options.log || ((msg) => console.log(msg));
+  const { instance } = await WebAssembly.instantiate(bytes, runtime.imports());
+  runtime.instance = instance;
+  runtime.heapTop = (instance.exports.__heap_base.value + HEADER_SIZE - 1) & ~(HEADER_SIZE - 1);
+
+  const exports = instance.exports;
+  const table = exports.__indirect_function_table;
+
+  // install osdi_log, the wasm trampoline is required to store the callback in the table
+  const trampoline = await WebAssembly.instantiate(LOG_TRAMPOLINE, {
+    e: {
+      f: (_handle, msg, lvl) => {
+        log(runtime.readCString(msg), lvl);
+        if ((lvl & LOG_FMT_ERR) === 0) {
+          runtime.free(msg);
+        }
+      },
+    },
+  });
+  const logIdx = table.grow(1);
+  table.set(logIdx, trampoline.instance.exports.f);
+  runtime.view().setUint32(exports.osdi_log.value, logIdx, true);
+
+  const readU32 = (global) => runtime.view().getUint32(global.value, true);
+
+  return {
+    instance,
+    memory: exports.memory,
+    table,
+    malloc: (size) => runtime.malloc(size),
+    free: (ptr) => runtime.free(ptr),
+    // the end of the memory currently used by allocations
+    heapTop: () => runtime.heapTop,
+    readCString: (ptr) => runtime.readCString(ptr),
+    versionMajor: readU32(exports.OSDI_VERSION_MAJOR),
+    versionMinor: readU32(exports.OSDI_VERSION_MINOR),
+
+    // Reads the `OsdiDescriptor`s exported by the library. Pointer fields (including the
+    // function pointers) are returned as addresses/table indices, except for `name`.
+    descriptors() {
+      const view = runtime.view();
+      const num = readU32(exports.OSDI_NUM_DESCRIPTORS);
+      const base = exports.OSDI_DESCRIPTORS.value;
+      const res = [];
+      for (let i = 0; i < num; i++) {
+        const ptr = base + i * DESCRIPTOR_SIZE;
+        const descriptor = { ptr };
+        DESCRIPTOR_FIELDS.forEach((field, j) => {
+          descriptor[field] = view.getUint32(ptr + j * 4, true);
+        });
+        descriptor.name = runtime.readCString(descriptor.name);
+        res.push(descriptor);
+      }
+      return res;
+    },
+  };
+}
@@ -0,0 +1,58 @@
+//! Compares the automatic differentiation modes on large compact models.
+//! Run with `cargo bench -p sim_back --bench autodiff`.
+//!
+//! For every model and mode the time required to build the DAE system and the number of
+//! instructions in the final `eval` function are reported.
+
+use std::time::{Duration, Instant};
+
+use hir::diagnostics::ConsoleSink;
+use hir::CompilationDB;
+use lasso::Rodeo;
+use paths::AbsPathBuf;
+use sim_back::{collect_modules, AutoDiffMode, AutoDiffOptions, CompiledModule};
+use stdx::project_root;
+
+const MODELS: [&str; 3] = ["BSIM4", "PSP103", "HICUML2"];
+const ITERATIONS: u32 = 3;
+
+fn main() {
+    let filter = std::env::args().nth(1).filter(|arg| !arg.starts_with('-'));
+    println!("{:<10} {:<8} {:<5} {:>12} {:>12}", "model", "mode", "cse", "time [ms]", "eval insts");
+    for model in MODELS {
+        if filter.as_deref().map_or(false, |filter| !model.eq_ignore_ascii_case(filter)) {
+            continue;
+        }
+        let root_file = project_root()
+            .join("integration_tests")
+            .join(model)
+            .join(format!("{}.va", model.to_lowercase()));
+        let root_file = AbsPathBuf::assert(root_file.canonicalize().unwrap());
+        let db = CompilationDB::new_fs(root_file, &[], &[], &[]).unwrap();
+        let mut modules = collect_modules(&db, false, &mut ConsoleSink::new(&db)).unwrap();
+
+        for mode in AutoDiffMode::ALL {
+            for cse in [false, true] {
+                let mut time = Duration::ZERO;
+                let mut num_insts = 0;
+                for _ in 0..ITERATIONS {
+                    let mut literals = Rodeo::new();
+                    let start = Instant::now();
+                    for module in &mut modules {
+                        module.autodiff = AutoDiffOptions { mode, cse };
+                        let compiled = CompiledModule::new(&db, module, &mut literals, false);
+                        let eval = &compiled.eval;
+                        num_insts = eval
+                            .layout
+                            .blocks()
+                            .map(|bb| eval.layout.block_insts(bb).count())
+                            .sum();
+                    }
+                    time += start.elapsed();
+                }
+                let time = time.as_secs_f64() * 1000.0 / ITERATIONS as f64;
+                println!("{model:<10} {:<8} {cse:<5} {time:>12.1} {num_insts:>12}", mode.name());
+            }
+        }
+    }
+}
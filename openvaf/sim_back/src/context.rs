@@ -1,12 +1,12 @@
 use bitset::{BitSet, SparseBitMatrix};
 use hir::CompilationDB;
-use hir_lower::{HirInterner, MirBuilder, PlaceKind};
+use hir_lower::{CallBackKind, HirInterner, MirBuilder, PlaceKind};
 use lasso::Rodeo;
-use mir::{Block, ControlFlowGraph, DominatorTree, Function, Inst, Value};
+use mir::{Block, ControlFlowGraph, DominatorTree, FuncRef, Function, InlineHint, Inst, Value};
 use mir_opt::{
-    aggressive_dead_code_elimination, dead_code_elimination, inst_combine, propagate_direct_taint,
-    propagate_taint, simplify_cfg, simplify_cfg_no_phi_merge,
-    sparse_conditional_constant_propagation, GVN,
+    aggressive_dead_code_elimination, dead_code_elimination, inline_calls, inst_combine,
+    loop_invariant_code_motion, propagate_direct_taint, propagate_taint, simplify_cfg,
+    simplify_cfg_no_phi_merge, sparse_conditional_constant_propagation, GVN,
 };
 use stdx::packed_option::PackedOption;
 
@@ -22,6 +22,9 @@ pub(crate) struct Context<'a> {
     pub(crate) output_values: BitSet<Value>,
     pub(crate) op_dependent_insts: BitSet<Inst>,
     pub(crate) op_dependent_vals: Vec<Value>,
+    /// Analog functions with a `fp_policy` attribute that were not inlined even tough their
+    /// derivatives of second order are required (see [`ModuleInfo::check_inlining`])
+    pub(crate) inline_conflicts: Vec<hir::Function>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -33,14 +36,17 @@ pub enum OptimiziationStage {
 
 impl<'a> Context<'a> {
     pub fn new(db: &'a CompilationDB, literals: &mut Rodeo, module: &'a ModuleInfo) -> Self {
-        let (mut func, mut intern) = MirBuilder::new(
+        let mut builder = MirBuilder::new(
             db,
             module.module,
             &|kind| match kind {
                 PlaceKind::Contribute { .. }
                 | PlaceKind::ImplicitResidual { .. }
                 | PlaceKind::CollapseImplicitEquation(_)
-                | PlaceKind::IsVoltageSrc(_) => true,
+                | PlaceKind::IsVoltageSrc(_)
+                | PlaceKind::BoundStep
+                | PlaceKind::Discontinuity
+                | PlaceKind::Delay(_) => true,
                 PlaceKind::Var(var) => module.op_vars.contains_key(&var),
                 _ => false,
             },
@@ -48,9 +54,43 @@ impl<'a> Context<'a> {
         )
         .with_equations()
         .with_tagged_writes()
-        .build(literals);
+        .with_analog_function_calls();
+        if module.source_locations() {
+            builder = builder.with_source_locations();
+        }
+        if module.symbolic_delays {
+            builder = builder.with_symbolic_delays();
+        }
+        let (mut func, mut intern) = builder.build(literals);
         // TODO hidden state
-        intern.insert_var_init(db, &mut func, literals);
+        intern.insert_var_init(db, &mut func, literals, module.source_locations());
+
+        // the size of a function is only a good inlining heuristic after it has been optimized
+        for analog_function in intern.analog_functions.values_mut() {
+            optimize_analog_function(&mut analog_function.func, &analog_function.returns);
+        }
+        // only first order derivatives are propagated trough calls so calls that require
+        // higher order derivatives are always inlined (regardless of `(* noinline *)`)
+        let higher_order_calls = higher_order_calls(&func, &intern, !module.hessian.is_empty());
+        let HirInterner { callbacks, analog_functions, .. } = &intern;
+        let mut inline_conflicts = Vec::new();
+        inline_calls(&mut func, |func_ref| match callbacks[func_ref] {
+            CallBackKind::AnalogFunction { fun, .. } => {
+                let analog_function = &analog_functions[&fun];
+                let mut callee = analog_function.callee();
+                if higher_order_calls.contains(func_ref) {
+                    // the floating point policy only applies to the function itself so inlining
+                    // would silently drop it
+                    if analog_function.fp_policy.is_none() {
+                        callee.hint = InlineHint::Always;
+                    } else if !inline_conflicts.contains(&fun) {
+                        inline_conflicts.push(fun);
+                    }
+                }
+                Some(callee)
+            }
+            _ => None,
+        });
 
         Context {
             output_values: BitSet::new_empty(func.dfg.num_values()),
@@ -62,6 +102,7 @@ impl<'a> Context<'a> {
             module,
             op_dependent_insts: BitSet::new_empty(0),
             op_dependent_vals: Vec::new(),
+            inline_conflicts,
         }
     }
 
@@ -77,6 +118,8 @@ impl<'a> Context<'a> {
             simplify_cfg_no_phi_merge(&mut self.func, &mut self.cfg);
         }
         self.compute_domtree(true, true, false);
+        // does not change the cfg so the dominator tree stays valid
+        loop_invariant_code_motion(&mut self.func, &self.cfg, &self.dom_tree);
 
         let mut gvn = GVN::default();
         gvn.init(&self.func, &self.dom_tree, self.intern.params.len() as u32);
@@ -115,7 +158,13 @@ impl<'a> Context<'a> {
         } else {
             for (kind, val) in self.intern.outputs.iter() {
                 if matches!(kind, PlaceKind::Var(var) if self.module.op_vars.contains_key(var))
-                    || matches!(kind, PlaceKind::CollapseImplicitEquation(_) | PlaceKind::BoundStep)
+                    || matches!(
+                        kind,
+                        PlaceKind::CollapseImplicitEquation(_)
+                            | PlaceKind::BoundStep
+                            | PlaceKind::Discontinuity
+                            | PlaceKind::Delay(_)
+                    )
                 {
                     self.output_values.insert(val.unwrap_unchecked());
                 }
@@ -188,3 +237,57 @@ impl<'a> Context<'a> {
         )
     }
 }
+
+/// Returns the calls that require derivatives of second (or higher) order: calls whose results are
+/// (transitively) differentiated with `ddx` (the result of `ddx` is differentiated again for the
+/// jacobian or by a nested `ddx`) and, if `hessian` is set, all calls the residual depends on.
+fn higher_order_calls(func: &Function, intern: &HirInterner, hessian: bool) -> BitSet<FuncRef> {
+    let is_ddx = |func_ref| {
+        matches!(
+            intern.callbacks[func_ref],
+            CallBackKind::Derivative(_) | CallBackKind::NodeDerivative(_)
+        )
+    };
+
+    let mut stack = Vec::new();
+    for bb in func.layout.blocks() {
+        for inst in func.layout.block_insts(bb) {
+            if func.dfg.func_ref(inst).map_or(false, is_ddx) {
+                stack.extend_from_slice(func.dfg.instr_args(inst));
+            }
+        }
+    }
+    if hessian {
+        stack.extend(intern.outputs.iter().filter_map(|(kind, val)| {
+            let is_residual =
+                matches!(kind, PlaceKind::Contribute { .. } | PlaceKind::ImplicitResidual { .. });
+            is_residual.then(|| val.expand()).flatten()
+        }));
+    }
+
+    let mut res = BitSet::new_empty(intern.callbacks.len());
+    let mut visited = BitSet::new_empty(func.dfg.num_values());
+    while let Some(val) = stack.pop() {
+        if !visited.insert(val) {
+            continue;
+        }
+        if let Some(inst) = func.dfg.value_def(val).inst() {
+            if let Some(func_ref) = func.dfg.func_ref(inst) {
+                res.insert(func_ref);
+            }
+            stack.extend_from_slice(func.dfg.instr_args(inst));
+        }
+    }
+    res
+}
+
+fn optimize_analog_function(func: &mut Function, returns: &[Value]) {
+    let mut output_values = BitSet::new_empty(func.dfg.num_values());
+    output_values.extend(returns.iter().copied());
+    let mut cfg = ControlFlowGraph::new();
+    cfg.compute(func);
+    dead_code_elimination(func, &output_values);
+    sparse_conditional_constant_propagation(func, &cfg);
+    inst_combine(func);
+    simplify_cfg(func, &mut cfg);
+}
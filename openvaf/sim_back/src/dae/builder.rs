@@ -1,10 +1,10 @@
 use std::mem::replace;
 use std::vec;
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use bitset::BitSet;
-use hir::{BranchWrite, CompilationDB, Node, ParamSysFun};
-use hir_lower::{HirInterner, ImplicitEquation, ParamKind};
+use hir::{BranchWrite, CompilationDB, Node, ParamSysFun, Parameter, Variable};
+use hir_lower::{HirInterner, ImplicitEquation, ParamKind, PlaceKind};
 use indexmap::IndexSet;
 use mir::builder::InstBuilder;
 use mir::cursor::{Cursor, FuncCursor};
@@ -12,15 +12,17 @@ use mir::{
     strip_optbarrier, Block, ControlFlowGraph, DominatorTree, Inst, KnownDerivatives, Unknown,
     Value, FALSE, F_ONE, F_ZERO, TRUE,
 };
-use mir_autodiff::auto_diff;
+use mir_autodiff::{auto_diff_with_options, partial_derivatives};
+use stdx::packed_option::PackedOption;
 use typed_index_collections::TiVec;
 
 use crate::context::Context;
-use crate::dae::{DaeSystem, MatrixEntry, Residual, SimUnknown};
+use crate::dae::{DaeSystem, HessianEntry, MatrixEntry, Residual, SensitivityEntry, SimUnknown};
+use crate::module_info::OpVarDerivative;
 use crate::noise::NoiseSource;
 use crate::topology::{BranchInfo, Contribution};
 use crate::util::{add, is_op_dependent, update_optbarrier};
-use crate::SimUnknownKind;
+use crate::{ModuleInfo, SimUnknownKind};
 
 impl Residual {
     fn add(&mut self, cursor: &mut FuncCursor, negate: bool, mut val: Value) {
@@ -51,6 +53,7 @@ pub(super) struct Builder<'a> {
     pub(super) system: DaeSystem,
     pub(super) cursor: FuncCursor<'a>,
     pub(super) db: &'a CompilationDB,
+    pub(super) module: &'a ModuleInfo,
     pub(super) intern: &'a mut HirInterner,
     pub(super) cfg: &'a mut ControlFlowGraph,
     pub(super) dom_tree: &'a mut DominatorTree,
@@ -65,6 +68,7 @@ impl<'a> Builder<'a> {
             system: DaeSystem::default(),
             cursor: FuncCursor::new(&mut ctx.func).at_exit(),
             db: ctx.db,
+            module: ctx.module,
             intern: &mut ctx.intern,
             cfg: &mut ctx.cfg,
             dom_tree: &mut ctx.dom_tree,
@@ -86,23 +90,73 @@ impl<'a> Builder<'a> {
 
     pub(super) fn finish(mut self) -> DaeSystem {
         let sim_unknown_reads = self.sim_unknown_reads();
-        let derivative_info = self.intern.unknowns(&self.cursor, true);
-        let extra_derivatives = self
+        self.build_analog_function_derivatives();
+        let mut derivative_info = self.intern.unknowns(&self.cursor, true);
+        let sensitivity_unknowns = self.sensitivity_unknowns(&mut derivative_info);
+        let mut extra_derivatives = self
             .jacobian_derivatives(sim_unknown_reads.iter().map(|&(_, val)| val), &derivative_info);
+        let op_var_derivatives = self.op_var_derivatives(&sim_unknown_reads, &derivative_info);
+        extra_derivatives.extend(op_var_derivatives.iter().flat_map(|(_, val, unknowns)| {
+            unknowns.iter().map(move |&(unknown, _)| (*val, unknown))
+        }));
+        extra_derivatives.extend(self.sensitivity_derivatives(&sensitivity_unknowns));
         // TODO(pref): incrementially update dom_tree (for switch branches) instead
         self.dom_tree.compute(self.cursor.func, self.cfg, true, false, true);
-        let derivatives =
-            auto_diff(&mut *self.cursor.func, self.dom_tree, &derivative_info, &extra_derivatives);
+        let derivatives = auto_diff_with_options(
+            &mut *self.cursor.func,
+            self.dom_tree,
+            &derivative_info,
+            &extra_derivatives,
+            self.module.autodiff,
+        );
         drop(extra_derivatives);
         // auto_diff may in an unlikely case add extra bb at the end, ensure we are building everything at the end
         self.cursor.goto_exit();
 
         self.build_jacobian(&sim_unknown_reads, &derivative_info, &derivatives);
+        self.build_op_var_derivatives(op_var_derivatives, &derivatives);
+        self.build_sensitivities(&sensitivity_unknowns, &derivatives);
         self.build_lim_rhs(&derivative_info, derivatives);
+        self.build_hessian(&sim_unknown_reads, &derivative_info);
         self.ensure_optbarriers();
         self.system
     }
 
+    /// Analog functions that were not inlined are differentiated by calling a function
+    /// that computes the partial derivatives of the called function.
+    fn build_analog_function_derivatives(&mut self) {
+        let func = &mut *self.cursor.func;
+        let mut called = AHashSet::new();
+        for bb in func.layout.blocks() {
+            called.extend(func.layout.block_insts(bb).filter_map(|inst| func.dfg.func_ref(inst)));
+        }
+
+        let HirInterner { callbacks, callback_uses, analog_functions, .. } = &mut *self.intern;
+        for (&fun, analog_function) in analog_functions.iter_mut() {
+            let is_called = callbacks
+                .index(&analog_function.callback(fun))
+                .map_or(false, |func_ref| called.contains(&func_ref));
+            if !is_called {
+                continue;
+            }
+            if analog_function.derivative.is_none() {
+                analog_function.derivative = Some(partial_derivatives(
+                    &analog_function.func,
+                    &analog_function.returns,
+                    analog_function.num_params as usize,
+                ));
+            }
+            let kind = analog_function.derivative_callback(fun);
+            let signature = kind.signature();
+            let (func_ref, changed) = callbacks.ensure(kind);
+            if changed {
+                callback_uses.push(Vec::new());
+                let func_ref_ = func.import_function(signature);
+                debug_assert_eq!(func_ref, func_ref_);
+            }
+        }
+    }
+
     pub(super) fn build_node(&mut self, node: Node) {
         self.ensure_unknown(SimUnknownKind::KirchoffLaw(node));
     }
@@ -275,6 +329,244 @@ impl<'a> Builder<'a> {
         }
     }
 
+    /// Returns the derivatives required to compute the operating point variables declared
+    /// with the `derivative_of` attribute. The derivative by a node potential is the sum
+    /// of the derivatives by all voltages probed from that node (just like a jacobian column).
+    #[allow(clippy::type_complexity)]
+    fn op_var_derivatives(
+        &self,
+        sim_unknown_reads: &[(ParamKind, Value)],
+        derivative_info: &KnownDerivatives,
+    ) -> Vec<(Variable, Value, Vec<(Unknown, bool)>)> {
+        self.module
+            .op_vars
+            .iter()
+            .filter_map(|(&var, info)| {
+                let OpVarDerivative { of, wrt } = info.derivative?;
+                let val = self.intern.outputs.get(&PlaceKind::Var(of))?.expand()?;
+                let val = strip_optbarrier(&self.cursor, val);
+                let mut unknowns = Vec::new();
+                for &(kind, param) in sim_unknown_reads {
+                    let negate = match kind {
+                        ParamKind::Voltage { hi, .. } if hi == wrt => false,
+                        ParamKind::Voltage { lo: Some(lo), .. } if lo == wrt => true,
+                        _ => continue,
+                    };
+                    if let Some(lim_vals) = self.intern.lim_state.raw.get(&param) {
+                        unknowns.extend(lim_vals.iter().filter_map(|(val, negate_lim)| {
+                            let unknown = derivative_info.unknowns.index(val)?;
+                            Some((unknown, negate != *negate_lim))
+                        }));
+                    }
+                    if let Some(unknown) = derivative_info.unknowns.index(&param) {
+                        unknowns.push((unknown, negate));
+                    }
+                }
+                Some((var, val, unknowns))
+            })
+            .collect()
+    }
+
+    fn build_op_var_derivatives(
+        &mut self,
+        op_var_derivatives: Vec<(Variable, Value, Vec<(Unknown, bool)>)>,
+        derivatives: &AHashMap<(Value, Unknown), Value>,
+    ) {
+        for (var, val, unknowns) in op_var_derivatives {
+            let mut ddx = F_ZERO;
+            for (unknown, negate) in unknowns {
+                if let Some(&val) = derivatives.get(&(val, unknown)) {
+                    add(&mut self.cursor, &mut ddx, val, negate)
+                }
+            }
+            let ddx = self.cursor.ins().ensure_optbarrier(ddx);
+            self.output_values.ensure(self.cursor.func.dfg.num_values());
+            self.output_values.insert(ddx);
+            // the value computed by the model itself (if any) is simply discarded
+            let old = self.intern.outputs.insert(PlaceKind::Var(var), ddx.into());
+            if let Some(old) = old.and_then(PackedOption::expand) {
+                self.output_values.remove(old);
+            }
+        }
+    }
+
+    /// Returns the unknowns used to differentiate the residual by the parameters
+    /// selected for sensitivity analysis. Parameters that do not affect the model are skipped.
+    fn sensitivity_unknowns(
+        &self,
+        derivative_info: &mut KnownDerivatives,
+    ) -> Vec<(Parameter, Unknown)> {
+        self.module
+            .sensitivities
+            .iter()
+            .filter_map(|&param| {
+                let val = *self.intern.params.raw.get(&ParamKind::Param(param))?;
+                if self.cursor.func.dfg.value_dead(val) {
+                    return None;
+                }
+                let unknown = derivative_info.unknowns.ensure(val).0;
+                Some((param, unknown))
+            })
+            .collect()
+    }
+
+    fn sensitivity_derivatives(
+        &self,
+        sensitivity_unknowns: &[(Parameter, Unknown)],
+    ) -> Vec<(Value, Unknown)> {
+        let mut res = Vec::new();
+        for residual in &self.system.residual {
+            for val in [residual.resist, residual.react] {
+                if self.cursor.func.dfg.value_def(val).as_const().is_none() {
+                    res.extend(sensitivity_unknowns.iter().map(|&(_, unknown)| (val, unknown)))
+                }
+            }
+        }
+        res
+    }
+
+    fn build_sensitivities(
+        &mut self,
+        sensitivity_unknowns: &[(Parameter, Unknown)],
+        derivatives: &AHashMap<(Value, Unknown), Value>,
+    ) {
+        for &(param, unknown) in sensitivity_unknowns {
+            for (row, residual) in self.system.residual.iter_enumerated() {
+                let ddx = |val| derivatives.get(&(val, unknown)).copied().unwrap_or(F_ZERO);
+                let resist = ddx(residual.resist);
+                let react = ddx(residual.react);
+                if resist == F_ZERO && react == F_ZERO {
+                    continue;
+                }
+                self.system.sensitivities.push(SensitivityEntry { row, param, resist, react });
+            }
+        }
+    }
+
+    /// Returns the unknowns (and whether their derivative must be negated) that make up the
+    /// derivative by each simulation unknown, just like a jacobian column.
+    fn sim_unknown_derivatives(
+        &self,
+        sim_unknown_reads: &[(ParamKind, Value)],
+        derivative_info: &KnownDerivatives,
+    ) -> TiVec<SimUnknown, Vec<(Unknown, bool)>> {
+        let mut res = TiVec::from(vec![Vec::new(); self.system.unknowns.len()]);
+        let mut add_unknown = |sim_unknown: SimUnknownKind, val, negate| {
+            let col = if let Some(col) = self.system.unknowns.index(&sim_unknown) {
+                col
+            } else {
+                return;
+            };
+            if let Some(lim_vals) = self.intern.lim_state.raw.get(&val) {
+                res[col].extend(lim_vals.iter().filter_map(|(val, negate_lim)| {
+                    let unknown = derivative_info.unknowns.index(val)?;
+                    Some((unknown, negate != *negate_lim))
+                }));
+            }
+            if let Some(unknown) = derivative_info.unknowns.index(&val) {
+                res[col].push((unknown, negate));
+            }
+        };
+
+        for &(kind, val) in sim_unknown_reads {
+            let sim_unknown = match kind {
+                ParamKind::Voltage { hi, lo } => {
+                    if let Some(lo) = lo {
+                        add_unknown(SimUnknownKind::KirchoffLaw(lo), val, true);
+                    }
+                    SimUnknownKind::KirchoffLaw(hi)
+                }
+                ParamKind::ImplicitUnknown(equation) => SimUnknownKind::Implicit(equation),
+                ParamKind::Current(kind) => SimUnknownKind::Current(kind),
+                _ => continue,
+            };
+            add_unknown(sim_unknown, val, false);
+        }
+        res
+    }
+
+    /// Computes the second order derivatives of the residual of the nodes selected with
+    /// [`ModuleInfo::add_hessian`] by differentiating the jacobian entries of these rows a
+    /// second time. Only Kirchhoff current law rows are differentiated, the rows of branch
+    /// currents and implicit equations have no hessian. Higher order derivatives are not
+    /// propagated trough calls, therefore all calls the residual depends on were inlined
+    /// by [`Context::new`](crate::context::Context::new).
+    fn build_hessian(
+        &mut self,
+        sim_unknown_reads: &[(ParamKind, Value)],
+        derivative_info: &KnownDerivatives,
+    ) {
+        let rows: Vec<_> = self
+            .module
+            .hessian
+            .iter()
+            .filter_map(|&node| self.system.unknowns.index(&SimUnknownKind::KirchoffLaw(node)))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+
+        let columns = self.sim_unknown_derivatives(sim_unknown_reads, derivative_info);
+        let entries: Vec<MatrixEntry> = self
+            .system
+            .jacobian
+            .iter()
+            .filter(|entry| rows.contains(&entry.row))
+            .copied()
+            .collect();
+        let mut extra_derivatives = Vec::new();
+        for entry in &entries {
+            for val in [entry.resist, entry.react] {
+                if self.cursor.func.dfg.value_def(val).as_const().is_none() {
+                    extra_derivatives
+                        .extend(columns.iter().flatten().map(|&(unknown, _)| (val, unknown)));
+                }
+            }
+        }
+
+        // the first auto_diff pass may have added blocks
+        self.cfg.compute(self.cursor.func);
+        self.dom_tree.compute(self.cursor.func, self.cfg, true, false, true);
+        let derivatives = auto_diff_with_options(
+            &mut *self.cursor.func,
+            self.dom_tree,
+            derivative_info,
+            &extra_derivatives,
+            self.module.autodiff,
+        );
+        drop(extra_derivatives);
+        self.cursor.goto_exit();
+
+        // the tensor is symmetric (ddx(ddx(f, x_j), x_k) = ddx(ddx(f, x_k), x_j))
+        for entry in entries {
+            for (col2, unknowns) in columns.iter_enumerated() {
+                if col2 < entry.col {
+                    continue;
+                }
+                let mut resist = F_ZERO;
+                let mut react = F_ZERO;
+                for &(unknown, negate) in unknowns {
+                    if let Some(&val) = derivatives.get(&(entry.resist, unknown)) {
+                        add(&mut self.cursor, &mut resist, val, negate)
+                    }
+                    if let Some(&val) = derivatives.get(&(entry.react, unknown)) {
+                        add(&mut self.cursor, &mut react, val, negate)
+                    }
+                }
+                if resist == F_ZERO && react == F_ZERO {
+                    continue;
+                }
+                self.system.hessian.push(HessianEntry {
+                    row: entry.row,
+                    col1: entry.col,
+                    col2,
+                    resist,
+                    react,
+                });
+            }
+        }
+    }
+
     pub fn jacobian_derivatives(
         &self,
         simulation_unknown: impl Iterator<Item = Value>,
@@ -556,5 +848,19 @@ impl<'a> Builder<'a> {
             entry.resist = ensure_optbarrier(entry.resist, is_kirchoff);
             entry.react = ensure_optbarrier(entry.react, is_kirchoff);
         }
+
+        for entry in &mut self.system.sensitivities {
+            let is_kirchoff =
+                matches!(self.system.unknowns[entry.row], SimUnknownKind::KirchoffLaw(_));
+            entry.resist = ensure_optbarrier(entry.resist, is_kirchoff);
+            entry.react = ensure_optbarrier(entry.react, is_kirchoff);
+        }
+
+        for entry in &mut self.system.hessian {
+            let is_kirchoff =
+                matches!(self.system.unknowns[entry.row], SimUnknownKind::KirchoffLaw(_));
+            entry.resist = ensure_optbarrier(entry.resist, is_kirchoff);
+            entry.react = ensure_optbarrier(entry.react, is_kirchoff);
+        }
     }
 }
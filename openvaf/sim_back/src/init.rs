@@ -1,7 +1,7 @@
 use ahash::{AHashMap, AHashSet, RandomState};
 use bitset::{BitSet, SparseBitMatrix};
 use hir::{CompilationDB, Type};
-use hir_lower::{HirInterner, ParamKind, PlaceKind};
+use hir_lower::{CallBackKind, HirInterner, ParamKind, PlaceKind};
 use indexmap::IndexMap;
 use mir::builder::InstBuilder;
 use mir::cursor::{Cursor, FuncCursor};
@@ -75,7 +75,11 @@ impl<'a> Builder<'a> {
                 func: Function::with_name(format!("{}_init", &ctx.func.name)),
                 cached_vals: IndexMap::with_capacity_and_hasher(128, RandomState::new()),
                 cache_slots: TiMap::default(),
-                intern: HirInterner::default(),
+                // the source locations of the copied instructions refer to the eval function
+                intern: HirInterner {
+                    source_locations: ctx.intern.source_locations.clone(),
+                    ..HirInterner::default()
+                },
             },
             init_cache: IndexMap::with_capacity_and_hasher(256, RandomState::default()),
             func: &mut ctx.func,
@@ -124,6 +128,14 @@ impl<'a> Builder<'a> {
     fn copy_callback(&mut self, cb: FuncRef) -> FuncRef {
         let cb = self.intern.callbacks[cb].clone();
         let signature = cb.signature();
+        if let CallBackKind::AnalogFunction { fun, .. }
+        | CallBackKind::AnalogFunctionDerivative { fun, .. } = cb
+        {
+            if !self.init.intern.analog_functions.contains_key(&fun) {
+                let analog_function = self.intern.analog_functions[&fun].clone();
+                self.init.intern.analog_functions.insert(fun, analog_function);
+            }
+        }
         let (func, changed) = self.init.intern.callbacks.ensure(cb);
         if changed {
             let func_ = self.init.func.import_function(signature);
@@ -1,10 +1,11 @@
 use hir::{BranchWrite, CompilationDB, Node};
-use hir_lower::{CurrentKind, HirInterner, ImplicitEquation};
+use hir_lower::{CurrentKind, HirInterner, ImplicitEquation, PlaceKind};
 use lasso::Rodeo;
-use mir::Function;
+use mir::{ControlFlowGraph, Function};
 use mir_opt::{simplify_cfg, sparse_conditional_constant_propagation};
 use stdx::impl_debug_display;
 
+pub use mir_autodiff::{AutoDiffMode, AutoDiffOptions};
 pub use module_info::{collect_modules, ModuleInfo};
 
 use crate::context::{Context, OptimiziationStage};
@@ -50,6 +51,9 @@ pub struct CompiledModule<'a> {
     pub model_param_setup: Function,
     pub model_param_intern: HirInterner,
     pub node_collapse: NodeCollapse,
+    /// Snapshots of `eval` before and after the initial `mir_opt` pipeline
+    /// (only recorded when requested with `dump_mir`)
+    pub mir_stages: Vec<(&'static str, Function)>,
 }
 
 impl<'a> CompiledModule<'a> {
@@ -57,12 +61,20 @@ impl<'a> CompiledModule<'a> {
         db: &CompilationDB,
         module: &'a ModuleInfo,
         literals: &mut Rodeo,
+        dump_mir: bool,
     ) -> CompiledModule<'a> {
+        let mut mir_stages = Vec::new();
         let mut cx = Context::new(db, literals, module);
+        if dump_mir {
+            mir_stages.push(("pre_opt", cx.func.clone()));
+        }
         cx.compute_outputs(true);
         cx.compute_cfg();
         cx.optimize(OptimiziationStage::Initial);
         debug_assert!(cx.func.validate());
+        if dump_mir {
+            mir_stages.push(("post_opt", cx.func.clone()));
+        }
 
         let topology = Topology::new(&mut cx);
         debug_assert!(cx.func.validate());
@@ -85,7 +97,16 @@ impl<'a> CompiledModule<'a> {
             .iter()
             .filter_map(|(param, info)| info.is_instance.then_some(*param))
             .collect();
-        init.intern.insert_param_init(db, &mut init.func, literals, false, true, &inst_params);
+        let source_locations = module.source_locations();
+        init.intern.insert_param_init(
+            db,
+            &mut init.func,
+            literals,
+            false,
+            true,
+            source_locations,
+            &inst_params,
+        );
 
         let mut model_param_setup = Function::default();
         let model_params: Vec<_> = module.params.keys().copied().collect();
@@ -96,6 +117,7 @@ impl<'a> CompiledModule<'a> {
             literals,
             false,
             true,
+            source_locations,
             &model_params,
         );
         cx.cfg.compute(&model_param_setup);
@@ -112,6 +134,32 @@ impl<'a> CompiledModule<'a> {
             model_param_intern,
             model_param_setup,
             node_collapse,
+            mir_stages,
+        }
+    }
+}
+
+/// Builds the function that computes the operating point variables of `module`.
+/// No derivatives are computed and no DAE system is constructed, so `ddt` and `ddx`
+/// remain calls to callbacks. This is intended for exporting the equations of a model
+/// for symbolic analysis and documentation.
+pub fn build_opvar_mir(
+    db: &CompilationDB,
+    module: &ModuleInfo,
+    literals: &mut Rodeo,
+) -> (Function, HirInterner, ControlFlowGraph) {
+    let mut cx = Context::new(db, literals, module);
+    cx.output_values.ensure(cx.func.dfg.num_values() + 1);
+    for (kind, val) in cx.intern.outputs.iter() {
+        if let (PlaceKind::Var(var), Some(val)) = (kind, val.expand()) {
+            if module.op_vars.contains_key(var) {
+                cx.output_values.insert(val);
+            }
         }
     }
+    cx.compute_cfg();
+    cx.optimize(OptimiziationStage::Initial);
+    cx.optimize(OptimiziationStage::Final);
+    debug_assert!(cx.func.validate());
+    (cx.func, cx.intern, cx.cfg)
 }
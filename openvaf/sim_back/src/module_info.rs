@@ -1,15 +1,20 @@
 use ahash::AHashSet;
 use hir::diagnostics::{BaseDB, ConsoleSink, Diagnostic, FileId, Label, LabelStyle, Report};
 use hir::{
-    CompilationDB, CompilationUnit, DiagnosticSink, Module, ParamSysFun, Parameter,
-    ResolvedAliasParameter, ScopeDef, Variable,
+    CompilationDB, CompilationUnit, DiagnosticSink, Function, Module, Node, ParamSysFun, Parameter,
+    ResolvedAliasParameter, Scope, ScopeDef, Type, Variable,
 };
 use indexmap::IndexMap;
+use lasso::Rodeo;
+use mir::FloatPolicy;
+use mir_autodiff::AutoDiffOptions;
 use smol_str::SmolStr;
 use syntax::ast::{self, Expr};
 use syntax::sourcemap::FileSpan;
 use syntax::AstNode;
 
+use crate::context::Context;
+
 #[cfg(test)]
 mod tests;
 
@@ -45,9 +50,91 @@ pub struct ModuleInfo {
     pub params: IndexMap<Parameter, ParamInfo, ahash::RandomState>,
     pub sys_fun_alias: IndexMap<ParamSysFun, Vec<SmolStr>, ahash::RandomState>,
     pub op_vars: IndexMap<Variable, OpVar, ahash::RandomState>,
+    /// Parameters by which the derivatives of the residual are computed
+    /// (parameter sensitivities). Selected with [`ModuleInfo::add_sensitivity`].
+    pub sensitivities: Vec<Parameter>,
+    /// Nodes whose residual is differentiated twice (second order jacobian).
+    /// Selected with [`ModuleInfo::add_hessian`].
+    pub hessian: Vec<Node>,
+    /// How the derivatives of the DAE system are computed
+    pub autodiff: AutoDiffOptions,
+    /// Check the result of every floating point operation in the generated code and
+    /// report the first `NaN`/`Inf` together with its location in the source code.
+    /// Analog functions that are not inlined are only checked at the call site (their results).
+    /// Requires [`HirInterner::source_locations`](hir_lower::HirInterner::source_locations).
+    pub check_fp: bool,
+    /// Emit debug information (line tables and local variables) for the generated code.
+    /// Requires [`HirInterner::source_locations`](hir_lower::HirInterner::source_locations).
+    pub debug_info: bool,
+    /// Represent `absdelay` with an implicit equation (an additional unknown) and export the
+    /// delay time so that simulators can apply the delay themselves. Otherwise the delay
+    /// is ignored and `absdelay(x, td)` evaluates to `x`.
+    pub symbolic_delays: bool,
 }
 
 impl ModuleInfo {
+    /// Whether the generated functions record the source location of each instruction in
+    /// [`HirInterner::source_locations`](hir_lower::HirInterner::source_locations)
+    pub fn source_locations(&self) -> bool {
+        self.check_fp || self.debug_info
+    }
+
+    /// Selects the real valued parameter called `name` (or one of its aliases) for
+    /// sensitivity analysis. Returns `false` if this module has no such parameter.
+    pub fn add_sensitivity(&mut self, db: &CompilationDB, name: &str) -> bool {
+        let param = self.params.iter().find_map(|(&param, info)| {
+            let matches = info.name == name || info.alias.iter().any(|alias| alias == name);
+            (matches && param.ty(db) == Type::Real).then_some(param)
+        });
+        match param {
+            Some(param) => {
+                if !self.sensitivities.contains(&param) {
+                    self.sensitivities.push(param)
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the node called `name` whose residual is differentiated twice by all
+    /// unknowns of the DAE system. Returns `false` if this module has no such node.
+    pub fn add_hessian(&mut self, db: &CompilationDB, name: &str) -> bool {
+        let mut nodes = self.module.ports(db).into_iter().chain(self.module.internal_nodes(db));
+        match nodes.find(|node| node.name(db) == name) {
+            Some(node) => {
+                if !self.hessian.contains(&node) {
+                    self.hessian.push(node)
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Analog functions with a `fp_policy` attribute are never inlined but derivatives of second
+    /// order are not propagated trough calls. Reports an error for every such function whose
+    /// result is differentiated twice (within `ddx` or for the hessian).
+    pub fn check_inlining(&self, db: &CompilationDB, sink: &mut ConsoleSink) {
+        let cu = db.compilation_unit();
+        let ast = cu.ast(db);
+        let has_fp_policy = |fun: Function| fun.get_attr(db, &ast, "fp_policy").is_some();
+        let has_fp_policy_functions = self
+            .module
+            .rec_declarations(db)
+            .any(|(_, dec)| matches!(dec, ScopeDef::Function(fun) if has_fp_policy(fun)));
+        // avoids lowering the module for the common case
+        if !has_fp_policy_functions {
+            return;
+        }
+
+        let cx = Context::new(db, &mut Rodeo::new(), self);
+        for fun in cx.inline_conflicts {
+            let attr = fun.get_attr(db, &ast, "fp_policy").unwrap();
+            sink.add_diagnostic(&FpPolicyInlineConflict { attr }, cu.root_file(), db);
+        }
+    }
+
     fn collect(
         db: &CompilationDB,
         cu: CompilationUnit,
@@ -58,7 +145,8 @@ impl ModuleInfo {
         let mut params: IndexMap<Parameter, ParamInfo, ahash::RandomState> = IndexMap::default();
         let mut sys_fun_alias: IndexMap<ParamSysFun, Vec<SmolStr>, ahash::RandomState> =
             IndexMap::default();
-        let mut op_vars = IndexMap::default();
+        let mut op_vars: IndexMap<Variable, OpVar, ahash::RandomState> = IndexMap::default();
+        let mut op_var_derivatives = Vec::new();
 
         let ast = cu.ast(db);
 
@@ -81,7 +169,12 @@ impl ModuleInfo {
                     // check for units or description
                     let units = var.get_attr(db, &ast, "units");
                     let desc = var.get_attr(db, &ast, "desc");
-                    if units.is_none() && desc.is_none() && !all_vars_opvars {
+                    let derivative_of = var.get_attr(db, &ast, "derivative_of");
+                    if units.is_none()
+                        && desc.is_none()
+                        && derivative_of.is_none()
+                        && !all_vars_opvars
+                    {
                         continue;
                     }
 
@@ -109,7 +202,11 @@ impl ModuleInfo {
                             lit
                         })
                         .unwrap_or_default();
-                    op_vars.insert(var, OpVar { unit: units, description: desc });
+                    if let Some(attr) = derivative_of {
+                        let wrt = var.get_attr(db, &ast, "wrt");
+                        op_var_derivatives.push((var, attr, wrt));
+                    }
+                    op_vars.insert(var, OpVar { unit: units, description: desc, derivative: None });
                 }
 
                 ScopeDef::Parameter(param) => {
@@ -193,7 +290,99 @@ impl ModuleInfo {
             }
         }
 
-        ModuleInfo { module, params, op_vars, sys_fun_alias }
+        for (_, def) in Scope::Module(module).declarations(db) {
+            let attr = match def {
+                ScopeDef::Function(fun) => fun.get_attr(db, &ast, "fp_policy"),
+                _ => None,
+            };
+            if let Some(attr) = attr {
+                let policy = attr.val().and_then(|e| e.as_str_literal());
+                if policy.and_then(|policy| FloatPolicy::from_name(&policy)).is_none() {
+                    add_diagnostic(attr.clone(), &IllegalFpPolicyAttr { attr });
+                }
+            }
+        }
+
+        // derivatives can only be resolved once all operating point variables are known
+        let nodes: Vec<_> = module.ports(db).into_iter().chain(module.internal_nodes(db)).collect();
+        let derived: AHashSet<_> = op_var_derivatives.iter().map(|(var, _, _)| *var).collect();
+        let (analog_block, analog_initial_block) =
+            (module.analog_block(db), module.analog_initial_block(db));
+        let assigned: AHashSet<_> = analog_block
+            .borrow()
+            .assigned_variables()
+            .chain(analog_initial_block.borrow().assigned_variables())
+            .collect();
+        for (var, of_attr, wrt_attr) in op_var_derivatives {
+            let mut illegal_attr = |attr: ast::Attr, err: Option<DerivativeAttrError>| match err {
+                Some(err) => add_diagnostic(attr.clone(), &IllegalDerivativeAttr { attr, err }),
+                None => add_diagnostic(attr.clone(), &IllegalAttr { attr }),
+            };
+
+            let of = if let Some(of) = of_attr.val().and_then(|e| e.as_str_literal()) {
+                of
+            } else {
+                illegal_attr(of_attr, None);
+                continue;
+            };
+            if var.ty(db) != Type::Real {
+                illegal_attr(of_attr, Some(DerivativeAttrError::NotReal));
+                continue;
+            }
+            // the derivative would silently replace the value computed by the model
+            if assigned.contains(&var) {
+                illegal_attr(
+                    of_attr,
+                    Some(DerivativeAttrError::Assigned(var.name(db).to_string())),
+                );
+                continue;
+            }
+            let wrt_attr = if let Some(attr) = wrt_attr {
+                attr
+            } else {
+                illegal_attr(of_attr, Some(DerivativeAttrError::MissingWrt));
+                continue;
+            };
+            let wrt = if let Some(wrt) = wrt_attr.val().and_then(|e| e.as_str_literal()) {
+                wrt
+            } else {
+                illegal_attr(wrt_attr, None);
+                continue;
+            };
+
+            let of = match op_vars.keys().copied().find(|op_var| op_var.name(db) == *of) {
+                Some(of) if !derived.contains(&of) => of,
+                Some(_) => {
+                    illegal_attr(of_attr, Some(DerivativeAttrError::DerivedOpVar(of)));
+                    continue;
+                }
+                None => {
+                    illegal_attr(of_attr, Some(DerivativeAttrError::UnknownOpVar(of)));
+                    continue;
+                }
+            };
+            let wrt = if let Some(node) = nodes.iter().copied().find(|node| node.name(db) == *wrt) {
+                node
+            } else {
+                illegal_attr(wrt_attr, Some(DerivativeAttrError::UnknownNode(wrt)));
+                continue;
+            };
+
+            op_vars[&var].derivative = Some(OpVarDerivative { of, wrt });
+        }
+
+        ModuleInfo {
+            module,
+            params,
+            op_vars,
+            sys_fun_alias,
+            sensitivities: Vec::new(),
+            hessian: Vec::new(),
+            autodiff: AutoDiffOptions::default(),
+            check_fp: false,
+            debug_info: false,
+            symbolic_delays: false,
+        }
     }
 }
 
@@ -245,6 +434,113 @@ impl Diagnostic for UnknownType<'_> {
     }
 }
 
+struct IllegalFpPolicyAttr {
+    attr: ast::Attr,
+}
+
+impl Diagnostic for IllegalFpPolicyAttr {
+    fn build_report(&self, root_file: FileId, db: &dyn BaseDB) -> Report {
+        let FileSpan { range, file } = db
+            .parse(root_file)
+            .to_file_span(self.attr.syntax().text_range(), &db.sourcemap(root_file));
+        let expected: Vec<_> =
+            FloatPolicy::ALL.iter().map(|policy| format!("\"{}\"", policy.name())).collect();
+        Report::error()
+            .with_message(format!(
+                "illegal expression supplied to 'fp_policy' attribute; expected one of {}",
+                expected.join(", ")
+            ))
+            .with_labels(vec![Label {
+                style: LabelStyle::Primary,
+                file_id: file,
+                range: range.into(),
+                message: "unknown floating point policy".to_owned(),
+            }])
+    }
+}
+
+struct FpPolicyInlineConflict {
+    attr: ast::Attr,
+}
+
+impl Diagnostic for FpPolicyInlineConflict {
+    fn build_report(&self, root_file: FileId, db: &dyn BaseDB) -> Report {
+        let FileSpan { range, file } = db
+            .parse(root_file)
+            .to_file_span(self.attr.syntax().text_range(), &db.sourcemap(root_file));
+        Report::error()
+            .with_message(
+                "second order derivatives of an analog function with a 'fp_policy' attribute are \
+                 required"
+                    .to_owned(),
+            )
+            .with_labels(vec![Label {
+                style: LabelStyle::Primary,
+                file_id: file,
+                range: range.into(),
+                message: "prevents inlining".to_owned(),
+            }])
+            .with_notes(vec![
+                "only first order derivatives are propagated trough calls".to_owned(),
+                "help: remove the attribute or avoid calling the function within ddx (or --hessian)"
+                    .to_owned(),
+            ])
+    }
+}
+
+enum DerivativeAttrError {
+    MissingWrt,
+    NotReal,
+    Assigned(String),
+    UnknownOpVar(String),
+    DerivedOpVar(String),
+    UnknownNode(String),
+}
+
+struct IllegalDerivativeAttr {
+    attr: ast::Attr,
+    err: DerivativeAttrError,
+}
+
+impl Diagnostic for IllegalDerivativeAttr {
+    fn build_report(&self, root_file: FileId, db: &dyn BaseDB) -> Report {
+        let FileSpan { range, file } = db
+            .parse(root_file)
+            .to_file_span(self.attr.syntax().text_range(), &db.sourcemap(root_file));
+        let (message, label) = match &self.err {
+            DerivativeAttrError::MissingWrt => (
+                "'derivative_of' attribute requires a 'wrt' attribute".to_owned(),
+                "missing 'wrt' attribute".to_owned(),
+            ),
+            DerivativeAttrError::NotReal => (
+                "'derivative_of' attribute is only allowed for real variables".to_owned(),
+                "derivative of a non-real variable".to_owned(),
+            ),
+            DerivativeAttrError::Assigned(name) => (
+                format!("\"{name}\" is assigned by the model but derived with 'derivative_of'"),
+                "remove this attribute or the assignments to the variable".to_owned(),
+            ),
+            DerivativeAttrError::UnknownOpVar(name) => (
+                format!("derivative of unknown operating point variable \"{name}\""),
+                "not an operating point variable".to_owned(),
+            ),
+            DerivativeAttrError::DerivedOpVar(name) => (
+                format!("operating point variable \"{name}\" is itself a derivative"),
+                "higher order derivatives are not supported".to_owned(),
+            ),
+            DerivativeAttrError::UnknownNode(name) => {
+                (format!("derivative by unknown node \"{name}\""), "unknown node".to_owned())
+            }
+        };
+        Report::error().with_message(message).with_labels(vec![Label {
+            style: LabelStyle::Primary,
+            file_id: file,
+            range: range.into(),
+            message: label,
+        }])
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct ParamInfo {
     pub name: SmolStr,
@@ -259,4 +555,16 @@ pub struct ParamInfo {
 pub struct OpVar {
     pub unit: String,
     pub description: String,
+    /// Set for operating point variables declared with the `derivative_of` and `wrt`
+    /// attributes. These are not computed by the model itself but instead derived
+    /// automatically after the DAE system was constructed.
+    pub derivative: Option<OpVarDerivative>,
+}
+
+/// The derivative of the operating point variable `of` by the potential of the node `wrt`
+/// (with all other node potentials held constant).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpVarDerivative {
+    pub of: Variable,
+    pub wrt: Node,
 }
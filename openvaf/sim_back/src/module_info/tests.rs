@@ -144,6 +144,7 @@ fn opvars() {
                 OpVar {
                     unit: "m",
                     description: "hmm",
+                    derivative: None,
                 },
             ),
             (
@@ -151,6 +152,7 @@ fn opvars() {
                 OpVar {
                     unit: "m",
                     description: "hmm",
+                    derivative: None,
                 },
             ),
             (
@@ -158,6 +160,7 @@ fn opvars() {
                 OpVar {
                     unit: "m",
                     description: "",
+                    derivative: None,
                 },
             ),
             (
@@ -165,9 +168,146 @@ fn opvars() {
                 OpVar {
                     unit: "",
                     description: "hmm",
+                    derivative: None,
                 },
             ),
         ]
     "#]]
     .assert_debug_eq(&params);
 }
+
+#[test]
+fn opvar_derivatives() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout electrical d, inout electrical g, inout electrical s);
+            electrical di;
+            (* units="A", desc="drain current" *) real ids;
+            (* units="S", desc="transconductance", derivative_of="ids", wrt="g" *) real gm;
+            (* units="S", derivative_of="ids", wrt="di" *) real gds;
+            analog I(d, s) <+ 0.0;
+        endmodule
+    "#};
+    let db = CompilationDB::new_virtual(src).unwrap();
+    let modules = super::collect_modules(&db, false, &mut ConsoleSink::new(&db)).unwrap();
+    assert_eq!(modules.len(), 1);
+    let derivatives: Vec<_> = modules[0]
+        .op_vars
+        .iter()
+        .map(|(k, v)| (k.name(&db), v.derivative.map(|it| (it.of.name(&db), it.wrt.name(&db)))))
+        .collect();
+    expect_test::expect![[r#"
+        [
+            (
+                "ids",
+                None,
+            ),
+            (
+                "gm",
+                Some(
+                    (
+                        "ids",
+                        "g",
+                    ),
+                ),
+            ),
+            (
+                "gds",
+                Some(
+                    (
+                        "ids",
+                        "di",
+                    ),
+                ),
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&derivatives);
+}
+
+#[test]
+fn invalid_opvar_derivatives() {
+    let src = indoc! {r#"
+        `include "disciplines.vams"
+        module test(inout electrical a);
+            (* units="A" *) real ids;
+            (* derivative_of="ids" *) real missing_wrt;
+            (* derivative_of="foo", wrt="a" *) real unknown_opvar;
+            (* derivative_of="ids", wrt="b" *) real unknown_node;
+            (* derivative_of="ids", wrt="a" *) real assigned;
+            analog assigned = 1.0;
+        endmodule
+    "#};
+    let db = CompilationDB::new_virtual(src).unwrap();
+    let mut buf = Buffer::no_color();
+    {
+        let mut sink = ConsoleSink::buffer(&db, &mut buf);
+        sink.annonymize_paths();
+        super::collect_modules(&db, false, &mut sink);
+    }
+    expect_test::expect![[r#"
+        error: 'derivative_of' attribute requires a 'wrt' attribute
+          --> /root.va:4:8
+          |
+        4 |     (* derivative_of="ids" *) real missing_wrt;
+          |        ^^^^^^^^^^^^^^^^^^^ missing 'wrt' attribute
+
+        error: derivative of unknown operating point variable "foo"
+          --> /root.va:5:8
+          |
+        5 |     (* derivative_of="foo", wrt="a" *) real unknown_opvar;
+          |        ^^^^^^^^^^^^^^^^^^^ not an operating point variable
+
+        error: derivative by unknown node "b"
+          --> /root.va:6:29
+          |
+        6 |     (* derivative_of="ids", wrt="b" *) real unknown_node;
+          |                             ^^^^^^^ unknown node
+
+        error: "assigned" is assigned by the model but derived with 'derivative_of'
+          --> /root.va:7:8
+          |
+        7 |     (* derivative_of="ids", wrt="a" *) real assigned;
+          |        ^^^^^^^^^^^^^^^^^^^ remove this attribute or the assignments to the variable
+
+        error: could not compile `root.va` due to 4 previous errors
+
+    "#]]
+    .assert_eq(&String::from_utf8(buf.into_inner()).unwrap());
+}
+
+#[test]
+fn invalid_fp_policy() {
+    let src = indoc! {r#"
+        module test;
+            (* fp_policy="exact" *) analog function real f;
+                input x;
+                real x;
+                f = x;
+            endfunction
+            (* fp_policy="strict" *) analog function real g;
+                input x;
+                real x;
+                g = x;
+            endfunction
+        endmodule
+    "#};
+    let db = CompilationDB::new_virtual(src).unwrap();
+    let mut buf = Buffer::no_color();
+    {
+        let mut sink = ConsoleSink::buffer(&db, &mut buf);
+        sink.annonymize_paths();
+        super::collect_modules(&db, false, &mut sink);
+    }
+    expect_test::expect![[r#"
+        error: illegal expression supplied to 'fp_policy' attribute; expected one of "strict", "partial", "fast"
+          --> /root.va:2:8
+          |
+        2 |     (* fp_policy="exact" *) analog function real f;
+          |        ^^^^^^^^^^^^^^^^^ unknown floating point policy
+
+        error: could not compile `root.va` due to 1 previous errors
+
+    "#]]
+    .assert_eq(&String::from_utf8(buf.into_inner()).unwrap());
+}
@@ -0,0 +1,7 @@
+use crate::spec::Target;
+
+pub fn target() -> Target {
+    let mut base = super::aarch64_unknown_linux::target();
+    base.llvm_target = "aarch64-unknown-linux-musl".to_string();
+    base
+}
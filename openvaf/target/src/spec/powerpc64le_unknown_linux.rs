@@ -0,0 +1,18 @@
+use crate::spec::{linux_base, LinkerFlavor, Target};
+
+pub fn target() -> Target {
+    let mut base = linux_base::opts();
+    base.cpu = "ppc64le".to_string();
+    let link_args = base.pre_link_args.entry(LinkerFlavor::Ld).or_default();
+    for arg in "-m elf64lppc".split(' ') {
+        link_args.push(arg.to_owned())
+    }
+
+    Target {
+        llvm_target: "powerpc64le-unknown-linux-gnu".to_string(),
+        arch: "powerpc64".to_string(),
+        data_layout: "e-m:e-i64:64-n32:64-S128-v256:256:256-v512:512:512".to_string(),
+        options: base,
+        pointer_width: 64,
+    }
+}
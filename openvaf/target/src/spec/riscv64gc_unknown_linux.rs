@@ -0,0 +1,21 @@
+use crate::spec::{linux_base, LinkerFlavor, Target};
+
+pub fn target() -> Target {
+    let mut base = linux_base::opts();
+    base.cpu = "generic-rv64".to_string();
+    base.features = "+m,+a,+f,+d,+c".to_string();
+    // hard float ABI used by all linux distributions
+    base.llvm_abiname = "lp64d".to_string();
+    let link_args = base.pre_link_args.entry(LinkerFlavor::Ld).or_default();
+    for arg in "-m elf64lriscv".split(' ') {
+        link_args.push(arg.to_owned())
+    }
+
+    Target {
+        llvm_target: "riscv64-unknown-linux-gnu".to_string(),
+        arch: "riscv64".to_string(),
+        data_layout: "e-m:e-p:64:64-i64:64-i128:128-n64-S128".to_string(),
+        options: base,
+        pointer_width: 64,
+    }
+}
@@ -0,0 +1,11 @@
+use crate::spec::{wasm_base, Target};
+
+pub fn target() -> Target {
+    Target {
+        llvm_target: "wasm32-unknown-unknown".to_string(),
+        arch: "wasm32".to_string(),
+        data_layout: "e-m:e-p:32:32-i64:64-n32:64-S128".to_string(),
+        options: wasm_base::opts(),
+        pointer_width: 32,
+    }
+}
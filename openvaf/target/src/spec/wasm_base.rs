@@ -0,0 +1,18 @@
+use crate::spec::{LinkerFlavor, TargetOptions};
+
+pub fn opts() -> TargetOptions {
+    let mut opts = TargetOptions {
+        linker_flavor: LinkerFlavor::WasmLd,
+        is_like_wasm: true,
+        ..TargetOptions::default()
+    };
+
+    // libm and the string functions used by the stdlib are imported from the
+    // host (see osdi/wasm/runtime.mjs). The table is exported so that the simulator
+    // can call the function pointers in the descriptors and install `osdi_log`.
+    let link_args = opts.pre_link_args.entry(LinkerFlavor::WasmLd).or_default();
+    for arg in "--allow-undefined --export-table --growable-table --export=__heap_base".split(' ') {
+        link_args.push(arg.to_owned())
+    }
+    opts
+}
@@ -0,0 +1,7 @@
+use crate::spec::Target;
+
+pub fn target() -> Target {
+    let mut base = super::x86_64_unknown_linux::target();
+    base.llvm_target = "x86_64-unknown-linux-musl".to_string();
+    base
+}
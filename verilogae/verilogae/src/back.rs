@@ -70,6 +70,9 @@ pub fn stub_callbacks<'ll>(
                 | CallBackKind::LimDiscontinuity
                 | CallBackKind::CollapseHint(_, _) => return None,
                 CallBackKind::Analysis => cx.const_callback(&[cx.ty_ptr()], cx.const_int(1)),
+                // verilogae always inlines analog functions
+                CallBackKind::AnalogFunction { .. }
+                | CallBackKind::AnalogFunctionDerivative { .. } => unreachable!(),
             };
 
             Some(res)
@@ -342,6 +345,7 @@ impl CodegenCtx<'_, '_> {
                     | ParamKind::Voltage { .. }
                     | ParamKind::Current(_)
                     | ParamKind::HiddenState(_) => return BuilderVal::Undef,
+                    ParamKind::FunctionArg(_) => unreachable!(),
                     ParamKind::Temperature => unsafe {
                         let temperature = llvm::LLVMGetParam(llfun, 8);
                         codegen.read_fat_ptr_at(0, offset, temperature, cx.ty_double())
@@ -669,7 +673,8 @@ impl CodegenCtx<'_, '_> {
                 let val = match kind {
                     ParamKind::Voltage { .. }
                     | ParamKind::Current(_)
-                    | ParamKind::HiddenState(_) => {
+                    | ParamKind::HiddenState(_)
+                    | ParamKind::FunctionArg(_) => {
                         unreachable!()
                     }
                     ParamKind::Param(_) | ParamKind::ParamGiven { .. } => return BuilderVal::Undef,
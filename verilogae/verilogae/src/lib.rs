@@ -6,7 +6,7 @@ use anyhow::{bail, Context, Result};
 use basedb::VfsStorage;
 use camino::{Utf8Path, Utf8PathBuf};
 use lasso::Rodeo;
-use linker::link;
+use linker::{link, LinkerKind};
 use mir_llvm::LLVMBackend;
 use salsa::ParallelDatabase;
 use stdx::iter::zip;
@@ -157,7 +157,7 @@ fn build_model(
     }
 
     // TODO configure linker
-    link(None, &target, dst, |linker| {
+    link(LinkerKind::External, None, &target, dst, false, |linker| {
         for obj in &object_files {
             linker.add_object(obj)
         }
@@ -92,7 +92,7 @@ pub fn build_module_mir(
     let mut output_values = BitSet::new_empty(func.dfg.num_values());
     output_values.extend(intern.outputs.values().filter_map(|it| it.expand()));
 
-    intern.insert_var_init(db, &mut func, &mut literals);
+    intern.insert_var_init(db, &mut func, &mut literals, false);
 
     let mut cfg = ControlFlowGraph::new();
     cfg.compute(&func);
@@ -134,7 +134,7 @@ pub fn build_param_init_mir(
     let mut intern = HirInterner::default();
 
     let params: Vec<_> = info.params.keys().copied().collect();
-    intern.insert_param_init(db, &mut func, literals, true, false, &params);
+    intern.insert_param_init(db, &mut func, literals, true, false, false, &params);
 
     (func, intern)
 }